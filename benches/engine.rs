@@ -0,0 +1,194 @@
+//! Criterion benchmarks for the token lifecycle
+//!
+//! run with `cargo bench --bench engine`. Scenario names follow the
+//! `<operation>/<parameter>/<value>` pattern and should be kept stable, so
+//! that results can be compared between revisions
+use biscuit_auth::{
+    crypto::KeyPair,
+    token::{builder::*, verifier::VerifierLimits, Biscuit},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{prelude::StdRng, SeedableRng};
+use std::time::Duration;
+
+/// numbers of blocks in the token, including the authority block
+const BLOCKS: &[usize] = &[1, 2, 5, 10];
+/// numbers of facts in the authority block
+const FACTS: &[usize] = &[10, 100, 1000];
+/// numbers of rules in the verifier
+const RULES: &[usize] = &[1, 10, 50];
+
+fn limits() -> VerifierLimits {
+    VerifierLimits {
+        max_facts: 100_000,
+        max_iterations: 1000,
+        max_time: Duration::from_secs(10),
+    }
+}
+
+/// creates a token with `facts` authority facts and `blocks - 1` attenuation blocks
+fn token(rng: &mut StdRng, root: &KeyPair, blocks: usize, facts: usize) -> Biscuit {
+    let mut builder = Biscuit::builder(root);
+    for i in 0..facts {
+        builder
+            .add_authority_fact(fact(
+                "right",
+                &[s("authority"), string(&format!("file{}", i)), s("read")],
+            ))
+            .unwrap();
+    }
+    let mut token = builder.build_with_rng(rng).unwrap();
+
+    for i in 1..blocks {
+        let keypair = KeyPair::new_with_rng(rng);
+        let mut block = token.create_block();
+        block
+            .add_check(
+                format!(
+                    "check if resource(#ambient, $file), operation(#ambient, #read), !($file == \"block{}\")",
+                    i
+                )
+                .as_str(),
+            )
+            .unwrap();
+        token = token.append_with_rng(rng, &keypair, block).unwrap();
+    }
+
+    token
+}
+
+fn deserialization(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let root = KeyPair::new_with_rng(&mut rng);
+
+    let mut group = c.benchmark_group("deserialize");
+    for blocks in BLOCKS {
+        let data = token(&mut rng, &root, *blocks, 10).to_vec().unwrap();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("blocks", blocks), &data, |b, data| {
+            b.iter(|| Biscuit::from(data).unwrap())
+        });
+    }
+    for facts in FACTS {
+        let data = token(&mut rng, &root, 1, *facts).to_vec().unwrap();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("facts", facts), &data, |b, data| {
+            b.iter(|| Biscuit::from(data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn signature(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let root = KeyPair::new_with_rng(&mut rng);
+
+    let mut group = c.benchmark_group("verify_signature");
+    for blocks in BLOCKS {
+        let token = token(&mut rng, &root, *blocks, 10);
+        group.bench_with_input(BenchmarkId::new("blocks", blocks), &token, |b, token| {
+            b.iter(|| token.check_root_key(root.public()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn attenuation(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let root = KeyPair::new_with_rng(&mut rng);
+    let keypair = KeyPair::new_with_rng(&mut rng);
+
+    let mut group = c.benchmark_group("attenuate");
+    for blocks in BLOCKS {
+        let token = token(&mut rng, &root, *blocks, 10);
+        group.bench_with_input(BenchmarkId::new("blocks", blocks), &token, |b, token| {
+            b.iter(|| {
+                let mut block = token.create_block();
+                block.check_resource("file1");
+                block.check_operation("read");
+                token.append_with_rng(&mut rng, &keypair, block).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn authorization(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let root = KeyPair::new_with_rng(&mut rng);
+
+    let mut group = c.benchmark_group("authorize");
+    for blocks in BLOCKS {
+        let token = token(&mut rng, &root, *blocks, 10);
+        group.bench_with_input(BenchmarkId::new("blocks", blocks), &token, |b, token| {
+            b.iter(|| {
+                let mut verifier = token.verify(root.public()).unwrap();
+                verifier.add_resource("file1");
+                verifier.add_operation("read");
+                verifier
+                    .add_check(
+                        "check if right(#authority, $file, #read), resource(#ambient, $file)",
+                    )
+                    .unwrap();
+                verifier.allow().unwrap();
+                verifier.verify_with_limits(limits()).unwrap()
+            })
+        });
+    }
+
+    for facts in FACTS {
+        let token = token(&mut rng, &root, 1, *facts);
+        group.throughput(Throughput::Elements(*facts as u64));
+        group.bench_with_input(BenchmarkId::new("facts", facts), &token, |b, token| {
+            b.iter(|| {
+                let mut verifier = token.verify(root.public()).unwrap();
+                verifier.add_resource("file1");
+                verifier.add_operation("read");
+                verifier
+                    .add_check(
+                        "check if right(#authority, $file, #read), resource(#ambient, $file)",
+                    )
+                    .unwrap();
+                verifier.allow().unwrap();
+                verifier.verify_with_limits(limits()).unwrap()
+            })
+        });
+    }
+
+    // every rule derives a new fact from each authority fact
+    let token = token(&mut rng, &root, 1, 100);
+    for rules in RULES {
+        group.throughput(Throughput::Elements(*rules as u64));
+        group.bench_with_input(BenchmarkId::new("rules", rules), &token, |b, token| {
+            b.iter(|| {
+                let mut verifier = token.verify(root.public()).unwrap();
+                verifier.add_resource("file1");
+                verifier.add_operation("read");
+                for i in 0..*rules {
+                    verifier
+                        .add_rule(
+                            format!(
+                                "derived{}($file) <- right(#authority, $file, #read), resource(#ambient, $file)",
+                                i
+                            )
+                            .as_str(),
+                        )
+                        .unwrap();
+                }
+                verifier.add_check("check if derived0(\"file1\")").unwrap();
+                verifier.allow().unwrap();
+                verifier.verify_with_limits(limits()).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    deserialization,
+    signature,
+    attenuation,
+    authorization
+);
+criterion_main!(benches);
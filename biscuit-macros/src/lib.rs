@@ -0,0 +1,590 @@
+//! procedural macros for the biscuit-auth crate
+//!
+//! those macros are reexported by `biscuit-auth` when the `derive` feature
+//! is activated, they should not be used directly from this crate.
+//!
+//! ```rust,ignore
+//! use biscuit_auth::{TryFromFact, TryIntoFact};
+//!
+//! #[derive(TryFromFact, TryIntoFact)]
+//! #[fact(name = "right")]
+//! struct Right {
+//!     user: String,
+//!     resource: String,
+//!     operation: String,
+//! }
+//! ```
+//!
+//! each field is converted to a term, in declaration order. The name of the
+//! fact defaults to the name of the structure in snake case.
+//!
+//! the `fact!`, `rule!`, `check!`, `block!` and `biscuit!` macros, reexported
+//! with the `macros` feature, parse Datalog at compile time:
+//!
+//! ```rust,ignore
+//! use biscuit_auth::{check, rule};
+//!
+//! let user = "alice";
+//! let r = rule!("right($file) <- owner({user}, $file)");
+//! let c = check!("check if operation(#ambient, {op})", op = "read");
+//! ```
+//!
+//! parse errors are reported at compile time, and parameters (`{name}`) are
+//! replaced by Rust values instead of being formatted in the source.
+extern crate proc_macro;
+
+use biscuit_parser::builder as parsed;
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use std::collections::BTreeSet;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    spanned::Spanned,
+    Data, DeriveInput, Expr, Fields, Ident, Index, Lit, LitStr, Meta, NestedMeta, Path, Token,
+};
+
+/// generates `TryFrom<Fact>` for a structure
+///
+/// the conversion fails if the fact's name or number of terms do not match,
+/// or if one of the terms cannot be converted to the field's type
+#[proc_macro_derive(TryFromFact, attributes(fact))]
+pub fn derive_try_from_fact(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    try_from_fact(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// generates `TryFrom<Structure> for Fact`, so the structure can be given
+/// directly to the builders and the verifier
+///
+/// each field must implement `TryInto<Term>`
+#[proc_macro_derive(TryIntoFact, attributes(fact))]
+pub fn derive_try_into_fact(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    try_into_fact(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+struct FactAttributes {
+    name: String,
+    krate: Path,
+}
+
+fn parse_attributes(input: &DeriveInput) -> syn::Result<FactAttributes> {
+    let mut name = None;
+    let mut krate = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("fact")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new(
+                    meta.span(),
+                    "expected #[fact(name = \"...\")]",
+                ))
+            }
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => {
+                    match &nv.lit {
+                        Lit::Str(s) => name = Some(s.value()),
+                        lit => return Err(syn::Error::new(lit.span(), "expected a string")),
+                    }
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("crate") => {
+                    match &nv.lit {
+                        Lit::Str(s) => krate = Some(s.parse::<Path>()?),
+                        lit => return Err(syn::Error::new(lit.span(), "expected a string")),
+                    }
+                }
+                other => {
+                    return Err(syn::Error::new(
+                        other.span(),
+                        "unknown attribute, expected `name` or `crate`",
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(FactAttributes {
+        name: name.unwrap_or_else(|| snake_case(&input.ident.to_string())),
+        krate: krate.unwrap_or_else(|| syn::parse_quote!(::biscuit_auth)),
+    })
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Unit => Err(syn::Error::new(
+                input.ident.span(),
+                "facts must have at least one term",
+            )),
+            fields => Ok(fields),
+        },
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            "facts can only be derived for structures",
+        )),
+    }
+}
+
+fn try_from_fact(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let FactAttributes { name, krate } = parse_attributes(input)?;
+    let fields = fields(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let count = fields.len();
+
+    let values = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote! {
+            <#ty as ::std::convert::TryFrom<#krate::token::builder::Term>>::try_from(
+                terms.next().unwrap(),
+            )
+            .map_err(::std::convert::Into::<#krate::error::Token>::into)?
+        }
+    });
+
+    let body = match fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { #ident { #(#names: #values),* } }
+        }
+        _ => quote! { #ident ( #(#values),* ) },
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::std::convert::TryFrom<#krate::token::builder::Fact>
+            for #ident #ty_generics #where_clause
+        {
+            type Error = #krate::error::Token;
+
+            fn try_from(
+                fact: #krate::token::builder::Fact,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                if fact.0.name != #name {
+                    return Err(#krate::error::Token::ConversionError(format!(
+                        "expected fact {}, got {}",
+                        #name, fact.0.name
+                    )));
+                }
+
+                if fact.0.ids.len() != #count {
+                    return Err(#krate::error::Token::ConversionError(format!(
+                        "expected {} terms in fact {}, got {}",
+                        #count,
+                        #name,
+                        fact.0.ids.len()
+                    )));
+                }
+
+                let mut terms = fact.0.ids.into_iter();
+                Ok(#body)
+            }
+        }
+    })
+}
+
+fn try_into_fact(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let FactAttributes { name, krate } = parse_attributes(input)?;
+    let fields = fields(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let accessors: Vec<TokenStream2> = match fields {
+        Fields::Named(_) => fields
+            .iter()
+            .map(|f| {
+                let name = f.ident.as_ref().unwrap();
+                quote! { #name }
+            })
+            .collect(),
+        _ => (0..fields.len())
+            .map(|i| {
+                let index = Index {
+                    index: i as u32,
+                    span: Span::call_site(),
+                };
+                quote! { #index }
+            })
+            .collect(),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::std::convert::TryFrom<#ident #ty_generics>
+            for #krate::token::builder::Fact #where_clause
+        {
+            type Error = #krate::error::Token;
+
+            fn try_from(
+                value: #ident #ty_generics,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let ids: ::std::vec::Vec<#krate::token::builder::Term> = vec![
+                    #(
+                        ::std::convert::TryInto::<#krate::token::builder::Term>::try_into(
+                            value.#accessors,
+                        )
+                        .map_err(::std::convert::Into::<#krate::error::Token>::into)?
+                    ),*
+                ];
+
+                Ok(#krate::token::builder::Fact::new(#name.to_string(), &ids))
+            }
+        }
+    })
+}
+
+fn snake_case(ident: &str) -> String {
+    let mut res = String::new();
+
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+
+    res
+}
+
+/// parses a fact at compile time
+///
+/// ```rust,ignore
+/// let user = "alice";
+/// let f: Fact = fact!("user({user}, {id})", id = 1234);
+/// ```
+///
+/// terms written as `{name}` are replaced by the value of the `name`
+/// argument, or by the variable with that name if there is no such argument.
+/// Values are converted with `Into<Term>`, captured variables are cloned.
+#[proc_macro]
+pub fn fact(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DatalogInput);
+
+    datalog(&input, |source, gen| {
+        let (_, fact) = biscuit_parser::parser::fact(source).map_err(|e| parse_error_kind(&e))?;
+        Ok(gen.fact(&fact))
+    })
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}
+
+/// parses a rule at compile time, see [`fact!`] for parameters
+#[proc_macro]
+pub fn rule(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DatalogInput);
+
+    datalog(&input, |source, gen| {
+        let (_, rule) = biscuit_parser::parser::rule(source).map_err(|e| parse_error_kind(&e))?;
+        Ok(gen.rule(&rule))
+    })
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}
+
+/// parses a check at compile time, see [`fact!`] for parameters
+#[proc_macro]
+pub fn check(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DatalogInput);
+
+    datalog(&input, |source, gen| {
+        let (_, check) = biscuit_parser::parser::check(source).map_err(|e| parse_error_kind(&e))?;
+        Ok(gen.check(&check))
+    })
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}
+
+/// parses a list of facts, rules and checks separated by `;` at compile
+/// time, and returns a `BlockBuilder` with index 0
+///
+/// the result is meant to be merged in another builder with
+/// `BlockBuilder::merge` or `BiscuitBuilder::merge`. See [`fact!`] for
+/// parameters
+#[proc_macro]
+pub fn block(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DatalogInput);
+
+    datalog(&input, block_source)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// creates a `BiscuitBuilder` from a root key pair and the source of the
+/// authority block
+///
+/// ```rust,ignore
+/// let root = KeyPair::new();
+/// let user = "alice";
+/// let token = biscuit!(&root, r#"user(#authority, {user})"#).build()?;
+/// ```
+#[proc_macro]
+pub fn biscuit(input: TokenStream) -> TokenStream {
+    let BiscuitInput { root, input } = parse_macro_input!(input as BiscuitInput);
+
+    datalog(&input, |source, gen| {
+        let block = block_source(source, gen)?;
+        Ok(quote! {
+            let mut __biscuit_builder = ::biscuit_auth::token::Biscuit::builder(#root);
+            __biscuit_builder.merge(#block);
+            __biscuit_builder
+        })
+    })
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}
+
+fn block_source(source: &str, gen: &mut Generator) -> Result<TokenStream2, String> {
+    let (_, result) = biscuit_parser::parser::parse_block_source(source).map_err(|errors| {
+        errors
+            .iter()
+            .map(parse_error)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    let facts = result
+        .facts
+        .iter()
+        .map(|(_, f)| gen.fact(f))
+        .collect::<Vec<_>>();
+    let rules = result
+        .rules
+        .iter()
+        .map(|(_, r)| gen.rule(r))
+        .collect::<Vec<_>>();
+    let checks = result
+        .checks
+        .iter()
+        .map(|(_, c)| gen.check(c))
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        {
+            let mut __biscuit_block = ::biscuit_auth::token::builder::BlockBuilder::new(0);
+            __biscuit_block.facts.extend(vec![#(#facts),*]);
+            __biscuit_block.rules.extend(vec![#(#rules),*]);
+            __biscuit_block.checks.extend(vec![#(#checks),*]);
+            __biscuit_block
+        }
+    })
+}
+
+fn parse_error(e: &biscuit_parser::parser::Error) -> String {
+    let error = match &e.message {
+        Some(message) => format!("datalog parse error: {} (at '{}')", message, e.input),
+        None => format!("datalog parse error at '{}'", e.input),
+    };
+    match e.hint() {
+        Some(hint) => format!("{}\n  hint: {}", error, hint),
+        None => error,
+    }
+}
+
+fn parse_error_kind(e: &nom::Err<biscuit_parser::parser::Error>) -> String {
+    match e {
+        nom::Err::Error(e) | nom::Err::Failure(e) => parse_error(e),
+        nom::Err::Incomplete(_) => "datalog parse error: incomplete input".to_string(),
+    }
+}
+
+/// the source string, followed by optional `name = value` parameters
+struct DatalogInput {
+    source: LitStr,
+    parameters: Vec<(Ident, Expr)>,
+}
+
+impl Parse for DatalogInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let source = input.parse()?;
+        let mut parameters = Vec::new();
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let name: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value: Expr = input.parse()?;
+            parameters.push((name, value));
+        }
+
+        Ok(DatalogInput { source, parameters })
+    }
+}
+
+struct BiscuitInput {
+    root: Expr,
+    input: DatalogInput,
+}
+
+impl Parse for BiscuitInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let root = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let input = input.parse()?;
+
+        Ok(BiscuitInput { root, input })
+    }
+}
+
+/// parses the source with `f`, then binds the parameters that were used
+fn datalog<F>(input: &DatalogInput, f: F) -> syn::Result<TokenStream2>
+where
+    F: FnOnce(&str, &mut Generator) -> Result<TokenStream2, String>,
+{
+    let source = input.source.value();
+    let mut gen = Generator::default();
+    let body = f(&source, &mut gen).map_err(|e| syn::Error::new(input.source.span(), e))?;
+
+    let mut bindings = Vec::new();
+    for (name, value) in input.parameters.iter() {
+        if !gen.used.remove(&name.to_string()) {
+            return Err(syn::Error::new(
+                name.span(),
+                format!("parameter `{}` is not used in the datalog source", name),
+            ));
+        }
+
+        let binding = Generator::binding(&name.to_string());
+        bindings.push(quote! {
+            let #binding: ::biscuit_auth::token::builder::Term =
+                ::std::convert::Into::into(#value);
+        });
+    }
+
+    // parameters without an explicit value are captured from the scope
+    for name in gen.used.iter() {
+        let binding = Generator::binding(name);
+        let variable = Ident::new(name, input.source.span());
+        bindings.push(quote! {
+            let #binding: ::biscuit_auth::token::builder::Term =
+                ::std::convert::Into::into(::std::clone::Clone::clone(&#variable));
+        });
+    }
+
+    Ok(quote! {
+        {
+            #(#bindings)*
+            #body
+        }
+    })
+}
+
+struct BuilderPath;
+
+impl ToTokens for BuilderPath {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        tokens.extend(quote! { ::biscuit_auth::token::builder });
+    }
+}
+
+const BUILDER: BuilderPath = BuilderPath;
+
+/// generates the code building `biscuit_auth::token::builder` values
+#[derive(Default)]
+struct Generator {
+    used: BTreeSet<String>,
+}
+
+impl Generator {
+    fn binding(name: &str) -> Ident {
+        Ident::new(&format!("__biscuit_param_{}", name), Span::call_site())
+    }
+
+    fn term(&mut self, term: &parsed::Term) -> TokenStream2 {
+        match term {
+            parsed::Term::Symbol(s) => quote! { #BUILDER::Term::Symbol(#s.to_string()) },
+            parsed::Term::Variable(v) => quote! { #BUILDER::Term::Variable(#v.to_string()) },
+            parsed::Term::Integer(i) => quote! { #BUILDER::Term::Integer(#i) },
+            parsed::Term::Str(s) => quote! { #BUILDER::Term::Str(#s.to_string()) },
+            parsed::Term::Date(d) => quote! { #BUILDER::Term::Date(#d) },
+            parsed::Term::Bytes(b) => quote! { #BUILDER::Term::Bytes(vec![#(#b),*]) },
+            parsed::Term::Bool(b) => quote! { #BUILDER::Term::Bool(#b) },
+            parsed::Term::Set(s) => {
+                let terms = s.iter().map(|t| self.term(t));
+                quote! {
+                    #BUILDER::Term::Set(
+                        vec![#(#terms),*]
+                            .into_iter()
+                            .collect::<::std::collections::BTreeSet<_>>(),
+                    )
+                }
+            }
+            parsed::Term::Parameter(name) => {
+                self.used.insert(name.clone());
+                let binding = Generator::binding(name);
+                quote! { ::std::clone::Clone::clone(&#binding) }
+            }
+        }
+    }
+
+    fn predicate(&mut self, p: &parsed::Predicate) -> TokenStream2 {
+        let name = &p.name;
+        let ids = p.ids.iter().map(|t| self.term(t)).collect::<Vec<_>>();
+        quote! {
+            #BUILDER::Predicate {
+                name: #name.to_string(),
+                ids: vec![#(#ids),*],
+            }
+        }
+    }
+
+    fn fact(&mut self, f: &parsed::Fact) -> TokenStream2 {
+        let p = self.predicate(&f.0);
+        quote! { #BUILDER::Fact(#p) }
+    }
+
+    fn expression(&mut self, e: &parsed::Expression) -> TokenStream2 {
+        let ops = e
+            .ops
+            .iter()
+            .map(|op| match op {
+                parsed::Op::Value(t) => {
+                    let t = self.term(t);
+                    quote! { #BUILDER::Op::Value(#t) }
+                }
+                // the operators have the same names in both crates
+                parsed::Op::Unary(u) => {
+                    let u = Ident::new(&format!("{:?}", u), Span::call_site());
+                    quote! { #BUILDER::Op::Unary(#BUILDER::Unary::#u) }
+                }
+                parsed::Op::Binary(b) => {
+                    let b = Ident::new(&format!("{:?}", b), Span::call_site());
+                    quote! { #BUILDER::Op::Binary(#BUILDER::Binary::#b) }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        quote! { #BUILDER::Expression { ops: vec![#(#ops),*] } }
+    }
+
+    fn rule(&mut self, r: &parsed::Rule) -> TokenStream2 {
+        let head = self.predicate(&r.0);
+        let body = r.1.iter().map(|p| self.predicate(p)).collect::<Vec<_>>();
+        let expressions = r.2.iter().map(|e| self.expression(e)).collect::<Vec<_>>();
+        quote! {
+            #BUILDER::Rule(#head, vec![#(#body),*], vec![#(#expressions),*])
+        }
+    }
+
+    fn check(&mut self, c: &parsed::Check) -> TokenStream2 {
+        let queries = c.queries.iter().map(|r| self.rule(r)).collect::<Vec<_>>();
+        quote! { #BUILDER::Check { queries: vec![#(#queries),*] } }
+    }
+}
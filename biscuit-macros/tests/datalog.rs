@@ -0,0 +1,93 @@
+use biscuit_auth::{biscuit, block, check, crypto::KeyPair, fact, rule, token::builder::*};
+use std::{collections::BTreeSet, convert::TryInto};
+
+#[test]
+fn literals() {
+    let f: Fact = fact!(r#"right(#authority, "file1", 12, true, hex:0a0b)"#);
+    assert_eq!(
+        f.to_string(),
+        r#"right(#authority, "file1", 12, true, hex:0a0b)"#
+    );
+
+    let r: Rule = "valid_date(\"file1\") <- time(#ambient, $0), $0 <= 2019-12-04T09:46:41+00:00"
+        .try_into()
+        .unwrap();
+    assert_eq!(
+        rule!("valid_date(\"file1\") <- time(#ambient, $0), $0 <= 2019-12-04T09:46:41+00:00"),
+        r
+    );
+
+    let c: Check =
+        "check if resource(#ambient, $0), [\"a\", \"b\"].contains($0) or admin(#authority)"
+            .try_into()
+            .unwrap();
+    assert_eq!(
+        check!("check if resource(#ambient, $0), [\"a\", \"b\"].contains($0) or admin(#authority)"),
+        c
+    );
+}
+
+#[test]
+fn parameters() {
+    let user = "alice".to_string();
+    let f = fact!("owner({user}, {file})", file = "file1");
+    assert_eq!(f.to_string(), r#"owner("alice", "file1")"#);
+    // captured variables are cloned
+    assert_eq!(user, "alice");
+
+    // values are inserted as terms, they cannot change the rule's structure
+    let file = "file1\"), admin(#authority";
+    let r = rule!("allowed($op) <- operation(#ambient, $op), resource(#ambient, {file})");
+    assert_eq!(r.1[1].ids[1], Term::Str(file.to_string()));
+
+    let mut ops = BTreeSet::new();
+    ops.insert(Term::from("read"));
+    ops.insert(Term::from("write"));
+    let c = check!("check if operation(#ambient, $op), {ops}.contains($op)");
+    assert_eq!(
+        c.to_string(),
+        r#"check if operation(#ambient, $op), ["read", "write"].contains($op)"#
+    );
+
+    let f = fact!("limit({max})", max = 10 * 2);
+    assert_eq!(f, fact("limit", &[int(20)]));
+}
+
+#[test]
+fn blocks() {
+    let root = KeyPair::new();
+    let user = "alice";
+
+    let mut builder = biscuit!(
+        &root,
+        r#"
+          user(#authority, {user});
+          right(#authority, {user}, $file) <- owner({user}, $file);
+          check if operation(#ambient, #read);
+        "#
+    );
+    builder.merge(block!("right(#authority, {user}, \"file2\")"));
+    let token = builder.build().unwrap();
+
+    let b = block!(
+        "check if resource(#ambient, {resource})",
+        resource = "file1"
+    );
+    assert_eq!(b.index, 0);
+    assert_eq!(b.checks.len(), 1);
+
+    let mut verifier = token.verify(root.public()).unwrap();
+    verifier
+        .add_fact(fact!("owner(\"alice\", \"file1\")"))
+        .unwrap();
+    verifier
+        .add_fact(fact!("operation(#ambient, #read)"))
+        .unwrap();
+    verifier.allow().unwrap();
+    verifier.verify().unwrap();
+
+    let res: Vec<Fact> = verifier
+        .query(rule!("data($file) <- right(#authority, {user}, $file)"))
+        .unwrap();
+    assert_eq!(res.len(), 2);
+}
@@ -0,0 +1,92 @@
+use biscuit_auth::{
+    crypto::KeyPair,
+    error,
+    token::{builder::*, Biscuit},
+    TryFromFact, TryIntoFact,
+};
+use std::convert::{TryFrom, TryInto};
+
+#[derive(Debug, Clone, PartialEq, TryFromFact, TryIntoFact)]
+#[fact(name = "right")]
+struct Right {
+    user: String,
+    resource: String,
+    operation: String,
+}
+
+#[derive(Debug, Clone, PartialEq, TryFromFact, TryIntoFact)]
+struct UserAge(String, i64);
+
+#[test]
+fn struct_to_fact() {
+    let right = Right {
+        user: "alice".to_string(),
+        resource: "file1".to_string(),
+        operation: "read".to_string(),
+    };
+
+    let f: Fact = right.try_into().unwrap();
+    assert_eq!(f.to_string(), r#"right("alice", "file1", "read")"#);
+
+    let f: Fact = UserAge("bob".to_string(), 42).try_into().unwrap();
+    assert_eq!(f.to_string(), r#"user_age("bob", 42)"#);
+}
+
+#[test]
+fn fact_to_struct() {
+    let f: Fact = r#"right("alice", "file1", "read")"#.try_into().unwrap();
+    let right = Right::try_from(f).unwrap();
+    assert_eq!(
+        right,
+        Right {
+            user: "alice".to_string(),
+            resource: "file1".to_string(),
+            operation: "read".to_string(),
+        }
+    );
+
+    let f: Fact = r#"owner("alice", "file1", "read")"#.try_into().unwrap();
+    assert!(matches!(
+        Right::try_from(f),
+        Err(error::Token::ConversionError(_))
+    ));
+
+    let f: Fact = r#"user_age("bob", "old")"#.try_into().unwrap();
+    assert!(matches!(
+        UserAge::try_from(f),
+        Err(error::Token::ConversionError(_))
+    ));
+
+    let f: Fact = r#"user_age("bob")"#.try_into().unwrap();
+    assert!(matches!(
+        UserAge::try_from(f),
+        Err(error::Token::ConversionError(_))
+    ));
+}
+
+#[test]
+fn verifier_roundtrip() {
+    let root = KeyPair::new();
+    let mut builder = Biscuit::builder(&root);
+    builder
+        .add_authority_fact("user(#authority, \"alice\")")
+        .unwrap();
+    let token = builder.build().unwrap();
+
+    let mut verifier = token.verify(root.public()).unwrap();
+    verifier
+        .add_fact(Right {
+            user: "alice".to_string(),
+            resource: "file1".to_string(),
+            operation: "read".to_string(),
+        })
+        .unwrap();
+
+    let res: Vec<Right> = verifier
+        .query("right($u, $r, $o) <- right($u, $r, $o), user(#authority, $u)")
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].user, "alice");
+    assert_eq!(res[0].resource, "file1");
+    assert_eq!(res[0].operation, "read");
+}
@@ -0,0 +1,289 @@
+//! data structures produced by the parser
+//!
+//! they mirror the types of `biscuit_auth::token::builder`, without the
+//! symbol table conversions, and can additionally represent parameters
+//! (`{name}`) that will be replaced by a value before use
+use std::{
+    collections::{BTreeSet, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Term {
+    Symbol(String),
+    Variable(String),
+    Integer(i64),
+    Str(String),
+    Date(u64),
+    Bytes(Vec<u8>),
+    Bool(bool),
+    Set(BTreeSet<Term>),
+    /// a named hole, written `{name}`, that must be replaced by a term
+    Parameter(String),
+}
+
+impl Term {
+    /// calls `f` on this term and, for sets, on each of their elements
+    pub fn visit_mut<F: FnMut(&mut Term)>(&mut self, f: &mut F) {
+        if let Term::Set(set) = self {
+            *set = std::mem::take(set)
+                .into_iter()
+                .map(|mut t| {
+                    t.visit_mut(f);
+                    t
+                })
+                .collect();
+        }
+        f(self);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+pub struct Predicate {
+    pub name: String,
+    pub ids: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+pub struct Fact(pub Predicate);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unary {
+    Negate,
+    Parens,
+    Length,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binary {
+    LessThan,
+    GreaterThan,
+    LessOrEqual,
+    GreaterOrEqual,
+    Equal,
+    Contains,
+    Prefix,
+    Suffix,
+    Regex,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Intersection,
+    Union,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Value(Term),
+    Unary(Unary),
+    Binary(Binary),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub ops: Vec<Op>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule(pub Predicate, pub Vec<Predicate>, pub Vec<Expression>);
+
+impl Rule {
+    /// calls `f` on every term of the rule, in the head, the body and the
+    /// expressions
+    pub fn visit_terms_mut<F: FnMut(&mut Term)>(&mut self, f: &mut F) {
+        let predicates = std::iter::once(&mut self.0).chain(self.1.iter_mut());
+        for term in predicates.flat_map(|p| p.ids.iter_mut()) {
+            term.visit_mut(f);
+        }
+
+        for op in self.2.iter_mut().flat_map(|e| e.ops.iter_mut()) {
+            if let Op::Value(term) = op {
+                term.visit_mut(f);
+            }
+        }
+    }
+
+    pub fn validate_variables(&self) -> Result<(), String> {
+        let mut head_variables: HashSet<String> = self
+            .0
+            .ids
+            .iter()
+            .filter_map(|term| match term {
+                Term::Variable(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        for predicate in self.1.iter() {
+            for term in predicate.ids.iter() {
+                if let Term::Variable(v) = term {
+                    head_variables.remove(v);
+                    if head_variables.is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if head_variables.is_empty() {
+            return Ok(());
+        }
+
+        Err(format!(
+            "rule head contains variables that are not used in predicates of the rule's body: {}",
+            head_variables
+                .iter()
+                .map(|s| format!("${}", s))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub queries: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyKind {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub queries: Vec<Rule>,
+    pub kind: PolicyKind,
+}
+
+/// creates a new fact
+pub fn fact<I: AsRef<Term>>(name: &str, ids: &[I]) -> Fact {
+    Fact(pred(name, ids))
+}
+
+/// creates a predicate
+pub fn pred<I: AsRef<Term>>(name: &str, ids: &[I]) -> Predicate {
+    Predicate {
+        name: name.to_string(),
+        ids: ids.iter().map(|id| id.as_ref().clone()).collect(),
+    }
+}
+
+/// creates a rule
+pub fn rule<I: AsRef<Term>, P: AsRef<Predicate>>(
+    head_name: &str,
+    head_ids: &[I],
+    predicates: &[P],
+) -> Rule {
+    Rule(
+        pred(head_name, head_ids),
+        predicates.iter().map(|p| p.as_ref().clone()).collect(),
+        Vec::new(),
+    )
+}
+
+/// creates a rule with constraints
+pub fn constrained_rule<I: AsRef<Term>, P: AsRef<Predicate>, E: AsRef<Expression>>(
+    head_name: &str,
+    head_ids: &[I],
+    predicates: &[P],
+    expressions: &[E],
+) -> Rule {
+    Rule(
+        pred(head_name, head_ids),
+        predicates.iter().map(|p| p.as_ref().clone()).collect(),
+        expressions.iter().map(|c| c.as_ref().clone()).collect(),
+    )
+}
+
+/// creates a check
+pub fn check<P: AsRef<Predicate>>(predicates: &[P]) -> Check {
+    let empty_terms: &[Term] = &[];
+    Check {
+        queries: vec![Rule(
+            pred("query", empty_terms),
+            predicates.iter().map(|p| p.as_ref().clone()).collect(),
+            Vec::new(),
+        )],
+    }
+}
+
+/// creates an integer value
+pub fn int(i: i64) -> Term {
+    Term::Integer(i)
+}
+
+/// creates a string
+pub fn string(s: &str) -> Term {
+    Term::Str(s.to_string())
+}
+
+/// creates a symbol
+pub fn s(s: &str) -> Term {
+    Term::Symbol(s.to_string())
+}
+
+/// creates a symbol
+pub fn symbol(s: &str) -> Term {
+    Term::Symbol(s.to_string())
+}
+
+/// creates a date
+///
+/// internally the date will be stored as seconds since UNIX_EPOCH
+pub fn date(t: &SystemTime) -> Term {
+    let dur = t.duration_since(UNIX_EPOCH).unwrap();
+    Term::Date(dur.as_secs())
+}
+
+/// creates a variable for a rule
+pub fn var(s: &str) -> Term {
+    Term::Variable(s.to_string())
+}
+
+/// creates a variable for a rule
+pub fn variable(s: &str) -> Term {
+    Term::Variable(s.to_string())
+}
+
+/// creates a byte array
+pub fn bytes(s: &[u8]) -> Term {
+    Term::Bytes(s.to_vec())
+}
+
+/// creates a boolean
+pub fn boolean(b: bool) -> Term {
+    Term::Bool(b)
+}
+
+/// creates a set
+pub fn set(s: BTreeSet<Term>) -> Term {
+    Term::Set(s)
+}
+
+/// creates a parameter
+pub fn parameter(p: &str) -> Term {
+    Term::Parameter(p.to_string())
+}
+
+impl AsRef<Term> for Term {
+    fn as_ref(&self) -> &Term {
+        self
+    }
+}
+
+impl AsRef<Predicate> for Predicate {
+    fn as_ref(&self) -> &Predicate {
+        self
+    }
+}
+
+impl AsRef<Expression> for Expression {
+    fn as_ref(&self) -> &Expression {
+        self
+    }
+}
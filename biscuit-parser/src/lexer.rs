@@ -0,0 +1,310 @@
+//! Datalog lexer, splitting source in spanned tokens
+//!
+//! the literals are recognized with the functions of the [`parser`](crate::parser),
+//! so a highlighter built on this module agrees with the parser on what is
+//! a string, a date or a symbol. The tokens cover the whole source,
+//! including whitespace and comments, and the lexer never fails: characters
+//! that do not start a token are returned as [`TokenKind::Unknown`].
+//!
+//! ```rust
+//! use biscuit_parser::lexer::{tokenize, TokenKind};
+//!
+//! let tokens = tokenize("check if time(#ambient, $t), $t < 2030-01-01T00:00:00Z")
+//!     .filter(|token| token.kind != TokenKind::Whitespace)
+//!     .map(|token| (token.kind, token.text))
+//!     .collect::<Vec<_>>();
+//!
+//! assert_eq!(tokens[0], (TokenKind::Keyword, "check"));
+//! assert_eq!(tokens[2], (TokenKind::Name, "time"));
+//! assert_eq!(tokens[4], (TokenKind::Symbol, "#ambient"));
+//! assert_eq!(tokens[10], (TokenKind::Operator, "<"));
+//! assert_eq!(tokens[11], (TokenKind::Date, "2030-01-01T00:00:00Z"));
+//! ```
+use crate::parser::{
+    name, parameter, parse_bytes, parse_date, parse_integer, parse_string, parse_symbol, variable,
+};
+use std::ops::Range;
+
+/// words with a special meaning, when they are not used as predicate names
+///
+/// they are matched without case, like the parser does
+pub const KEYWORDS: &[&str] = &[
+    "check", "caveat", "if", "or", "allow", "deny", "context", "include",
+];
+
+/// operators, the longest first so `<=` is not read as `<`
+pub const OPERATORS: &[&str] = &[
+    "<-", "<=", ">=", "==", "&&", "||", "<", ">", "+", "-", "*", "/", "!",
+];
+
+const PUNCTUATION: &str = "()[],;.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TokenKind {
+    /// one of the [`KEYWORDS`]
+    Keyword,
+    /// name of a predicate, or of a method like `starts_with`
+    Name,
+    /// `$name`
+    Variable,
+    /// `{name}`
+    Parameter,
+    /// `#name`
+    Symbol,
+    String,
+    Integer,
+    Date,
+    /// `hex:` followed by hexadecimal digits
+    Bytes,
+    Bool,
+    /// one of the [`OPERATORS`]
+    Operator,
+    /// parentheses, brackets, `,`, `;` and `.`
+    Punctuation,
+    /// line or multiline comment
+    Comment,
+    Whitespace,
+    /// character that does not start a token, or an unterminated string or
+    /// comment
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    /// the token, as written in the source
+    pub text: &'a str,
+    /// position of the token in the source, in bytes
+    pub span: Range<usize>,
+}
+
+/// iterator over the tokens of a Datalog source, see [`tokenize`]
+#[derive(Clone, Debug)]
+pub struct Lexer<'a> {
+    source: &'a str,
+    offset: usize,
+    /// the previous token can end an expression, so a `-` is an operator
+    after_value: bool,
+    /// the previous token is a `.`, so the next word is a method name
+    after_dot: bool,
+}
+
+/// splits the source in tokens
+pub fn tokenize(source: &str) -> Lexer<'_> {
+    Lexer {
+        source,
+        offset: 0,
+        after_value: false,
+        after_dot: false,
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let input = &self.source[self.offset..];
+        let c = input.chars().next()?;
+        let (kind, len) = self.token_at(input, c);
+
+        let start = self.offset;
+        self.offset += len;
+        let text = &self.source[start..self.offset];
+
+        if kind != TokenKind::Whitespace && kind != TokenKind::Comment {
+            self.after_value = match kind {
+                TokenKind::Variable
+                | TokenKind::Parameter
+                | TokenKind::Symbol
+                | TokenKind::String
+                | TokenKind::Integer
+                | TokenKind::Date
+                | TokenKind::Bytes
+                | TokenKind::Bool => true,
+                TokenKind::Punctuation => text == ")" || text == "]",
+                _ => false,
+            };
+            self.after_dot = text == ".";
+        }
+
+        Some(Token {
+            kind,
+            text,
+            span: start..self.offset,
+        })
+    }
+}
+
+impl<'a> Lexer<'a> {
+    /// kind and length of the token at the start of `input`, that starts
+    /// with `c`
+    fn token_at(&self, input: &'a str, c: char) -> (TokenKind, usize) {
+        if c.is_whitespace() {
+            return (
+                TokenKind::Whitespace,
+                input.len() - input.trim_start().len(),
+            );
+        }
+        if input.starts_with("//") {
+            return (TokenKind::Comment, input.find('\n').unwrap_or(input.len()));
+        }
+        if input.starts_with("/*") {
+            return match input.find("*/") {
+                Some(end) => (TokenKind::Comment, end + 2),
+                None => (TokenKind::Unknown, input.len()),
+            };
+        }
+
+        let literal = match c {
+            '"' => parse_string(input)
+                .map(|(rest, _)| (TokenKind::String, rest))
+                .ok(),
+            '#' => parse_symbol(input)
+                .map(|(rest, _)| (TokenKind::Symbol, rest))
+                .ok(),
+            '$' => variable(input)
+                .map(|(rest, _)| (TokenKind::Variable, rest))
+                .ok(),
+            '{' => parameter(input)
+                .map(|(rest, _)| (TokenKind::Parameter, rest))
+                .ok(),
+            'h' => parse_bytes(input)
+                .map(|(rest, _)| (TokenKind::Bytes, rest))
+                .ok(),
+            '-' if self.after_value => None,
+            '0'..='9' | '-' => parse_date(input)
+                .map(|(rest, _)| (TokenKind::Date, rest))
+                .or_else(|_| parse_integer(input).map(|(rest, _)| (TokenKind::Integer, rest)))
+                .ok(),
+            _ => None,
+        };
+        if let Some((kind, rest)) = literal {
+            return (kind, input.len() - rest.len());
+        }
+        if c == '"' {
+            return (TokenKind::Unknown, input.find('\n').unwrap_or(input.len()));
+        }
+
+        if let Some(operator) = OPERATORS.iter().find(|op| input.starts_with(*op)) {
+            return (TokenKind::Operator, operator.len());
+        }
+        if PUNCTUATION.contains(c) {
+            return (TokenKind::Punctuation, 1);
+        }
+
+        if let Ok((rest, word)) = name(input) {
+            let predicate = rest.trim_start().starts_with('(');
+            let kind = if predicate || self.after_dot {
+                TokenKind::Name
+            } else if word == "true" || word == "false" {
+                TokenKind::Bool
+            } else if KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word)) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Name
+            };
+            return (kind, word.len());
+        }
+
+        (TokenKind::Unknown, c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(source)
+            .filter(|token| token.kind != TokenKind::Whitespace)
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_and_spans() {
+        use TokenKind::*;
+
+        let source =
+            "// rights\nright($0, #read) <- resource(#ambient, $0), $0.starts_with(\"/a;\");";
+        assert_eq!(
+            tokens(source),
+            vec![
+                (Comment, "// rights"),
+                (Name, "right"),
+                (Punctuation, "("),
+                (Variable, "$0"),
+                (Punctuation, ","),
+                (Symbol, "#read"),
+                (Punctuation, ")"),
+                (Operator, "<-"),
+                (Name, "resource"),
+                (Punctuation, "("),
+                (Symbol, "#ambient"),
+                (Punctuation, ","),
+                (Variable, "$0"),
+                (Punctuation, ")"),
+                (Punctuation, ","),
+                (Variable, "$0"),
+                (Punctuation, "."),
+                (Name, "starts_with"),
+                (Punctuation, "("),
+                (String, "\"/a;\""),
+                (Punctuation, ")"),
+                (Punctuation, ";"),
+            ]
+        );
+
+        let mut end = 0;
+        for token in tokenize(source) {
+            assert_eq!(token.span.start, end);
+            assert_eq!(&source[token.span.clone()], token.text);
+            end = token.span.end;
+        }
+        assert_eq!(end, source.len());
+
+        assert_eq!(
+            tokens(
+                "allow if 1-2 > -3 || !false && [hex:0aff, {p}].contains(billing:user($u)) /* x"
+            ),
+            vec![
+                (Keyword, "allow"),
+                (Keyword, "if"),
+                (Integer, "1"),
+                (Operator, "-"),
+                (Integer, "2"),
+                (Operator, ">"),
+                (Integer, "-3"),
+                (Operator, "||"),
+                (Operator, "!"),
+                (Bool, "false"),
+                (Operator, "&&"),
+                (Punctuation, "["),
+                (Bytes, "hex:0aff"),
+                (Punctuation, ","),
+                (Parameter, "{p}"),
+                (Punctuation, "]"),
+                (Punctuation, "."),
+                (Name, "contains"),
+                (Punctuation, "("),
+                (Name, "billing:user"),
+                (Punctuation, "("),
+                (Variable, "$u"),
+                (Punctuation, ")"),
+                (Punctuation, ")"),
+                (Unknown, "/* x"),
+            ]
+        );
+
+        assert_eq!(
+            tokens("user(\"alice) ~ @\n"),
+            vec![
+                (Name, "user"),
+                (Punctuation, "("),
+                (Unknown, "\"alice) ~ @"),
+            ]
+        );
+        assert_eq!(tokens("~"), vec![(Unknown, "~")]);
+    }
+}
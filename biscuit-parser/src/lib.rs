@@ -0,0 +1,10 @@
+//! Datalog parser for Biscuit tokens
+//!
+//! this crate is used by [biscuit-auth](https://docs.rs/biscuit-auth) to parse
+//! Datalog at runtime, and by `biscuit-macros` to parse it at compile time.
+//! The parser produces the data-only structures of the [`builder`] module,
+//! that `biscuit-auth` then converts to its own builder types. The [`lexer`]
+//! splits source in tokens, for editors and highlighters.
+pub mod builder;
+pub mod lexer;
+pub mod parser;
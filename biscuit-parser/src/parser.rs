@@ -0,0 +1,2167 @@
+//! Datalog text format parsing
+//!
+//! the parsers produce the data structures from [`builder`](crate::builder),
+//! that can then be converted to the types used by `biscuit-auth`. They are
+//! reexported as `biscuit_auth::parser`.
+//!
+//! terms can be written as parameters (`{name}`) so the same source can be
+//! parsed once and filled with values afterwards, as the macros of the
+//! `biscuit-macros` crate do
+use crate::builder;
+use nom::{
+    branch::alt,
+    bytes::complete::{escaped_transform, tag, tag_no_case, take_until, take_while, take_while1},
+    character::{
+        complete::{char, digit1, multispace0 as space0},
+        is_alphanumeric,
+    },
+    combinator::{consumed, cut, eof, map, map_res, opt, recognize, value},
+    error::{ErrorKind, FromExternalError, ParseError},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult, Offset,
+};
+use std::{cell::Cell, collections::BTreeSet, convert::TryInto};
+use thiserror::Error;
+
+/// parse a Datalog fact
+pub fn fact(i: &str) -> IResult<&str, builder::Fact, Error> {
+    let (i, fact) = fact_inner(i)?;
+
+    let (i, _) = error(
+        preceded(space0, eof),
+        |input| format!("unexpected trailing data after fact: '{}'", input),
+        " ,\n",
+    )(i)?;
+
+    Ok((i, fact))
+}
+
+pub fn fact_inner(i: &str) -> IResult<&str, builder::Fact, Error> {
+    let (i, _) = space0(i)?;
+    let (i, fact_name) = name(i)?;
+
+    let (i, _) = space0(i)?;
+    let (i, ids) = delimited(
+        char('('),
+        cut(separated_list1(
+            preceded(space0, char(',')),
+            cut(term_in_fact),
+        )),
+        preceded(space0, char(')')),
+    )(i)?;
+
+    Ok((
+        i,
+        builder::Fact(builder::Predicate {
+            name: fact_name.to_string(),
+            ids,
+        }),
+    ))
+}
+
+/// parse a Datalog check
+pub fn check(i: &str) -> IResult<&str, builder::Check, Error> {
+    let (i, check) = check_inner(i)?;
+
+    let (i, _) = error(
+        preceded(space0, eof),
+        |input| {
+            match input.chars().next() {
+            Some(')') => "unexpected parens".to_string(),
+            //Some('$') => "variables are not allowed in facts".to_string(),
+            _ => format!("expected either the next term after ',' or the next check variant after 'or', but got '{}'",
+                     input)
+        }
+        },
+        " ,\n",
+    )(i)?;
+
+    Ok((i, check))
+}
+
+fn check_inner(i: &str) -> IResult<&str, builder::Check, Error> {
+    let (i, _) = space0(i)?;
+
+    // `caveat if` is the deprecated name of checks
+    let (i, _) = alt((tag_no_case("check if"), tag_no_case("caveat if")))(i)?;
+
+    let (i, queries) = cut(check_body)(i)?;
+    Ok((i, builder::Check { queries }))
+}
+
+/// parse an allow or deny rule
+pub fn policy(i: &str) -> IResult<&str, builder::Policy, Error> {
+    let (i, policy) = policy_inner(i)?;
+
+    let (i, _) = error(
+        preceded(space0, eof),
+        |input| {
+            match input.chars().next() {
+            Some(')') => "unexpected parens".to_string(),
+            //Some('$') => "variables are not allowed in facts".to_string(),
+            _ => format!("expected either the next term after ',' or the next policy variant after 'or', but got '{}'",
+                     input)
+        }
+        },
+        " ,\n",
+    )(i)?;
+
+    Ok((i, policy))
+}
+
+fn policy_inner(i: &str) -> IResult<&str, builder::Policy, Error> {
+    alt((allow, deny))(i)
+}
+
+/// parse an allow rule
+pub fn allow(i: &str) -> IResult<&str, builder::Policy, Error> {
+    let (i, _) = space0(i)?;
+
+    let (i, _) = tag_no_case("allow if")(i)?;
+
+    let (i, queries) = cut(check_body)(i)?;
+    Ok((
+        i,
+        builder::Policy {
+            queries,
+            kind: builder::PolicyKind::Allow,
+        },
+    ))
+}
+
+/// parse a deny rule
+pub fn deny(i: &str) -> IResult<&str, builder::Policy, Error> {
+    let (i, _) = space0(i)?;
+
+    let (i, _) = tag_no_case("deny if")(i)?;
+
+    let (i, queries) = cut(check_body)(i)?;
+    Ok((
+        i,
+        builder::Policy {
+            queries,
+            kind: builder::PolicyKind::Deny,
+        },
+    ))
+}
+
+/// parse a Datalog check body
+pub fn check_body(i: &str) -> IResult<&str, Vec<builder::Rule>, Error> {
+    let (i, mut queries) = separated_list1(
+        preceded(space0, tag_no_case("or")),
+        preceded(space0, cut(rule_body)),
+    )(i)?;
+
+    let queries = queries
+        .drain(..)
+        .map(|rule_body| {
+            builder::Rule(
+                builder::Predicate {
+                    name: "query".to_string(),
+                    ids: Vec::new(),
+                },
+                rule_body.0,
+                rule_body.1,
+            )
+        })
+        .collect();
+    Ok((i, queries))
+}
+
+/// parse a Datalog rule
+pub fn rule(i: &str) -> IResult<&str, builder::Rule, Error> {
+    let (i, rule) = rule_inner(i)?;
+
+    let (i, _) = error(
+        preceded(space0, eof),
+        |input| match input.chars().next() {
+            Some(')') => "unexpected parens".to_string(),
+            //Some('$') => "variables are not allowed in facts".to_string(),
+            _ => format!(
+                "expected the next term or expression after ',', but got '{}'",
+                input
+            ),
+        },
+        " ,\n",
+    )(i)?;
+
+    Ok((i, rule))
+}
+
+pub fn rule_inner(i: &str) -> IResult<&str, builder::Rule, Error> {
+    let (i, (head_input, head)) = consumed(rule_head)(i)?;
+    let (i, _) = space0(i)?;
+
+    let (i, _) = tag("<-")(i)?;
+
+    let (i, (predicates, expressions)) = cut(rule_body)(i)?;
+
+    let rule = builder::Rule(head, predicates, expressions);
+
+    if let Err(message) = rule.validate_variables() {
+        return Err(nom::Err::Error(Error {
+            input: head_input,
+            code: ErrorKind::Satisfy,
+            message: Some(message),
+            expected: Vec::new(),
+        }));
+    }
+
+    Ok((i, rule))
+}
+
+/// parse a Datalog predicate
+pub fn predicate(i: &str) -> IResult<&str, builder::Predicate, Error> {
+    let (i, _) = space0(i)?;
+    let (i, fact_name) = name(i)?;
+
+    let (i, _) = space0(i)?;
+    let (i, ids) = delimited(
+        char('('),
+        cut(separated_list1(preceded(space0, char(',')), cut(term))),
+        preceded(space0, char(')')),
+    )(i)?;
+
+    Ok((
+        i,
+        builder::Predicate {
+            name: fact_name.to_string(),
+            ids,
+        },
+    ))
+}
+
+fn rule_head(i: &str) -> IResult<&str, builder::Predicate, Error> {
+    let (i, _) = space0(i)?;
+    // older versions of the language prefixed rule heads with `*`
+    let (i, _) = opt(terminated(char('*'), space0))(i)?;
+    let (i, fact_name) = name(i)?;
+
+    let (i, _) = space0(i)?;
+    let (i, ids) = delimited(
+        char('('),
+        cut(separated_list0(preceded(space0, char(',')), cut(term))),
+        preceded(space0, char(')')),
+    )(i)?;
+
+    Ok((
+        i,
+        builder::Predicate {
+            name: fact_name.to_string(),
+            ids,
+        },
+    ))
+}
+
+/// parse a Datalog rule body
+pub fn rule_body(
+    i: &str,
+) -> IResult<&str, (Vec<builder::Predicate>, Vec<builder::Expression>), Error> {
+    let (i, mut elements) = separated_list1(
+        preceded(space0, char(',')),
+        preceded(space0, cut(predicate_or_expression)),
+    )(i)?;
+
+    let mut predicates = Vec::new();
+    let mut expressions = Vec::new();
+
+    for el in elements.drain(..) {
+        match el {
+            PredOrExpr::P(predicate) => predicates.push(predicate),
+            PredOrExpr::E(expression) => {
+                let ops = expression.opcodes();
+                let e = builder::Expression { ops };
+                expressions.push(e);
+            }
+        }
+    }
+
+    Ok((i, (predicates, expressions)))
+}
+
+enum PredOrExpr {
+    P(builder::Predicate),
+    E(Expr),
+}
+
+fn predicate_or_expression(i: &str) -> IResult<&str, PredOrExpr, Error> {
+    reduce(
+        alt((map(predicate, PredOrExpr::P), map(expr, PredOrExpr::E))),
+        ",;",
+    )(i)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Value(builder::Term),
+    Unary(builder::Op, Box<Expr>),
+    Binary(builder::Op, Box<Expr>, Box<Expr>),
+}
+
+/// maximum nesting depth of an expression
+///
+/// deeper expressions are rejected by the parser, and by the Datalog engine
+/// for expressions coming from serialized blocks, so that processing them
+/// cannot overflow the stack
+pub const MAX_EXPRESSION_DEPTH: usize = 128;
+
+impl Expr {
+    pub fn opcodes(self) -> Vec<builder::Op> {
+        // post-order traversal with an explicit stack: an operation is
+        // written once its operands are
+        enum Item {
+            Expr(Expr),
+            Op(builder::Op),
+        }
+
+        let mut v = Vec::new();
+        let mut stack = vec![Item::Expr(self)];
+        while let Some(item) = stack.pop() {
+            match item {
+                Item::Op(op) => v.push(op),
+                Item::Expr(Expr::Value(t)) => v.push(builder::Op::Value(t)),
+                Item::Expr(Expr::Unary(op, expr)) => {
+                    stack.push(Item::Op(op));
+                    stack.push(Item::Expr(*expr));
+                }
+                Item::Expr(Expr::Binary(op, left, right)) => {
+                    stack.push(Item::Op(op));
+                    stack.push(Item::Expr(*right));
+                    stack.push(Item::Expr(*left));
+                }
+            }
+        }
+        v
+    }
+
+    /// number of levels of the expression tree, a single value has a depth of 1
+    pub fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((expr, depth)) = stack.pop() {
+            max = max.max(depth);
+            match expr {
+                Expr::Value(_) => {}
+                Expr::Unary(_, e) => stack.push((e, depth + 1)),
+                Expr::Binary(_, left, right) => {
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+            }
+        }
+        max
+    }
+}
+
+fn unary(i: &str) -> IResult<&str, Expr, Error> {
+    alt((unary_parens, unary_negate, unary_length))(i)
+}
+
+fn unary_negate(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("!")(i)?;
+    let (i, _) = space0(i)?;
+    let (i, value) = expr(i)?;
+
+    Ok((
+        i,
+        Expr::Unary(builder::Op::Unary(builder::Unary::Negate), Box::new(value)),
+    ))
+}
+
+fn unary_parens(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("(")(i)?;
+    let (i, _) = space0(i)?;
+    let (i, value) = expr(i)?;
+    let (i, _) = space0(i)?;
+    let (i, _) = tag(")")(i)?;
+
+    Ok((
+        i,
+        Expr::Unary(builder::Op::Unary(builder::Unary::Parens), Box::new(value)),
+    ))
+}
+
+fn unary_length(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, _) = space0(i)?;
+    let (i, value) = alt((map(term, Expr::Value), unary_parens))(i)?;
+    let (i, _) = space0(i)?;
+    let (i, _) = tag(".length()")(i)?;
+
+    Ok((
+        i,
+        Expr::Unary(builder::Op::Unary(builder::Unary::Length), Box::new(value)),
+    ))
+}
+
+fn binary_op_0(i: &str) -> IResult<&str, builder::Binary, Error> {
+    use builder::Binary;
+    alt((value(Binary::And, tag("&&")), value(Binary::Or, tag("||"))))(i)
+}
+
+fn binary_op_1(i: &str) -> IResult<&str, builder::Binary, Error> {
+    use builder::Binary;
+    alt((
+        value(Binary::LessOrEqual, tag("<=")),
+        value(Binary::GreaterOrEqual, tag(">=")),
+        value(Binary::LessThan, tag("<")),
+        value(Binary::GreaterThan, tag(">")),
+        value(Binary::Equal, tag("==")),
+    ))(i)
+}
+
+fn binary_op_2(i: &str) -> IResult<&str, builder::Binary, Error> {
+    use builder::Binary;
+    alt((value(Binary::Add, tag("+")), value(Binary::Sub, tag("-"))))(i)
+}
+
+fn binary_op_3(i: &str) -> IResult<&str, builder::Binary, Error> {
+    use builder::Binary;
+    alt((value(Binary::Mul, tag("*")), value(Binary::Div, tag("/"))))(i)
+}
+
+fn binary_op_4(i: &str) -> IResult<&str, builder::Binary, Error> {
+    use builder::Binary;
+
+    alt((
+        value(Binary::Contains, tag("contains")),
+        value(Binary::Prefix, tag("starts_with")),
+        value(Binary::Suffix, tag("ends_with")),
+        value(Binary::Regex, tag("matches")),
+    ))(i)
+}
+
+fn expr_term(i: &str) -> IResult<&str, Expr, Error> {
+    alt((unary, reduce(map(term, Expr::Value), " ,\n);")))(i)
+}
+
+fn fold_exprs<'a>(
+    i: &'a str,
+    initial: Expr,
+    remainder: Vec<(builder::Binary, Expr)>,
+) -> IResult<&'a str, Expr, Error<'a>> {
+    // each operation of the chain adds a level to the tree
+    if remainder.len() >= MAX_EXPRESSION_DEPTH {
+        return Err(too_deep(i));
+    }
+
+    let expr = remainder.into_iter().fold(initial, |acc, pair| {
+        let (op, expr) = pair;
+        Expr::Binary(builder::Op::Binary(op), Box::new(acc), Box::new(expr))
+    });
+    Ok((i, expr))
+}
+
+fn too_deep(i: &str) -> nom::Err<Error> {
+    nom::Err::Failure(Error {
+        input: i,
+        code: ErrorKind::TooLarge,
+        message: Some(format!(
+            "expression nested too deeply, the maximum depth is {}",
+            MAX_EXPRESSION_DEPTH
+        )),
+        expected: Vec::new(),
+    })
+}
+
+thread_local! {
+    /// number of `expr` calls currently running
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// parse a Datalog expression
+///
+/// expressions deeper than [`MAX_EXPRESSION_DEPTH`] are rejected
+pub fn expr(i: &str) -> IResult<&str, Expr, Error> {
+    let nesting = NESTING.with(|n| n.get()) + 1;
+    if nesting > MAX_EXPRESSION_DEPTH {
+        return Err(too_deep(i));
+    }
+
+    NESTING.with(|n| n.set(nesting));
+    let res = expr0(i);
+    NESTING.with(|n| n.set(nesting - 1));
+
+    let (i, e) = res?;
+    if e.depth() > MAX_EXPRESSION_DEPTH {
+        return Err(too_deep(i));
+    }
+    Ok((i, e))
+}
+
+fn expr0(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, initial) = expr1(i)?;
+
+    let (i, remainder) = many0(tuple((preceded(space0, binary_op_0), expr1)))(i)?;
+
+    fold_exprs(i, initial, remainder)
+}
+
+fn expr1(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, initial) = expr2(i)?;
+
+    let (i, remainder) = many0(tuple((preceded(space0, binary_op_1), expr2)))(i)?;
+
+    fold_exprs(i, initial, remainder)
+}
+
+fn expr2(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, initial) = expr3(i)?;
+
+    let (i, remainder) = many0(tuple((preceded(space0, binary_op_2), expr3)))(i)?;
+
+    fold_exprs(i, initial, remainder)
+}
+
+fn expr3(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, initial) = expr4(i)?;
+
+    let (i, remainder) = many0(tuple((preceded(space0, binary_op_3), expr4)))(i)?;
+
+    fold_exprs(i, initial, remainder)
+}
+
+fn expr4(i: &str) -> IResult<&str, Expr, Error> {
+    let (i, initial) = expr_term(i)?;
+
+    if let Ok((i, _)) = char::<_, ()>('.')(i) {
+        let (i, op) = binary_op_4(i)?;
+
+        let (i, _) = char('(')(i)?;
+        let (i, _) = space0(i)?;
+        // we only support a single argument for now
+        let (i, arg) = expr(i)?;
+        let (i, _) = space0(i)?;
+        let (i, _) = char(')')(i)?;
+
+        let e = Expr::Binary(builder::Op::Binary(op), Box::new(initial), Box::new(arg));
+
+        Ok((i, e))
+    } else {
+        Ok((i, initial))
+    }
+}
+
+pub(crate) fn name(i: &str) -> IResult<&str, &str, Error> {
+    let is_name_char = |c: char| is_alphanumeric(c as u8) || c == '_';
+
+    // names can be prefixed by a namespace, as in `billing:right`
+    reduce(
+        recognize(pair(
+            take_while1(is_name_char),
+            opt(pair(char(':'), take_while1(is_name_char))),
+        )),
+        " ,:(\n;",
+    )(i)
+}
+
+fn printable(i: &str) -> IResult<&str, &str, Error> {
+    take_while1(|c: char| c != '\\' && c != '"')(i)
+}
+
+fn parse_string_internal(i: &str) -> IResult<&str, String, Error> {
+    escaped_transform(
+        printable,
+        '\\',
+        alt((
+            map(char('\\'), |_| "\\"),
+            map(char('"'), |_| "\""),
+            map(char('n'), |_| "\n"),
+        )),
+    )(i)
+}
+
+pub(crate) fn parse_string(i: &str) -> IResult<&str, String, Error> {
+    delimited(char('"'), parse_string_internal, char('"'))(i)
+}
+
+fn string(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_string(i).map(|(i, s)| (i, builder::Term::Str(s)))
+}
+
+pub(crate) fn parse_symbol(i: &str) -> IResult<&str, &str, Error> {
+    preceded(char('#'), name)(i)
+}
+
+fn symbol(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_symbol(i).map(|(i, s)| (i, builder::s(s)))
+}
+
+pub(crate) fn parse_integer(i: &str) -> IResult<&str, i64, Error> {
+    map_res(recognize(pair(opt(char('-')), digit1)), |s: &str| s.parse())(i)
+}
+
+fn integer(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_integer(i).map(|(i, n)| (i, builder::int(n)))
+}
+
+pub(crate) fn parse_date(i: &str) -> IResult<&str, u64, Error> {
+    map_res(
+        map_res(
+            take_while1(|c: char| c != ',' && c != ' ' && c != ')' && c != ']' && c != ';'),
+            |s| chrono::DateTime::parse_from_rfc3339(s),
+        ),
+        |t| t.timestamp().try_into(),
+    )(i)
+}
+
+fn date(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_date(i).map(|(i, t)| (i, builder::Term::Date(t)))
+}
+
+pub(crate) fn parse_bytes(i: &str) -> IResult<&str, Vec<u8>, Error> {
+    preceded(
+        tag("hex:"),
+        map_res(
+            take_while1(|c| {
+                let c = c as u8;
+                (b'0'..=b'9').contains(&c)
+                    || (b'a'..=b'f').contains(&c)
+                    || (b'A'..=b'F').contains(&c)
+            }),
+            hex::decode,
+        ),
+    )(i)
+}
+
+fn bytes(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_bytes(i).map(|(i, s)| (i, builder::Term::Bytes(s)))
+}
+
+pub(crate) fn variable(i: &str) -> IResult<&str, builder::Term, Error> {
+    map(preceded(char('$'), name), builder::variable)(i)
+}
+
+fn parse_bool(i: &str) -> IResult<&str, bool, Error> {
+    alt((value(true, tag("true")), value(false, tag("false"))))(i)
+}
+
+fn boolean(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_bool(i).map(|(i, b)| (i, builder::boolean(b)))
+}
+
+fn set(i: &str) -> IResult<&str, builder::Term, Error> {
+    //println!("set:\t{}", i);
+    let (i, _) = preceded(space0, char('['))(i)?;
+    let (i, (list_input, mut list)) = consumed(cut(separated_list0(
+        preceded(space0, char(',')),
+        term_in_set,
+    )))(i)?;
+
+    let mut set = BTreeSet::new();
+
+    let mut kind: Option<u8> = None;
+    for term in list.drain(..) {
+        let index = match term {
+            builder::Term::Symbol(_) => 0,
+            builder::Term::Variable(_) => {
+                return Err(set_error(list_input, "variables are not allowed in sets"))
+            }
+            builder::Term::Integer(_) => 2,
+            builder::Term::Str(_) => 3,
+            builder::Term::Date(_) => 4,
+            builder::Term::Bytes(_) => 5,
+            builder::Term::Bool(_) => 6,
+            builder::Term::Set(_) => {
+                return Err(set_error(list_input, "sets cannot contain other sets"))
+            }
+            // the type of a parameter is only known once it is replaced
+            builder::Term::Parameter(_) => {
+                set.insert(term);
+                continue;
+            }
+        };
+
+        if let Some(k) = kind {
+            if k != index {
+                return Err(set_error(
+                    list_input,
+                    "set elements must have the same type",
+                ));
+            }
+        } else {
+            kind = Some(index);
+        }
+
+        set.insert(term);
+    }
+
+    let (i, _) = preceded(space0, char(']'))(i)?;
+
+    Ok((i, builder::set(set)))
+}
+
+fn set_error<'a>(input: &'a str, message: &str) -> nom::Err<Error<'a>> {
+    nom::Err::Failure(Error {
+        input,
+        code: ErrorKind::Verify,
+        message: Some(message.to_string()),
+        expected: Vec::new(),
+    })
+}
+
+pub(crate) fn parameter(i: &str) -> IResult<&str, builder::Term, Error> {
+    map(delimited(char('{'), name, char('}')), builder::parameter)(i)
+}
+
+fn term(i: &str) -> IResult<&str, builder::Term, Error> {
+    preceded(
+        space0,
+        alt((
+            symbol, string, date, variable, integer, bytes, boolean, set, parameter,
+        )),
+    )(i)
+}
+
+fn term_in_fact(i: &str) -> IResult<&str, builder::Term, Error> {
+    preceded(
+        space0,
+        error(
+            alt((
+                symbol, string, date, integer, bytes, boolean, set, parameter,
+            )),
+            |input| match input.chars().next() {
+                None | Some(',') | Some(')') => "missing term".to_string(),
+                Some('$') => "variables are not allowed in facts".to_string(),
+                _ => "expected a valid term".to_string(),
+            },
+            " ,)\n;",
+        ),
+    )(i)
+}
+
+fn term_in_set(i: &str) -> IResult<&str, builder::Term, Error> {
+    preceded(
+        space0,
+        error(
+            alt((symbol, string, date, integer, bytes, boolean, parameter)),
+            |input| match input.chars().next() {
+                None | Some(',') | Some(']') => "missing term".to_string(),
+                Some('$') => "variables are not allowed in sets".to_string(),
+                _ => "expected a valid term".to_string(),
+            },
+            " ,]\n;",
+        ),
+    )(i)
+}
+
+fn line_comment(i: &str) -> IResult<&str, (), Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("//")(i)?;
+    let (i, _) = take_while(|c| c != '\r' && c != '\n')(i)?;
+    let (i, _) = alt((tag("\n"), tag("\r\n"), eof))(i)?;
+
+    Ok((i, ()))
+}
+
+fn multiline_comment(i: &str) -> IResult<&str, (), Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("/*")(i)?;
+    let (i, _) = take_until("*/")(i)?;
+    let (i, _) = tag("*/")(i)?;
+
+    Ok((i, ()))
+}
+
+fn context(i: &str) -> IResult<&str, String, Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("context")(i)?;
+    let (i, _) = space0(i)?;
+
+    cut(error(
+        parse_string,
+        |_| "expected a string after 'context'".to_string(),
+        " ;\n",
+    ))(i)
+}
+
+fn include(i: &str) -> IResult<&str, String, Error> {
+    let (i, _) = space0(i)?;
+    let (i, _) = tag("include")(i)?;
+    let (i, _) = space0(i)?;
+
+    cut(error(
+        parse_string,
+        |_| "expected a file name string after 'include'".to_string(),
+        " ;\n",
+    ))(i)
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SourceResult<'a> {
+    pub facts: Vec<(&'a str, builder::Fact)>,
+    pub rules: Vec<(&'a str, builder::Rule)>,
+    pub checks: Vec<(&'a str, builder::Check)>,
+    pub policies: Vec<(&'a str, builder::Policy)>,
+    /// block context, written `context "text";`
+    pub context: Option<String>,
+    /// files included with `include "file";`, that must be written before
+    /// any other element
+    pub includes: Vec<(&'a str, String)>,
+    /// elements written with the syntax of an older version of the language
+    pub deprecations: Vec<Deprecation<'a>>,
+}
+
+/// an element using a construct from an older version of the language, that
+/// is still accepted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deprecation<'a> {
+    /// the element, as written in the source
+    pub input: &'a str,
+    pub message: String,
+    /// the element written with the current syntax
+    pub replacement: String,
+}
+
+impl<'a> Deprecation<'a> {
+    /// looks for older constructs in a parsed rule or check
+    fn find(element: &'a str) -> Option<Self> {
+        let input = element.trim();
+        if let Some(rest) = input.strip_prefix('*') {
+            return Some(Deprecation {
+                input,
+                message: "rule heads are not prefixed with `*` anymore".to_string(),
+                replacement: rest.trim_start().to_string(),
+            });
+        }
+
+        let keyword = input.get(..6)?;
+        if keyword.eq_ignore_ascii_case("caveat") {
+            return Some(Deprecation {
+                input,
+                message: "`caveat` was renamed to `check`".to_string(),
+                replacement: format!("check{}", &input[6..]),
+            });
+        }
+
+        None
+    }
+}
+
+enum SourceElement<'a> {
+    Fact(&'a str, builder::Fact),
+    Rule(&'a str, builder::Rule),
+    Check(&'a str, builder::Check),
+    Policy(&'a str, builder::Policy),
+    Context(&'a str, String),
+    Include(&'a str, String),
+    Comment,
+}
+
+pub fn sep(i: &str) -> IResult<&str, &str, Error> {
+    let (i, _) = space0(i)?;
+    alt((map(char(';'), |_| ";"), eof))(i)
+}
+
+pub fn parse_source(i: &str) -> Result<(&str, SourceResult), Vec<Error>> {
+    let (result, errors) = parse_source_partial(i);
+    if errors.is_empty() {
+        Ok((&i[i.len()..], result))
+    } else {
+        Err(errors)
+    }
+}
+
+/// parses Datalog source like [`parse_source`], without stopping at the
+/// first error
+///
+/// after an invalid element, parsing resumes after the next `;`, so the
+/// result holds all the valid elements, and the errors of all the invalid
+/// ones, as editors need to show diagnostics on code that is being written
+pub fn parse_source_partial(mut i: &str) -> (SourceResult, Vec<Error>) {
+    let mut result = SourceResult::default();
+    let mut errors = Vec::new();
+
+    loop {
+        if i.is_empty() {
+            return (result, errors);
+        }
+
+        match terminated(
+            alt((
+                map(terminated(consumed(rule_inner), cut(sep)), |(i, r)| {
+                    SourceElement::Rule(i, r)
+                }),
+                map(terminated(consumed(fact_inner), cut(sep)), |(i, f)| {
+                    SourceElement::Fact(i, f)
+                }),
+                map(terminated(consumed(check_inner), cut(sep)), |(i, c)| {
+                    SourceElement::Check(i, c)
+                }),
+                map(terminated(consumed(policy_inner), cut(sep)), |(i, p)| {
+                    SourceElement::Policy(i, p)
+                }),
+                map(terminated(consumed(include), cut(sep)), |(i, f)| {
+                    SourceElement::Include(i, f)
+                }),
+                map(line_comment, |_| SourceElement::Comment),
+                map(multiline_comment, |_| SourceElement::Comment),
+            )),
+            space0,
+        )(i)
+        {
+            Ok((i2, o)) => {
+                match o {
+                    SourceElement::Fact(i, f) => result.facts.push((i, f)),
+                    SourceElement::Rule(i, r) => {
+                        result.deprecations.extend(Deprecation::find(i));
+                        result.rules.push((i, r))
+                    }
+                    SourceElement::Check(i, c) => {
+                        result.deprecations.extend(Deprecation::find(i));
+                        result.checks.push((i, c))
+                    }
+                    SourceElement::Policy(i, p) => result.policies.push((i, p)),
+                    SourceElement::Context(_, _) => {}
+                    SourceElement::Include(i, f) => {
+                        if result.facts.is_empty()
+                            && result.rules.is_empty()
+                            && result.checks.is_empty()
+                            && result.policies.is_empty()
+                        {
+                            result.includes.push((i, f));
+                        } else {
+                            errors.push(Error {
+                                input: i,
+                                code: ErrorKind::Verify,
+                                message: Some(
+                                    "include directives must be written before other elements"
+                                        .to_string(),
+                                ),
+                                expected: Vec::new(),
+                            });
+                        }
+                    }
+                    SourceElement::Comment => {}
+                }
+
+                i = i2;
+            }
+            Err(nom::Err::Incomplete(_)) => panic!(),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                i = recover(i, e, &mut errors);
+            }
+        }
+    }
+}
+
+pub fn parse_block_source(i: &str) -> Result<(&str, SourceResult), Vec<Error>> {
+    let (result, errors) = parse_block_source_partial(i);
+    if errors.is_empty() {
+        Ok((&i[i.len()..], result))
+    } else {
+        Err(errors)
+    }
+}
+
+/// parses the source of a block like [`parse_block_source`], without
+/// stopping at the first error, see [`parse_source_partial`]
+pub fn parse_block_source_partial(mut i: &str) -> (SourceResult, Vec<Error>) {
+    let mut result = SourceResult::default();
+    let mut errors = Vec::new();
+
+    loop {
+        if i.is_empty() {
+            return (result, errors);
+        }
+
+        match terminated(
+            alt((
+                map(terminated(consumed(rule_inner), cut(sep)), |(i, r)| {
+                    SourceElement::Rule(i, r)
+                }),
+                map(terminated(consumed(fact_inner), cut(sep)), |(i, f)| {
+                    SourceElement::Fact(i, f)
+                }),
+                map(terminated(consumed(check_inner), cut(sep)), |(i, c)| {
+                    SourceElement::Check(i, c)
+                }),
+                map(terminated(consumed(context), cut(sep)), |(i, c)| {
+                    SourceElement::Context(i, c)
+                }),
+                map(line_comment, |_| SourceElement::Comment),
+                map(multiline_comment, |_| SourceElement::Comment),
+            )),
+            space0,
+        )(i)
+        {
+            Ok((i2, o)) => {
+                match o {
+                    SourceElement::Fact(i, f) => result.facts.push((i, f)),
+                    SourceElement::Rule(i, r) => {
+                        result.deprecations.extend(Deprecation::find(i));
+                        result.rules.push((i, r))
+                    }
+                    SourceElement::Check(i, c) => {
+                        result.deprecations.extend(Deprecation::find(i));
+                        result.checks.push((i, c))
+                    }
+                    SourceElement::Policy(_, _) | SourceElement::Include(_, _) => {}
+                    SourceElement::Context(i, c) => {
+                        if result.context.is_some() {
+                            errors.push(Error {
+                                input: i,
+                                code: ErrorKind::Verify,
+                                message: Some("a block can only have one context".to_string()),
+                                expected: Vec::new(),
+                            });
+                        } else {
+                            result.context = Some(c);
+                        }
+                    }
+                    SourceElement::Comment => {}
+                }
+
+                i = i2;
+            }
+            Err(nom::Err::Incomplete(_)) => panic!(),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                i = recover(i, e, &mut errors);
+            }
+        }
+    }
+}
+
+/// records the error of an element, and skips to the end of the element
+///
+/// parsing resumes after the next `;`, at the start of the next element
+fn recover<'a>(i: &'a str, mut e: Error<'a>, errors: &mut Vec<Error<'a>>) -> &'a str {
+    if let Some(index) = e.input.find(';') {
+        e.input = &(e.input)[..index];
+    }
+
+    let offset = i.offset(e.input);
+    let rest = match i[offset..].find(';') {
+        Some(index) => &i[offset + index + 1..],
+        None => &i[i.len()..],
+    };
+
+    errors.push(e);
+    rest.trim_start_matches(&[' ', '\t', '\r', '\n'][..])
+}
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Parse error on input: {input}. Message: {message:?}")]
+pub struct Error<'a> {
+    pub input: &'a str,
+    pub code: ErrorKind,
+    pub message: Option<String>,
+    /// tokens that would have been accepted at this position, when known
+    pub expected: Vec<String>,
+}
+
+impl<'a> Error<'a> {
+    /// suggests a fix for common mistakes, from the input where the parser stopped
+    pub fn hint(&self) -> Option<String> {
+        let input = self.input.trim_start();
+        if input.starts_with('=') && !input.starts_with("==") {
+            return Some("values are compared with `==`, not `=`".to_string());
+        }
+
+        let name: String = input
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        if !name.starts_with(char::is_alphabetic) {
+            return None;
+        }
+
+        if self.expected.iter().any(|e| e == "'$'") {
+            Some(format!(
+                "variables start with `$`, did you mean `${}`?",
+                name
+            ))
+        } else if self.expected.iter().any(|e| e == "'#'") {
+            Some(format!(
+                "did you mean the symbol `#{}` or the string `\"{}\"`?",
+                name, name
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ParseError<&'a str> for Error<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        let expected = match kind {
+            ErrorKind::Eof => vec!["end of input".to_string()],
+            ErrorKind::Digit => vec!["digit".to_string()],
+            ErrorKind::HexDigit => vec!["hexadecimal digit".to_string()],
+            _ => Vec::new(),
+        };
+
+        Self {
+            input,
+            code: kind,
+            message: None,
+            expected,
+        }
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'a str, c: char) -> Self {
+        Self {
+            input,
+            code: ErrorKind::Char,
+            message: None,
+            expected: vec![format!("'{}'", c)],
+        }
+    }
+
+    // alternatives failing at the same position accumulate what they expected
+    fn or(self, mut other: Self) -> Self {
+        if self.input.as_ptr() == other.input.as_ptr() {
+            let mut expected = self.expected;
+            for e in other.expected.drain(..) {
+                if !expected.contains(&e) {
+                    expected.push(e);
+                }
+            }
+            other.expected = expected;
+        }
+        other
+    }
+}
+
+//FIXME: poperly handle other errors
+impl<'a, E> FromExternalError<&'a str, E> for Error<'a> {
+    fn from_external_error(input: &'a str, kind: ErrorKind, _e: E) -> Self {
+        Self::from_error_kind(input, kind)
+    }
+}
+
+fn error<'a, F, O, P>(
+    mut parser: P,
+    context: F,
+    reducer: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O, Error<'a>>
+where
+    P: nom::Parser<&'a str, O, Error<'a>>,
+    F: Fn(&'a str) -> String,
+{
+    move |i: &str| match parser.parse(i) {
+        Ok(res) => Ok(res),
+        Err(nom::Err::Incomplete(i)) => Err(nom::Err::Incomplete(i)),
+        Err(nom::Err::Error(mut e)) => {
+            if let Some(index) = e.input.find(|c| reducer.contains(c)) {
+                e.input = &(e.input)[..index];
+            }
+
+            if e.message.is_none() {
+                e.message = Some(context(e.input));
+            }
+
+            Err(nom::Err::Error(e))
+        }
+        Err(nom::Err::Failure(mut e)) => {
+            if let Some(index) = e.input.find(|c| reducer.contains(c)) {
+                e.input = &(e.input)[..index];
+            }
+
+            if e.message.is_none() {
+                e.message = Some(context(e.input));
+            }
+
+            Err(nom::Err::Failure(e))
+        }
+    }
+}
+
+fn reduce<'a, O, P>(
+    mut parser: P,
+    reducer: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O, Error<'a>>
+where
+    P: nom::Parser<&'a str, O, Error<'a>>,
+{
+    move |i: &str| match parser.parse(i) {
+        Ok(res) => Ok(res),
+        Err(nom::Err::Incomplete(i)) => Err(nom::Err::Incomplete(i)),
+        Err(nom::Err::Error(mut e)) => {
+            if let Some(index) = e.input.find(|c| reducer.contains(c)) {
+                e.input = &(e.input)[..index];
+            }
+
+            Err(nom::Err::Error(e))
+        }
+        Err(nom::Err::Failure(mut e)) => {
+            if let Some(index) = e.input.find(|c| reducer.contains(c)) {
+                e.input = &(e.input)[..index];
+            }
+
+            Err(nom::Err::Failure(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use crate::builder;
+    use nom::error::ErrorKind;
+
+    #[test]
+    fn name() {
+        assert_eq!(
+            super::name("operation(#ambient, #read)"),
+            Ok(("(#ambient, #read)", "operation"))
+        );
+        assert_eq!(
+            super::name("billing:right(#authority, \"invoice\")"),
+            Ok(("(#authority, \"invoice\")", "billing:right"))
+        );
+    }
+
+    #[test]
+    fn symbol() {
+        assert_eq!(super::symbol("#ambient"), Ok(("", builder::s("ambient"))));
+    }
+
+    #[test]
+    fn string() {
+        assert_eq!(
+            super::string("\"file1 a hello - 123_\""),
+            Ok(("", builder::string("file1 a hello - 123_")))
+        );
+    }
+
+    #[test]
+    fn integer() {
+        assert_eq!(super::integer("123"), Ok(("", builder::int(123))));
+        assert_eq!(super::integer("-42"), Ok(("", builder::int(-42))));
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(
+            super::date("2019-12-02T13:49:53Z"),
+            Ok(("", builder::Term::Date(1575294593)))
+        );
+    }
+
+    #[test]
+    fn variable() {
+        assert_eq!(super::variable("$1"), Ok(("", builder::variable("1"))));
+    }
+
+    #[test]
+    fn constraint() {
+        use builder::{date, int, set, string, symbol, var, Binary, Op, Unary};
+        use std::collections::BTreeSet;
+        use std::time::{Duration, SystemTime};
+
+        assert_eq!(
+            super::expr("$0 <= 2030-12-31T12:59:59+00:00").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(date(
+                        &(SystemTime::UNIX_EPOCH + Duration::from_secs(1924952399))
+                    )),
+                    Op::Binary(Binary::LessOrEqual),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 >= 2030-12-31T12:59:59+00:00").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(date(
+                        &(SystemTime::UNIX_EPOCH + Duration::from_secs(1924952399))
+                    )),
+                    Op::Binary(Binary::GreaterOrEqual),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 < 1234").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(1234)),
+                    Op::Binary(Binary::LessThan),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 > 1234").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(1234)),
+                    Op::Binary(Binary::GreaterThan),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 <= 1234").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(1234)),
+                    Op::Binary(Binary::LessOrEqual),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 >= -1234").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(-1234)),
+                    Op::Binary(Binary::GreaterOrEqual),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 == 1").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(1)),
+                    Op::Binary(Binary::Equal),
+                ],
+            ))
+        );
+
+        let h = [int(1), int(2)].iter().cloned().collect::<BTreeSet<_>>();
+        assert_eq!(
+            super::expr("[1, 2].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h.clone())),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("![1, 2].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h)),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                    Op::Unary(Unary::Negate),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0 == \"abc\"").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(string("abc")),
+                    Op::Binary(Binary::Equal),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0.ends_with(\"abc\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(string("abc")),
+                    Op::Binary(Binary::Suffix),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0.starts_with(\"abc\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(string("abc")),
+                    Op::Binary(Binary::Prefix),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0.matches(\"abc[0-9]+\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(string("abc[0-9]+")),
+                    Op::Binary(Binary::Regex),
+                ],
+            ))
+        );
+
+        let h = [string("abc"), string("def")]
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            super::expr("[\"abc\", \"def\"].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h.clone())),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("![\"abc\", \"def\"].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h.clone())),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                    Op::Unary(Unary::Negate),
+                ],
+            ))
+        );
+
+        let h = [symbol("abc"), symbol("def")]
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            super::expr("[#abc, #def].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h.clone())),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("![#abc, #def].contains($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set(h.clone())),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::Contains),
+                    Op::Unary(Unary::Negate),
+                ],
+            ))
+        );
+    }
+
+    #[test]
+    fn fact() {
+        assert_eq!(
+            super::fact("right( #authority, \"file1\", #read )"),
+            Ok((
+                "",
+                builder::fact(
+                    "right",
+                    &[
+                        builder::s("authority"),
+                        builder::string("file1"),
+                        builder::s("read")
+                    ]
+                )
+            ))
+        );
+
+        use nom::error::ErrorKind;
+        // facts should not contain variables
+        assert_eq!(
+            super::fact("right( #authority, $var, #read )"),
+            Err(nom::Err::Failure(super::Error {
+                code: ErrorKind::Char,
+                input: "$var",
+                message: Some("variables are not allowed in facts".to_string()),
+                expected: ["'#'", "'\"'", "digit", "'['", "'{'"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            }))
+        );
+    }
+
+    #[test]
+    fn set_errors() {
+        use nom::error::ErrorKind;
+
+        assert_eq!(
+            super::fact("values([1, \"a\"])"),
+            Err(nom::Err::Failure(super::Error {
+                code: ErrorKind::Verify,
+                input: "1",
+                message: Some("set elements must have the same type".to_string()),
+                expected: Vec::new(),
+            }))
+        );
+
+        assert!(super::parse_source("values([#a, 2]);").is_err());
+    }
+
+    #[test]
+    fn expression_depth() {
+        use super::MAX_EXPRESSION_DEPTH;
+
+        // each pair of parentheses adds a level
+        let nested = |n: usize| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        let (_, e) = super::expr(&nested(MAX_EXPRESSION_DEPTH - 1)).unwrap();
+        assert_eq!(e.depth(), MAX_EXPRESSION_DEPTH);
+        assert_eq!(e.opcodes().len(), MAX_EXPRESSION_DEPTH);
+
+        let too_deep = |source: &str| matches!(super::expr(source), Err(nom::Err::Failure(e)) if e.code == ErrorKind::TooLarge);
+        assert!(too_deep(&nested(MAX_EXPRESSION_DEPTH)));
+        assert!(too_deep(&nested(100_000)));
+
+        let chain = |n: usize| vec!["1"; n].join(" + ");
+        assert!(super::expr(&chain(MAX_EXPRESSION_DEPTH - 1)).is_ok());
+        assert!(too_deep(&chain(100_000)));
+
+        assert!(super::rule(&format!("data($x) <- data($x), {}", nested(1000))).is_err());
+    }
+
+    #[test]
+    fn fact_with_date() {
+        assert_eq!(
+            super::fact("date(#ambient,2019-12-02T13:49:53Z)"),
+            Ok((
+                "",
+                builder::fact(
+                    "date",
+                    &[builder::s("ambient"), builder::Term::Date(1575294593)]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn rule() {
+        assert_eq!(
+            super::rule("right(#authority, $0, #read) <- resource( #ambient, $0), operation(#ambient, #read)"),
+            Ok((
+                "",
+                builder::rule(
+                    "right",
+                    &[
+                        builder::s("authority"),
+                        builder::variable("0"),
+                        builder::s("read"),
+                    ],
+                    &[
+                        builder::pred("resource", &[builder::s("ambient"), builder::variable("0")]),
+                        builder::pred("operation", &[builder::s("ambient"), builder::s("read")]),
+                    ]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn constrained_rule() {
+        use builder::{date, var, Binary, Expression, Op};
+        use std::time::{Duration, SystemTime};
+
+        assert_eq!(
+            super::rule("valid_date(\"file1\") <- time(#ambient, $0 ), resource( #ambient, \"file1\"), $0 <= 2019-12-04T09:46:41+00:00"),
+            Ok((
+                "",
+                builder::constrained_rule(
+                    "valid_date",
+                    &[
+                        builder::string("file1"),
+                    ],
+                    &[
+                        builder::pred("time", &[builder::s("ambient"), builder::variable("0")]),
+                        builder::pred("resource", &[builder::s("ambient"), builder::string("file1")]),
+                    ],
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(var("0")),
+                            Op::Value(date(&(SystemTime::UNIX_EPOCH + Duration::from_secs(1575452801)))),
+                            Op::Binary(Binary::LessOrEqual),
+                        ]
+                    }],
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn constrained_rule_ordering() {
+        use builder::{date, var, Binary, Expression, Op};
+        use std::time::{Duration, SystemTime};
+
+        assert_eq!(
+            super::rule("valid_date(\"file1\") <- time(#ambient, $0 ), $0 <= 2019-12-04T09:46:41+00:00, resource(#ambient, \"file1\")"),
+            Ok((
+                "",
+                builder::constrained_rule(
+                    "valid_date",
+                    &[
+                        builder::string("file1"),
+                    ],
+                    &[
+                        builder::pred("time", &[builder::s("ambient"), builder::variable("0")]),
+                        builder::pred("resource", &[builder::s("ambient"), builder::string("file1")]),
+                    ],
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(var("0")),
+                            Op::Value(date(&(SystemTime::UNIX_EPOCH + Duration::from_secs(1575452801)))),
+                            Op::Binary(Binary::LessOrEqual),
+                        ]
+                    }],
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn rule_with_unused_head_variables() {
+        assert_eq!(
+            super::rule("right(#authority, $0, $test) <- resource( #ambienu, $0), operation(#ambient, #read)"),
+            Err( nom::Err::Error(Error {
+                input: "right(#authority, $0, $test)",
+                code: ErrorKind::Satisfy,
+                message: Some("rule head contains variables that are not used in predicates of the rule's body: $test".to_string()),
+                expected: Vec::new(),
+            }))
+        );
+    }
+
+    #[test]
+    fn check() {
+        let empty: &[builder::Term] = &[];
+        assert_eq!(
+            super::check(
+                "check if resource(#ambient, $0), operation(#ambient, #read) or admin(#authority)"
+            ),
+            Ok((
+                "",
+                builder::Check {
+                    queries: vec![
+                        builder::rule(
+                            "query",
+                            empty,
+                            &[
+                                builder::pred(
+                                    "resource",
+                                    &[builder::s("ambient"), builder::variable("0")]
+                                ),
+                                builder::pred(
+                                    "operation",
+                                    &[builder::s("ambient"), builder::s("read")]
+                                ),
+                            ]
+                        ),
+                        builder::rule(
+                            "query",
+                            empty,
+                            &[builder::pred("admin", &[builder::s("authority")]),]
+                        ),
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_check() {
+        assert_eq!(
+            super::check(
+                "check if resource(#ambient, $0) and operation(#ambient, #read) or admin(#authority)"
+            ),
+            Err( nom::Err::Error(Error {
+                input: "and",
+                code: ErrorKind::Eof,
+                message: Some("expected either the next term after ',' or the next check variant after 'or', but got 'and'".to_string()),
+                expected: vec!["end of input".to_string()],
+            }))
+        );
+
+        assert_eq!(
+            super::check(
+                "check if resource(#ambient, \"{}\"), operation(#ambient, #write)) or operation(#ambient, #read)"
+            ),
+            Err( nom::Err::Error(Error {
+                input: ")",
+                code: ErrorKind::Eof,
+                message: Some("unexpected parens".to_string()),
+                expected: vec!["end of input".to_string()],
+            }))
+        );
+
+        assert_eq!(
+            super::check(
+                "check if resource(#ambient, \"{}\") && operation(#ambient, #write)) || operation(#ambient, #read)"
+            ),
+            Err( nom::Err::Error(Error {
+                input: "&&",
+                code: ErrorKind::Eof,
+                message: Some("expected either the next term after ',' or the next check variant after 'or', but got '&&'".to_string()),
+                expected: vec!["end of input".to_string()],
+            }))
+        );
+    }
+
+    #[test]
+    fn parameters() {
+        use builder::{fact, parameter, pred, rule, s, set, var};
+
+        assert_eq!(
+            super::fact("right({user}, #read)"),
+            Ok(("", fact("right", &[parameter("user"), s("read")])))
+        );
+
+        assert_eq!(
+            super::rule("allowed($0) <- resource($0), owner({user}, $0)"),
+            Ok((
+                "",
+                rule(
+                    "allowed",
+                    &[var("0")],
+                    &[
+                        pred("resource", &[var("0")]),
+                        pred("owner", &[parameter("user"), var("0")]),
+                    ]
+                )
+            ))
+        );
+
+        let h = [parameter("a"), s("b")].iter().cloned().collect();
+        assert_eq!(
+            super::fact("values([{a}, #b])"),
+            Ok(("", fact("values", &[set(h)])))
+        );
+    }
+
+    #[test]
+    fn source_file() {
+        use builder::{
+            boolean, constrained_rule, fact, int, pred, rule, s, string, var, Binary, Check,
+            Expression, Op, Policy, PolicyKind,
+        };
+        use std::time::{Duration, SystemTime};
+
+        let input = r#"
+          fact("string", #symbol);
+          fact2(1234);
+
+          rule_head($var0) <- fact($var0, $var1), 1 < 2;
+
+          // line comment
+          check if 1 == 2;
+
+          allow if rule_head("string");
+
+          /*
+           other comment
+          */
+
+          check if
+              fact(5678)
+              or fact(1234), "test".starts_with("abc");
+
+          check if 2021-01-01T00:00:00Z <= 2021-01-01T00:00:00Z;
+
+          deny if true;
+        "#;
+
+        let res = super::parse_source(input);
+        println!("parse_source res:\n{:#?}", res);
+
+        let empty_terms: &[builder::Term] = &[];
+        let empty_preds: &[builder::Predicate] = &[];
+
+        let expected_facts = vec![
+            fact("fact", &[string("string"), s("symbol")]),
+            fact("fact2", &[int(1234)]),
+        ];
+
+        let expected_rules = vec![constrained_rule(
+            "rule_head",
+            &[var("var0")],
+            &[pred("fact", &[var("var0"), var("var1")])],
+            &[Expression {
+                ops: vec![
+                    Op::Value(int(1)),
+                    Op::Value(int(2)),
+                    Op::Binary(Binary::LessThan),
+                ],
+            }],
+        )];
+
+        let expected_checks = vec![
+            Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    empty_terms,
+                    empty_preds,
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(int(1)),
+                            Op::Value(int(2)),
+                            Op::Binary(Binary::Equal),
+                        ],
+                    }],
+                )],
+            },
+            Check {
+                queries: vec![
+                    rule("query", empty_terms, &[pred("fact", &[int(5678)])]),
+                    constrained_rule(
+                        "query",
+                        empty_terms,
+                        &[pred("fact", &[int(1234)])],
+                        &[Expression {
+                            ops: vec![
+                                Op::Value(string("test")),
+                                Op::Value(string("abc")),
+                                Op::Binary(Binary::Prefix),
+                            ],
+                        }],
+                    ),
+                ],
+            },
+            Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    empty_terms,
+                    empty_preds,
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(builder::date(
+                                &(SystemTime::UNIX_EPOCH + Duration::from_secs(1609459200)),
+                            )),
+                            Op::Value(builder::date(
+                                &(SystemTime::UNIX_EPOCH + Duration::from_secs(1609459200)),
+                            )),
+                            Op::Binary(Binary::LessOrEqual),
+                        ],
+                    }],
+                )],
+            },
+        ];
+
+        let expected_policies = vec![
+            Policy {
+                kind: PolicyKind::Allow,
+                queries: vec![rule(
+                    "query",
+                    empty_terms,
+                    &[pred("rule_head", &[string("string")])],
+                )],
+            },
+            Policy {
+                kind: PolicyKind::Deny,
+                queries: vec![constrained_rule(
+                    "query",
+                    empty_terms,
+                    empty_preds,
+                    &[Expression {
+                        ops: vec![Op::Value(boolean(true))],
+                    }],
+                )],
+            },
+        ];
+
+        let (_remaining, mut result) = res.unwrap();
+        //assert_eq!(remaining, "\n");
+        assert_eq!(
+            result.facts.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_facts
+        );
+        assert_eq!(
+            result.rules.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_rules
+        );
+        assert_eq!(
+            result.checks.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_checks
+        );
+        assert_eq!(
+            result
+                .policies
+                .drain(..)
+                .map(|(_, r)| r)
+                .collect::<Vec<_>>(),
+            expected_policies
+        );
+    }
+
+    #[test]
+    fn block_source_file() {
+        use builder::{
+            constrained_rule, fact, int, pred, rule, s, string, var, Binary, Check, Expression, Op,
+        };
+        use std::time::{Duration, SystemTime};
+
+        let input = r#"
+          fact("string", #symbol);
+          fact2(1234);
+
+          rule_head($var0) <- fact($var0, $var1), 1 < 2;
+
+          // line comment
+          check if 1 == 2;
+
+          /*
+           other comment
+          */
+
+          check if
+              fact(5678)
+              or fact(1234), "test".starts_with("abc");
+
+          check if 2021-01-01T00:00:00Z <= 2021-01-01T00:00:00Z;
+        "#;
+
+        let res = super::parse_block_source(input);
+        println!("parse_block_source res:\n{:#?}", res);
+
+        let empty_terms: &[builder::Term] = &[];
+        let empty_preds: &[builder::Predicate] = &[];
+
+        let expected_facts = vec![
+            fact("fact", &[string("string"), s("symbol")]),
+            fact("fact2", &[int(1234)]),
+        ];
+
+        let expected_rules = vec![constrained_rule(
+            "rule_head",
+            &[var("var0")],
+            &[pred("fact", &[var("var0"), var("var1")])],
+            &[Expression {
+                ops: vec![
+                    Op::Value(int(1)),
+                    Op::Value(int(2)),
+                    Op::Binary(Binary::LessThan),
+                ],
+            }],
+        )];
+
+        let expected_checks = vec![
+            Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    empty_terms,
+                    empty_preds,
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(int(1)),
+                            Op::Value(int(2)),
+                            Op::Binary(Binary::Equal),
+                        ],
+                    }],
+                )],
+            },
+            Check {
+                queries: vec![
+                    rule("query", empty_terms, &[pred("fact", &[int(5678)])]),
+                    constrained_rule(
+                        "query",
+                        empty_terms,
+                        &[pred("fact", &[int(1234)])],
+                        &[Expression {
+                            ops: vec![
+                                Op::Value(string("test")),
+                                Op::Value(string("abc")),
+                                Op::Binary(Binary::Prefix),
+                            ],
+                        }],
+                    ),
+                ],
+            },
+            Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    empty_terms,
+                    empty_preds,
+                    &[Expression {
+                        ops: vec![
+                            Op::Value(builder::date(
+                                &(SystemTime::UNIX_EPOCH + Duration::from_secs(1609459200)),
+                            )),
+                            Op::Value(builder::date(
+                                &(SystemTime::UNIX_EPOCH + Duration::from_secs(1609459200)),
+                            )),
+                            Op::Binary(Binary::LessOrEqual),
+                        ],
+                    }],
+                )],
+            },
+        ];
+
+        let (_remaining, mut result) = res.unwrap();
+        //assert_eq!(remaining, "\n");
+        assert_eq!(
+            result.facts.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_facts
+        );
+        assert_eq!(
+            result.rules.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_rules
+        );
+        assert_eq!(
+            result.checks.drain(..).map(|(_, r)| r).collect::<Vec<_>>(),
+            expected_checks
+        );
+    }
+
+    #[test]
+    fn block_context() {
+        let (_, result) = super::parse_block_source(
+            r#"
+              context "attenuated for service \"A\"";
+              right(#authority, "file1", #read);
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            result.context,
+            Some("attenuated for service \"A\"".to_string())
+        );
+        assert_eq!(result.facts.len(), 1);
+
+        assert!(super::parse_block_source(r#"context "a"; context "b";"#).is_err());
+        assert!(super::parse_block_source("context 12;").is_err());
+    }
+
+    #[test]
+    fn source_includes() {
+        let (_, result) = super::parse_source(
+            r#"
+              include "common.datalog";
+              include "roles.datalog";
+              allow if admin(#authority);
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            result
+                .includes
+                .iter()
+                .map(|(_, f)| f.as_str())
+                .collect::<Vec<_>>(),
+            vec!["common.datalog", "roles.datalog"]
+        );
+        assert_eq!(result.policies.len(), 1);
+
+        assert!(super::parse_source(r#"allow if true; include "common.datalog";"#).is_err());
+    }
+
+    #[test]
+    fn partial_source() {
+        let input = r#"
+          right(#authority, "file1", #read);
+          right(#authority, $0);
+          check if resource(#ambient, $0) and true;
+          check if operation(#ambient, #read);
+          allow if
+        "#;
+        let (result, errors) = super::parse_source_partial(input);
+        assert_eq!(result.facts.len(), 1);
+        assert_eq!(result.checks.len(), 1);
+        assert_eq!(result.checks[0].0, "check if operation(#ambient, #read)");
+        assert!(result.policies.is_empty());
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| e.message.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("variables are not allowed in facts"), None, None]
+        );
+        assert_eq!(errors[1].input, "and true");
+
+        let (result, errors) =
+            super::parse_block_source_partial(r#"context "a"; context "b"; check if true;"#);
+        assert_eq!(result.context.as_deref(), Some("a"));
+        assert_eq!(result.checks.len(), 1);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn hints() {
+        let hint = |e: nom::Err<Error>| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.hint(),
+            nom::Err::Incomplete(_) => None,
+        };
+
+        assert_eq!(
+            super::check("check if resource(#ambient, $0), $0 = \"file1\"").map_err(hint),
+            Err(Some("values are compared with `==`, not `=`".to_string()))
+        );
+        assert_eq!(
+            super::check("check if resource(#ambient, file)").map_err(hint),
+            Err(Some(
+                "variables start with `$`, did you mean `$file`?".to_string()
+            ))
+        );
+        assert_eq!(
+            super::fact("right(#authority, file)").map_err(hint),
+            Err(Some(
+                "did you mean the symbol `#file` or the string `\"file\"`?".to_string()
+            ))
+        );
+        assert_eq!(super::fact("right(#authority, )").map_err(hint), Err(None));
+    }
+
+    #[test]
+    fn deprecations() {
+        let (_, result) = super::parse_source(
+            r#"
+              *valid_date($0) <- time(#ambient, $0), $0 < 2030-01-01T00:00:00Z;
+              caveat if valid_date($0);
+              check if resource(#ambient, "file1");
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(result.rules.len(), 1);
+        assert_eq!(result.checks.len(), 2);
+        assert_eq!(
+            result.deprecations,
+            vec![
+                super::Deprecation {
+                    input: "*valid_date($0) <- time(#ambient, $0), $0 < 2030-01-01T00:00:00Z",
+                    message: "rule heads are not prefixed with `*` anymore".to_string(),
+                    replacement: "valid_date($0) <- time(#ambient, $0), $0 < 2030-01-01T00:00:00Z"
+                        .to_string(),
+                },
+                super::Deprecation {
+                    input: "caveat if valid_date($0)",
+                    message: "`caveat` was renamed to `check`".to_string(),
+                    replacement: "check if valid_date($0)".to_string(),
+                },
+            ]
+        );
+    }
+}
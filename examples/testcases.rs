@@ -220,7 +220,7 @@ fn validate_token(
             policies: policies.drain(..).map(|p| p.to_string()).collect(),
         }),
         match res {
-            Ok(i) => VerifierResult::Ok(i),
+            Ok(i) => VerifierResult::Ok(i.policy_id),
             Err(e) => {
                 if let error::Token::FailedLogic(error::Logic::FailedChecks(mut v)) = e {
                     VerifierResult::Err(v.drain(..).map(|e| format!("{:?}", e)).collect())
@@ -0,0 +1,386 @@
+//! actix-web integration
+//!
+//! [`BiscuitAuth`] reads the token from the `Authorization` header,
+//! checks it against the root public keys, then runs a verifier with the
+//! current time, prepared by the authorizer function of the route. Requests without a valid token are
+//! refused with 401, requests failing the authorization with 403, and the
+//! verified token is available to handlers with the [`BiscuitToken`]
+//! extractor:
+//!
+//! ```rust
+//! use actix_web::{web, App, HttpResponse};
+//! use biscuit_auth::{actix::{BiscuitAuth, BiscuitToken}, crypto::KeyPair};
+//!
+//! let root = KeyPair::new();
+//!
+//! let app = App::new().service(
+//!     web::resource("/files/{name}")
+//!         .wrap(BiscuitAuth::new(root.public()).authorizer(|req, verifier| {
+//!             verifier.add_resource(req.match_info().query("name"));
+//!             verifier.add_operation(req.method().as_str());
+//!             verifier.allow()
+//!         }))
+//!         .to(|token: BiscuitToken| async move {
+//!             HttpResponse::Ok().body(format!("{} blocks", token.block_count()))
+//!         }),
+//! );
+//! ```
+//!
+//! the extractor can also be used without the middleware, with a
+//! `BiscuitAuth` added to the application or resource data
+use crate::crypto::PublicKey;
+use crate::error;
+use crate::http;
+use crate::token::{verifier::Verifier, Biscuit};
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, StatusCode},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
+use std::{
+    fmt,
+    future::{ready, Future, Ready},
+    ops::Deref,
+    pin::Pin,
+    rc::Rc,
+};
+
+/// function adding the facts and policies of a route to the verifier
+pub type Authorizer = dyn Fn(&HttpRequest, &mut Verifier) -> Result<(), error::Token>;
+
+/// root keys and authorizer of a route
+///
+/// it is used as a middleware with `wrap`, or as application data for the
+/// [`BiscuitToken`] extractor
+#[derive(Clone)]
+pub struct BiscuitAuth {
+    root_keys: Vec<PublicKey>,
+    authorizer: Option<Rc<Authorizer>>,
+}
+
+impl BiscuitAuth {
+    pub fn new(root: PublicKey) -> Self {
+        BiscuitAuth::with_root_keys(vec![root])
+    }
+
+    /// accepts tokens issued by any of these keys, as an example during a
+    /// root key rotation
+    pub fn with_root_keys(root_keys: Vec<PublicKey>) -> Self {
+        BiscuitAuth {
+            root_keys,
+            authorizer: None,
+        }
+    }
+
+    /// sets the function preparing the verifier for each request
+    ///
+    /// the verifier already contains the current time. The authorizer
+    /// should add the facts describing the request and the policies.
+    /// Without an authorizer, the checks of the token are verified with
+    /// the `allow if true` policy
+    pub fn authorizer<F>(mut self, authorizer: F) -> Self
+    where
+        F: Fn(&HttpRequest, &mut Verifier) -> Result<(), error::Token> + 'static,
+    {
+        self.authorizer = Some(Rc::new(authorizer));
+        self
+    }
+
+    /// extracts the token from the request, verifies it, and runs the
+    /// authorizer
+    pub fn authorize(&self, req: &HttpRequest) -> Result<Biscuit, BiscuitError> {
+        let token = token_from_request(req)?;
+        let root = self
+            .root_keys
+            .iter()
+            .find(|root| token.check_root_key(**root).is_ok())
+            .ok_or(BiscuitError::InvalidToken(
+                error::Format::UnknownPublicKey.into(),
+            ))?;
+
+        let mut verifier = token.verify(*root).map_err(BiscuitError::InvalidToken)?;
+        verifier.set_time();
+        match self.authorizer.as_ref() {
+            Some(authorizer) => authorizer(req, &mut verifier),
+            None => verifier.allow(),
+        }
+        .map_err(BiscuitError::Authorizer)?;
+        verifier.verify().map_err(BiscuitError::Unauthorized)?;
+
+        Ok(token)
+    }
+}
+
+impl fmt::Debug for BiscuitAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BiscuitAuth")
+            .field("root_keys", &self.root_keys)
+            .field("authorizer", &self.authorizer.is_some())
+            .finish()
+    }
+}
+
+fn token_from_request(req: &HttpRequest) -> Result<Biscuit, BiscuitError> {
+    let value = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or(BiscuitError::MissingToken)?;
+
+    match http::parse_token(value.as_bytes(), http::MAX_HEADER_SIZE) {
+        Ok((_, token)) => Ok(token),
+        Err(http::HeaderError::Token(e)) => Err(BiscuitError::InvalidToken(e)),
+        Err(e) => Err(BiscuitError::InvalidHeader(e)),
+    }
+}
+
+/// error returned by the middleware and the extractor
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum BiscuitError {
+    /// there is no `Authorization` header
+    #[error("missing token")]
+    MissingToken,
+    /// the `Authorization` header could not be parsed
+    #[error("invalid header: {0}")]
+    InvalidHeader(http::HeaderError),
+    /// the token could not be deserialized, or its signature or root key
+    /// are invalid
+    #[error("invalid token: {0}")]
+    InvalidToken(error::Token),
+    /// the token failed the authorization
+    #[error("authorization failed: {0}")]
+    Unauthorized(error::Token),
+    /// the authorizer function returned an error
+    #[error("authorizer error: {0}")]
+    Authorizer(error::Token),
+    /// the extractor was used without the middleware or a `BiscuitAuth` in
+    /// the application data
+    #[error("no BiscuitAuth configured for this route")]
+    NotConfigured,
+}
+
+impl ResponseError for BiscuitError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BiscuitError::MissingToken
+            | BiscuitError::InvalidHeader(_)
+            | BiscuitError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            BiscuitError::Unauthorized(_) => StatusCode::FORBIDDEN,
+            BiscuitError::Authorizer(_) | BiscuitError::NotConfigured => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        // the details of the error stay on the server
+        match self {
+            BiscuitError::MissingToken
+            | BiscuitError::InvalidHeader(_)
+            | BiscuitError::Authorizer(_)
+            | BiscuitError::NotConfigured => response.finish(),
+            BiscuitError::InvalidToken(e) | BiscuitError::Unauthorized(e) => {
+                response.body(e.code())
+            }
+        }
+    }
+}
+
+/// token verified by [`BiscuitAuth`]
+#[derive(Clone, Debug)]
+pub struct BiscuitToken(pub Biscuit);
+
+impl BiscuitToken {
+    pub fn into_inner(self) -> Biscuit {
+        self.0
+    }
+}
+
+impl Deref for BiscuitToken {
+    type Target = Biscuit;
+
+    fn deref(&self) -> &Biscuit {
+        &self.0
+    }
+}
+
+impl FromRequest for BiscuitToken {
+    type Error = BiscuitError;
+    type Future = Ready<Result<Self, BiscuitError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // set by the middleware
+        if let Some(token) = req.extensions().get::<Biscuit>() {
+            return ready(Ok(BiscuitToken(token.clone())));
+        }
+
+        ready(match req.app_data::<BiscuitAuth>() {
+            Some(auth) => auth.authorize(req).map(BiscuitToken),
+            None => Err(BiscuitError::NotConfigured),
+        })
+    }
+}
+
+impl<S, B: 'static> Transform<S, ServiceRequest> for BiscuitAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = BiscuitAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BiscuitAuthMiddleware {
+            service,
+            auth: self.clone(),
+        }))
+    }
+}
+
+/// service created by the [`BiscuitAuth`] middleware
+pub struct BiscuitAuthMiddleware<S> {
+    service: S,
+    auth: BiscuitAuth,
+}
+
+impl<S, B: 'static> Service<ServiceRequest> for BiscuitAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, actix_web::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match self.auth.authorize(req.request()) {
+            Ok(token) => {
+                req.extensions_mut().insert(token);
+                Box::pin(self.service.call(req))
+            }
+            Err(e) => Box::pin(ready(Err(e.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use actix_web::{rt::System, test, web, App};
+
+    #[test]
+    fn middleware() {
+        System::new().block_on(async {
+            let root = KeyPair::new();
+            let mut builder = Biscuit::builder(&root);
+            builder.add_right("/files/a", "GET");
+            let token = builder.build().unwrap().to_base64().unwrap();
+            let other = Biscuit::builder(&KeyPair::new())
+                .build()
+                .unwrap()
+                .to_base64()
+                .unwrap();
+
+            let app = test::init_service(
+                App::new().service(
+                    web::resource("/files/{name}")
+                        .wrap(BiscuitAuth::new(root.public()).authorizer(|req, verifier| {
+                            verifier.add_resource(&format!(
+                                "/files/{}",
+                                req.match_info().query("name")
+                            ));
+                            verifier.add_operation(req.method().as_str());
+                            verifier.add_policy("allow if right(#authority, $r, $o), resource(#ambient, $r), operation(#ambient, $o)")
+                        }))
+                        .to(|token: BiscuitToken| async move {
+                            HttpResponse::Ok().body(token.block_count().to_string())
+                        }),
+                ),
+            )
+            .await;
+
+            let status = |path: &str, token: Option<&str>| {
+                let mut req = test::TestRequest::get().uri(path);
+                if let Some(token) = token {
+                    req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+                }
+                let req = req.to_request();
+                let app = &app;
+                async move {
+                    match app.call(req).await {
+                        Ok(res) => res.status(),
+                        Err(e) => e.as_response_error().status_code(),
+                    }
+                }
+            };
+
+            assert_eq!(status("/files/a", Some(&token)).await, StatusCode::OK);
+            assert_eq!(
+                status("/files/b", Some(&token)).await,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                status("/files/a", Some(&other)).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                status("/files/a", Some("not a token")).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(status("/files/a", None).await, StatusCode::UNAUTHORIZED);
+        });
+    }
+
+    #[test]
+    fn extractor() {
+        System::new().block_on(async {
+            let root = KeyPair::new();
+            let token = Biscuit::builder(&root).build().unwrap();
+
+            let req = test::TestRequest::default()
+                .insert_header((
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", token.to_base64().unwrap()),
+                ))
+                .app_data(BiscuitAuth::new(root.public()))
+                .to_http_request();
+            let extracted = BiscuitToken::extract(&req).await.unwrap();
+            assert_eq!(extracted.to_vec(), token.to_vec());
+
+            let req = test::TestRequest::default()
+                .insert_header((
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", token.to_base64().unwrap()),
+                ))
+                .to_http_request();
+            assert_eq!(
+                BiscuitToken::extract(&req).await.err(),
+                Some(BiscuitError::NotConfigured)
+            );
+
+            // the checks of the token are verified without an authorizer
+            let mut block = token.create_block();
+            block.expiration_date(std::time::SystemTime::UNIX_EPOCH);
+            let expired = token.append(&KeyPair::new(), block).unwrap();
+            let req = test::TestRequest::default()
+                .insert_header((
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", expired.to_base64().unwrap()),
+                ))
+                .app_data(BiscuitAuth::new(root.public()))
+                .to_http_request();
+            assert!(matches!(
+                BiscuitToken::extract(&req).await,
+                Err(BiscuitError::Unauthorized(_))
+            ));
+        });
+    }
+}
@@ -0,0 +1,281 @@
+//! [`Arbitrary`] implementations, generating Datalog and tokens from fuzzer input
+//!
+//! the names of predicates, symbols and variables are drawn from small sets,
+//! so the generated facts, rules and checks often match each other. Rules
+//! only use variables bound by their body, and facts do not contain
+//! variables, so the generated Datalog is accepted by the builders.
+//!
+//! [`token`] mints a validly signed token for a provided root key, while the
+//! [`Biscuit`] implementation derives the root key from the input: it is
+//! then the first of [`Biscuit::root_keys`].
+//!
+//! ```rust
+//! use arbitrary::Unstructured;
+//! use biscuit_auth::crypto::KeyPair;
+//!
+//! let root = KeyPair::new();
+//! let data = [7u8; 512];
+//! let token = biscuit_auth::arbitrary::token(&mut Unstructured::new(&data), &root).unwrap();
+//!
+//! // the policies under test
+//! let mut verifier = token.verify(root.public()).unwrap();
+//! verifier.add_policy("deny if user(#authority, \"admin\")").unwrap();
+//! let _ = verifier.verify();
+//! ```
+use crate::crypto::KeyPair;
+use crate::token::builder::{
+    s, var, Binary, BlockBuilder, Check, Expression, Fact, Op, Predicate, Rule, Term,
+};
+use crate::token::Biscuit;
+use ::arbitrary::{Arbitrary, Error, Result, Unstructured};
+use rand::{rngs::StdRng, SeedableRng};
+use std::collections::BTreeSet;
+
+const PREDICATES: &[&str] = &["right", "resource", "operation", "user", "owner"];
+const SYMBOLS: &[&str] = &["read", "write", "admin", "file1", "file2"];
+const VARIABLES: &[&str] = &["x", "y", "z"];
+const BINARY: &[Binary] = &[
+    Binary::LessThan,
+    Binary::GreaterThan,
+    Binary::LessOrEqual,
+    Binary::GreaterOrEqual,
+    Binary::Equal,
+    Binary::Contains,
+    Binary::Prefix,
+    Binary::Suffix,
+    Binary::Regex,
+    Binary::Add,
+    Binary::Sub,
+    Binary::Mul,
+    Binary::Div,
+    Binary::And,
+    Binary::Or,
+    Binary::Intersection,
+    Binary::Union,
+];
+/// 2100-01-01T00:00:00Z, dates stay printable
+const MAX_DATE: u64 = 4_102_444_800;
+
+impl<'a> Arbitrary<'a> for Term {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.ratio(1, 4)? {
+            Ok(var(u.choose(VARIABLES)?))
+        } else {
+            constant(u)
+        }
+    }
+}
+
+impl<'a> Arbitrary<'a> for Predicate {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        predicate(u, Term::arbitrary)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Fact {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        predicate(u, constant).map(Fact)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Rule {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut rule = query(u, 1)?;
+        rule.0.name = u.choose(PREDICATES)?.to_string();
+        Ok(rule)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Check {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let queries = (0..u.int_in_range(1..=2)?)
+            .map(|_| query(u, 0))
+            .collect::<Result<_>>()?;
+        Ok(Check { queries })
+    }
+}
+
+impl<'a> Arbitrary<'a> for BlockBuilder {
+    /// block with the index 1, to be set when appending it
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut block = BlockBuilder::new(1);
+        block.facts = items(u, 4)?;
+        block.rules = items(u, 2)?;
+        block.checks = items(u, 2)?;
+        block.context = u.arbitrary()?;
+        Ok(block)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Biscuit {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut rng = StdRng::from_seed(u.arbitrary()?);
+        let root = KeyPair::new_with_rng(&mut rng);
+        token_with_rng(u, &mut rng, &root)
+    }
+}
+
+/// token signed by `root`, with up to 3 attenuation blocks
+///
+/// the facts and rule heads of the authority block start with `#authority`
+pub fn token(u: &mut Unstructured<'_>, root: &KeyPair) -> Result<Biscuit> {
+    let mut rng = StdRng::from_seed(u.arbitrary()?);
+    token_with_rng(u, &mut rng, root)
+}
+
+fn token_with_rng(u: &mut Unstructured<'_>, rng: &mut StdRng, root: &KeyPair) -> Result<Biscuit> {
+    let mut builder = Biscuit::builder(root);
+    for mut fact in items::<Fact>(u, 4)? {
+        fact.0.ids.insert(0, s("authority"));
+        builder
+            .add_authority_fact(fact)
+            .map_err(|_| Error::IncorrectFormat)?;
+    }
+    for mut rule in items::<Rule>(u, 2)? {
+        rule.0.ids.insert(0, s("authority"));
+        builder
+            .add_authority_rule(rule)
+            .map_err(|_| Error::IncorrectFormat)?;
+    }
+    for check in items::<Check>(u, 2)? {
+        builder
+            .add_authority_check(check)
+            .map_err(|_| Error::IncorrectFormat)?;
+    }
+    if let Some(context) = u.arbitrary()? {
+        builder.set_context(context);
+    }
+    let mut token = builder
+        .build_with_rng(rng)
+        .map_err(|_| Error::IncorrectFormat)?;
+
+    for _ in 0..u.int_in_range(0..=3)? {
+        let mut block = BlockBuilder::arbitrary(u)?;
+        block.index = token.create_block().index;
+        let keypair = KeyPair::new_with_rng(rng);
+        token = token
+            .append_with_rng(rng, &keypair, block)
+            .map_err(|_| Error::IncorrectFormat)?;
+    }
+    Ok(token)
+}
+
+fn items<'a, T: Arbitrary<'a>>(u: &mut Unstructured<'a>, max: usize) -> Result<Vec<T>> {
+    (0..u.int_in_range(0..=max)?)
+        .map(|_| T::arbitrary(u))
+        .collect()
+}
+
+/// term without variables
+fn constant(u: &mut Unstructured<'_>) -> Result<Term> {
+    Ok(match u.int_in_range(0..=6u8)? {
+        0 => Term::Symbol(u.choose(SYMBOLS)?.to_string()),
+        1 => Term::Integer(u.arbitrary()?),
+        2 => Term::Str(u.arbitrary()?),
+        3 => Term::Date(u.int_in_range(0..=MAX_DATE)?),
+        4 => Term::Bytes(u.arbitrary()?),
+        5 => Term::Bool(u.arbitrary()?),
+        // sets contain terms of the same type, without variables or sets
+        _ => {
+            let len = u.int_in_range(0..=3)?;
+            let set = if u.arbitrary()? {
+                (0..len)
+                    .map(|_| u.arbitrary().map(Term::Integer))
+                    .collect::<Result<BTreeSet<_>>>()?
+            } else {
+                (0..len)
+                    .map(|_| u.arbitrary().map(Term::Str))
+                    .collect::<Result<BTreeSet<_>>>()?
+            };
+            Term::Set(set)
+        }
+    })
+}
+
+fn predicate<'a>(
+    u: &mut Unstructured<'a>,
+    term: impl Fn(&mut Unstructured<'a>) -> Result<Term>,
+) -> Result<Predicate> {
+    let name = u.choose(PREDICATES)?.to_string();
+    let ids = (0..u.int_in_range(1..=3)?)
+        .map(|_| term(u))
+        .collect::<Result<_>>()?;
+    Ok(Predicate { name, ids })
+}
+
+/// rule whose head and expressions only use the variables of its body, with
+/// at least `min_ids` terms in its head
+fn query(u: &mut Unstructured<'_>, min_ids: usize) -> Result<Rule> {
+    let body = (0..u.int_in_range(1..=3)?)
+        .map(|_| Predicate::arbitrary(u))
+        .collect::<Result<Vec<_>>>()?;
+    let mut variables = body
+        .iter()
+        .flat_map(|p| p.ids.iter())
+        .filter_map(|term| match term {
+            Term::Variable(v) => Some(v.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    variables.sort();
+    variables.dedup();
+
+    let bound = |u: &mut Unstructured<'_>| -> Result<Term> {
+        if !variables.is_empty() && u.arbitrary()? {
+            Ok(var(u.choose(&variables)?))
+        } else {
+            constant(u)
+        }
+    };
+
+    let ids = (0..u.int_in_range(min_ids..=3)?)
+        .map(|_| bound(u))
+        .collect::<Result<_>>()?;
+    let expressions = (0..u.int_in_range(0..=2)?)
+        .map(|_| {
+            Ok(Expression {
+                ops: vec![
+                    Op::Value(bound(u)?),
+                    Op::Value(constant(u)?),
+                    Op::Binary(u.choose(BINARY)?.clone()),
+                ],
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Rule(
+        Predicate {
+            name: "query".to_string(),
+            ids,
+        },
+        body,
+        expressions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[test]
+    fn tokens() {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let root = KeyPair::new_with_rng(&mut rng);
+        for _ in 0..50 {
+            let mut data = vec![0u8; 2048];
+            rng.fill_bytes(&mut data);
+
+            let token = token(&mut Unstructured::new(&data), &root).unwrap();
+            let token = Biscuit::from(&token.to_vec().unwrap()).unwrap();
+            let mut verifier = token.verify(root.public()).unwrap();
+            let _ = verifier.verify();
+
+            let token = Biscuit::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            assert!(token.verify(token.root_keys()[0]).is_ok());
+
+            let rule = Rule::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            assert!(rule.validate_variables().is_ok());
+        }
+    }
+}
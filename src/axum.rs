@@ -0,0 +1,463 @@
+//! axum integration
+//!
+//! [`BiscuitLayer`] reads the token from the `Authorization` header,
+//! checks it against the root public keys, and prepares a verifier with the
+//! current time and the facts of the request, added by hooks. Requests
+//! without a valid token are refused with 401.
+//!
+//! With [`BiscuitLayer::authorize`], the verifier runs in the layer and
+//! requests failing the authorization are refused with 403. Otherwise the
+//! layer only verifies the checks of the token, refusing the requests that
+//! fail them with 403, and the handlers get the prepared verifier with the
+//! [`BiscuitVerifier`] extractor, to add their own facts and policies. In
+//! both cases, the token is available with the [`BiscuitToken`] extractor:
+//!
+//! ```rust
+//! use axum::{routing::get, Router};
+//! use biscuit_auth::{axum::{BiscuitLayer, BiscuitToken}, crypto::KeyPair};
+//!
+//! let root = KeyPair::new();
+//!
+//! let app: Router = Router::new()
+//!     .route(
+//!         "/files/{name}",
+//!         get(|token: BiscuitToken| async move { format!("{} blocks", token.block_count()) }),
+//!     )
+//!     .layer(
+//!         BiscuitLayer::new(root.public())
+//!             .operation_from_method()
+//!             .resource_from_path()
+//!             .hook(|_parts, verifier| {
+//!                 verifier.add_policy("allow if right(#authority, $r, $o), resource(#ambient, $r), operation(#ambient, $o)")
+//!             })
+//!             .authorize(),
+//!     );
+//! ```
+use crate::crypto::PublicKey;
+use crate::error;
+use crate::http;
+use crate::token::{verifier::Verifier, Biscuit};
+use ::axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{
+    fmt,
+    future::{ready, Future},
+    ops::Deref,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// function adding facts or policies to the verifier of a request
+pub type Hook = dyn Fn(&Parts, &mut Verifier) -> Result<(), error::Token> + Send + Sync;
+
+/// layer verifying the token of each request
+#[derive(Clone)]
+pub struct BiscuitLayer {
+    root_keys: Vec<PublicKey>,
+    hooks: Vec<Arc<Hook>>,
+    authorize: bool,
+}
+
+impl BiscuitLayer {
+    pub fn new(root: PublicKey) -> Self {
+        BiscuitLayer::with_root_keys(vec![root])
+    }
+
+    /// accepts tokens issued by any of these keys, as an example during a
+    /// root key rotation
+    pub fn with_root_keys(root_keys: Vec<PublicKey>) -> Self {
+        BiscuitLayer {
+            root_keys,
+            hooks: Vec::new(),
+            authorize: false,
+        }
+    }
+
+    /// adds a function preparing the verifier, called for each request in
+    /// the order the hooks were added
+    pub fn hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Parts, &mut Verifier) -> Result<(), error::Token> + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// adds `operation(#ambient, #method)`, with the method of the request
+    /// in lowercase, like `#get`
+    pub fn operation_from_method(self) -> Self {
+        self.hook(|parts, verifier| {
+            verifier.add_operation(&parts.method.as_str().to_lowercase());
+            Ok(())
+        })
+    }
+
+    /// adds `resource(#ambient, "path")`, with the path of the request
+    pub fn resource_from_path(self) -> Self {
+        self.hook(|parts, verifier| {
+            verifier.add_resource(parts.uri.path());
+            Ok(())
+        })
+    }
+
+    /// runs the verifier in the layer, after the hooks
+    ///
+    /// the hooks must then add the policies. Without it, the layer runs the
+    /// checks of the token with the `allow if true` policy, and the
+    /// handlers must run the [`BiscuitVerifier`] with their own policies
+    pub fn authorize(mut self) -> Self {
+        self.authorize = true;
+        self
+    }
+
+    /// verifies the token of the request, and runs the hooks
+    ///
+    /// the verifier is returned if [`BiscuitLayer::authorize`] was not set,
+    /// the checks of the token passed but it was not run
+    pub fn verify(&self, parts: &Parts) -> Result<(Biscuit, Option<Verifier>), BiscuitRejection> {
+        let token = token_from_request(parts)?;
+        let root = self
+            .root_keys
+            .iter()
+            .find(|root| token.check_root_key(**root).is_ok())
+            .ok_or(BiscuitRejection::InvalidToken(
+                error::Format::UnknownPublicKey.into(),
+            ))?;
+
+        let mut verifier = token
+            .verify(*root)
+            .map_err(BiscuitRejection::InvalidToken)?;
+        verifier.set_time();
+        for hook in self.hooks.iter() {
+            hook(parts, &mut verifier).map_err(BiscuitRejection::Hook)?;
+        }
+
+        if self.authorize {
+            verifier.verify().map_err(BiscuitRejection::Unauthorized)?;
+            Ok((token, None))
+        } else {
+            // the handler adds its policies to a verifier that was not run
+            let mut checks = verifier.clone();
+            checks.allow().map_err(BiscuitRejection::Hook)?;
+            checks.verify().map_err(BiscuitRejection::Unauthorized)?;
+            Ok((token, Some(verifier)))
+        }
+    }
+}
+
+impl fmt::Debug for BiscuitLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BiscuitLayer")
+            .field("root_keys", &self.root_keys)
+            .field("hooks", &self.hooks.len())
+            .field("authorize", &self.authorize)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for BiscuitLayer {
+    type Service = BiscuitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BiscuitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+fn token_from_request(parts: &Parts) -> Result<Biscuit, BiscuitRejection> {
+    let value = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .ok_or(BiscuitRejection::MissingToken)?;
+
+    match http::parse_token(value.as_bytes(), http::MAX_HEADER_SIZE) {
+        Ok((_, token)) => Ok(token),
+        Err(http::HeaderError::Token(e)) => Err(BiscuitRejection::InvalidToken(e)),
+        Err(e) => Err(BiscuitRejection::InvalidHeader(e)),
+    }
+}
+
+/// service created by [`BiscuitLayer`]
+#[derive(Clone, Debug)]
+pub struct BiscuitService<S> {
+    inner: S,
+    layer: BiscuitLayer,
+}
+
+impl<S, B> Service<Request<B>> for BiscuitService<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        match self.layer.verify(&parts) {
+            Ok((token, verifier)) => {
+                parts.extensions.insert(token);
+                if let Some(verifier) = verifier {
+                    parts.extensions.insert(verifier);
+                }
+                Box::pin(self.inner.call(Request::from_parts(parts, body)))
+            }
+            Err(e) => Box::pin(ready(Ok(e.into_response()))),
+        }
+    }
+}
+
+/// error returned by the layer and the extractors
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum BiscuitRejection {
+    /// there is no `Authorization` header
+    #[error("missing token")]
+    MissingToken,
+    /// the `Authorization` header could not be parsed
+    #[error("invalid header: {0}")]
+    InvalidHeader(http::HeaderError),
+    /// the token could not be deserialized, or its signature or root key
+    /// are invalid
+    #[error("invalid token: {0}")]
+    InvalidToken(error::Token),
+    /// the token failed the authorization
+    #[error("authorization failed: {0}")]
+    Unauthorized(error::Token),
+    /// a hook returned an error
+    #[error("hook error: {0}")]
+    Hook(error::Token),
+    /// the route is not behind a [`BiscuitLayer`], or the verifier was
+    /// already run by the layer
+    #[error("missing BiscuitLayer")]
+    MissingLayer,
+}
+
+impl IntoResponse for BiscuitRejection {
+    fn into_response(self) -> Response {
+        match self {
+            BiscuitRejection::MissingToken | BiscuitRejection::InvalidHeader(_) => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+            )
+                .into_response(),
+            // only the error code is sent, the details stay on the server
+            BiscuitRejection::InvalidToken(e) => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                e.code(),
+            )
+                .into_response(),
+            BiscuitRejection::Unauthorized(e) => (StatusCode::FORBIDDEN, e.code()).into_response(),
+            BiscuitRejection::Hook(_) | BiscuitRejection::MissingLayer => {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// token verified by [`BiscuitLayer`]
+#[derive(Clone, Debug)]
+pub struct BiscuitToken(pub Biscuit);
+
+impl BiscuitToken {
+    pub fn into_inner(self) -> Biscuit {
+        self.0
+    }
+}
+
+impl Deref for BiscuitToken {
+    type Target = Biscuit;
+
+    fn deref(&self) -> &Biscuit {
+        &self.0
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for BiscuitToken {
+    type Rejection = BiscuitRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Biscuit>()
+            .cloned()
+            .map(BiscuitToken)
+            .ok_or(BiscuitRejection::MissingLayer)
+    }
+}
+
+/// verifier prepared by the hooks of [`BiscuitLayer`], when it does not
+/// authorize the requests itself
+///
+/// only the checks of the token were verified by the layer: the handler
+/// must add its policies and call [`Verifier::verify`]
+pub struct BiscuitVerifier(pub Verifier);
+
+impl BiscuitVerifier {
+    pub fn into_inner(self) -> Verifier {
+        self.0
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for BiscuitVerifier {
+    type Rejection = BiscuitRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .remove::<Verifier>()
+            .map(BiscuitVerifier)
+            .ok_or(BiscuitRejection::MissingLayer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use ::axum::body::Body;
+    use std::future::Ready;
+
+    /// the futures of these tests are ready on the first poll
+    fn now<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(std::task::Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future is not ready"),
+        }
+    }
+
+    /// answers with the number of blocks of the token
+    #[derive(Clone)]
+    struct Handler;
+
+    impl Service<Request<Body>> for Handler {
+        type Response = Response;
+        type Error = std::convert::Infallible;
+        type Future = Ready<Result<Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<Body>) -> Self::Future {
+            let (mut parts, _) = req.into_parts();
+            let token = now(BiscuitToken::from_request_parts(&mut parts, &())).unwrap();
+            let verifier = now(BiscuitVerifier::from_request_parts(&mut parts, &()));
+            ready(Ok((
+                [("x-verifier", verifier.is_ok().to_string())],
+                token.block_count().to_string(),
+            )
+                .into_response()))
+        }
+    }
+
+    fn request(path: &str, token: Option<&str>) -> Request<Body> {
+        let mut req = Request::get(path);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn layer() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/files/a", "get");
+        let token = builder.build().unwrap().to_base64().unwrap();
+        let other = Biscuit::builder(&KeyPair::new())
+            .build()
+            .unwrap()
+            .to_base64()
+            .unwrap();
+
+        let mut service = BiscuitLayer::new(root.public())
+            .operation_from_method()
+            .resource_from_path()
+            .hook(|_parts, verifier| {
+                verifier.add_policy("allow if right(#authority, $r, $o), resource(#ambient, $r), operation(#ambient, $o)")
+            })
+            .authorize()
+            .layer(Handler);
+        let mut status = |req| now(service.call(req)).unwrap().status();
+
+        assert_eq!(status(request("/files/a", Some(&token))), StatusCode::OK);
+        assert_eq!(
+            status(request("/files/b", Some(&token))),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(request("/files/a", Some(&other))),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(request("/files/a", Some("not a token"))),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(request("/files/a", None)), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn verifier_extractor() {
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root)
+            .build()
+            .unwrap()
+            .to_base64()
+            .unwrap();
+
+        let mut service = BiscuitLayer::new(root.public())
+            .operation_from_method()
+            .layer(Handler);
+        let response = now(service.call(request("/", Some(&token)))).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-verifier"], "true");
+
+        let (mut parts, _) = request("/", Some(&token)).into_parts();
+        let (_, verifier) = BiscuitLayer::new(root.public())
+            .operation_from_method()
+            .verify(&parts)
+            .unwrap();
+        let mut verifier = verifier.unwrap();
+        verifier
+            .add_policy("allow if operation(#ambient, #get)")
+            .unwrap();
+        assert!(verifier.verify().is_ok());
+
+        assert_eq!(
+            now(BiscuitToken::from_request_parts(&mut parts, &())).err(),
+            Some(BiscuitRejection::MissingLayer)
+        );
+
+        // the checks of the token are verified before the handler
+        let token = Biscuit::from_base64(&token).unwrap();
+        let mut block = token.create_block();
+        block.expiration_date(std::time::SystemTime::UNIX_EPOCH);
+        let expired = token
+            .append(&KeyPair::new(), block)
+            .unwrap()
+            .to_base64()
+            .unwrap();
+        let response = now(service.call(request("/", Some(&expired)))).unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -50,6 +50,30 @@ pub extern "C" fn error_message() -> *const c_char {
     })
 }
 
+/// stable identifier of the last error, see [`crate::error::Token::code`]
+#[no_mangle]
+pub extern "C" fn error_code() -> *const c_char {
+    thread_local! {
+        static LAST: RefCell<Option<CString>> = RefCell::new(None);
+    }
+    LAST_ERROR.with(|prev| match *prev.borrow() {
+        Some(ref err) => {
+            let code = match err {
+                Error::InvalidArgument => "invalid_argument",
+                Error::Biscuit(e) => e.code(),
+            };
+            LAST.with(|ret| {
+                *ret.borrow_mut() = CString::new(code).ok();
+                ret.borrow()
+                    .as_ref()
+                    .map(|x| x.as_ptr())
+                    .unwrap_or(std::ptr::null())
+            })
+        }
+        None => std::ptr::null(),
+    })
+}
+
 #[repr(C)]
 pub enum ErrorKind {
     None,
@@ -83,6 +107,18 @@ pub enum ErrorKind {
     TooManyIterations,
     Timeout,
     ConversionError,
+    LoadError,
+    FormatTermTooLarge,
+    ExpressionDepth,
+    Revoked,
+    FormatTooManyBlocks,
+    FormatRootKeyThreshold,
+    FormatInvalidDelegation,
+    FormatInvalidRevocationList,
+    FormatExpiredRevocationList,
+    LogicForbiddenNamespace,
+    FormatOutdatedRevocationList,
+    FormatUnknownSealKey,
 }
 
 #[no_mangle]
@@ -94,13 +130,14 @@ pub extern "C" fn error_kind() -> ErrorKind {
                 use crate::error::*;
                 match e {
                     Token::InternalError => ErrorKind::InternalError,
-                    Token::Format(Format::Signature(Signature::InvalidFormat)) => {
-                        ErrorKind::FormatSignatureInvalidFormat
-                    }
                     Token::Format(Format::Signature(Signature::InvalidSignature)) => {
                         ErrorKind::FormatSignatureInvalidSignature
                     }
-                    Token::Format(Format::SealedSignature) => ErrorKind::FormatSealedSignature,
+                    // the detailed kind is available from `error_code`
+                    Token::Format(Format::Signature(_)) => ErrorKind::FormatSignatureInvalidFormat,
+                    Token::Format(Format::SealedSignature { .. }) => {
+                        ErrorKind::FormatSealedSignature
+                    }
                     Token::Format(Format::EmptyKeys) => ErrorKind::FormatEmptyKeys,
                     Token::Format(Format::UnknownPublicKey) => ErrorKind::FormatUnknownPublickKey,
                     Token::Format(Format::DeserializationError(_)) => {
@@ -116,12 +153,28 @@ pub extern "C" fn error_kind() -> ErrorKind {
                         ErrorKind::FormatBlockSerializationError
                     }
                     Token::Format(Format::Version { .. }) => ErrorKind::FormatVersion,
+                    Token::Format(Format::TermTooLarge { .. }) => ErrorKind::FormatTermTooLarge,
+                    Token::Format(Format::TooManyBlocks { .. }) => ErrorKind::FormatTooManyBlocks,
+                    Token::Format(Format::RootKeyThreshold { .. }) => {
+                        ErrorKind::FormatRootKeyThreshold
+                    }
+                    Token::Format(Format::InvalidDelegation) => ErrorKind::FormatInvalidDelegation,
+                    Token::Format(Format::InvalidRevocationList) => {
+                        ErrorKind::FormatInvalidRevocationList
+                    }
+                    Token::Format(Format::ExpiredRevocationList) => {
+                        ErrorKind::FormatExpiredRevocationList
+                    }
+                    Token::Format(Format::OutdatedRevocationList { .. }) => {
+                        ErrorKind::FormatOutdatedRevocationList
+                    }
+                    Token::Format(Format::UnknownSealKey { .. }) => ErrorKind::FormatUnknownSealKey,
                     Token::InvalidAuthorityIndex(_) => ErrorKind::InvalidAuthorityIndex,
                     Token::InvalidBlockIndex(_) => ErrorKind::InvalidBlockIndex,
                     Token::SymbolTableOverlap => ErrorKind::SymbolTableOverlap,
                     Token::MissingSymbols => ErrorKind::MissingSymbols,
                     Token::Sealed => ErrorKind::Sealed,
-                    Token::ParseError => ErrorKind::ParseError,
+                    Token::ParseError(_) => ErrorKind::ParseError,
                     Token::FailedLogic(Logic::InvalidAuthorityFact(_)) => {
                         ErrorKind::LogicInvalidAuthorityFact
                     }
@@ -136,13 +189,19 @@ pub extern "C" fn error_kind() -> ErrorKind {
                     }
                     Token::FailedLogic(Logic::FailedChecks(_)) => ErrorKind::LogicFailedChecks,
                     Token::FailedLogic(Logic::VerifierNotEmpty) => ErrorKind::LogicVerifierNotEmpty,
-                    Token::FailedLogic(Logic::Deny(_)) => ErrorKind::LogicDeny,
+                    Token::FailedLogic(Logic::Deny { .. }) => ErrorKind::LogicDeny,
                     Token::FailedLogic(Logic::NoMatchingPolicy) => ErrorKind::LogicNoMatchingPolicy,
+                    Token::FailedLogic(Logic::ForbiddenNamespace(_, _)) => {
+                        ErrorKind::LogicForbiddenNamespace
+                    }
                     Token::RunLimit(RunLimit::TooManyFacts) => ErrorKind::TooManyFacts,
                     Token::RunLimit(RunLimit::TooManyIterations) => ErrorKind::TooManyIterations,
                     Token::RunLimit(RunLimit::Timeout) => ErrorKind::Timeout,
+                    Token::RunLimit(RunLimit::ExpressionDepth) => ErrorKind::ExpressionDepth,
                     Token::ConversionError(_) => ErrorKind::ConversionError,
                     Token::Base64(_) => ErrorKind::FormatDeserializationError,
+                    Token::LoadError(_) => ErrorKind::LoadError,
+                    Token::Revoked(_) => ErrorKind::Revoked,
                 }
             }
         },
@@ -662,16 +721,24 @@ pub unsafe extern "C" fn biscuit_block_fact_count(
 
     let biscuit = biscuit.unwrap();
 
-    if block_index == 0 {
-        biscuit.0.authority.facts.len()
+    let block = if block_index == 0 {
+        &biscuit.0.authority
     } else {
         match biscuit.0.blocks.get(block_index as usize - 1) {
-            Some(b) => b.facts.len(),
+            Some(b) => b,
             None => {
                 update_last_error(Error::InvalidArgument);
                 return 0;
             }
         }
+    };
+
+    match block.get() {
+        Ok(block) => block.facts.len(),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            0
+        }
     }
 }
 
@@ -687,16 +754,24 @@ pub unsafe extern "C" fn biscuit_block_rule_count(
 
     let biscuit = biscuit.unwrap();
 
-    if block_index == 0 {
-        biscuit.0.authority.rules.len()
+    let block = if block_index == 0 {
+        &biscuit.0.authority
     } else {
         match biscuit.0.blocks.get(block_index as usize - 1) {
-            Some(b) => b.rules.len(),
+            Some(b) => b,
             None => {
                 update_last_error(Error::InvalidArgument);
                 return 0;
             }
         }
+    };
+
+    match block.get() {
+        Ok(block) => block.rules.len(),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            0
+        }
     }
 }
 
@@ -712,16 +787,24 @@ pub unsafe extern "C" fn biscuit_block_check_count(
 
     let biscuit = biscuit.unwrap();
 
-    if block_index == 0 {
-        biscuit.0.authority.checks.len()
+    let block = if block_index == 0 {
+        &biscuit.0.authority
     } else {
         match biscuit.0.blocks.get(block_index as usize - 1) {
-            Some(b) => b.checks.len(),
+            Some(b) => b,
             None => {
                 update_last_error(Error::InvalidArgument);
                 return 0;
             }
         }
+    };
+
+    match block.get() {
+        Ok(block) => block.checks.len(),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            0
+        }
     }
 }
 
@@ -750,16 +833,30 @@ pub unsafe extern "C" fn biscuit_block_fact(
         }
     };
 
+    let block = match block.get() {
+        Ok(block) => block,
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            return std::ptr::null_mut();
+        }
+    };
+
     match block.facts.get(fact_index as usize) {
         None => {
             update_last_error(Error::InvalidArgument);
             return std::ptr::null_mut();
         }
-        Some(fact) => match CString::new(biscuit.0.symbols.print_fact(fact)) {
-            Ok(s) => s.into_raw(),
-            Err(_) => {
-                update_last_error(Error::InvalidArgument);
-                return std::ptr::null_mut();
+        Some(fact) => match biscuit.0.symbols() {
+            Ok(symbols) => match CString::new(symbols.print_fact(fact)) {
+                Ok(s) => s.into_raw(),
+                Err(_) => {
+                    update_last_error(Error::InvalidArgument);
+                    std::ptr::null_mut()
+                }
+            },
+            Err(e) => {
+                update_last_error(Error::Biscuit(e));
+                std::ptr::null_mut()
             }
         },
     }
@@ -790,16 +887,30 @@ pub unsafe extern "C" fn biscuit_block_rule(
         }
     };
 
+    let block = match block.get() {
+        Ok(block) => block,
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            return std::ptr::null_mut();
+        }
+    };
+
     match block.rules.get(rule_index as usize) {
         None => {
             update_last_error(Error::InvalidArgument);
             return std::ptr::null_mut();
         }
-        Some(rule) => match CString::new(biscuit.0.symbols.print_rule(rule)) {
-            Ok(s) => s.into_raw(),
-            Err(_) => {
-                update_last_error(Error::InvalidArgument);
-                return std::ptr::null_mut();
+        Some(rule) => match biscuit.0.symbols() {
+            Ok(symbols) => match CString::new(symbols.print_rule(rule)) {
+                Ok(s) => s.into_raw(),
+                Err(_) => {
+                    update_last_error(Error::InvalidArgument);
+                    std::ptr::null_mut()
+                }
+            },
+            Err(e) => {
+                update_last_error(Error::Biscuit(e));
+                std::ptr::null_mut()
             }
         },
     }
@@ -830,16 +941,30 @@ pub unsafe extern "C" fn biscuit_block_check(
         }
     };
 
+    let block = match block.get() {
+        Ok(block) => block,
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            return std::ptr::null_mut();
+        }
+    };
+
     match block.checks.get(check_index as usize) {
         None => {
             update_last_error(Error::InvalidArgument);
             return std::ptr::null_mut();
         }
-        Some(check) => match CString::new(biscuit.0.symbols.print_check(check)) {
-            Ok(s) => s.into_raw(),
-            Err(_) => {
-                update_last_error(Error::InvalidArgument);
-                return std::ptr::null_mut();
+        Some(check) => match biscuit.0.symbols() {
+            Ok(symbols) => match CString::new(symbols.print_check(check)) {
+                Ok(s) => s.into_raw(),
+                Err(_) => {
+                    update_last_error(Error::InvalidArgument);
+                    std::ptr::null_mut()
+                }
+            },
+            Err(e) => {
+                update_last_error(Error::Biscuit(e));
+                std::ptr::null_mut()
             }
         },
     }
@@ -869,6 +994,14 @@ pub unsafe extern "C" fn biscuit_block_context(
         }
     };
 
+    let block = match block.get() {
+        Ok(block) => block,
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            return std::ptr::null_mut();
+        }
+    };
+
     match &block.context {
         None => {
             return std::ptr::null_mut();
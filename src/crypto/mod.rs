@@ -48,8 +48,12 @@ impl KeyPair {
         KeyPair { private, public }
     }
 
-    #[allow(dead_code)]
-    fn sign<T: RngCore + CryptoRng>(&self, rng: &mut T, message: &[u8]) -> (Scalar, Scalar) {
+    /// signs a single message
+    pub(crate) fn sign<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        message: &[u8],
+    ) -> (Scalar, Scalar) {
         let mut r = Scalar::random(rng);
         let A = r * RISTRETTO_BASEPOINT_POINT;
         let d = hash_points(&[A]);
@@ -76,8 +80,12 @@ impl Drop for KeyPair {
     }
 }
 
-#[allow(dead_code)]
-fn verify(public: &RistrettoPoint, message: &[u8], signature: &(Scalar, Scalar)) -> bool {
+/// verifies the signature of a single message, from [`KeyPair::sign`]
+pub(crate) fn verify(
+    public: &RistrettoPoint,
+    message: &[u8],
+    signature: &(Scalar, Scalar),
+) -> bool {
     let (d, z) = signature;
     let e = hash_message(*public, message);
     let d_inv = d.invert();
@@ -212,46 +220,93 @@ impl TokenSignature {
         t
     }
 
+    /// adds a signature of `message` with another key to the aggregated
+    /// signature
+    ///
+    /// the signature parameter is not added to the list of parameters, it is
+    /// returned to be stored with the public key, and must be given to the
+    /// verification with [`SignatureVerification::add_block`]
+    pub fn cosign<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        keypair: &KeyPair,
+        message: &[u8],
+    ) -> (Self, RistrettoPoint) {
+        let mut r = Scalar::random(rng);
+        let A = r * RISTRETTO_BASEPOINT_POINT;
+        let d = hash_points(&[A]);
+        let e = hash_message(keypair.public, message);
+        let z = r * d - e * keypair.private;
+
+        r.zeroize();
+
+        let t = TokenSignature {
+            parameters: self.parameters.clone(),
+            z: self.z + z,
+        };
+
+        (t, A)
+    }
+
     pub fn verify<M: Deref<Target = [u8]>>(
         &self,
         public_keys: &[PublicKey],
         messages: &[M],
     ) -> Result<(), error::Signature> {
         if !(public_keys.len() == messages.len() && public_keys.len() == self.parameters.len()) {
-            println!("invalid data");
             return Err(error::Signature::InvalidFormat);
         }
 
-        let zP = self.z * RISTRETTO_BASEPOINT_POINT;
-        let eiXi = public_keys
-            .iter()
-            .zip(messages)
-            .map(|(pubkey, message)| {
-                let e = hash_message((*pubkey).0, message);
-                e * pubkey.0
-            })
-            .fold(RistrettoPoint::identity(), |acc, point| acc + point);
-
-        let diAi = self
-            .parameters
-            .iter()
-            .map(|A| {
-                let d = hash_points(&[*A]);
-                d * A
-            })
-            .fold(RistrettoPoint::identity(), |acc, point| acc + point);
-
-        let res = zP + eiXi - diAi;
-
-        /*
-        println!("verify identity={:?}", RistrettoPoint::identity());
-        println!("verify res={:?}", res);
-        println!("verify identity={:?}", RistrettoPoint::identity().compress());
-        println!("verify res={:?}", res.compress());
-        println!("returning: {:?}", RistrettoPoint::identity() == res);
-        */
-
-        if RistrettoPoint::identity() == res {
+        let mut verification = SignatureVerification::new();
+        for ((public_key, message), A) in public_keys.iter().zip(messages).zip(&self.parameters) {
+            verification.add_block(public_key, A, message);
+        }
+
+        verification.finish(&self.z)
+    }
+}
+
+/// verification of an aggregated signature, one block at a time
+///
+/// the blocks do not have their own signature: each block adds a term to
+/// the verification equation, computed from its public key, its message and
+/// its signature parameter, and the sum is checked with the aggregated
+/// scalar of the token once all the blocks were added
+#[derive(Clone, Debug)]
+pub struct SignatureVerification {
+    /// sum of the `e_i * X_i - d_i * A_i` terms of the blocks
+    sum: RistrettoPoint,
+    blocks: usize,
+}
+
+impl SignatureVerification {
+    pub fn new() -> Self {
+        SignatureVerification {
+            sum: RistrettoPoint::identity(),
+            blocks: 0,
+        }
+    }
+
+    /// adds a block signed by `public_key`, with its signature parameter
+    pub fn add_block(&mut self, public_key: &PublicKey, A: &RistrettoPoint, message: &[u8]) {
+        let e = hash_message(public_key.0, message);
+        let d = hash_points(&[*A]);
+        self.sum += e * public_key.0 - d * A;
+        self.blocks += 1;
+    }
+
+    /// number of blocks added
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    /// checks the blocks added so far against the aggregated scalar
+    pub fn finish(&self, z: &Scalar) -> Result<(), error::Signature> {
+        if self.blocks == 0 {
+            return Err(error::Signature::InvalidFormat);
+        }
+
+        if RistrettoPoint::identity() == z * RISTRETTO_BASEPOINT_POINT + self.sum {
             Ok(())
         } else {
             Err(error::Signature::InvalidSignature)
@@ -259,6 +314,12 @@ impl TokenSignature {
     }
 }
 
+impl Default for SignatureVerification {
+    fn default() -> Self {
+        SignatureVerification::new()
+    }
+}
+
 //FIXME: is the output value in the right set?
 fn hash_points(points: &[RistrettoPoint]) -> Scalar {
     let mut h = Sha512::new();
@@ -295,6 +356,40 @@ mod tests {
         assert!(!verify(&keypair.public, b"AAAA", &signature));
     }
 
+    #[test]
+    fn step_by_step_verification() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+
+        let keypair1 = KeyPair::new_with_rng(&mut rng);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let token = Token::new(&mut rng, &keypair1, &b"hello"[..]).append(
+            &mut rng,
+            &keypair2,
+            &b"world"[..],
+        );
+
+        let mut verification = SignatureVerification::new();
+        assert_eq!(
+            verification.finish(&token.signature.z),
+            Err(error::Signature::InvalidFormat)
+        );
+        verification.add_block(&token.keys[0], &token.signature.parameters[0], b"hello");
+        assert_eq!(
+            verification.finish(&token.signature.z),
+            Err(error::Signature::InvalidSignature)
+        );
+        let mut tampered = verification.clone();
+        verification.add_block(&token.keys[1], &token.signature.parameters[1], b"world");
+        assert_eq!(verification.blocks(), 2);
+        assert_eq!(verification.finish(&token.signature.z), Ok(()));
+
+        tampered.add_block(&token.keys[1], &token.signature.parameters[1], b"WORLD");
+        assert_eq!(
+            tampered.finish(&token.signature.z),
+            Err(error::Signature::InvalidSignature)
+        );
+    }
+
     #[test]
     fn three_messages() {
         //let mut rng: OsRng = OsRng::new().unwrap();
@@ -1,13 +1,22 @@
 use super::SymbolTable;
 use super::ID;
 use regex::Regex;
+use smallvec::SmallVec;
+use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::fmt;
+
+pub use biscuit_parser::parser::MAX_EXPRESSION_DEPTH;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Expression {
-    pub ops: Vec<Op>,
+    pub ops: Ops,
 }
 
+/// operations of an expression, stored without a heap allocation for the
+/// common expressions of at most 4 operations
+pub type Ops = SmallVec<[Op; 4]>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Op {
     Value(ID),
@@ -38,10 +47,16 @@ impl Unary {
     }
 
     pub fn print(&self, value: String, _symbols: &SymbolTable) -> String {
+        let (prefix, suffix) = self.affixes();
+        format!("{}{}{}", prefix, value, suffix)
+    }
+
+    /// text written before and after the operand
+    fn affixes(&self) -> (&'static str, &'static str) {
         match self {
-            Unary::Negate => format!("!{}", value),
-            Unary::Parens => format!("({})", value),
-            Unary::Length => format!("{}.length()", value),
+            Unary::Negate => ("!", ""),
+            Unary::Parens => ("(", ")"),
+            Unary::Length => ("", ".length()"),
         }
     }
 }
@@ -145,37 +160,114 @@ impl Binary {
     }
 
     pub fn print(&self, left: String, right: String, _symbols: &SymbolTable) -> String {
+        let (infix, suffix) = self.affixes();
+        format!("{}{}{}{}", left, infix, right, suffix)
+    }
+
+    /// text written between the operands and after the right operand
+    fn affixes(&self) -> (&'static str, &'static str) {
         match self {
-            Binary::LessThan => format!("{} < {}", left, right),
-            Binary::GreaterThan => format!("{} > {}", left, right),
-            Binary::LessOrEqual => format!("{} <= {}", left, right),
-            Binary::GreaterOrEqual => format!("{} >= {}", left, right),
-            Binary::Equal => format!("{} == {}", left, right),
-            Binary::Contains => format!("{}.contains({})", left, right),
-            Binary::Prefix => format!("{}.starts_with({})", left, right),
-            Binary::Suffix => format!("{}.ends_with({})", left, right),
-            Binary::Regex => format!("{}.matches({})", left, right),
-            Binary::Add => format!("{} + {}", left, right),
-            Binary::Sub => format!("{} - {}", left, right),
-            Binary::Mul => format!("{} * {}", left, right),
-            Binary::Div => format!("{} / {}", left, right),
-            Binary::And => format!("{} && {}", left, right),
-            Binary::Or => format!("{} || {}", left, right),
-            Binary::Intersection => format!("{}.intersection({})", left, right),
-            Binary::Union => format!("{}.union({})", left, right),
+            Binary::LessThan => (" < ", ""),
+            Binary::GreaterThan => (" > ", ""),
+            Binary::LessOrEqual => (" <= ", ""),
+            Binary::GreaterOrEqual => (" >= ", ""),
+            Binary::Equal => (" == ", ""),
+            Binary::Contains => (".contains(", ")"),
+            Binary::Prefix => (".starts_with(", ")"),
+            Binary::Suffix => (".ends_with(", ")"),
+            Binary::Regex => (".matches(", ")"),
+            Binary::Add => (" + ", ""),
+            Binary::Sub => (" - ", ""),
+            Binary::Mul => (" * ", ""),
+            Binary::Div => (" / ", ""),
+            Binary::And => (" && ", ""),
+            Binary::Or => (" || ", ""),
+            Binary::Intersection => (".intersection(", ")"),
+            Binary::Union => (".union(", ")"),
         }
     }
 }
 
+#[cfg(feature = "arena")]
+thread_local! {
+    static ARENA: std::cell::RefCell<bumpalo::Bump> = std::cell::RefCell::new(bumpalo::Bump::new());
+}
+
+/// stack of values used to evaluate expressions
+trait Stack {
+    fn push(&mut self, id: ID);
+    fn pop(&mut self) -> Option<ID>;
+    fn len(&self) -> usize;
+}
+
+impl Stack for SmallVec<[ID; 4]> {
+    fn push(&mut self, id: ID) {
+        SmallVec::push(self, id)
+    }
+
+    fn pop(&mut self) -> Option<ID> {
+        SmallVec::pop(self)
+    }
+
+    fn len(&self) -> usize {
+        SmallVec::len(self)
+    }
+}
+
+#[cfg(feature = "arena")]
+impl<'bump> Stack for bumpalo::collections::Vec<'bump, ID> {
+    fn push(&mut self, id: ID) {
+        bumpalo::collections::Vec::push(self, id)
+    }
+
+    fn pop(&mut self) -> Option<ID> {
+        bumpalo::collections::Vec::pop(self)
+    }
+
+    fn len(&self) -> usize {
+        bumpalo::collections::Vec::len(self)
+    }
+}
+
 impl Expression {
     pub fn evaluate(&self, values: &HashMap<u32, ID>) -> Option<ID> {
-        let mut stack: Vec<ID> = Vec::new();
+        self.evaluate_with(values)
+    }
+
+    /// evaluates the expression with variables bound to owned or borrowed terms
+    #[cfg(not(feature = "arena"))]
+    pub(crate) fn evaluate_with<V: Borrow<ID>>(&self, values: &HashMap<u32, V>) -> Option<ID> {
+        self.evaluate_on(values, &mut SmallVec::<[ID; 4]>::new())
+    }
+
+    /// evaluates the expression with variables bound to owned or borrowed terms
+    ///
+    /// the stack is allocated in a thread local arena, reset after each evaluation
+    #[cfg(feature = "arena")]
+    pub(crate) fn evaluate_with<V: Borrow<ID>>(&self, values: &HashMap<u32, V>) -> Option<ID> {
+        ARENA.with(|arena| {
+            let mut arena = arena.borrow_mut();
+            let res = self.evaluate_on(values, &mut bumpalo::collections::Vec::new_in(&arena));
+            arena.reset();
+            res
+        })
+    }
 
+    fn evaluate_on<V: Borrow<ID>, S: Stack>(
+        &self,
+        values: &HashMap<u32, V>,
+        stack: &mut S,
+    ) -> Option<ID> {
         for op in self.ops.iter() {
+            // the stack never holds more values than the depth of the expression
+            if stack.len() >= MAX_EXPRESSION_DEPTH {
+                return None;
+            }
+
             //println!("op: {:?}\t| stack: {:?}", op, stack);
             match op {
                 Op::Value(ID::Variable(i)) => match values.get(&i) {
-                    Some(id) => stack.push(id.clone()),
+                    Some(id) => stack.push(id.borrow().clone()),
                     None => {
                         //println!("unknown variable {}", i);
                         return None;
@@ -206,50 +298,134 @@ impl Expression {
         }
 
         if stack.len() == 1 {
-            Some(stack.remove(0))
+            stack.pop()
         } else {
             None
         }
     }
 
-    pub fn print(&self, symbols: &SymbolTable) -> Option<String> {
-        let mut stack: Vec<String> = Vec::new();
+    /// number of levels of the expression tree, a single value has a depth of 1
+    ///
+    /// the operations are not checked, an invalid expression can have any depth
+    pub fn depth(&self) -> usize {
+        let mut stack: SmallVec<[usize; 8]> = SmallVec::new();
+        let mut max = 0;
 
         for op in self.ops.iter() {
-            //println!("op: {:?}\t| stack: {:?}", op, stack);
-            match op {
-                Op::Value(i) => stack.push(symbols.print_id(&i)),
-                Op::Unary(unary) => match stack.pop() {
-                    None => return None,
-                    Some(s) => stack.push(unary.print(s, symbols)),
-                },
-                Op::Binary(binary) => match (stack.pop(), stack.pop()) {
-                    (Some(right), Some(left)) => stack.push(binary.print(left, right, symbols)),
-                    _ => return None,
-                },
-            }
+            let depth = match op {
+                Op::Value(_) => 1,
+                Op::Unary(_) => stack.pop().unwrap_or(0) + 1,
+                Op::Binary(_) => {
+                    let right = stack.pop().unwrap_or(0);
+                    let left = stack.pop().unwrap_or(0);
+                    right.max(left) + 1
+                }
+            };
+            max = max.max(depth);
+            stack.push(depth);
+        }
+
+        max
+    }
+
+    /// returns `None` for invalid expressions and expressions deeper than
+    /// [`MAX_EXPRESSION_DEPTH`]
+    pub fn print(&self, symbols: &SymbolTable) -> Option<String> {
+        if self.depth() > MAX_EXPRESSION_DEPTH {
+            return None;
+        }
+        let starts = self.starts()?;
+        let mut res = String::new();
+        self.write_op(&mut res, symbols, &starts, self.ops.len() - 1)
+            .ok()?;
+        Some(res)
+    }
+
+    /// writes the expression in Datalog syntax, or a placeholder if the
+    /// operations do not form a valid expression
+    pub fn write<W: fmt::Write>(&self, w: &mut W, symbols: &SymbolTable) -> fmt::Result {
+        // printing is recursive
+        if self.depth() > MAX_EXPRESSION_DEPTH {
+            return w.write_str("<expression nested too deeply>");
+        }
+
+        match self.starts() {
+            Some(starts) => self.write_op(w, symbols, &starts, self.ops.len() - 1),
+            None => write!(w, "<invalid expression: {:?}>", self.ops),
+        }
+    }
+
+    /// for each operation, the index of the first operation of the
+    /// subexpression it ends, or `None` if the operations do not form a
+    /// single expression
+    fn starts(&self) -> Option<SmallVec<[usize; 8]>> {
+        let mut starts = SmallVec::with_capacity(self.ops.len());
+        let mut stack: SmallVec<[usize; 8]> = SmallVec::new();
+
+        for (index, op) in self.ops.iter().enumerate() {
+            let start = match op {
+                Op::Value(_) => index,
+                Op::Unary(_) => stack.pop()?,
+                Op::Binary(_) => {
+                    stack.pop()?;
+                    stack.pop()?
+                }
+            };
+            starts.push(start);
+            stack.push(start);
         }
 
         if stack.len() == 1 {
-            Some(stack.remove(0))
+            Some(starts)
         } else {
             None
         }
     }
+
+    /// writes the subexpression ending at operation `index`
+    fn write_op<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        symbols: &SymbolTable,
+        starts: &[usize],
+        index: usize,
+    ) -> fmt::Result {
+        match &self.ops[index] {
+            Op::Value(id) => symbols.write_id(w, id),
+            Op::Unary(unary) => {
+                let (prefix, suffix) = unary.affixes();
+                w.write_str(prefix)?;
+                self.write_op(w, symbols, starts, index - 1)?;
+                w.write_str(suffix)
+            }
+            Op::Binary(binary) => {
+                let (infix, suffix) = binary.affixes();
+                // the right operand ends just before the operation, and the
+                // left operand just before the right one
+                self.write_op(w, symbols, starts, starts[index - 1] - 1)?;
+                w.write_str(infix)?;
+                self.write_op(w, symbols, starts, index - 1)?;
+                w.write_str(suffix)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::datalog::SymbolTable;
+    use smallvec::smallvec;
 
     #[test]
     fn negate() {
-        let symbols = SymbolTable {
-            symbols: vec!["test1".to_string(), "test2".to_string(), "var1".to_string()],
-        };
+        let symbols = SymbolTable::from_symbols(vec![
+            "test1".to_string(),
+            "test2".to_string(),
+            "var1".to_string(),
+        ]);
 
-        let ops = vec![
+        let ops = smallvec![
             Op::Value(ID::Integer(1)),
             Op::Value(ID::Variable(2)),
             Op::Binary(Binary::LessThan),
@@ -270,7 +446,7 @@ mod tests {
 
     #[test]
     fn checked() {
-        let ops = vec![
+        let ops = smallvec![
             Op::Value(ID::Integer(1)),
             Op::Value(ID::Integer(0)),
             Op::Binary(Binary::Div),
@@ -281,7 +457,7 @@ mod tests {
         let res = e.evaluate(&values);
         assert_eq!(res, None);
 
-        let ops = vec![
+        let ops = smallvec![
             Op::Value(ID::Integer(1)),
             Op::Value(ID::Integer(i64::MAX)),
             Op::Binary(Binary::Add),
@@ -292,7 +468,7 @@ mod tests {
         let res = e.evaluate(&values);
         assert_eq!(res, None);
 
-        let ops = vec![
+        let ops = smallvec![
             Op::Value(ID::Integer(-10)),
             Op::Value(ID::Integer(i64::MAX)),
             Op::Binary(Binary::Sub),
@@ -303,7 +479,7 @@ mod tests {
         let res = e.evaluate(&values);
         assert_eq!(res, None);
 
-        let ops = vec![
+        let ops = smallvec![
             Op::Value(ID::Integer(2)),
             Op::Value(ID::Integer(i64::MAX)),
             Op::Binary(Binary::Mul),
@@ -317,17 +493,19 @@ mod tests {
 
     #[test]
     fn printer() {
-        let symbols = SymbolTable {
-            symbols: vec!["test1".to_string(), "test2".to_string(), "var1".to_string()],
-        };
+        let symbols = SymbolTable::from_symbols(vec![
+            "test1".to_string(),
+            "test2".to_string(),
+            "var1".to_string(),
+        ]);
 
-        let ops1 = vec![
+        let ops1 = smallvec![
             Op::Value(ID::Integer(-1)),
             Op::Value(ID::Variable(2)),
             Op::Binary(Binary::LessThan),
         ];
 
-        let ops2 = vec![
+        let ops2 = smallvec![
             Op::Value(ID::Integer(1)),
             Op::Value(ID::Integer(2)),
             Op::Value(ID::Integer(3)),
@@ -335,7 +513,7 @@ mod tests {
             Op::Binary(Binary::LessThan),
         ];
 
-        let ops3 = vec![
+        let ops3 = smallvec![
             Op::Value(ID::Integer(1)),
             Op::Value(ID::Integer(2)),
             Op::Binary(Binary::Add),
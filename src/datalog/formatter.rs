@@ -0,0 +1,253 @@
+use super::{quote_string, SymbolTable};
+use crate::error;
+use crate::parser::{parse_block_source, parse_source, source_error};
+use crate::token::builder;
+use std::convert::TryInto;
+
+/// kinds of statements, in the order they are written by [`format_source`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Include,
+    Context,
+    Fact,
+    Rule,
+    Check,
+    Policy,
+}
+
+struct Statement<'a> {
+    kind: Kind,
+    text: String,
+    /// comments written on the lines before the statement
+    comments: Vec<&'a str>,
+    /// comment written after the statement, on the same line
+    trailing: Option<&'a str>,
+}
+
+/// formats Datalog source, like a policy file or the code of a block
+///
+/// every statement is printed on its own line, with the spacing of
+/// [`SymbolTable::print_rule`], and statements are grouped by kind: includes,
+/// context, facts, rules, checks, then policies, separated by blank lines.
+/// Statements of the same kind keep their order, so policies are still
+/// tried in the same order. Deprecated constructs like `caveat` are
+/// rewritten with the current syntax.
+///
+/// comments are kept with the statement following them, or with the
+/// statement they follow on the same line. Comments after the last
+/// statement stay at the end.
+///
+/// ```rust
+/// use biscuit_auth::datalog::format_source;
+///
+/// let source = "allow if user($u)  ;\n// admins\nadmin(  \"alice\"); check if  time(#ambient,$t),$t<2030-01-01T00:00:00Z;";
+/// assert_eq!(
+///     format_source(source).unwrap(),
+///     "// admins\nadmin(\"alice\");\n\ncheck if time(#ambient, $t), $t < 2030-01-01T00:00:00+00:00;\n\nallow if user($u);\n"
+/// );
+/// ```
+///
+/// the source must parse: the error of the first invalid statement is
+/// returned. Parameters like `{name}` are not supported.
+pub fn format_source(source: &str) -> Result<String, error::Token> {
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut comments = Vec::new();
+    // set when a line starts after the last statement
+    let mut new_line = true;
+    let mut rest = source;
+
+    loop {
+        let trimmed = rest.trim_start();
+        new_line |= rest[..rest.len() - trimmed.len()].contains('\n');
+        rest = trimmed;
+        if rest.is_empty() {
+            break;
+        }
+
+        let comment_end = if rest.starts_with("//") {
+            Some(rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            // an unterminated comment is reported by the parser
+            rest.find("*/").map(|i| i + 2)
+        } else {
+            None
+        };
+
+        match comment_end {
+            Some(end) => {
+                let comment = rest[..end].trim_end();
+                match statements.last_mut() {
+                    Some(statement) if !new_line && statement.trailing.is_none() => {
+                        statement.trailing = Some(comment)
+                    }
+                    _ => comments.push(comment),
+                }
+                rest = &rest[end..];
+            }
+            None => {
+                let end = statement_end(rest);
+                let (kind, text) = format_statement(source, &rest[..end])?;
+                statements.push(Statement {
+                    kind,
+                    text,
+                    comments: std::mem::take(&mut comments),
+                    trailing: None,
+                });
+                new_line = false;
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    statements.sort_by_key(|statement| statement.kind);
+
+    let mut out = String::new();
+    let mut previous = None;
+    for statement in statements {
+        if previous.map(|kind| kind != statement.kind).unwrap_or(false) {
+            out.push('\n');
+        }
+        for comment in statement.comments {
+            out.push_str(comment);
+            out.push('\n');
+        }
+        out.push_str(&statement.text);
+        out.push(';');
+        if let Some(comment) = statement.trailing {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+        previous = Some(statement.kind);
+    }
+
+    if !comments.is_empty() && !out.is_empty() {
+        out.push('\n');
+    }
+    for comment in comments {
+        out.push_str(comment);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// position after the `;` ending the statement, ignoring the ones in strings
+fn statement_end(input: &str) -> usize {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return index + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// parses a single statement, a slice of `source` so errors are located in it
+fn format_statement(source: &str, statement: &str) -> Result<(Kind, String), error::Token> {
+    let parsed = if statement.starts_with("context") {
+        parse_block_source(statement)
+    } else {
+        parse_source(statement)
+    };
+    let (_, result) = parsed.map_err(|errors| source_error(source, errors))?;
+
+    let mut symbols = SymbolTable::new();
+    let formatted = if let Some((_, file)) = result.includes.into_iter().next() {
+        (Kind::Include, format!("include {}", quote_string(&file)))
+    } else if let Some(context) = result.context {
+        (Kind::Context, format!("context {}", quote_string(&context)))
+    } else if let Some((_, fact)) = result.facts.into_iter().next() {
+        let fact: builder::Fact = fact.try_into()?;
+        let fact = fact.convert(&mut symbols);
+        (Kind::Fact, symbols.print_fact(&fact))
+    } else if let Some((_, rule)) = result.rules.into_iter().next() {
+        let rule: builder::Rule = rule.try_into()?;
+        let rule = rule.convert(&mut symbols);
+        (Kind::Rule, symbols.print_rule(&rule))
+    } else if let Some((_, check)) = result.checks.into_iter().next() {
+        let check: builder::Check = check.try_into()?;
+        let check = check.convert(&mut symbols);
+        (Kind::Check, symbols.print_check(&check))
+    } else if let Some((_, policy)) = result.policies.into_iter().next() {
+        let policy: builder::Policy = policy.try_into()?;
+        let kind = match policy.kind {
+            builder::PolicyKind::Allow => "allow",
+            builder::PolicyKind::Deny => "deny",
+        };
+        let queries = policy
+            .queries
+            .iter()
+            .map(|query| {
+                let query = query.convert(&mut symbols);
+                symbols.print_rule_body(&query)
+            })
+            .collect::<Vec<_>>();
+        (
+            Kind::Policy,
+            format!("{} if {}", kind, queries.join(" or ")),
+        )
+    } else {
+        return Err(source_error(source, Vec::new()));
+    };
+
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let source = r#"
+// policies
+allow if resource(#ambient,$0),right( #authority,$0 , #read )  ;
+deny if true; // default
+
+/* authority data */
+right(#authority, "file;1", #read);
+right($0, #read) <- resource(#ambient, $0), owner(#ambient, $0);
+caveat if [1,2 ].contains( 1 ) or resource(#ambient,$0),$0.starts_with("/a")&& 1+2*3 > 4;
+include "common.datalog";
+
+// end
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            r#"include "common.datalog";
+
+/* authority data */
+right(#authority, "file;1", #read);
+
+right($0, #read) <- resource(#ambient, $0), owner(#ambient, $0);
+
+check if [1, 2].contains(1) or resource(#ambient, $0), $0.starts_with("/a") && 1 + 2 * 3 > 4;
+
+// policies
+allow if resource(#ambient, $0), right(#authority, $0, #read);
+deny if true; // default
+
+// end
+"#
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+        assert_eq!(
+            format_source("context \"a\";check if true;").unwrap(),
+            "context \"a\";\n\ncheck if true;\n"
+        );
+        assert_eq!(format_source("").unwrap(), "");
+
+        match format_source("right(#authority, \"file1\");\ncheck if resource($0) and true;") {
+            Err(error::Token::ParseError(e)) => assert_eq!((e.line, e.column), (2, 23)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
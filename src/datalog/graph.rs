@@ -0,0 +1,88 @@
+//! dependency graph between the predicates of a set of rules
+use super::{Rule, Symbol};
+use std::collections::HashSet;
+
+/// links each rule to the predicates it reads
+///
+/// predicates are identified by their name. The graph is updated as rules
+/// are added to a [`RuleSet`](super::RuleSet), and is used to skip the rules
+/// whose inputs did not change during the last iteration.
+#[derive(Clone, Debug, Default)]
+pub struct RuleGraph {
+    /// names of the body predicates of each rule
+    inputs: Vec<HashSet<Symbol>>,
+}
+
+impl RuleGraph {
+    pub fn new<'a, I: IntoIterator<Item = &'a Rule>>(rules: I) -> Self {
+        let mut graph = RuleGraph::default();
+        for rule in rules {
+            graph.push(rule);
+        }
+        graph
+    }
+
+    /// adds a rule, at the index following the previous ones
+    pub fn push(&mut self, rule: &Rule) {
+        self.inputs.push(rule.body.iter().map(|p| p.name).collect());
+    }
+
+    /// adds the rules of another graph after the ones of this graph
+    pub fn append(&mut self, other: &mut RuleGraph) {
+        self.inputs.append(&mut other.inputs);
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// returns `true` if the rule at `index` can generate new facts when
+    /// facts with the `changed` predicate names were added
+    ///
+    /// rules without predicates in their body are always evaluated
+    pub fn depends_on(&self, index: usize, changed: &HashSet<Symbol>) -> bool {
+        let inputs = &self.inputs[index];
+        inputs.is_empty() || !inputs.is_disjoint(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{pred, rule, var, Predicate, SymbolTable};
+
+    #[test]
+    fn depends_on() {
+        let mut syms = SymbolTable::new();
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+        let related = syms.insert("related");
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+        let z = var(&mut syms, "z");
+
+        let rules = vec![
+            rule(related, &[&x, &y], &[pred(ancestor, &[&x, &y])]),
+            rule(ancestor, &[&x, &y], &[pred(parent, &[&x, &y])]),
+            rule(
+                ancestor,
+                &[&x, &z],
+                &[pred(parent, &[&x, &y]), pred(ancestor, &[&y, &z])],
+            ),
+            rule::<_, Predicate>(related, &[&x, &x], &[]),
+        ];
+
+        let graph = RuleGraph::new(&rules);
+        assert_eq!(graph.len(), 4);
+
+        let changed = [parent].iter().cloned().collect();
+        assert!(!graph.depends_on(0, &changed));
+        assert!(graph.depends_on(1, &changed));
+        assert!(graph.depends_on(2, &changed));
+        assert!(graph.depends_on(3, &changed));
+    }
+}
@@ -1,5 +1,6 @@
 //! Logic language implementation for checks
 use crate::time::Instant;
+use smallvec::SmallVec;
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::AsRef;
 use std::fmt;
@@ -7,8 +8,14 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub type Symbol = u64;
 mod expression;
+mod formatter;
+mod graph;
+mod storage;
 mod symbol;
 pub use expression::*;
+pub use formatter::*;
+pub use graph::*;
+pub use storage::*;
 pub use symbol::*;
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq, PartialOrd, Ord)]
@@ -44,17 +51,21 @@ impl AsRef<ID> for ID {
     }
 }
 
+/// terms of a predicate, most predicates have at most 4 terms and are
+/// stored without a heap allocation
+pub type Terms = SmallVec<[ID; 4]>;
+
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Predicate {
     pub name: Symbol,
-    pub ids: Vec<ID>,
+    pub ids: Terms,
 }
 
 impl Predicate {
     pub fn new(name: Symbol, ids: &[ID]) -> Predicate {
         Predicate {
             name,
-            ids: ids.to_vec(),
+            ids: ids.iter().cloned().collect(),
         }
     }
 }
@@ -103,7 +114,20 @@ impl fmt::Display for Fact {
 }
 
 impl Rule {
-    pub fn apply<'a>(&'a self, facts: &'a HashSet<Fact>) -> impl Iterator<Item = Fact> + 'a {
+    /// fails if one of the expressions is deeper than [`MAX_EXPRESSION_DEPTH`]
+    pub fn validate_expression_depth(&self) -> Result<(), crate::error::RunLimit> {
+        if self
+            .expressions
+            .iter()
+            .any(|e| e.depth() > MAX_EXPRESSION_DEPTH)
+        {
+            Err(crate::error::RunLimit::ExpressionDepth)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn apply<'a>(&'a self, facts: &'a FactSet) -> impl Iterator<Item = Fact> + 'a {
         // gather all of the variables used in that rule
         let variables_set = self
             .body
@@ -116,31 +140,41 @@ impl Rule {
             })
             .collect::<HashSet<_>>();
 
-        let head = self.head.clone();
+        let head = &self.head;
         let variables = MatchedVariables::new(variables_set);
         CombineIt::new(variables, &self.body, &self.expressions, facts).filter_map(move |h| {
-            let mut p = head.clone();
-            for index in 0..p.ids.len() {
-                let value = match &p.ids[index] {
-                    ID::Variable(i) => match h.get(i) {
-                      Some(val) => val,
-                      None => {
-                        println!("error: variables that appear in the head should appear in the body and constraints as well");
-                        return None;
-                      }
-                    },
-                    _ => continue,
-                };
+            // the bindings refer to the terms of the matched facts, they are
+            // only copied to create the generated fact
+            let ids = head
+                .ids
+                .iter()
+                .map(|id| match id {
+                    ID::Variable(i) => h.get(i).map(|value| (*value).clone()),
+                    id => Some(id.clone()),
+                })
+                .collect::<Option<Terms>>();
 
-                p.ids[index] = value.clone();
+            match ids {
+                Some(ids) => Some(Fact {
+                    predicate: Predicate {
+                        name: head.name,
+                        ids,
+                    },
+                }),
+                None => {
+                    println!("error: variables that appear in the head should appear in the body and constraints as well");
+                    None
+                }
             }
-
-            Some(Fact { predicate: p })
         })
     }
 
-    pub fn find_match(&self, facts: &HashSet<Fact>) -> bool {
-        // gather all of the variables used in that rule
+    pub fn find_match(&self, facts: &FactSet) -> bool {
+        self.apply(facts).next().is_some()
+    }
+
+    /// returns the first variable bindings for which the body and expressions match
+    pub fn find_bindings(&self, facts: &FactSet) -> Option<HashMap<u32, ID>> {
         let variables_set = self
             .body
             .iter()
@@ -153,48 +187,75 @@ impl Rule {
             .collect::<HashSet<_>>();
 
         let variables = MatchedVariables::new(variables_set);
+        CombineIt::new(variables, &self.body, &self.expressions, facts)
+            .next()
+            .map(|bindings| bindings.into_iter().map(|(k, v)| (k, v.clone())).collect())
+    }
 
-        let mut it = CombineIt::new(variables, &self.body, &self.expressions, facts).filter_map(|h| {
-            let mut p = self.head.clone();
-            for index in 0..p.ids.len() {
-                let value = match &p.ids[index] {
-                    ID::Variable(i) => match h.get(i) {
-                        Some(val) => val,
-                        None => {
-                            println!("error: variables that appear in the head should appear in the body and constraints as well");
-                            return None;
-                        }
-                    },
-                    _ => continue,
-                };
+    /// looks for the reasons why the rule does not match
+    ///
+    /// returns the indexes of the body predicates that match no fact. If
+    /// they all match some facts, returns the indexes of the expressions that
+    /// are false for every combination of facts matching the body
+    pub fn explain_failure(&self, facts: &FactSet) -> (Vec<usize>, Vec<usize>) {
+        let missing = self
+            .body
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !facts.iter().any(|f| match_preds(p, &f.predicate)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
 
-                p.ids[index] = value.clone();
-            }
+        if !missing.is_empty() {
+            return (missing, Vec::new());
+        }
+
+        let variables_set = self
+            .body
+            .iter()
+            .flat_map(|pred| {
+                pred.ids.iter().filter_map(|id| match id {
+                    ID::Variable(i) => Some(*i),
+                    _ => None,
+                })
+            })
+            .collect::<HashSet<_>>();
+
+        let variables = MatchedVariables::new(variables_set);
+        let candidates = CombineIt::new(variables, &self.body, &[], facts).collect::<Vec<_>>();
 
-            Some(Fact { predicate: p })
-        });
+        let failed = self
+            .expressions
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| {
+                !candidates
+                    .iter()
+                    .any(|c| e.evaluate_with(c) == Some(ID::Bool(true)))
+            })
+            .map(|(i, _)| i)
+            .collect();
 
-        let next = it.next();
-        next.is_some()
+        (missing, failed)
     }
 }
 
 /// recursive iterator for rule application
 pub struct CombineIt<'a> {
-    variables: MatchedVariables,
+    variables: MatchedVariables<'a>,
     predicates: &'a [Predicate],
     expressions: &'a [Expression],
-    all_facts: &'a HashSet<Fact>,
+    all_facts: &'a FactSet,
     current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a>,
     current_it: Option<Box<CombineIt<'a>>>,
 }
 
 impl<'a> CombineIt<'a> {
     pub fn new(
-        variables: MatchedVariables,
+        variables: MatchedVariables<'a>,
         predicates: &'a [Predicate],
         expressions: &'a [Expression],
-        facts: &'a HashSet<Fact>,
+        facts: &'a FactSet,
     ) -> Self {
         let current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a> = if predicates.is_empty() {
             Box::new(facts.iter())
@@ -219,9 +280,9 @@ impl<'a> CombineIt<'a> {
 }
 
 impl<'a> Iterator for CombineIt<'a> {
-    type Item = HashMap<u32, ID>;
+    type Item = HashMap<u32, &'a ID>;
 
-    fn next(&mut self) -> Option<HashMap<u32, ID>> {
+    fn next(&mut self) -> Option<HashMap<u32, &'a ID>> {
         // if we're the last iterator in the recursive chain, stop here
         if self.predicates.is_empty() {
             //return None;
@@ -233,7 +294,7 @@ impl<'a> Iterator for CombineIt<'a> {
                     //println!("predicates empty, will test variables: {:?}", variables);
                     let mut valid = true;
                     for e in self.expressions.iter() {
-                        match e.evaluate(&variables) {
+                        match e.evaluate_with(&variables) {
                             Some(ID::Bool(true)) => {}
                             _res => {
                                 //println!("expr returned {:?}", res);
@@ -265,7 +326,7 @@ impl<'a> Iterator for CombineIt<'a> {
                         let mut match_ids = true;
                         for (key, id) in pred.ids.iter().zip(&current_fact.predicate.ids) {
                             if let (ID::Variable(k), id) = (key, id) {
-                                if !vars.insert(*k, &id) {
+                                if !vars.insert(*k, id) {
                                     match_ids = false;
                                 }
 
@@ -290,7 +351,7 @@ impl<'a> Iterator for CombineIt<'a> {
                                     //println!("will test with variables: {:?}", variables);
                                     let mut valid = true;
                                     for e in self.expressions.iter() {
-                                        match e.evaluate(&variables) {
+                                        match e.evaluate_with(&variables) {
                                             Some(ID::Bool(true)) => {
                                                 //println!("expression returned true");
                                             }
@@ -339,21 +400,22 @@ impl<'a> Iterator for CombineIt<'a> {
     }
 }
 
+/// variables of a rule, bound to terms of the facts they matched
 #[derive(Debug, Clone, PartialEq)]
-pub struct MatchedVariables(pub HashMap<u32, Option<ID>>);
+pub struct MatchedVariables<'a>(pub HashMap<u32, Option<&'a ID>>);
 
-impl MatchedVariables {
+impl<'a> MatchedVariables<'a> {
     pub fn new(import: HashSet<u32>) -> Self {
         MatchedVariables(import.iter().map(|key| (*key, None)).collect())
     }
 
-    pub fn insert(&mut self, key: u32, value: &ID) -> bool {
+    pub fn insert(&mut self, key: u32, value: &'a ID) -> bool {
         match self.0.get(&key) {
             Some(None) => {
-                self.0.insert(key, Some(value.clone()));
+                self.0.insert(key, Some(value));
                 true
             }
-            Some(Some(v)) => value == v,
+            Some(Some(v)) => value == *v,
             None => false,
         }
     }
@@ -362,11 +424,11 @@ impl MatchedVariables {
         self.0.values().all(|v| v.is_some())
     }
 
-    pub fn complete(&self) -> Option<HashMap<u32, ID>> {
+    pub fn complete(&self) -> Option<HashMap<u32, &'a ID>> {
         let mut result = HashMap::new();
         for (k, v) in self.0.iter() {
             match v {
-                Some(value) => result.insert(*k, value.clone()),
+                Some(value) => result.insert(*k, *value),
                 None => return None,
             };
         }
@@ -423,6 +485,67 @@ pub fn string(s: &str) -> ID {
     ID::Str(s.to_string())
 }
 
+/// escapes a string so it can be written between double quotes in Datalog source
+///
+/// backslashes, double quotes and line feeds are escaped, so once quoted, the
+/// result is always parsed back as a single string term equal to `s`, whatever
+/// its content
+pub fn escape_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            '\n' => res.push_str("\\n"),
+            c => res.push(c),
+        }
+    }
+
+    res
+}
+
+/// escapes a string and wraps it in double quotes, producing a string literal
+/// for Datalog source
+pub fn quote_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    // writing to a String cannot fail
+    let _ = write_quoted_string(&mut res, s);
+    res
+}
+
+/// writes the string literal produced by [`quote_string`], without allocating
+pub fn write_quoted_string<W: fmt::Write>(w: &mut W, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '\\' => w.write_str("\\\\")?,
+            '"' => w.write_str("\\\"")?,
+            '\n' => w.write_str("\\n")?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+/// checks that a name can be used as is in Datalog source, for a symbol
+/// (after `#`), a variable (after `$`) or a predicate
+///
+/// names are made of ASCII letters, digits and underscores. There is no
+/// escaping for names: values that do not pass this check must be
+/// written as strings instead
+pub fn is_valid_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// namespace of a predicate name, like `billing` in `billing:right`
+///
+/// the namespace is part of the name, and stored in the symbol table with
+/// it, so `billing:right` and `right` are different predicates
+pub fn namespace(name: &str) -> Option<&str> {
+    name.split_once(':').map(|(namespace, _)| namespace)
+}
+
 pub fn date(t: &SystemTime) -> ID {
     let dur = t.duration_since(UNIX_EPOCH).unwrap();
     ID::Date(dur.as_secs())
@@ -457,10 +580,10 @@ pub fn match_preds(rule_pred: &Predicate, fact_pred: &Predicate) -> bool {
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct World {
-    pub facts: HashSet<Fact>,
-    pub rules: Vec<Rule>,
+    pub facts: FactSet,
+    pub rules: RuleSet,
     /// special rules that can generate authority or ambient facts
-    pub privileged_rules: Vec<Rule>,
+    pub privileged_rules: RuleSet,
 }
 
 impl World {
@@ -480,63 +603,115 @@ impl World {
         self.privileged_rules.push(rule);
     }
 
+    /// shares the current facts and rules between this world and its
+    /// future clones, instead of copying them on every clone
+    pub fn freeze(&mut self) {
+        self.facts.freeze();
+        self.rules.freeze();
+        self.privileged_rules.freeze();
+    }
+
     pub fn run(&mut self, restricted_symbols: &[u64]) -> Result<(), crate::error::RunLimit> {
         self.run_with_limits(RunLimits::default(), restricted_symbols)
+            .map(|_| ())
     }
 
+    /// applies the rules until no new facts are generated
+    ///
+    /// after the first iteration, a rule is only applied again if facts
+    /// matching one of its body predicates were generated in the previous
+    /// iteration (see [`RuleGraph`], maintained by the [`RuleSet`])
+    ///
+    /// on success, returns the number of iterations that generated new facts
     pub fn run_with_limits(
         &mut self,
         limits: RunLimits,
         restricted_symbols: &[u64],
-    ) -> Result<(), crate::error::RunLimit> {
+    ) -> Result<u32, crate::error::RunLimit> {
         let start = Instant::now();
         let time_limit = start + limits.max_time;
         let mut index = 0;
 
+        for rule in self.privileged_rules.iter().chain(self.rules.iter()) {
+            rule.validate_expression_depth()?;
+        }
+
+        // names of the predicates generated in the last iteration, `None`
+        // before the first one
+        let mut changed: Option<HashSet<Symbol>> = None;
+        let should_apply =
+            |rules: &RuleSet, changed: &Option<HashSet<Symbol>>, rule: usize| match changed {
+                None => true,
+                Some(changed) => rules.depends_on(rule, changed),
+            };
+
         loop {
-            let mut new_facts: Vec<Fact> = Vec::new();
-            for rule in self.privileged_rules.iter() {
-                new_facts.extend(rule.apply(&self.facts));
-                //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
+            // facts are deduplicated as they are generated, so the facts
+            // limit is checked before computing the rest of the iteration
+            let mut new_facts: HashSet<Fact> = HashSet::new();
+            let mut add = |fact: Fact| {
+                if !self.facts.contains(&fact) && new_facts.insert(fact) {
+                    self.facts.len() + new_facts.len() < limits.max_facts as usize
+                } else {
+                    true
+                }
+            };
+
+            for (i, rule) in self.privileged_rules.iter().enumerate() {
+                if !should_apply(&self.privileged_rules, &changed, i) {
+                    continue;
+                }
+
+                for fact in rule.apply(&self.facts) {
+                    if !add(fact) {
+                        return Err(crate::error::RunLimit::TooManyFacts);
+                    }
+                }
             }
 
-            for rule in self.rules.iter() {
-                new_facts.extend(rule.apply(&self.facts).filter_map(|fact| {
-                    match fact.predicate.ids.get(0) {
-                        Some(ID::Symbol(sym)) => {
-                            if restricted_symbols.contains(&sym) {
-                                return None;
-                            }
+            for (i, rule) in self.rules.iter().enumerate() {
+                if !should_apply(&self.rules, &changed, i) {
+                    continue;
+                }
+
+                for fact in rule.apply(&self.facts) {
+                    if let Some(ID::Symbol(sym)) = fact.predicate.ids.get(0) {
+                        if restricted_symbols.contains(sym) {
+                            continue;
                         }
-                        _ => {}
-                    };
-                    Some(fact)
-                }));
-                //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
+                    }
+
+                    if !add(fact) {
+                        return Err(crate::error::RunLimit::TooManyFacts);
+                    }
+                }
             }
 
-            let len = self.facts.len();
-            self.facts.extend(new_facts.drain(..));
-            if self.facts.len() == len {
+            if new_facts.is_empty() {
                 break;
             }
+            changed = Some(new_facts.iter().map(|f| f.predicate.name).collect());
+            self.facts.extend(new_facts);
 
             index += 1;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                iteration = index,
+                facts = self.facts.len(),
+                "datalog iteration"
+            );
+
             if index == limits.max_iterations {
                 return Err(crate::error::RunLimit::TooManyIterations);
             }
 
-            if self.facts.len() >= limits.max_facts as usize {
-                return Err(crate::error::RunLimit::TooManyFacts);
-            }
-
             let now = Instant::now();
             if now >= time_limit {
                 return Err(crate::error::RunLimit::Timeout);
             }
         }
 
-        Ok(())
+        Ok(index)
     }
 
     pub fn query(&self, pred: Predicate) -> Vec<&Fact> {
@@ -598,6 +773,7 @@ impl std::default::Default for RunLimits {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use smallvec::smallvec;
 
     #[test]
     fn family() {
@@ -777,7 +953,7 @@ mod tests {
                 ),
             ],
             &[Expression {
-                ops: vec![
+                ops: smallvec![
                     Op::Value(var(&mut syms, "id")),
                     Op::Value(ID::Integer(1)),
                     Op::Binary(Binary::LessThan),
@@ -831,7 +1007,7 @@ mod tests {
                     ],
                 )],
                 &[Expression {
-                    ops: vec![
+                    ops: smallvec![
                         Op::Value(var(syms, "domain_name")),
                         Op::Value(ID::Str(suffix.to_string())),
                         Op::Binary(Binary::Suffix),
@@ -896,14 +1072,14 @@ mod tests {
             &[pred(x, &[var(&mut syms, "date"), var(&mut syms, "val")])],
             &[
                 Expression {
-                    ops: vec![
+                    ops: smallvec![
                         Op::Value(var(&mut syms, "date")),
                         Op::Value(ID::Date(t2_timestamp)),
                         Op::Binary(Binary::LessOrEqual),
                     ],
                 },
                 Expression {
-                    ops: vec![
+                    ops: smallvec![
                         Op::Value(var(&mut syms, "date")),
                         Op::Value(ID::Date(0)),
                         Op::Binary(Binary::GreaterOrEqual),
@@ -930,14 +1106,14 @@ mod tests {
             &[pred(x, &[var(&mut syms, "date"), var(&mut syms, "val")])],
             &[
                 Expression {
-                    ops: vec![
+                    ops: smallvec![
                         Op::Value(var(&mut syms, "date")),
                         Op::Value(ID::Date(t2_timestamp)),
                         Op::Binary(Binary::GreaterOrEqual),
                     ],
                 },
                 Expression {
-                    ops: vec![
+                    ops: smallvec![
                         Op::Value(var(&mut syms, "date")),
                         Op::Value(ID::Date(0)),
                         Op::Binary(Binary::GreaterOrEqual),
@@ -986,7 +1162,7 @@ mod tests {
                 ],
             )],
             &[Expression {
-                ops: vec![
+                ops: smallvec![
                     Op::Value(ID::Set(
                         [ID::Integer(0), ID::Integer(1)].iter().cloned().collect(),
                     )),
@@ -1025,7 +1201,7 @@ mod tests {
                 ],
             )],
             &[Expression {
-                ops: vec![
+                ops: smallvec![
                     Op::Value(ID::Set(
                         [ID::Symbol(abc_sym_id), ID::Symbol(ghi_sym_id)]
                             .iter()
@@ -1065,7 +1241,7 @@ mod tests {
                 ],
             )],
             &[Expression {
-                ops: vec![
+                ops: smallvec![
                     Op::Value(ID::Set(
                         [ID::Str("test".to_string()), ID::Str("aaa".to_string())]
                             .iter()
@@ -1158,7 +1334,7 @@ mod tests {
             &[var(&mut syms, "nb"), var(&mut syms, "val")],
             &[pred(x, &[var(&mut syms, "nb"), var(&mut syms, "val")])],
             &[Expression {
-                ops: vec![
+                ops: smallvec![
                     Op::Value(ID::Integer(5)),
                     Op::Value(ID::Integer(-4)),
                     Op::Binary(Binary::Add),
@@ -1233,4 +1409,210 @@ mod tests {
         }
         assert!(res.is_empty());
     }
+
+    #[test]
+    fn escaping() {
+        use crate::token::builder;
+        use std::convert::TryInto;
+
+        let values = [
+            "simple",
+            "quote\" and backslash \\",
+            "\"), admin(#authority",
+            "line\nfeed",
+            "trailing backslash \\",
+        ];
+
+        for value in values.iter() {
+            let source = format!("user({})", quote_string(value));
+            let fact: builder::Fact = source.as_str().try_into().unwrap();
+            assert_eq!(fact.0.ids, vec![builder::string(value)]);
+            // printed facts can be parsed again
+            assert_eq!(fact.to_string(), source);
+        }
+
+        assert!(is_valid_name("read_1"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("read), admin(#authority"));
+        assert_eq!(namespace("billing:right"), Some("billing"));
+        assert_eq!(namespace("right"), None);
+    }
+
+    #[test]
+    fn symbol_table() {
+        let mut syms = SymbolTable::from_symbols(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(syms.insert("b"), 1);
+        assert_eq!(syms.insert("c"), 2);
+
+        // duplicates from appended tables resolve to the first occurrence
+        syms.extend(vec!["a".to_string(), "d".to_string()]);
+        assert_eq!(syms.get("a"), Some(0));
+        assert_eq!(syms.get("d"), Some(4));
+
+        let new = syms.split_off(2);
+        assert_eq!(new.symbols(), &["c", "a", "d"]);
+        assert_eq!(new.get("a"), Some(1));
+        assert_eq!(syms.symbols(), &["a", "b"]);
+        assert_eq!(syms.get("c"), None);
+        assert_eq!(syms.insert("d"), 2);
+    }
+
+    #[test]
+    fn printing() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let abc = syms.add("abc");
+        let x = syms.insert("x");
+        let y = syms.insert("y");
+
+        w.add_fact(fact(
+            x,
+            &[
+                &abc,
+                &ID::Str("a \"quote\"".to_string()),
+                &ID::Bytes(vec![0, 171]),
+                &ID::Set([int(1), int(2)].iter().cloned().collect()),
+            ],
+        ));
+        let r1 = expressed_rule(
+            y,
+            &[var(&mut syms, "nb")],
+            &[pred(x, &[var(&mut syms, "nb")])],
+            &[Expression {
+                ops: smallvec![
+                    Op::Value(ID::Integer(5)),
+                    Op::Value(ID::Integer(-4)),
+                    Op::Binary(Binary::Add),
+                    Op::Unary(Unary::Parens),
+                    Op::Value(var(&mut syms, "nb")),
+                    Op::Binary(Binary::LessThan),
+                    Op::Unary(Unary::Negate),
+                ],
+            }],
+        );
+        w.add_rule(r1.clone());
+
+        assert_eq!(syms.print_rule(&r1), "y($nb) <- x($nb), !(5 + -4) < $nb");
+        assert_eq!(
+            syms.print_world(&w),
+            "World {\n  facts: [\n    \"x(#abc, \\\"a \\\\\\\"quote\\\\\\\"\\\", hex:00ab, [1, 2])\",\n]\n  \
+             rules: [\n    \"y($nb) <- x($nb), !(5 + -4) < $nb\",\n]\n}"
+        );
+        assert_eq!(
+            syms.display_world(&World::new()).to_string(),
+            "World {\n  facts: []\n  rules: []\n}"
+        );
+
+        let invalid = Expression {
+            ops: smallvec![Op::Binary(Binary::Add)],
+        };
+        assert_eq!(invalid.print(&syms), None);
+        assert_eq!(
+            syms.print_expression(&invalid),
+            "<invalid expression: [Binary(Add)]>"
+        );
+    }
+
+    #[test]
+    fn shared_facts() {
+        let mut syms = SymbolTable::new();
+        let a = syms.add("a");
+        let b = syms.add("b");
+        let name = syms.insert("name");
+
+        let mut w = World::new();
+        w.add_fact(fact(name, &[&a]));
+        w.freeze();
+
+        let mut w2 = w.clone();
+        assert!(!w2.facts.insert(fact(name, &[&a])));
+        assert!(w2.facts.insert(fact(name, &[&b])));
+        assert_eq!(w2.facts.len(), 2);
+        assert_eq!(w.facts.len(), 1);
+
+        w2.freeze();
+        assert_eq!(
+            w2.facts,
+            vec![fact(name, &[&b]), fact(name, &[&a])]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn facts_limit() {
+        let mut syms = SymbolTable::new();
+        let value = syms.insert("value");
+        let pair = syms.insert("pair");
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+
+        let mut w = World::new();
+        for i in 0..10 {
+            w.add_fact(fact(value, &[&int(i)]));
+        }
+        w.add_rule(rule(
+            pair,
+            &[&x, &y],
+            &[pred(value, &[&x]), pred(value, &[&y])],
+        ));
+
+        // the 100 pairs are deduplicated from the existing facts as they are
+        // generated, and the limit is reached during the first iteration
+        let limits = RunLimits {
+            max_facts: 50,
+            ..Default::default()
+        };
+        assert_eq!(
+            w.clone().run_with_limits(limits, &[]),
+            Err(crate::error::RunLimit::TooManyFacts)
+        );
+
+        let limits = RunLimits {
+            max_facts: 111,
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+        assert_eq!(w.run_with_limits(limits, &[]), Ok(1));
+        assert_eq!(w.facts.len(), 110);
+    }
+
+    #[test]
+    fn long_rule_chain() {
+        const LENGTH: usize = 1500;
+
+        // the rules come from tokens, so a long chain must not need a
+        // deep stack
+        let handle = std::thread::Builder::new()
+            .stack_size(128 * 1024)
+            .spawn(|| {
+                let mut w = World::new();
+                let mut syms = SymbolTable::new();
+                let x = var(&mut syms, "x");
+                let names = (0..=LENGTH)
+                    .map(|i| syms.insert(&format!("p{}", i)))
+                    .collect::<Vec<_>>();
+
+                w.add_fact(fact(names[0], &[&int(1)]));
+                for i in 0..LENGTH {
+                    w.add_rule(rule(names[i + 1], &[&x], &[pred(names[i], &[&x])]));
+                }
+
+                let limits = || RunLimits {
+                    max_facts: 2 * LENGTH as u32 + 3,
+                    max_iterations: LENGTH as u32 + 1,
+                    max_time: Duration::from_secs(60),
+                };
+                let iterations = w.run_with_limits(limits(), &[]).unwrap();
+                assert!(w.facts.contains(&fact(names[LENGTH], &[&int(1)])));
+
+                // the rules are not analyzed again on the next run
+                w.add_fact(fact(names[0], &[&int(2)]));
+                assert_eq!(w.run_with_limits(limits(), &[]), Ok(iterations));
+                assert_eq!(w.facts.len(), 2 * (LENGTH + 1));
+            })
+            .unwrap();
+        handle.join().unwrap();
+    }
 }
@@ -0,0 +1,161 @@
+//! fact and rule storage, shared between clones of a world
+use super::{Fact, Rule, RuleGraph, Symbol};
+use std::collections::HashSet;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+/// facts of a world
+///
+/// the facts present at the last call to [`FactSet::freeze`] are shared by
+/// all the clones of the set, and facts inserted after that are only stored
+/// in the set they were added to
+#[derive(Clone, Debug, Default)]
+pub struct FactSet {
+    shared: Arc<HashSet<Fact>>,
+    local: HashSet<Fact>,
+}
+
+impl FactSet {
+    pub fn new() -> Self {
+        FactSet::default()
+    }
+
+    /// returns `false` if the fact was already present
+    pub fn insert(&mut self, fact: Fact) -> bool {
+        if self.shared.contains(&fact) {
+            false
+        } else {
+            self.local.insert(fact)
+        }
+    }
+
+    /// reserves space for at least `additional` new facts
+    pub fn reserve(&mut self, additional: usize) {
+        self.local.reserve(additional);
+    }
+
+    pub fn contains(&self, fact: &Fact) -> bool {
+        self.shared.contains(fact) || self.local.contains(fact)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len() + self.local.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.is_empty() && self.local.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Fact> {
+        self.shared.iter().chain(self.local.iter())
+    }
+
+    /// moves the facts added since the last call to the shared facts
+    pub fn freeze(&mut self) {
+        if !self.local.is_empty() {
+            Arc::make_mut(&mut self.shared).extend(self.local.drain());
+        }
+    }
+}
+
+impl PartialEq for FactSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|fact| other.contains(fact))
+    }
+}
+
+impl Extend<Fact> for FactSet {
+    fn extend<T: IntoIterator<Item = Fact>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for fact in iter {
+            self.insert(fact);
+        }
+    }
+}
+
+impl FromIterator<Fact> for FactSet {
+    fn from_iter<T: IntoIterator<Item = Fact>>(iter: T) -> Self {
+        let mut facts = FactSet::new();
+        facts.extend(iter);
+        facts
+    }
+}
+
+/// rules of a world
+///
+/// like [`FactSet`], the rules present at the last call to
+/// [`RuleSet::freeze`] are shared by all the clones of the set, with their
+/// dependency graph
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    shared: Arc<(Vec<Rule>, RuleGraph)>,
+    local: Vec<Rule>,
+    local_graph: RuleGraph,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    pub fn push(&mut self, rule: Rule) {
+        self.local_graph.push(&rule);
+        self.local.push(rule);
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.0.len() + self.local.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.0.is_empty() && self.local.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.shared.0.iter().chain(self.local.iter())
+    }
+
+    /// returns `true` if the rule at `index` can generate new facts when
+    /// facts with the `changed` predicate names were added, see
+    /// [`RuleGraph::depends_on`]
+    pub fn depends_on(&self, index: usize, changed: &HashSet<Symbol>) -> bool {
+        let shared = &self.shared.1;
+        if index < shared.len() {
+            shared.depends_on(index, changed)
+        } else {
+            self.local_graph.depends_on(index - shared.len(), changed)
+        }
+    }
+
+    /// moves the rules added since the last call to the shared rules
+    pub fn freeze(&mut self) {
+        if !self.local.is_empty() {
+            let (rules, graph) = Arc::make_mut(&mut self.shared);
+            rules.append(&mut self.local);
+            graph.append(&mut self.local_graph);
+        }
+    }
+}
+
+impl PartialEq for RuleSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Extend<Rule> for RuleSet {
+    fn extend<T: IntoIterator<Item = Rule>>(&mut self, iter: T) {
+        for rule in iter {
+            self.push(rule);
+        }
+    }
+}
+
+impl FromIterator<Rule> for RuleSet {
+    fn from_iter<T: IntoIterator<Item = Rule>>(iter: T) -> Self {
+        let mut rules = RuleSet::new();
+        rules.extend(iter);
+        rules
+    }
+}
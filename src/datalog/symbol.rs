@@ -1,12 +1,17 @@
 //! Symbol table implementation
 use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
 
 pub type Symbol = u64;
 use super::{Check, Fact, Predicate, Rule, World, ID};
 
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct SymbolTable {
-    pub symbols: Vec<String>,
+    symbols: Vec<String>,
+    /// position of each symbol in `symbols`. If a symbol appears multiple
+    /// times, this is its first position
+    index: HashMap<String, Symbol>,
 }
 
 impl SymbolTable {
@@ -14,12 +19,44 @@ impl SymbolTable {
         SymbolTable::default()
     }
 
+    pub fn from_symbols(symbols: Vec<String>) -> Self {
+        let mut table = SymbolTable::new();
+        table.extend(symbols);
+        table
+    }
+
+    /// symbols, in the order of their indexes
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub fn into_symbols(self) -> Vec<String> {
+        self.symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// removes the symbols starting at index `at`, and returns them in a new table
+    pub fn split_off(&mut self, at: usize) -> SymbolTable {
+        let symbols = self.symbols.split_off(at);
+        self.index.retain(|_, i| (*i as usize) < at);
+        SymbolTable::from_symbols(symbols)
+    }
+
     pub fn insert(&mut self, s: &str) -> Symbol {
-        match self.symbols.iter().position(|sym| sym.as_str() == s) {
-            Some(index) => index as u64,
+        match self.index.get(s) {
+            Some(index) => *index,
             None => {
                 self.symbols.push(s.to_string());
-                (self.symbols.len() - 1) as u64
+                let index = (self.symbols.len() - 1) as u64;
+                self.index.insert(s.to_string(), index);
+                index
             }
         }
     }
@@ -30,114 +67,240 @@ impl SymbolTable {
     }
 
     pub fn get(&self, s: &str) -> Option<Symbol> {
-        self.symbols
-            .iter()
-            .position(|sym| sym.as_str() == s)
-            .map(|i| i as u64)
+        self.index.get(s).copied()
     }
 
     pub fn print_symbol(&self, s: Symbol) -> String {
-        self.symbols
-            .get(s as usize)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| format!("<{}?>", s))
+        print(|w| self.write_symbol(w, s))
     }
 
     pub fn print_world(&self, w: &World) -> String {
-        let facts = w
-            .facts
-            .iter()
-            .map(|f| self.print_fact(f))
-            .collect::<Vec<_>>();
-        let rules = w
-            .rules
-            .iter()
-            .map(|r| self.print_rule(r))
-            .collect::<Vec<_>>();
-        format!("World {{\n  facts: {:#?}\n  rules: {:#?}\n}}", facts, rules)
+        print(|out| self.write_world(out, w))
     }
 
     pub fn print_id(&self, id: &ID) -> String {
+        print(|w| self.write_id(w, id))
+    }
+
+    pub fn print_fact(&self, f: &Fact) -> String {
+        print(|w| self.write_fact(w, f))
+    }
+
+    pub fn print_predicate(&self, p: &Predicate) -> String {
+        print(|w| self.write_predicate(w, p))
+    }
+
+    pub fn print_expression(&self, e: &super::expression::Expression) -> String {
+        print(|w| self.write_expression(w, e))
+    }
+
+    pub fn print_rule_body(&self, r: &Rule) -> String {
+        print(|w| self.write_rule_body(w, r))
+    }
+
+    pub fn print_rule(&self, r: &Rule) -> String {
+        print(|w| self.write_rule(w, r))
+    }
+
+    pub fn print_check(&self, c: &Check) -> String {
+        print(|w| self.write_check(w, c))
+    }
+
+    /// formats a world with [`SymbolTable::write_world`], to print it
+    /// directly to a `fmt::Write` or an `io::Write`:
+    ///
+    /// ```rust
+    /// use biscuit_auth::datalog::{SymbolTable, World};
+    /// use std::io::Write;
+    ///
+    /// let symbols = SymbolTable::new();
+    /// let world = World::new();
+    /// let mut out = std::io::sink();
+    /// write!(out, "{}", symbols.display_world(&world)).unwrap();
+    /// ```
+    pub fn display_world<'a>(&'a self, w: &'a World) -> impl fmt::Display + 'a {
+        DisplayWorld {
+            symbols: self,
+            world: w,
+        }
+    }
+
+    pub fn write_symbol<W: fmt::Write>(&self, w: &mut W, s: Symbol) -> fmt::Result {
+        match self.symbols.get(s as usize) {
+            Some(symbol) => w.write_str(symbol),
+            None => write!(w, "<{}?>", s),
+        }
+    }
+
+    /// writes the same output as [`SymbolTable::print_world`]
+    ///
+    /// a single buffer is reused to escape the facts and rules
+    pub fn write_world<W: fmt::Write>(&self, out: &mut W, w: &World) -> fmt::Result {
+        let mut buffer = String::new();
+
+        out.write_str("World {\n  facts: ")?;
+        self.write_debug_list(out, &mut buffer, w.facts.iter(), |buffer, f| {
+            self.write_fact(buffer, f)
+        })?;
+        out.write_str("\n  rules: ")?;
+        self.write_debug_list(out, &mut buffer, w.rules.iter(), |buffer, r| {
+            self.write_rule(buffer, r)
+        })?;
+        out.write_str("\n}")
+    }
+
+    /// writes items like `{:#?}` on a list of their printed forms
+    fn write_debug_list<W: fmt::Write, T, I: Iterator<Item = T>>(
+        &self,
+        out: &mut W,
+        buffer: &mut String,
+        items: I,
+        write_item: impl Fn(&mut String, T) -> fmt::Result,
+    ) -> fmt::Result {
+        let mut empty = true;
+        for item in items {
+            if empty {
+                out.write_str("[\n")?;
+                empty = false;
+            }
+            buffer.clear();
+            write_item(buffer, item)?;
+            writeln!(out, "    {:?},", buffer)?;
+        }
+
+        if empty {
+            out.write_str("[]")
+        } else {
+            out.write_str("]")
+        }
+    }
+
+    pub fn write_id<W: fmt::Write>(&self, w: &mut W, id: &ID) -> fmt::Result {
         match id {
-            ID::Variable(i) => format!("${}", self.print_symbol(*i as u64)),
-            ID::Integer(i) => i.to_string(),
-            ID::Str(s) => format!("\"{}\"", s),
-            ID::Symbol(index) => format!("#{}", self.print_symbol(*index as u64)),
+            ID::Variable(i) => {
+                w.write_char('$')?;
+                self.write_symbol(w, *i as u64)
+            }
+            ID::Integer(i) => write!(w, "{}", i),
+            ID::Str(s) => super::write_quoted_string(w, s),
+            ID::Symbol(index) => {
+                w.write_char('#')?;
+                self.write_symbol(w, *index)
+            }
             ID::Date(d) => {
                 let date =
                     DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(*d as i64, 0), Utc);
-                date.to_rfc3339()
+                w.write_str(&date.to_rfc3339())
             }
-            ID::Bytes(s) => format!("hex:{}", hex::encode(s)),
-            ID::Bool(b) => {
-                if *b {
-                    "true".to_string()
-                } else {
-                    "false".to_string()
-                }
+            ID::Bytes(s) => {
+                w.write_str("hex:")?;
+                s.iter().try_for_each(|byte| write!(w, "{:02x}", byte))
             }
+            ID::Bool(b) => w.write_str(if *b { "true" } else { "false" }),
             ID::Set(s) => {
-                let ids = s.iter().map(|id| self.print_id(id)).collect::<Vec<_>>();
-                format!("[{}]", ids.join(", "))
+                w.write_char('[')?;
+                for (index, id) in s.iter().enumerate() {
+                    if index > 0 {
+                        w.write_str(", ")?;
+                    }
+                    self.write_id(w, id)?;
+                }
+                w.write_char(']')
             }
         }
     }
-    pub fn print_fact(&self, f: &Fact) -> String {
-        self.print_predicate(&f.predicate)
+
+    pub fn write_fact<W: fmt::Write>(&self, w: &mut W, f: &Fact) -> fmt::Result {
+        self.write_predicate(w, &f.predicate)
     }
 
-    pub fn print_predicate(&self, p: &Predicate) -> String {
-        let strings = p.ids.iter().map(|id| self.print_id(id)).collect::<Vec<_>>();
-        format!(
-            "{}({})",
+    pub fn write_predicate<W: fmt::Write>(&self, w: &mut W, p: &Predicate) -> fmt::Result {
+        w.write_str(
             self.symbols
                 .get(p.name as usize)
                 .map(|s| s.as_str())
                 .unwrap_or("<?>"),
-            strings.join(", ")
-        )
+        )?;
+        w.write_char('(')?;
+        for (index, id) in p.ids.iter().enumerate() {
+            if index > 0 {
+                w.write_str(", ")?;
+            }
+            self.write_id(w, id)?;
+        }
+        w.write_char(')')
     }
 
-    pub fn print_expression(&self, e: &super::expression::Expression) -> String {
-        e.print(self)
-            .unwrap_or_else(|| format!("<invalid expression: {:?}>", e.ops))
+    pub fn write_expression<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        e: &super::expression::Expression,
+    ) -> fmt::Result {
+        e.write(w, self)
     }
 
-    pub fn print_rule_body(&self, r: &Rule) -> String {
-        let preds: Vec<_> = r.body.iter().map(|p| self.print_predicate(p)).collect();
-
-        let expressions: Vec<_> = r
-            .expressions
-            .iter()
-            .map(|c| self.print_expression(c))
-            .collect();
+    pub fn write_rule_body<W: fmt::Write>(&self, w: &mut W, r: &Rule) -> fmt::Result {
+        for (index, p) in r.body.iter().enumerate() {
+            if index > 0 {
+                w.write_str(", ")?;
+            }
+            self.write_predicate(w, p)?;
+        }
 
-        let e = if expressions.is_empty() {
-            String::new()
-        } else {
-            if preds.is_empty() {
-                expressions.join(", ")
-            } else {
-                format!(", {}", expressions.join(", "))
+        for (index, e) in r.expressions.iter().enumerate() {
+            if index > 0 || !r.body.is_empty() {
+                w.write_str(", ")?;
             }
-        };
+            self.write_expression(w, e)?;
+        }
 
-        format!("{}{}", preds.join(", "), e)
+        Ok(())
     }
 
-    pub fn print_rule(&self, r: &Rule) -> String {
-        let res = self.print_predicate(&r.head);
+    pub fn write_rule<W: fmt::Write>(&self, w: &mut W, r: &Rule) -> fmt::Result {
+        self.write_predicate(w, &r.head)?;
+        w.write_str(" <- ")?;
+        self.write_rule_body(w, r)
+    }
 
-        format!("{} <- {}", res, self.print_rule_body(r))
+    pub fn write_check<W: fmt::Write>(&self, w: &mut W, c: &Check) -> fmt::Result {
+        w.write_str("check if ")?;
+        for (index, r) in c.queries.iter().enumerate() {
+            if index > 0 {
+                w.write_str(" or ")?;
+            }
+            self.write_rule_body(w, r)?;
+        }
+        Ok(())
     }
+}
 
-    pub fn print_check(&self, c: &Check) -> String {
-        let queries = c
-            .queries
-            .iter()
-            .map(|r| self.print_rule_body(r))
-            .collect::<Vec<_>>();
+/// prints to a new string
+fn print<F: FnOnce(&mut String) -> fmt::Result>(f: F) -> String {
+    let mut res = String::new();
+    // writing to a String cannot fail
+    let _ = f(&mut res);
+    res
+}
+
+struct DisplayWorld<'a> {
+    symbols: &'a SymbolTable,
+    world: &'a World,
+}
 
-        format!("check if {}", queries.join(" or "))
+impl<'a> fmt::Display for DisplayWorld<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.symbols.write_world(f, self.world)
+    }
+}
+
+/// appends symbols, even if they are already present in the table
+impl Extend<String> for SymbolTable {
+    fn extend<T: IntoIterator<Item = String>>(&mut self, iter: T) {
+        for symbol in iter {
+            let index = self.symbols.len() as u64;
+            self.index.entry(symbol.clone()).or_insert(index);
+            self.symbols.push(symbol);
+        }
     }
 }
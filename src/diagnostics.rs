@@ -0,0 +1,235 @@
+//! rendering of errors with source snippets
+//!
+//! parse errors and checks added from Datalog source know where they come
+//! from. A [`Diagnostic`] extracts those positions from an error, and renders
+//! them with the lines of source they point to:
+//!
+//! ```rust
+//! use biscuit_auth::{diagnostics::Diagnostic, token::verifier::Verifier};
+//!
+//! let source = "resource(#ambient, \"file1\");\ncheck if operation(#ambient, #read);";
+//! let mut verifier = Verifier::new().unwrap();
+//! verifier.add_code(source).unwrap();
+//! verifier.allow().unwrap();
+//!
+//! let error = verifier.verify().unwrap_err();
+//! let diagnostic = Diagnostic::from_error(&error);
+//! assert_eq!(
+//!     diagnostic.render(source),
+//!     "error[logic.checks.failed]: check validation failed
+//!  --> 2:1
+//!   |
+//! 2 | check if operation(#ambient, #read);
+//!   | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ this check failed
+//!   |
+//! "
+//! );
+//! ```
+use crate::error;
+use crate::token::verifier::PolicyLoader;
+use std::fmt::Write;
+
+/// an error, with the parts of the source it points to
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// stable error code, from [`error::Token::code`]
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    /// additional information that has no position in the source
+    pub notes: Vec<String>,
+    /// suggested fix
+    pub help: Option<String>,
+}
+
+/// a location in the source, with a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    /// policy file, `None` for the source given to [`Diagnostic::render`]
+    pub file: Option<String>,
+    /// line, starting at 1
+    pub line: usize,
+    /// column, in characters, starting at 1
+    pub column: usize,
+    /// length of the underlined part, in characters
+    pub length: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn from_error(e: &error::Token) -> Self {
+        let mut diagnostic = Diagnostic {
+            code: e.code(),
+            message: e.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        };
+
+        match e {
+            error::Token::ParseError(e) => {
+                diagnostic.message = "Datalog parsing error".to_string();
+                diagnostic.labels.push(Label {
+                    file: None,
+                    line: e.line,
+                    column: e.column,
+                    length: e.fragment.lines().next().unwrap_or("").chars().count(),
+                    message: e
+                        .message
+                        .clone()
+                        .unwrap_or_else(|| "unexpected input".to_string()),
+                });
+                if !e.expected.is_empty() {
+                    diagnostic
+                        .notes
+                        .push(format!("expected {}", e.expected.join(" or ")));
+                }
+                diagnostic.help = e.hint.clone();
+            }
+            error::Token::FailedLogic(error::Logic::FailedChecks(checks)) => {
+                for check in checks.iter() {
+                    match check {
+                        error::FailedCheck::Verifier(error::FailedVerifierCheck {
+                            span: Some(span),
+                            ..
+                        })
+                        | error::FailedCheck::Block(error::FailedBlockCheck {
+                            span: Some(span),
+                            ..
+                        }) => diagnostic.labels.push(Label {
+                            file: span.file.clone(),
+                            line: span.line,
+                            column: span.column,
+                            length: span.end - span.start,
+                            message: "this check failed".to_string(),
+                        }),
+                        error::FailedCheck::Verifier(c) => diagnostic
+                            .notes
+                            .push(format!("verifier check {} failed: {}", c.check_id, c.rule)),
+                        error::FailedCheck::Block(c) => diagnostic.notes.push(format!(
+                            "check {} of block {} failed: {}",
+                            c.check_id, c.block_id, c.rule
+                        )),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        diagnostic
+    }
+
+    /// renders the diagnostic, with labels pointing into `source`
+    ///
+    /// this is the source given to [`Verifier::add_code`](crate::token::verifier::Verifier::add_code)
+    /// or to the parser
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(|file| match file {
+            None => Some(source.to_string()),
+            Some(_) => None,
+        })
+    }
+
+    /// renders the diagnostic, with labels pointing into policy files
+    /// loaded with [`Verifier::add_policy_file`](crate::token::verifier::Verifier::add_policy_file)
+    pub fn render_files<L: PolicyLoader + ?Sized>(&self, loader: &L) -> String {
+        self.render_with(|file| file.and_then(|path| loader.load(path).ok()))
+    }
+
+    fn render_with<F: Fn(Option<&str>) -> Option<String>>(&self, source: F) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "error[{}]: {}", self.code, self.message);
+
+        for label in self.labels.iter() {
+            let location = match &label.file {
+                Some(file) => format!("{}:{}:{}", file, label.line, label.column),
+                None => format!("{}:{}", label.line, label.column),
+            };
+            let line = source(label.file.as_deref())
+                .and_then(|s| s.lines().nth(label.line - 1).map(str::to_string));
+
+            let gutter = " ".repeat(label.line.to_string().len());
+            let _ = writeln!(out, "{}--> {}", gutter, location);
+            match line {
+                Some(line) => {
+                    let available = line.chars().count().saturating_sub(label.column - 1);
+                    let _ = writeln!(out, "{} |", gutter);
+                    let _ = writeln!(out, "{} | {}", label.line, line);
+                    let _ = writeln!(
+                        out,
+                        "{} | {}{} {}",
+                        gutter,
+                        " ".repeat(label.column - 1),
+                        "^".repeat(label.length.min(available).max(1)),
+                        label.message
+                    );
+                    let _ = writeln!(out, "{} |", gutter);
+                }
+                None => {
+                    let _ = writeln!(out, "{} = {}", gutter, label.message);
+                }
+            }
+        }
+
+        for note in self.notes.iter() {
+            let _ = writeln!(out, "  = note: {}", note);
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "  = help: {}", help);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{builder::Check, verifier::Verifier};
+    use std::{collections::HashMap, convert::TryFrom};
+
+    #[test]
+    fn parse_error() {
+        let source = "check if resource(#ambient, file)";
+        let e = Check::try_from(source).unwrap_err();
+
+        assert_eq!(
+            Diagnostic::from_error(&e).render(source),
+            r#"error[datalog.parse]: Datalog parsing error
+ --> 1:29
+  |
+1 | check if resource(#ambient, file)
+  |                             ^^^^^ unexpected input
+  |
+  = note: expected '#' or '"' or '$' or digit or '[' or '{'
+  = help: variables start with `$`, did you mean `$file`?
+"#
+        );
+    }
+
+    #[test]
+    fn failed_checks_in_files() {
+        let mut files = HashMap::new();
+        files.insert(
+            "main.datalog".to_string(),
+            "allow if true;\n  check if user($user);".to_string(),
+        );
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_policy_file("main.datalog", &files).unwrap();
+        verifier.add_check("check if admin(#authority)").unwrap();
+        let e = verifier.verify().unwrap_err();
+
+        assert_eq!(
+            Diagnostic::from_error(&e).render_files(&files),
+            "error[logic.checks.failed]: check validation failed
+ --> main.datalog:2:3
+  |
+2 |   check if user($user);
+  |   ^^^^^^^^^^^^^^^^^^^^ this check failed
+  |
+  = note: verifier check 1 failed: check if admin(#authority)
+"
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! discovery of the root keys of token issuers
+//!
+//! each issuer publishes the root public keys it currently signs tokens
+//! with at `https://issuer/.well-known/biscuit-keys`, as a [`KeySet`] signed
+//! by a long term signing key. Verifiers pin the signing key of each issuer,
+//! so a compromised web server cannot publish its own root keys:
+//!
+//! ```json
+//! {"issuer": "https://issuer", "sequence": 2, "keys": ["<hex>", ...], "signature": "<hex>"}
+//! ```
+//!
+//! the issuer increments the sequence number of each new key set, so an
+//! older key set, replayed after a key was removed, is refused
+//!
+//! [`KeyDiscovery`] fetches the key sets through an [`HttpClient`], and
+//! caches them following the `max-age` of their `Cache-Control` header.
+//! When a token is signed by a key missing from the cache, the key set is
+//! fetched again, so the issuer can rotate its keys without waiting for
+//! the cache to expire
+use crate::crypto::{self, KeyPair, PublicKey};
+use crate::error;
+use crate::token::verifier::{Clock, SystemClock, Verifier};
+use crate::token::Biscuit;
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// path of the key set, relative to the issuer
+pub const WELL_KNOWN_PATH: &str = "/.well-known/biscuit-keys";
+
+/// HTTP client used to fetch the key sets
+pub trait HttpClient: Send + Sync {
+    /// sends a GET request, and returns the response if its status is
+    /// successful
+    fn get(&self, url: &str) -> Result<Response, DiscoveryError>;
+}
+
+/// response to a GET request
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Response {
+    pub body: Vec<u8>,
+    /// value of the `Cache-Control` header
+    pub cache_control: Option<String>,
+}
+
+/// error returned when discovering the keys of an issuer
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum DiscoveryError {
+    /// the issuer was not added with [`KeyDiscovery::add_issuer`]
+    #[error("unknown issuer {0}")]
+    UnknownIssuer(String),
+    #[error("the issuer {0} is not an https URL")]
+    InsecureIssuer(String),
+    /// the request failed, or returned an unsuccessful status
+    #[error("HTTP error: {0}")]
+    Http(String),
+    #[error("malformed key set: {0}")]
+    Malformed(String),
+    /// the key set was not signed by the pinned signing key of the issuer
+    #[error("invalid key set signature")]
+    InvalidSignature,
+    #[error("the key set is for the issuer {found}, expected {expected}")]
+    IssuerMismatch { expected: String, found: String },
+    /// the sequence number of the key set is lower than the one of the
+    /// cached key set
+    #[error("the key set has the sequence number {found}, older than {cached}")]
+    Outdated { cached: u64, found: u64 },
+    /// the token is not signed by one of the keys of the issuer
+    #[error("invalid token: {0}")]
+    Token(error::Token),
+}
+
+/// root keys published by an issuer
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeySet {
+    pub issuer: String,
+    /// incremented by the issuer for each new key set
+    pub sequence: u64,
+    pub keys: Vec<PublicKey>,
+    signature: (Scalar, Scalar),
+}
+
+impl KeySet {
+    /// signs a key set with the signing key of the issuer
+    pub fn new(signing: &KeyPair, issuer: &str, sequence: u64, keys: Vec<PublicKey>) -> Self {
+        KeySet::new_with_rng(&mut rand::rngs::OsRng, signing, issuer, sequence, keys)
+    }
+
+    /// signs a key set, using the provided CSPRNG
+    pub fn new_with_rng<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        signing: &KeyPair,
+        issuer: &str,
+        sequence: u64,
+        keys: Vec<PublicKey>,
+    ) -> Self {
+        let signature = signing.sign(rng, &message(issuer, sequence, &keys));
+        KeySet {
+            issuer: issuer.to_string(),
+            sequence,
+            keys,
+            signature,
+        }
+    }
+
+    /// checks the signature of the key set
+    pub fn verify(&self, signing_key: PublicKey) -> bool {
+        crypto::verify(
+            &signing_key.0,
+            &message(&self.issuer, self.sequence, &self.keys),
+            &self.signature,
+        )
+    }
+
+    /// serializes the key set to the JSON document published by the issuer
+    pub fn to_json(&self) -> String {
+        let (d, z) = &self.signature;
+        let mut signature = d.to_bytes().to_vec();
+        signature.extend_from_slice(&z.to_bytes());
+
+        json!({
+            "issuer": self.issuer,
+            "sequence": self.sequence,
+            "keys": self.keys.iter().map(|k| hex::encode(k.to_bytes())).collect::<Vec<_>>(),
+            "signature": hex::encode(signature),
+        })
+        .to_string()
+    }
+
+    pub fn from_json(data: &[u8]) -> Result<Self, DiscoveryError> {
+        let malformed = |message: &str| DiscoveryError::Malformed(message.to_string());
+        let value: Value =
+            serde_json::from_slice(data).map_err(|e| DiscoveryError::Malformed(e.to_string()))?;
+
+        let issuer = value["issuer"]
+            .as_str()
+            .ok_or_else(|| malformed("missing issuer"))?;
+        let sequence = value["sequence"]
+            .as_u64()
+            .ok_or_else(|| malformed("missing sequence"))?;
+        let keys = value["keys"]
+            .as_array()
+            .ok_or_else(|| malformed("missing keys"))?
+            .iter()
+            .map(|key| {
+                key.as_str()
+                    .and_then(|key| hex::decode(key).ok())
+                    .and_then(|key| PublicKey::from_bytes(&key))
+                    .ok_or_else(|| malformed("invalid key"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let signature = value["signature"]
+            .as_str()
+            .and_then(|s| hex::decode(s).ok())
+            .filter(|s| s.len() == 64)
+            .ok_or_else(|| malformed("invalid signature"))?;
+        let scalar = |bytes: &[u8]| {
+            Scalar::from_canonical_bytes(bytes.try_into().unwrap())
+                .ok_or_else(|| malformed("invalid signature"))
+        };
+
+        Ok(KeySet {
+            issuer: issuer.to_string(),
+            sequence,
+            keys,
+            signature: (scalar(&signature[..32])?, scalar(&signature[32..])?),
+        })
+    }
+}
+
+/// content signed by the signing key of the issuer
+fn message(issuer: &str, sequence: u64, keys: &[PublicKey]) -> Vec<u8> {
+    let mut message = b"biscuit-keys".to_vec();
+    message.extend_from_slice(&(issuer.len() as u64).to_le_bytes());
+    message.extend_from_slice(issuer.as_bytes());
+    message.extend_from_slice(&sequence.to_le_bytes());
+    for key in keys {
+        message.extend_from_slice(&key.to_bytes());
+    }
+    message
+}
+
+/// URL of the key set of an issuer, which must use https
+pub fn well_known_url(issuer: &str) -> Result<String, DiscoveryError> {
+    if !issuer.starts_with("https://") {
+        return Err(DiscoveryError::InsecureIssuer(issuer.to_string()));
+    }
+    Ok(format!(
+        "{}{}",
+        issuer.trim_end_matches('/'),
+        WELL_KNOWN_PATH
+    ))
+}
+
+struct CachedKeys {
+    keys: Vec<PublicKey>,
+    sequence: u64,
+    expires: SystemTime,
+}
+
+/// fetches and caches the root keys of a set of issuers
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::Biscuit};
+/// use biscuit_auth::discovery::{DiscoveryError, HttpClient, KeyDiscovery, KeySet, Response};
+///
+/// let signing = KeyPair::new();
+/// let root = KeyPair::new();
+/// let key_set = KeySet::new(&signing, "https://issuer.example", 1, vec![root.public()]);
+///
+/// // serves the key set, a real client would send a request
+/// struct Client(String);
+/// impl HttpClient for Client {
+///     fn get(&self, url: &str) -> Result<Response, DiscoveryError> {
+///         assert_eq!(url, "https://issuer.example/.well-known/biscuit-keys");
+///         Ok(Response { body: self.0.clone().into_bytes(), cache_control: None })
+///     }
+/// }
+///
+/// let mut discovery = KeyDiscovery::new(Client(key_set.to_json()));
+/// discovery.add_issuer("https://issuer.example", signing.public()).unwrap();
+///
+/// let token = Biscuit::builder(&root).build().unwrap();
+/// let mut verifier = discovery.verify(&token, "https://issuer.example").unwrap();
+/// verifier.allow().unwrap();
+/// assert!(verifier.verify().is_ok());
+/// ```
+pub struct KeyDiscovery<C: HttpClient> {
+    client: C,
+    /// pinned signing key of each issuer
+    issuers: HashMap<String, PublicKey>,
+    cache: RwLock<HashMap<String, CachedKeys>>,
+    /// time of the last request for each issuer, successful or not
+    requests: RwLock<HashMap<String, SystemTime>>,
+    clock: Arc<dyn Clock + Send + Sync>,
+    default_ttl: Duration,
+    max_ttl: Duration,
+    max_stale: Duration,
+    min_refresh: Duration,
+}
+
+impl<C: HttpClient> KeyDiscovery<C> {
+    /// the key sets are cached for one hour if the response has no
+    /// `max-age`, and at most one day. An expired key set is used for one
+    /// more day if the issuer cannot be reached
+    pub fn new(client: C) -> Self {
+        KeyDiscovery {
+            client,
+            issuers: HashMap::new(),
+            cache: RwLock::new(HashMap::new()),
+            requests: RwLock::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+            default_ttl: Duration::from_secs(3600),
+            max_ttl: Duration::from_secs(86400),
+            max_stale: Duration::from_secs(86400),
+            min_refresh: Duration::from_secs(60),
+        }
+    }
+
+    /// trusts an issuer, whose key sets must be signed by `signing_key`
+    pub fn add_issuer(
+        &mut self,
+        issuer: &str,
+        signing_key: PublicKey,
+    ) -> Result<(), DiscoveryError> {
+        well_known_url(issuer)?;
+        self.issuers.insert(issuer.to_string(), signing_key);
+        Ok(())
+    }
+
+    /// sets the duration of the cache when the response has no `max-age`,
+    /// and the maximum duration
+    pub fn set_ttl(&mut self, default_ttl: Duration, max_ttl: Duration) {
+        self.default_ttl = default_ttl;
+        self.max_ttl = max_ttl;
+    }
+
+    /// sets how long after its expiration a key set is still used when the
+    /// issuer cannot be reached, one day by default
+    pub fn set_max_stale(&mut self, max_stale: Duration) {
+        self.max_stale = max_stale;
+    }
+
+    /// sets the minimum delay between two requests for the same issuer,
+    /// one minute by default, so tokens signed by unknown keys do not
+    /// trigger a request each
+    pub fn set_min_refresh(&mut self, min_refresh: Duration) {
+        self.min_refresh = min_refresh;
+    }
+
+    /// replaces the clock used for the cache, the system clock by default
+    pub fn set_clock<T: Clock + Send + Sync + 'static>(&mut self, clock: T) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// returns the root keys of the issuer, from the cache if it has not
+    /// expired
+    ///
+    /// if the request fails, the keys of a cache that expired less than the
+    /// [maximum stale duration](KeyDiscovery::set_max_stale) ago are returned,
+    /// and the key set is not fetched again before the
+    /// [minimum refresh delay](KeyDiscovery::set_min_refresh)
+    pub fn keys(&self, issuer: &str) -> Result<Vec<PublicKey>, DiscoveryError> {
+        let now = self.clock.now();
+        let stale = |now: SystemTime| {
+            self.cache
+                .read()
+                .unwrap()
+                .get(issuer)
+                .filter(|cached| now < cached.expires + self.max_stale)
+                .map(|cached| cached.keys.clone())
+        };
+        if let Some(cached) = self.cache.read().unwrap().get(issuer) {
+            if now < cached.expires {
+                return Ok(cached.keys.clone());
+            }
+        }
+        if self.requested_recently(issuer, now) {
+            if let Some(keys) = stale(now) {
+                return Ok(keys);
+            }
+        }
+
+        self.refresh(issuer).or_else(|e| stale(now).ok_or(e))
+    }
+
+    /// fetches the key set of the issuer, ignoring the cache
+    ///
+    /// a key set older than the cached one is refused
+    pub fn refresh(&self, issuer: &str) -> Result<Vec<PublicKey>, DiscoveryError> {
+        let signing_key = *self
+            .issuers
+            .get(issuer)
+            .ok_or_else(|| DiscoveryError::UnknownIssuer(issuer.to_string()))?;
+
+        // failed requests count for the minimum refresh delay
+        self.requests
+            .write()
+            .unwrap()
+            .insert(issuer.to_string(), self.clock.now());
+        let response = self.client.get(&well_known_url(issuer)?)?;
+        let key_set = KeySet::from_json(&response.body)?;
+        if key_set.issuer != issuer {
+            return Err(DiscoveryError::IssuerMismatch {
+                expected: issuer.to_string(),
+                found: key_set.issuer,
+            });
+        }
+        if !key_set.verify(signing_key) {
+            return Err(DiscoveryError::InvalidSignature);
+        }
+
+        let ttl = response
+            .cache_control
+            .as_deref()
+            .and_then(max_age)
+            .unwrap_or(self.default_ttl)
+            .min(self.max_ttl);
+        let now = self.clock.now();
+        let mut cache = self.cache.write().unwrap();
+        if let Some(cached) = cache.get(issuer) {
+            if key_set.sequence < cached.sequence {
+                return Err(DiscoveryError::Outdated {
+                    cached: cached.sequence,
+                    found: key_set.sequence,
+                });
+            }
+        }
+        cache.insert(
+            issuer.to_string(),
+            CachedKeys {
+                keys: key_set.keys.clone(),
+                sequence: key_set.sequence,
+                expires: now + ttl,
+            },
+        );
+        Ok(key_set.keys)
+    }
+
+    /// verifies that the token was signed by one of the keys of the issuer,
+    /// and creates a verifier for it
+    ///
+    /// if the key is not in the cache, the key set is fetched again, unless
+    /// it was fetched less than the minimum refresh delay ago
+    pub fn verify(&self, token: &Biscuit, issuer: &str) -> Result<Verifier, DiscoveryError> {
+        let keys = self.keys(issuer)?;
+        let error = match verify_with(token, &keys) {
+            Ok(verifier) => return Ok(verifier),
+            Err(e) => e,
+        };
+
+        if self.requested_recently(issuer, self.clock.now()) {
+            return Err(error);
+        }
+
+        let keys = self.refresh(issuer)?;
+        verify_with(token, &keys)
+    }
+
+    /// returns `true` if the key set was requested less than the minimum
+    /// refresh delay before `now`
+    fn requested_recently(&self, issuer: &str, now: SystemTime) -> bool {
+        self.requests
+            .read()
+            .unwrap()
+            .get(issuer)
+            .map(|requested| {
+                now.duration_since(*requested)
+                    .map(|elapsed| elapsed < self.min_refresh)
+                    .unwrap_or(true)
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn verify_with(token: &Biscuit, keys: &[PublicKey]) -> Result<Verifier, DiscoveryError> {
+    let key = keys
+        .iter()
+        .find(|key| token.check_root_key(**key).is_ok())
+        .ok_or(DiscoveryError::Token(error::Token::Format(
+            error::Format::UnknownPublicKey,
+        )))?;
+    token.verify(*key).map_err(DiscoveryError::Token)
+}
+
+/// extracts the `max-age` directive of a `Cache-Control` header
+fn max_age(cache_control: &str) -> Option<Duration> {
+    cache_control.split(',').find_map(|directive| {
+        let (name, value) = directive.trim().split_once('=')?;
+        if name.eq_ignore_ascii_case("max-age") {
+            value
+                .trim_matches('"')
+                .parse()
+                .ok()
+                .map(Duration::from_secs)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// serves the responses in order, and counts the requests
+    #[derive(Default)]
+    struct TestClient {
+        responses: Mutex<Vec<Result<Response, DiscoveryError>>>,
+        requests: Mutex<usize>,
+    }
+
+    impl TestClient {
+        fn push(&self, response: Result<Response, DiscoveryError>) {
+            self.responses.lock().unwrap().push(response);
+        }
+    }
+
+    impl HttpClient for Arc<TestClient> {
+        fn get(&self, url: &str) -> Result<Response, DiscoveryError> {
+            assert_eq!(url, "https://issuer.example/.well-known/biscuit-keys");
+            *self.requests.lock().unwrap() += 1;
+            self.responses.lock().unwrap().remove(0)
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestClock(Arc<Mutex<SystemTime>>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn response(key_set: &KeySet, cache_control: &str) -> Result<Response, DiscoveryError> {
+        Ok(Response {
+            body: key_set.to_json().into_bytes(),
+            cache_control: Some(cache_control.to_string()),
+        })
+    }
+
+    #[test]
+    fn rotation() {
+        const ISSUER: &str = "https://issuer.example";
+        let signing = KeyPair::new();
+        let root1 = KeyPair::new();
+        let root2 = KeyPair::new();
+        let token1 = Biscuit::builder(&root1).build().unwrap();
+        let token2 = Biscuit::builder(&root2).build().unwrap();
+
+        let client = Arc::new(TestClient::default());
+        let clock = TestClock(Arc::new(Mutex::new(SystemTime::now())));
+        let mut discovery = KeyDiscovery::new(client.clone());
+        discovery.set_clock(clock.clone());
+        discovery.add_issuer(ISSUER, signing.public()).unwrap();
+
+        let key_set = KeySet::new(&signing, ISSUER, 1, vec![root1.public()]);
+        assert_eq!(
+            KeySet::from_json(key_set.to_json().as_bytes()),
+            Ok(key_set.clone())
+        );
+        client.push(response(&key_set, "public, max-age=600"));
+        assert!(discovery.verify(&token1, ISSUER).is_ok());
+        assert!(discovery.verify(&token1, ISSUER).is_ok());
+        assert_eq!(*client.requests.lock().unwrap(), 1);
+
+        // the key is unknown, but the key set was fetched recently
+        assert_eq!(
+            discovery.verify(&token2, ISSUER).err(),
+            Some(DiscoveryError::Token(error::Token::Format(
+                error::Format::UnknownPublicKey
+            )))
+        );
+        assert_eq!(*client.requests.lock().unwrap(), 1);
+
+        // the issuer rotated its keys
+        *clock.0.lock().unwrap() += Duration::from_secs(120);
+        let key_set = KeySet::new(&signing, ISSUER, 2, vec![root1.public(), root2.public()]);
+        client.push(response(&key_set, "max-age=600"));
+        assert!(discovery.verify(&token2, ISSUER).is_ok());
+        assert_eq!(*client.requests.lock().unwrap(), 2);
+
+        // the cache expired, and the request fails
+        *clock.0.lock().unwrap() += Duration::from_secs(700);
+        client.push(Err(DiscoveryError::Http("503".to_string())));
+        assert!(discovery.verify(&token2, ISSUER).is_ok());
+        assert_eq!(*client.requests.lock().unwrap(), 3);
+
+        // the failed request counts for the minimum refresh delay, the
+        // expired cache is used until then
+        let token3 = Biscuit::builder(&KeyPair::new()).build().unwrap();
+        assert!(discovery.verify(&token3, ISSUER).is_err());
+        assert!(discovery.verify(&token2, ISSUER).is_ok());
+        assert_eq!(
+            discovery.keys(ISSUER),
+            Ok(vec![root1.public(), root2.public()])
+        );
+        assert_eq!(*client.requests.lock().unwrap(), 3);
+
+        *clock.0.lock().unwrap() += Duration::from_secs(30);
+        assert!(discovery.keys(ISSUER).is_ok());
+        assert_eq!(*client.requests.lock().unwrap(), 3);
+
+        *clock.0.lock().unwrap() += Duration::from_secs(30);
+        client.push(Err(DiscoveryError::Http("503".to_string())));
+        assert!(discovery.keys(ISSUER).is_ok());
+        assert_eq!(*client.requests.lock().unwrap(), 4);
+
+        // a replayed key set is refused
+        *clock.0.lock().unwrap() += Duration::from_secs(120);
+        client.push(response(
+            &KeySet::new(&signing, ISSUER, 1, vec![root1.public()]),
+            "max-age=600",
+        ));
+        assert_eq!(
+            discovery.refresh(ISSUER),
+            Err(DiscoveryError::Outdated {
+                cached: 2,
+                found: 1
+            })
+        );
+
+        // the expired cache is not used forever
+        *clock.0.lock().unwrap() += Duration::from_secs(86400);
+        client.push(Err(DiscoveryError::Http("503".to_string())));
+        assert_eq!(
+            discovery.keys(ISSUER),
+            Err(DiscoveryError::Http("503".to_string()))
+        );
+        assert_eq!(*client.requests.lock().unwrap(), 6);
+    }
+
+    #[test]
+    fn pinning() {
+        const ISSUER: &str = "https://issuer.example";
+        let signing = KeyPair::new();
+        let root = KeyPair::new();
+
+        let client = Arc::new(TestClient::default());
+        let mut discovery = KeyDiscovery::new(client.clone());
+        discovery.add_issuer(ISSUER, signing.public()).unwrap();
+
+        client.push(response(
+            &KeySet::new(&KeyPair::new(), ISSUER, 1, vec![root.public()]),
+            "",
+        ));
+        assert_eq!(
+            discovery.keys(ISSUER),
+            Err(DiscoveryError::InvalidSignature)
+        );
+
+        client.push(response(
+            &KeySet::new(&signing, "https://other.example", 1, vec![root.public()]),
+            "",
+        ));
+        assert_eq!(
+            discovery.keys(ISSUER),
+            Err(DiscoveryError::IssuerMismatch {
+                expected: ISSUER.to_string(),
+                found: "https://other.example".to_string(),
+            })
+        );
+
+        assert_eq!(
+            discovery.keys("https://other.example"),
+            Err(DiscoveryError::UnknownIssuer(
+                "https://other.example".to_string()
+            ))
+        );
+        assert_eq!(
+            discovery.add_issuer("http://issuer.example", signing.public()),
+            Err(DiscoveryError::InsecureIssuer(
+                "http://issuer.example".to_string()
+            ))
+        );
+        assert_eq!(
+            max_age("no-cache, max-age=\"30\""),
+            Some(Duration::from_secs(30))
+        );
+    }
+}
@@ -1,11 +1,19 @@
 //! error types
 //!
+//! with the `serde-error` feature, the errors implement `serde::Serialize`.
+//! Variant and field names are part of the serialized format, so they will
+//! not be renamed
 
-use std::convert::{From, Infallible};
+use std::{
+    collections::HashMap,
+    convert::{From, Infallible},
+    fmt,
+};
 use thiserror::Error;
 
 /// the global error type for Biscuit
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum Token {
     #[error("internal error")]
     InternalError,
@@ -23,14 +31,133 @@ pub enum Token {
     Sealed,
     #[error("check validation failed")]
     FailedLogic(Logic),
-    #[error("Datalog parsing error")]
-    ParseError,
+    #[error("Datalog parsing error: {0}")]
+    ParseError(ParseError),
     #[error("Reached Datalog execution limits")]
     RunLimit(RunLimit),
     #[error("Cannot convert from Term: %s")]
     ConversionError(String),
     #[error("Cannot decode base64 token: %s")]
-    Base64(base64::DecodeError),
+    Base64(
+        #[cfg_attr(feature = "serde-error", serde(serialize_with = "serialize_display"))]
+        base64::DecodeError,
+    ),
+    #[error("Cannot load Datalog source: {0}")]
+    LoadError(String),
+    /// a revocation identifier of the block at this index is in the
+    /// verifier's [`RevocationStore`](crate::token::revocation::RevocationStore)
+    #[error("the token was revoked")]
+    Revoked(u32),
+}
+
+impl Token {
+    /// stable identifier of the error, that can be used by HTTP APIs and
+    /// FFI layers instead of the display message
+    ///
+    /// codes will not change in later versions, and new variants get new codes
+    pub fn code(&self) -> &'static str {
+        match self {
+            Token::InternalError => "internal",
+            Token::Format(e) => e.code(),
+            Token::InvalidAuthorityIndex(_) => "token.authority_index.invalid",
+            Token::InvalidBlockIndex(_) => "token.block_index.invalid",
+            Token::SymbolTableOverlap => "token.symbols.overlap",
+            Token::MissingSymbols => "token.symbols.missing",
+            Token::Sealed => "token.sealed",
+            Token::FailedLogic(e) => e.code(),
+            Token::ParseError(_) => "datalog.parse",
+            Token::RunLimit(e) => e.code(),
+            Token::ConversionError(_) => "datalog.conversion",
+            Token::Base64(_) => "format.base64",
+            Token::LoadError(_) => "datalog.load",
+            Token::Revoked(_) => "token.revoked",
+        }
+    }
+
+    /// renders the error with the catalog's template for its code, or with
+    /// the default message if the catalog has none
+    pub fn localized_message(&self, catalog: &dyn MessageCatalog) -> String {
+        let params = match self {
+            Token::InvalidAuthorityIndex(index) => vec![("index", index.to_string())],
+            Token::Revoked(block_id) => vec![("block_id", block_id.to_string())],
+            Token::InvalidBlockIndex(e) => vec![
+                ("expected", e.expected.to_string()),
+                ("found", e.found.to_string()),
+            ],
+            Token::Format(e) => e.params(),
+            Token::FailedLogic(e) => e.params(catalog),
+            Token::ParseError(e) => vec![
+                ("line", e.line.to_string()),
+                ("column", e.column.to_string()),
+                ("fragment", e.fragment.clone()),
+            ],
+            Token::ConversionError(message) | Token::LoadError(message) => {
+                vec![("message", message.clone())]
+            }
+            Token::Base64(e) => vec![("message", e.to_string())],
+            _ => vec![],
+        };
+
+        localize(catalog, self.code(), self.to_string(), &params)
+    }
+}
+
+/// templates of the error messages, used to translate them for end users
+///
+/// templates are looked up by error code (see [`Token::code`]), and their
+/// `{name}` placeholders are replaced with the parameters of the error:
+///
+/// - `{index}` for `token.authority_index.invalid`
+/// - `{block_id}` for `token.revoked`
+/// - `{expected}` and `{found}` for `token.block_index.invalid`
+/// - `{maximum}` and `{actual}` for `format.version.unsupported`
+/// - `{expected}` and `{actual}` for `format.sealed_signature.invalid`, the
+///   key ids or `none`
+/// - `{accepted}` and `{actual}` for `format.sealed_signature.unknown_key`,
+///   the key ids of the set separated by `, `, and the key id or `none`
+/// - `{kind}`, `{size}` and `{maximum}` for `format.term.too_large`
+/// - `{maximum}` and `{actual}` for `format.blocks.too_many`
+/// - `{threshold}` and `{signers}` for `format.root_keys.threshold`
+/// - `{minimum}` and `{actual}` for `format.revocation_list.outdated`
+/// - `{line}`, `{column}` and `{fragment}` for `datalog.parse`
+/// - `{fact}`, `{rule}` and `{block_id}` for the invalid facts and rules
+/// - `{checks}` for `logic.checks.failed`, the failed checks rendered with
+///   their own templates and separated by `, `
+/// - `{block_id}`, `{check_id}` and `{rule}` for `logic.check.block.failed`
+///   and `logic.check.verifier.failed`
+/// - `{policy_id}`, `{label}` and `{policy}` for `logic.policy.deny`
+/// - `{message}` for the errors carrying a message
+pub trait MessageCatalog {
+    /// template for this error code, or `None` to use the default message
+    fn template(&self, code: &str) -> Option<String>;
+}
+
+impl MessageCatalog for HashMap<String, String> {
+    fn template(&self, code: &str) -> Option<String> {
+        self.get(code).cloned()
+    }
+}
+
+fn localize(
+    catalog: &dyn MessageCatalog,
+    code: &str,
+    default: String,
+    params: &[(&str, String)],
+) -> String {
+    match catalog.template(code) {
+        None => default,
+        Some(template) => params.iter().fold(template, |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        }),
+    }
+}
+
+#[cfg(feature = "serde-error")]
+fn serialize_display<T: fmt::Display, S: serde::Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
 }
 
 impl From<Infallible> for Token {
@@ -57,7 +184,43 @@ impl From<base64::DecodeError> for Token {
     }
 }
 
+/// Datalog parsing error
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
+pub struct ParseError {
+    /// line of the error in the source, starting at 1
+    pub line: usize,
+    /// column of the error, in characters, starting at 1
+    pub column: usize,
+    /// part of the source that could not be parsed
+    pub fragment: String,
+    /// tokens that would have been accepted at this position, when known
+    pub expected: Vec<String>,
+    /// description of the error, when available
+    pub message: Option<String>,
+    /// suggested fix, for common mistakes like `=` instead of `==`
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        match &self.message {
+            Some(message) => write!(f, ": {}", message)?,
+            None => write!(f, ": unexpected '{}'", self.fragment)?,
+        }
+        if !self.expected.is_empty() {
+            write!(f, " (expected {})", self.expected.join(" or "))?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "; {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub struct InvalidBlockIndex {
     pub expected: u32,
     pub found: u32,
@@ -66,11 +229,26 @@ pub struct InvalidBlockIndex {
 /// Errors related to the token's serialization format or cryptographic
 /// signature
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum Format {
     #[error("failed verifying the signature")]
     Signature(Signature),
+    /// `expected` is the key id of the secret used to check the signature,
+    /// `None` if no secret was selected by key id, and `actual` is the key id
+    /// recorded in the token
     #[error("failed verifying the signature of a sealed token")]
-    SealedSignature,
+    SealedSignature {
+        expected: Option<u32>,
+        actual: Option<u32>,
+    },
+    /// the sealed token was sealed without a key id, or with a key id
+    /// absent from the [`SealKeys`](crate::token::sealed::SealKeys) set.
+    /// `accepted` lists the key ids of the set, in increasing order
+    #[error("the sealed token's key id is not among the accepted ones")]
+    UnknownSealKey {
+        accepted: Vec<u32>,
+        actual: Option<u32>,
+    },
     #[error("the token does not provide intermediate public keys")]
     EmptyKeys,
     #[error("the root public key was not recognized")]
@@ -85,19 +263,160 @@ pub enum Format {
     BlockSerializationError(String),
     #[error("Block format version is higher than supported")]
     Version { maximum: u32, actual: u32 },
+    /// the signature of the [`Delegation`](crate::token::delegation::Delegation)
+    /// is invalid, or it does not delegate to the key of the authority block
+    #[error("the delegation certificate is invalid")]
+    InvalidDelegation,
+    /// the [`RevocationList`](crate::token::revocation::RevocationList) could
+    /// not be decoded, or was not signed by the expected issuer
+    #[error("the revocation list is invalid")]
+    InvalidRevocationList,
+    /// the [`RevocationList`](crate::token::revocation::RevocationList) is
+    /// past its expiration date
+    #[error("the revocation list has expired")]
+    ExpiredRevocationList,
+    /// the sequence number of the [`RevocationList`](crate::token::revocation::RevocationList)
+    /// is lower than the one of a list already seen, so it could omit newly
+    /// revoked identifiers
+    #[error("the revocation list has the sequence number {actual}, older than {minimum}")]
+    OutdatedRevocationList { minimum: u64, actual: u64 },
+    /// fewer than `threshold` keys of the root key set signed the authority
+    /// block, see [`Biscuit::verify_with_root_keys`](crate::token::Biscuit::verify_with_root_keys)
+    #[error("{signers} root keys signed the token, {threshold} are required")]
+    RootKeyThreshold { threshold: u32, signers: u32 },
+    /// the token has more blocks than allowed when deserializing it or
+    /// appending a block, see [`DecodeOptions::max_blocks`](crate::token::DecodeOptions::max_blocks)
+    #[error("the token has {actual} blocks, more than the maximum of {maximum}")]
+    TooManyBlocks { maximum: u32, actual: u32 },
+    /// a string, byte array or set term exceeds the [`TermLimits`](crate::format::TermLimits)
+    #[error("{kind} term of size {size} is larger than the maximum of {maximum}")]
+    TermTooLarge {
+        kind: String,
+        size: usize,
+        maximum: usize,
+    },
+}
+
+impl Format {
+    /// stable identifier of the error, see [`Token::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Format::Signature(e) => e.code(),
+            Format::SealedSignature { .. } => "format.sealed_signature.invalid",
+            Format::UnknownSealKey { .. } => "format.sealed_signature.unknown_key",
+            Format::EmptyKeys => "format.keys.empty",
+            Format::UnknownPublicKey => "format.public_key.unknown",
+            Format::DeserializationError(_) => "format.deserialization",
+            Format::SerializationError(_) => "format.serialization",
+            Format::BlockDeserializationError(_) => "format.block.deserialization",
+            Format::BlockSerializationError(_) => "format.block.serialization",
+            Format::Version { .. } => "format.version.unsupported",
+            Format::TermTooLarge { .. } => "format.term.too_large",
+            Format::TooManyBlocks { .. } => "format.blocks.too_many",
+            Format::RootKeyThreshold { .. } => "format.root_keys.threshold",
+            Format::InvalidDelegation => "format.delegation.invalid",
+            Format::InvalidRevocationList => "format.revocation_list.invalid",
+            Format::ExpiredRevocationList => "format.revocation_list.expired",
+            Format::OutdatedRevocationList { .. } => "format.revocation_list.outdated",
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Format::DeserializationError(message)
+            | Format::SerializationError(message)
+            | Format::BlockDeserializationError(message)
+            | Format::BlockSerializationError(message) => vec![("message", message.clone())],
+            Format::SealedSignature { expected, actual } => {
+                let key_id = |id: &Option<u32>| id.map_or("none".to_string(), |id| id.to_string());
+                vec![("expected", key_id(expected)), ("actual", key_id(actual))]
+            }
+            Format::UnknownSealKey { accepted, actual } => vec![
+                (
+                    "accepted",
+                    accepted
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                (
+                    "actual",
+                    actual.map_or("none".to_string(), |id| id.to_string()),
+                ),
+            ],
+            Format::Version { maximum, actual } | Format::TooManyBlocks { maximum, actual } => {
+                vec![
+                    ("maximum", maximum.to_string()),
+                    ("actual", actual.to_string()),
+                ]
+            }
+            Format::OutdatedRevocationList { minimum, actual } => vec![
+                ("minimum", minimum.to_string()),
+                ("actual", actual.to_string()),
+            ],
+            Format::RootKeyThreshold { threshold, signers } => vec![
+                ("threshold", threshold.to_string()),
+                ("signers", signers.to_string()),
+            ],
+            Format::TermTooLarge {
+                kind,
+                size,
+                maximum,
+            } => vec![
+                ("kind", kind.clone()),
+                ("size", size.to_string()),
+                ("maximum", maximum.to_string()),
+            ],
+            _ => vec![],
+        }
+    }
 }
 
 /// Signature errors
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum Signature {
+    /// the numbers of keys, blocks and signature parameters are different
     #[error("could not parse the signature elements")]
     InvalidFormat,
+    /// the signature does not match the blocks and keys: they were modified,
+    /// or signed with another key
     #[error("the signature did not match")]
     InvalidSignature,
+    #[error("invalid size for a public key: {0} bytes")]
+    InvalidKeySize(usize),
+    #[error("a public key is not a valid Ristretto point")]
+    InvalidKey,
+    #[error("invalid size for a signature parameter: {0} bytes")]
+    InvalidParameterSize(usize),
+    #[error("a signature parameter is not a valid Ristretto point")]
+    InvalidParameter,
+    #[error("invalid size for the signature scalar: {0} bytes")]
+    InvalidScalarSize(usize),
+    #[error("the signature scalar is not canonical")]
+    NonCanonicalScalar,
+}
+
+impl Signature {
+    /// stable identifier of the error, see [`Token::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Signature::InvalidFormat => "format.signature.format",
+            Signature::InvalidSignature => "format.signature.invalid",
+            Signature::InvalidKeySize(_) => "format.signature.key_size",
+            Signature::InvalidKey => "format.signature.key",
+            Signature::InvalidParameterSize(_) => "format.signature.parameter_size",
+            Signature::InvalidParameter => "format.signature.parameter",
+            Signature::InvalidScalarSize(_) => "format.signature.scalar_size",
+            Signature::NonCanonicalScalar => "format.signature.scalar",
+        }
+    }
 }
 
 /// errors in the Datalog evaluation
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum Logic {
     #[error("a fact of the authority block did not have the authority tag")]
     InvalidAuthorityFact(String),
@@ -111,14 +430,88 @@ pub enum Logic {
     FailedChecks(Vec<FailedCheck>),
     #[error("the verifier already contains a token")]
     VerifierNotEmpty,
-    #[error("denied by policy")]
-    Deny(usize),
+    #[error("denied by policy {policy_id}: {policy}")]
+    Deny {
+        policy_id: usize,
+        /// label given with `Verifier::add_labeled_policy`
+        label: Option<String>,
+        /// pretty print of the policy
+        policy: String,
+    },
     #[error("no matching policy was found")]
     NoMatchingPolicy,
+    /// a block provided facts or rules in a namespace that the verifier
+    /// does not allow, see `Verifier::restrict_block_namespaces`
+    #[error("a block is defining facts in a namespace that is not allowed")]
+    ForbiddenNamespace(u32, String),
+}
+
+impl Logic {
+    /// stable identifier of the error, see [`Token::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Logic::InvalidAuthorityFact(_) => "logic.authority_fact.invalid",
+            Logic::InvalidAmbientFact(_) => "logic.ambient_fact.invalid",
+            Logic::InvalidBlockFact(_, _) => "logic.block_fact.invalid",
+            Logic::InvalidBlockRule(_, _) => "logic.block_rule.invalid",
+            Logic::FailedChecks(_) => "logic.checks.failed",
+            Logic::VerifierNotEmpty => "logic.verifier.not_empty",
+            Logic::Deny { .. } => "logic.policy.deny",
+            Logic::NoMatchingPolicy => "logic.policy.no_match",
+            Logic::ForbiddenNamespace(_, _) => "logic.namespace.forbidden",
+        }
+    }
+
+    /// renders the error with the catalog's template, see [`Token::localized_message`]
+    pub fn localized_message(&self, catalog: &dyn MessageCatalog) -> String {
+        localize(
+            catalog,
+            self.code(),
+            self.to_string(),
+            &self.params(catalog),
+        )
+    }
+
+    fn params(&self, catalog: &dyn MessageCatalog) -> Vec<(&'static str, String)> {
+        match self {
+            Logic::InvalidAuthorityFact(fact) | Logic::InvalidAmbientFact(fact) => {
+                vec![("fact", fact.clone())]
+            }
+            Logic::InvalidBlockFact(block_id, fact) => {
+                vec![("block_id", block_id.to_string()), ("fact", fact.clone())]
+            }
+            Logic::InvalidBlockRule(block_id, rule) => {
+                vec![("block_id", block_id.to_string()), ("rule", rule.clone())]
+            }
+            Logic::ForbiddenNamespace(block_id, predicate) => vec![
+                ("block_id", block_id.to_string()),
+                ("predicate", predicate.clone()),
+            ],
+            Logic::FailedChecks(checks) => vec![(
+                "checks",
+                checks
+                    .iter()
+                    .map(|c| c.localized_message(catalog))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )],
+            Logic::Deny {
+                policy_id,
+                label,
+                policy,
+            } => vec![
+                ("policy_id", policy_id.to_string()),
+                ("label", label.clone().unwrap_or_default()),
+                ("policy", policy.clone()),
+            ],
+            _ => vec![],
+        }
+    }
 }
 
 /// check check errors
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum FailedCheck {
     #[error("a check failed in a block")]
     Block(FailedBlockCheck),
@@ -126,23 +519,76 @@ pub enum FailedCheck {
     Verifier(FailedVerifierCheck),
 }
 
+impl FailedCheck {
+    /// stable identifier of the error, see [`Token::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            FailedCheck::Block(_) => "logic.check.block.failed",
+            FailedCheck::Verifier(_) => "logic.check.verifier.failed",
+        }
+    }
+
+    /// renders the error with the catalog's template, see [`Token::localized_message`]
+    pub fn localized_message(&self, catalog: &dyn MessageCatalog) -> String {
+        let params = match self {
+            FailedCheck::Block(c) => vec![
+                ("block_id", c.block_id.to_string()),
+                ("check_id", c.check_id.to_string()),
+                ("rule", c.rule.clone()),
+            ],
+            FailedCheck::Verifier(c) => vec![
+                ("check_id", c.check_id.to_string()),
+                ("rule", c.rule.clone()),
+            ],
+        };
+
+        localize(catalog, self.code(), self.to_string(), &params)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub struct FailedBlockCheck {
     pub block_id: u32,
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// position of the check in its source text, if it is known. Tokens
+    /// do not carry the source of their checks, so this is `None` for
+    /// deserialized tokens
+    pub span: Option<SourceSpan>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub struct FailedVerifierCheck {
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// position of the check in its source text, if it was added from
+    /// Datalog source
+    pub span: Option<SourceSpan>,
+}
+
+/// position of an element in the Datalog source it was parsed from
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
+pub struct SourceSpan {
+    /// policy file containing the element, if it was loaded from a file
+    pub file: Option<String>,
+    /// byte offset of the start of the element
+    pub start: usize,
+    /// byte offset of the end of the element
+    pub end: usize,
+    /// line of the start of the element, starting at 1
+    pub line: usize,
+    /// column of the start of the element, in characters, starting at 1
+    pub column: usize,
 }
 
 /// runtime limits errors
 #[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize))]
 pub enum RunLimit {
     #[error("too many facts generated")]
     TooManyFacts,
@@ -150,4 +596,19 @@ pub enum RunLimit {
     TooManyIterations,
     #[error("spent too much time verifying")]
     Timeout,
+    /// an expression is nested deeper than [`MAX_EXPRESSION_DEPTH`](crate::datalog::MAX_EXPRESSION_DEPTH)
+    #[error("expression nested too deeply")]
+    ExpressionDepth,
+}
+
+impl RunLimit {
+    /// stable identifier of the error, see [`Token::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            RunLimit::TooManyFacts => "limit.facts",
+            RunLimit::TooManyIterations => "limit.iterations",
+            RunLimit::Timeout => "limit.timeout",
+            RunLimit::ExpressionDepth => "limit.expression_depth",
+        }
+    }
 }
@@ -0,0 +1,480 @@
+//! decoding of blocks borrowing from the serialized data
+//!
+//! the structures generated by prost own all their strings and byte arrays,
+//! which are then copied again when converting to the Datalog types. Here,
+//! the Protobuf wire format is read directly: a [`BlockRef`] keeps slices of
+//! the serialized block, and strings and byte arrays are only allocated once,
+//! when building the final [`Block`].
+//!
+//! blocks using the version 0 of the format are decoded with prost.
+use super::convert::{proto_block_to_token_block, v1::proto_op_to_token_op};
+use super::schema;
+use super::TermLimits;
+use crate::datalog::*;
+use crate::error;
+use crate::token::Block;
+use prost::Message;
+use std::collections::BTreeSet;
+
+/// decodes a serialized block
+///
+/// terms larger than the limits are rejected before being copied, except in
+/// version 0 blocks, checked after decoding
+pub fn decode_block(data: &[u8], limits: &TermLimits) -> Result<Block, error::Format> {
+    let block = BlockRef::decode(data)?;
+
+    if block.version.unwrap_or(0) == 0 {
+        let proto = schema::Block::decode(data).map_err(|e| {
+            error::Format::BlockDeserializationError(format!("error deserializing block: {:?}", e))
+        })?;
+        let block = proto_block_to_token_block(&proto)?;
+        check_terms(&block, limits)?;
+        return Ok(block);
+    }
+
+    block.to_block(limits)
+}
+
+fn check_terms(block: &Block, limits: &TermLimits) -> Result<(), error::Format> {
+    let check_predicate = |p: &Predicate| p.ids.iter().try_for_each(|id| limits.check_id(id));
+    let check_rule = |r: &Rule| -> Result<(), error::Format> {
+        check_predicate(&r.head)?;
+        r.body.iter().try_for_each(check_predicate)?;
+        r.expressions
+            .iter()
+            .flat_map(|e| e.ops.iter())
+            .try_for_each(|op| match op {
+                Op::Value(id) => limits.check_id(id),
+                _ => Ok(()),
+            })
+    };
+
+    block
+        .facts
+        .iter()
+        .try_for_each(|f| check_predicate(&f.predicate))?;
+    block.rules.iter().try_for_each(check_rule)?;
+    block
+        .checks
+        .iter()
+        .flat_map(|c| c.queries.iter())
+        .try_for_each(check_rule)
+}
+
+/// a block whose fields point into its serialized form
+#[derive(Clone, Debug, Default)]
+pub struct BlockRef<'a> {
+    pub index: u32,
+    pub symbols: Vec<&'a str>,
+    pub context: Option<&'a str>,
+    pub version: Option<u32>,
+    pub nonce: Option<&'a [u8]>,
+    facts: Vec<&'a [u8]>,
+    rules: Vec<&'a [u8]>,
+    checks: Vec<&'a [u8]>,
+}
+
+impl<'a> BlockRef<'a> {
+    pub fn decode(data: &'a [u8]) -> Result<Self, error::Format> {
+        let mut block = BlockRef::default();
+        let mut reader = Reader::new(data);
+
+        while let Some((tag, value)) = reader.next()? {
+            match tag {
+                1 => block.index = value.varint()? as u32,
+                2 => block.symbols.push(value.string()?),
+                6 => block.context = Some(value.string()?),
+                7 => block.version = Some(value.varint()? as u32),
+                8 => block.facts.push(value.bytes()?),
+                9 => block.rules.push(value.bytes()?),
+                10 => block.checks.push(value.bytes()?),
+                11 => block.nonce = Some(value.bytes()?),
+                // version 0 content, the block will be decoded by prost
+                _ => {}
+            }
+        }
+
+        Ok(block)
+    }
+
+    pub fn to_block(&self, limits: &TermLimits) -> Result<Block, error::Format> {
+        let version = self.version.unwrap_or(0);
+        if version > crate::token::MAX_SCHEMA_VERSION {
+            return Err(error::Format::Version {
+                maximum: crate::token::MAX_SCHEMA_VERSION,
+                actual: version,
+            });
+        }
+
+        let facts = self
+            .facts
+            .iter()
+            .map(|data| fact(data, limits))
+            .collect::<Result<_, _>>()?;
+        let rules = self
+            .rules
+            .iter()
+            .map(|data| rule(data, limits))
+            .collect::<Result<_, _>>()?;
+        let checks = self
+            .checks
+            .iter()
+            .map(|data| check(data, limits))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Block {
+            index: self.index,
+            symbols: SymbolTable::from_symbols(
+                self.symbols.iter().map(|s| s.to_string()).collect(),
+            ),
+            facts,
+            rules,
+            checks,
+            context: self.context.map(str::to_string),
+            version,
+            nonce: self.nonce.map(|nonce| nonce.to_vec()),
+        })
+    }
+}
+
+fn fact(data: &[u8], limits: &TermLimits) -> Result<Fact, error::Format> {
+    let mut predicate = None;
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        if tag == 1 {
+            predicate = Some(self::predicate(value.bytes()?, limits)?);
+        }
+    }
+
+    Ok(Fact {
+        predicate: predicate.ok_or_else(|| missing("fact predicate"))?,
+    })
+}
+
+fn rule(data: &[u8], limits: &TermLimits) -> Result<Rule, error::Format> {
+    let mut head = None;
+    let mut body = vec![];
+    let mut expressions = vec![];
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        match tag {
+            1 => head = Some(predicate(value.bytes()?, limits)?),
+            2 => body.push(predicate(value.bytes()?, limits)?),
+            3 => expressions.push(expression(value.bytes()?, limits)?),
+            _ => {}
+        }
+    }
+
+    Ok(Rule {
+        head: head.ok_or_else(|| missing("rule head"))?,
+        body,
+        expressions,
+    })
+}
+
+fn check(data: &[u8], limits: &TermLimits) -> Result<Check, error::Format> {
+    let mut queries = vec![];
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        if tag == 1 {
+            queries.push(rule(value.bytes()?, limits)?);
+        }
+    }
+
+    Ok(Check { queries })
+}
+
+fn predicate(data: &[u8], limits: &TermLimits) -> Result<Predicate, error::Format> {
+    let mut name = 0;
+    let mut ids = Terms::new();
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        match tag {
+            1 => name = value.varint()?,
+            2 => ids.push(id(value.bytes()?, false, limits)?),
+            _ => {}
+        }
+    }
+
+    Ok(Predicate { name, ids })
+}
+
+/// decodes an `IDV1` message, sets cannot contain variables or other sets
+fn id(data: &[u8], in_set: bool, limits: &TermLimits) -> Result<ID, error::Format> {
+    let mut content = None;
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        content = Some(match tag {
+            1 => ID::Symbol(value.varint()?),
+            2 if in_set => {
+                return Err(error::Format::DeserializationError(
+                    "deserialization error: sets cannot contain variables".to_string(),
+                ))
+            }
+            2 => ID::Variable(value.varint()? as u32),
+            3 => ID::Integer(value.varint()? as i64),
+            4 => {
+                let s = value.string()?;
+                limits.check_string(s.len())?;
+                ID::Str(s.to_string())
+            }
+            5 => ID::Date(value.varint()?),
+            6 => {
+                let bytes = value.bytes()?;
+                limits.check_bytes(bytes.len())?;
+                ID::Bytes(bytes.to_vec())
+            }
+            7 => ID::Bool(value.varint()? != 0),
+            8 if in_set => {
+                return Err(error::Format::DeserializationError(
+                    "deserialization error: sets cannot contain other sets".to_string(),
+                ))
+            }
+            8 => ID::Set(set(value.bytes()?, limits)?),
+            _ => continue,
+        });
+    }
+
+    content.ok_or_else(|| {
+        error::Format::DeserializationError(
+            "deserialization error: ID content enum is empty".to_string(),
+        )
+    })
+}
+
+fn set(data: &[u8], limits: &TermLimits) -> Result<BTreeSet<ID>, error::Format> {
+    let mut len = 0;
+    let mut reader = Reader::new(data);
+    while let Some((tag, _)) = reader.next()? {
+        if tag == 1 {
+            len += 1;
+        }
+    }
+    limits.check_set(len)?;
+
+    let mut kind = None;
+    let mut set = BTreeSet::new();
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        if tag != 1 {
+            continue;
+        }
+
+        let element = id(value.bytes()?, true, limits)?;
+        let index = std::mem::discriminant(&element);
+        if let Some(k) = kind.as_ref() {
+            if *k != index {
+                return Err(error::Format::DeserializationError(
+                    "deserialization error: sets elements must have the same type".to_string(),
+                ));
+            }
+        } else {
+            kind = Some(index);
+        }
+
+        set.insert(element);
+    }
+
+    Ok(set)
+}
+
+fn expression(data: &[u8], limits: &TermLimits) -> Result<Expression, error::Format> {
+    let mut ops = Ops::new();
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        if tag == 1 {
+            ops.push(op(value.bytes()?, limits)?);
+        }
+    }
+
+    Ok(Expression { ops })
+}
+
+fn op(data: &[u8], limits: &TermLimits) -> Result<Op, error::Format> {
+    let mut op = None;
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        op = Some(match tag {
+            1 => Op::Value(id(value.bytes()?, false, limits)?),
+            // unary and binary operations only contain an enum, converting
+            // them through the generated structures does not allocate
+            2 => proto_op_to_token_op(&schema::op::Content::Unary(schema::OpUnary {
+                kind: operation_kind(value.bytes()?)?,
+            }))?,
+            3 => proto_op_to_token_op(&schema::op::Content::Binary(schema::OpBinary {
+                kind: operation_kind(value.bytes()?)?,
+            }))?,
+            _ => continue,
+        });
+    }
+
+    op.ok_or_else(|| {
+        error::Format::DeserializationError("deserialization error: operation is empty".to_string())
+    })
+}
+
+/// reads the `kind` field of `OpUnary` and `OpBinary`
+fn operation_kind(data: &[u8]) -> Result<i32, error::Format> {
+    let mut kind = 0;
+    let mut reader = Reader::new(data);
+
+    while let Some((tag, value)) = reader.next()? {
+        if tag == 1 {
+            kind = value.varint()? as i32;
+        }
+    }
+
+    Ok(kind)
+}
+
+fn missing(field: &str) -> error::Format {
+    error::Format::BlockDeserializationError(format!(
+        "error deserializing block: missing {}",
+        field
+    ))
+}
+
+fn invalid(reason: &str) -> error::Format {
+    error::Format::BlockDeserializationError(format!("error deserializing block: {}", reason))
+}
+
+/// a field value, as found in the wire format
+enum Value<'a> {
+    Varint(u64),
+    Fixed,
+    LengthDelimited(&'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    fn varint(&self) -> Result<u64, error::Format> {
+        match self {
+            Value::Varint(v) => Ok(*v),
+            _ => Err(invalid("invalid wire type")),
+        }
+    }
+
+    fn bytes(&self) -> Result<&'a [u8], error::Format> {
+        match self {
+            Value::LengthDelimited(data) => Ok(data),
+            _ => Err(invalid("invalid wire type")),
+        }
+    }
+
+    fn string(&self) -> Result<&'a str, error::Format> {
+        std::str::from_utf8(self.bytes()?).map_err(|_| invalid("invalid UTF-8 string"))
+    }
+}
+
+/// iterates over the fields of a Protobuf message
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn next(&mut self) -> Result<Option<(u32, Value<'a>)>, error::Format> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+        let tag = (key >> 3) as u32;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::LengthDelimited(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(invalid("unsupported wire type")),
+        };
+
+        if tag == 0 {
+            return Err(invalid("invalid tag"));
+        }
+
+        Ok(Some((tag, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, error::Format> {
+        let mut value = 0u64;
+
+        for (i, byte) in self.data.iter().enumerate().take(10) {
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.data = &self.data[i + 1..];
+                return Ok(value);
+            }
+        }
+
+        Err(invalid("invalid varint"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], error::Format> {
+        if len > self.data.len() {
+            return Err(invalid("buffer underflow"));
+        }
+
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::convert::token_block_to_proto_block;
+    use crate::token::builder::BlockBuilder;
+    use crate::token::default_symbol_table;
+
+    #[test]
+    fn same_as_prost() {
+        let mut builder = BlockBuilder::new(1);
+        builder
+            .add_code(
+                r#"context "zero copy";
+                resource(#ambient, "file1");
+                data(#ambient, hex:0a0b, true, 2021-01-01T00:00:00Z, [1, 2, 3]);
+                valid($b) <- data(#ambient, $b, $bool, $date, $set), $set.contains(2), !$bool, $b.length() > 1;
+                check if valid($b) or resource(#ambient, $f), $f.starts_with("file"), !($f == "file2");"#,
+            )
+            .unwrap();
+        let block = builder.build(default_symbol_table());
+
+        let mut data = Vec::new();
+        token_block_to_proto_block(&block)
+            .encode(&mut data)
+            .unwrap();
+
+        let borrowed = decode_block(&data, &TermLimits::default()).unwrap();
+        let proto = proto_block_to_token_block(&schema::Block::decode(&data[..]).unwrap()).unwrap();
+        assert_eq!(borrowed.index, proto.index);
+        assert_eq!(borrowed.symbols.symbols(), proto.symbols.symbols());
+        assert_eq!(borrowed.facts, proto.facts);
+        assert_eq!(borrowed.rules, proto.rules);
+        assert_eq!(borrowed.checks, proto.checks);
+        assert_eq!(borrowed.version, proto.version);
+        assert_eq!(borrowed.context.as_deref(), Some("zero copy"));
+
+        assert!(decode_block(&data[..data.len() - 1], &TermLimits::default()).is_err());
+    }
+}
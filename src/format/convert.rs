@@ -1,12 +1,17 @@
 //! helper functions for conversion between internal structures and Protobuf
-use crate::crypto::TokenSignature;
-use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
+use crate::crypto::{PublicKey, TokenSignature};
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
 
 use super::schema;
 use crate::datalog::*;
 use crate::error;
 use crate::token::{verifier::VerifierPolicies, Block};
 
+use super::Cosignature;
+
 pub fn token_sig_to_proto_sig(input: &TokenSignature) -> schema::Signature {
     schema::Signature {
         parameters: input
@@ -22,20 +27,7 @@ pub fn proto_sig_to_token_sig(input: schema::Signature) -> Result<TokenSignature
     let mut parameters = vec![];
 
     for data in input.parameters {
-        if data.len() == 32 {
-            if let Some(d) = CompressedRistretto::from_slice(&data[..]).decompress() {
-                parameters.push(d);
-            } else {
-                return Err(error::Format::DeserializationError(
-                    "deserialization error: cannot decompress parameters point".to_string(),
-                ));
-            }
-        } else {
-            return Err(error::Format::DeserializationError(format!(
-                "deserialization error: invalid size for parameters = {}",
-                data.len()
-            )));
-        }
+        parameters.push(proto_parameter_to_point(&data)?);
     }
 
     let z = if input.z.len() == 32 {
@@ -44,24 +36,67 @@ pub fn proto_sig_to_token_sig(input: schema::Signature) -> Result<TokenSignature
         if let Some(d) = Scalar::from_canonical_bytes(bytes) {
             d
         } else {
-            return Err(error::Format::DeserializationError(
-                "deserialization error: non canonical z scalar".to_string(),
+            return Err(error::Format::Signature(
+                error::Signature::NonCanonicalScalar,
             ));
         }
     } else {
-        return Err(error::Format::DeserializationError(format!(
-            "deserialization error: invalid size for z = {} bytes",
-            input.z.len()
-        )));
+        return Err(error::Format::Signature(
+            error::Signature::InvalidScalarSize(input.z.len()),
+        ));
     };
 
     Ok(TokenSignature { parameters, z })
 }
 
+fn proto_parameter_to_point(data: &[u8]) -> Result<RistrettoPoint, error::Format> {
+    if data.len() == 32 {
+        if let Some(d) = CompressedRistretto::from_slice(data).decompress() {
+            Ok(d)
+        } else {
+            Err(error::Format::Signature(error::Signature::InvalidParameter))
+        }
+    } else {
+        Err(error::Format::Signature(
+            error::Signature::InvalidParameterSize(data.len()),
+        ))
+    }
+}
+
+pub fn proto_key_to_public_key(key: &[u8]) -> Result<PublicKey, error::Format> {
+    if key.len() == 32 {
+        if let Some(k) = CompressedRistretto::from_slice(key).decompress() {
+            Ok(PublicKey(k))
+        } else {
+            Err(error::Format::Signature(error::Signature::InvalidKey))
+        }
+    } else {
+        Err(error::Format::Signature(error::Signature::InvalidKeySize(
+            key.len(),
+        )))
+    }
+}
+
+pub fn cosignature_to_proto_cosignature(input: &Cosignature) -> schema::Cosignature {
+    schema::Cosignature {
+        key: Vec::from(&input.key.0.compress().to_bytes()[..]),
+        parameter: Vec::from(&input.parameter.compress().to_bytes()[..]),
+    }
+}
+
+pub fn proto_cosignature_to_cosignature(
+    input: &schema::Cosignature,
+) -> Result<Cosignature, error::Format> {
+    Ok(Cosignature {
+        key: proto_key_to_public_key(&input.key)?,
+        parameter: proto_parameter_to_point(&input.parameter)?,
+    })
+}
+
 pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
     schema::Block {
         index: input.index,
-        symbols: input.symbols.symbols.clone(),
+        symbols: input.symbols.symbols().to_vec(),
         facts_v0: Vec::new(),
         rules_v0: Vec::new(),
         caveats_v0: Vec::new(),
@@ -82,6 +117,7 @@ pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
             .iter()
             .map(v1::token_check_to_proto_check)
             .collect(),
+        nonce: input.nonce.clone(),
     }
 }
 
@@ -127,14 +163,13 @@ pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error:
 
     Ok(Block {
         index: input.index,
-        symbols: SymbolTable {
-            symbols: input.symbols.clone(),
-        },
+        symbols: SymbolTable::from_symbols(input.symbols.clone()),
         facts,
         rules,
         checks,
         context,
         version,
+        nonce: input.nonce.clone(),
     })
 }
 
@@ -147,7 +182,7 @@ pub fn verifier_to_proto_verifier(input: &VerifierPolicies) -> schema::VerifierP
         .collect();
 
     schema::VerifierPolicies {
-        symbols: symbols.symbols,
+        symbols: symbols.into_symbols(),
         version: Some(input.version),
         facts: input
             .facts
@@ -184,9 +219,7 @@ pub fn proto_verifier_to_verifier(
         });
     }
 
-    let symbols = SymbolTable {
-        symbols: input.symbols.clone(),
-    };
+    let symbols = SymbolTable::from_symbols(input.symbols.clone());
 
     let mut facts = vec![];
     let mut rules = vec![];
@@ -229,6 +262,7 @@ pub mod v0 {
     use super::schema;
     use crate::datalog::*;
     use crate::error;
+    use smallvec::smallvec;
 
     pub fn proto_fact_to_token_fact(input: &schema::FactV0) -> Result<Fact, error::Format> {
         Ok(Fact {
@@ -269,7 +303,7 @@ pub mod v0 {
     pub fn proto_predicate_to_token_predicate(
         input: &schema::PredicateV0,
     ) -> Result<Predicate, error::Format> {
-        let mut ids = vec![];
+        let mut ids = Terms::new();
 
         for id in input.ids.iter() {
             ids.push(proto_id_to_token_id(id)?);
@@ -403,7 +437,7 @@ pub mod v0 {
             Kind::Lower => {
                 if let Some(i) = input.lower {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Integer(i)),
                             Op::Binary(Binary::LessThan),
@@ -414,7 +448,7 @@ pub mod v0 {
             Kind::Larger => {
                 if let Some(i) = input.larger {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Integer(i)),
                             Op::Binary(Binary::GreaterThan),
@@ -425,7 +459,7 @@ pub mod v0 {
             Kind::LowerOrEqual => {
                 if let Some(i) = input.lower_or_equal {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Integer(i)),
                             Op::Binary(Binary::LessOrEqual),
@@ -436,7 +470,7 @@ pub mod v0 {
             Kind::LargerOrEqual => {
                 if let Some(i) = input.larger_or_equal {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Integer(i)),
                             Op::Binary(Binary::GreaterOrEqual),
@@ -447,7 +481,7 @@ pub mod v0 {
             Kind::Equal => {
                 if let Some(i) = input.equal {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Integer(i)),
                             Op::Binary(Binary::Equal),
@@ -458,7 +492,7 @@ pub mod v0 {
             Kind::In => {
                 if !input.in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|i| ID::Integer(*i)).collect(),
                             )),
@@ -471,7 +505,7 @@ pub mod v0 {
             Kind::NotIn => {
                 if !input.not_in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|i| ID::Integer(*i)).collect(),
                             )),
@@ -507,7 +541,7 @@ pub mod v0 {
             Kind::Prefix => {
                 if let Some(ref s) = input.prefix {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Str(s.clone())),
                             Op::Binary(Binary::Prefix),
@@ -518,7 +552,7 @@ pub mod v0 {
             Kind::Suffix => {
                 if let Some(ref s) = input.suffix {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Str(s.clone())),
                             Op::Binary(Binary::Suffix),
@@ -529,7 +563,7 @@ pub mod v0 {
             Kind::Equal => {
                 if let Some(ref s) = input.equal {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Str(s.clone())),
                             Op::Binary(Binary::Equal),
@@ -540,7 +574,7 @@ pub mod v0 {
             Kind::Regex => {
                 if let Some(ref r) = input.regex {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Str(r.clone())),
                             Op::Binary(Binary::Regex),
@@ -551,7 +585,7 @@ pub mod v0 {
             Kind::In => {
                 if !input.in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|s| ID::Str(s.clone())).collect(),
                             )),
@@ -564,7 +598,7 @@ pub mod v0 {
             Kind::NotIn => {
                 if !input.not_in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|s| ID::Str(s.clone())).collect(),
                             )),
@@ -600,7 +634,7 @@ pub mod v0 {
             Kind::Before => {
                 if let Some(i) = input.before {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Date(i)),
                             Op::Binary(Binary::LessOrEqual),
@@ -611,7 +645,7 @@ pub mod v0 {
             Kind::After => {
                 if let Some(i) = input.after {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Date(i)),
                             Op::Binary(Binary::GreaterOrEqual),
@@ -644,7 +678,7 @@ pub mod v0 {
             Kind::In => {
                 if !input.in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|s| ID::Symbol(*s)).collect(),
                             )),
@@ -657,7 +691,7 @@ pub mod v0 {
             Kind::NotIn => {
                 if !input.not_in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|s| ID::Symbol(*s)).collect(),
                             )),
@@ -693,7 +727,7 @@ pub mod v0 {
             Kind::Equal => {
                 if let Some(ref s) = input.equal {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(id),
                             Op::Value(ID::Bytes(s.clone())),
                             Op::Binary(Binary::Equal),
@@ -704,7 +738,7 @@ pub mod v0 {
             Kind::In => {
                 if !input.in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|b| ID::Bytes(b.clone())).collect(),
                             )),
@@ -717,7 +751,7 @@ pub mod v0 {
             Kind::NotIn => {
                 if !input.not_in_set.is_empty() {
                     return Ok(Expression {
-                        ops: vec![
+                        ops: smallvec![
                             Op::Value(ID::Set(
                                 input.in_set.iter().map(|b| ID::Bytes(b.clone())).collect(),
                             )),
@@ -863,7 +897,7 @@ pub mod v1 {
     pub fn proto_predicate_to_token_predicate(
         input: &schema::PredicateV1,
     ) -> Result<Predicate, error::Format> {
-        let mut ids = vec![];
+        let mut ids = Terms::new();
 
         for id in input.ids.iter() {
             ids.push(proto_id_to_token_id(id)?);
@@ -1027,46 +1061,11 @@ pub mod v1 {
     pub fn proto_expression_to_token_expression(
         input: &schema::ExpressionV1,
     ) -> Result<Expression, error::Format> {
-        use schema::{op, op_binary, op_unary};
-        let mut ops = Vec::new();
+        let mut ops = Ops::new();
 
         for op in input.ops.iter() {
             let translated = match op.content.as_ref() {
-                Some(op::Content::Value(id)) => Op::Value(proto_id_to_token_id(&id)?),
-                Some(op::Content::Unary(u)) => match op_unary::Kind::from_i32(u.kind) {
-                    Some(op_unary::Kind::Negate) => Op::Unary(Unary::Negate),
-                    Some(op_unary::Kind::Parens) => Op::Unary(Unary::Parens),
-                    Some(op_unary::Kind::Length) => Op::Unary(Unary::Length),
-                    None => {
-                        return Err(error::Format::DeserializationError(
-                            "deserialization error: unary operation is empty".to_string(),
-                        ))
-                    }
-                },
-                Some(op::Content::Binary(b)) => match op_binary::Kind::from_i32(b.kind) {
-                    Some(op_binary::Kind::LessThan) => Op::Binary(Binary::LessThan),
-                    Some(op_binary::Kind::GreaterThan) => Op::Binary(Binary::GreaterThan),
-                    Some(op_binary::Kind::LessOrEqual) => Op::Binary(Binary::LessOrEqual),
-                    Some(op_binary::Kind::GreaterOrEqual) => Op::Binary(Binary::GreaterOrEqual),
-                    Some(op_binary::Kind::Equal) => Op::Binary(Binary::Equal),
-                    Some(op_binary::Kind::Contains) => Op::Binary(Binary::Contains),
-                    Some(op_binary::Kind::Prefix) => Op::Binary(Binary::Prefix),
-                    Some(op_binary::Kind::Suffix) => Op::Binary(Binary::Suffix),
-                    Some(op_binary::Kind::Regex) => Op::Binary(Binary::Regex),
-                    Some(op_binary::Kind::Add) => Op::Binary(Binary::Add),
-                    Some(op_binary::Kind::Sub) => Op::Binary(Binary::Sub),
-                    Some(op_binary::Kind::Mul) => Op::Binary(Binary::Mul),
-                    Some(op_binary::Kind::Div) => Op::Binary(Binary::Div),
-                    Some(op_binary::Kind::And) => Op::Binary(Binary::And),
-                    Some(op_binary::Kind::Or) => Op::Binary(Binary::Or),
-                    Some(op_binary::Kind::Intersection) => Op::Binary(Binary::Intersection),
-                    Some(op_binary::Kind::Union) => Op::Binary(Binary::Union),
-                    None => {
-                        return Err(error::Format::DeserializationError(
-                            "deserialization error: binary operation is empty".to_string(),
-                        ))
-                    }
-                },
+                Some(content) => proto_op_to_token_op(content)?,
                 None => {
                     return Err(error::Format::DeserializationError(
                         "deserialization error: operation is empty".to_string(),
@@ -1078,4 +1077,46 @@ pub mod v1 {
 
         Ok(Expression { ops })
     }
+
+    pub fn proto_op_to_token_op(input: &schema::op::Content) -> Result<Op, error::Format> {
+        use schema::{op, op_binary, op_unary};
+
+        Ok(match input {
+            op::Content::Value(id) => Op::Value(proto_id_to_token_id(&id)?),
+            op::Content::Unary(u) => match op_unary::Kind::from_i32(u.kind) {
+                Some(op_unary::Kind::Negate) => Op::Unary(Unary::Negate),
+                Some(op_unary::Kind::Parens) => Op::Unary(Unary::Parens),
+                Some(op_unary::Kind::Length) => Op::Unary(Unary::Length),
+                None => {
+                    return Err(error::Format::DeserializationError(
+                        "deserialization error: unary operation is empty".to_string(),
+                    ))
+                }
+            },
+            op::Content::Binary(b) => match op_binary::Kind::from_i32(b.kind) {
+                Some(op_binary::Kind::LessThan) => Op::Binary(Binary::LessThan),
+                Some(op_binary::Kind::GreaterThan) => Op::Binary(Binary::GreaterThan),
+                Some(op_binary::Kind::LessOrEqual) => Op::Binary(Binary::LessOrEqual),
+                Some(op_binary::Kind::GreaterOrEqual) => Op::Binary(Binary::GreaterOrEqual),
+                Some(op_binary::Kind::Equal) => Op::Binary(Binary::Equal),
+                Some(op_binary::Kind::Contains) => Op::Binary(Binary::Contains),
+                Some(op_binary::Kind::Prefix) => Op::Binary(Binary::Prefix),
+                Some(op_binary::Kind::Suffix) => Op::Binary(Binary::Suffix),
+                Some(op_binary::Kind::Regex) => Op::Binary(Binary::Regex),
+                Some(op_binary::Kind::Add) => Op::Binary(Binary::Add),
+                Some(op_binary::Kind::Sub) => Op::Binary(Binary::Sub),
+                Some(op_binary::Kind::Mul) => Op::Binary(Binary::Mul),
+                Some(op_binary::Kind::Div) => Op::Binary(Binary::Div),
+                Some(op_binary::Kind::And) => Op::Binary(Binary::And),
+                Some(op_binary::Kind::Or) => Op::Binary(Binary::Or),
+                Some(op_binary::Kind::Intersection) => Op::Binary(Binary::Intersection),
+                Some(op_binary::Kind::Union) => Op::Binary(Binary::Union),
+                None => {
+                    return Err(error::Format::DeserializationError(
+                        "deserialization error: binary operation is empty".to_string(),
+                    ))
+                }
+            },
+        })
+    }
 }
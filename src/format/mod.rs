@@ -4,14 +4,14 @@
 //!
 //! - serialization of Biscuit blocks to Protobuf then `Vec<u8>`
 //! - serialization of a wrapper structure containing serialized blocks and the signature
-use super::crypto::{KeyPair, TokenSignature};
+use super::crypto::{KeyPair, SignatureVerification, TokenSignature};
 use crate::crypto::PublicKey;
-use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::ristretto::RistrettoPoint;
 use prost::Message;
 use rand_core::{CryptoRng, RngCore};
 
 use super::error;
-use super::token::Block;
+use super::token::{cache::SignatureCache, delegation::Delegation, Block};
 
 /// Structures generated from the Protobuf schema
 pub mod schema; /* {
@@ -20,8 +20,88 @@ pub mod schema; /* {
 
 pub mod convert;
 
+pub(crate) mod borrowed;
+
 use self::convert::*;
 
+/// returns [`error::Format::TooManyBlocks`] if `count` blocks, authority
+/// block included, are more than `maximum`
+pub(crate) fn check_block_count(maximum: u32, count: usize) -> Result<(), error::Format> {
+    if count > maximum as usize {
+        return Err(error::Format::TooManyBlocks {
+            maximum,
+            actual: count as u32,
+        });
+    }
+    Ok(())
+}
+
+/// maximum sizes of the terms of a block
+///
+/// they are checked when a block is decoded, before its strings and byte
+/// arrays are copied, so an oversized term is rejected with
+/// [`error::Format::TermTooLarge`] instead of being loaded in memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TermLimits {
+    /// maximum length of a string, in bytes
+    pub max_string_len: usize,
+    /// maximum length of a byte array
+    pub max_bytes_len: usize,
+    /// maximum number of elements of a set
+    pub max_set_len: usize,
+}
+
+impl Default for TermLimits {
+    fn default() -> Self {
+        TermLimits {
+            max_string_len: 1 << 20,
+            max_bytes_len: 1 << 20,
+            max_set_len: 10_000,
+        }
+    }
+}
+
+impl TermLimits {
+    pub(crate) fn check_string(&self, len: usize) -> Result<(), error::Format> {
+        too_large("string", len, self.max_string_len)
+    }
+
+    pub(crate) fn check_bytes(&self, len: usize) -> Result<(), error::Format> {
+        too_large("bytes", len, self.max_bytes_len)
+    }
+
+    pub(crate) fn check_set(&self, len: usize) -> Result<(), error::Format> {
+        too_large("set", len, self.max_set_len)
+    }
+
+    /// checks a term that was already decoded
+    pub(crate) fn check_id(&self, id: &crate::datalog::ID) -> Result<(), error::Format> {
+        use crate::datalog::ID;
+
+        match id {
+            ID::Str(s) => self.check_string(s.len()),
+            ID::Bytes(b) => self.check_bytes(b.len()),
+            ID::Set(set) => {
+                self.check_set(set.len())?;
+                set.iter().try_for_each(|id| self.check_id(id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn too_large(kind: &str, size: usize, maximum: usize) -> Result<(), error::Format> {
+    if size > maximum {
+        Err(error::Format::TermTooLarge {
+            kind: kind.to_string(),
+            size,
+            maximum,
+        })
+    } else {
+        Ok(())
+    }
+}
+
 /// Intermediate structure for token serialization
 ///
 /// This structure contains the blocks serialized to byte arrays. Those arrays
@@ -32,46 +112,103 @@ pub struct SerializedBiscuit {
     pub blocks: Vec<Vec<u8>>,
     pub keys: Vec<PublicKey>,
     pub signature: TokenSignature,
+    /// signatures of the authority block by additional root keys
+    pub cosignatures: Vec<Cosignature>,
+    /// certificate allowing the key of the authority block to act as a root key
+    pub delegation: Option<Delegation>,
+}
+
+/// signature of the authority block by an additional root key
+///
+/// the signature is aggregated with the signature of the token, only its
+/// parameter is kept with the key
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cosignature {
+    pub key: PublicKey,
+    pub parameter: RistrettoPoint,
 }
 
 impl SerializedBiscuit {
     pub fn from_slice(slice: &[u8]) -> Result<Self, error::Format> {
+        SerializedBiscuit::from_slice_with_max_blocks(slice, None)
+    }
+
+    /// deserializes the token, refusing it before checking the signatures if
+    /// it has more than `max_blocks` blocks, authority block included
+    pub fn from_slice_with_max_blocks(
+        slice: &[u8],
+        max_blocks: Option<u32>,
+    ) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::decode(slice, max_blocks)?;
+
+        match deser.verify() {
+            Ok(()) => Ok(deser),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// deserializes the token, skipping the signature verification if the
+    /// same token is in the cache
+    pub fn from_slice_with_cache(
+        slice: &[u8],
+        cache: &SignatureCache,
+        max_blocks: Option<u32>,
+    ) -> Result<Self, error::Format> {
+        let key = SignatureCache::digest(slice);
+        if cache.contains(&key) {
+            return SerializedBiscuit::decode(slice, max_blocks);
+        }
+
+        let deser = SerializedBiscuit::from_slice_with_max_blocks(slice, max_blocks)?;
+        cache.insert(key);
+        Ok(deser)
+    }
+
+    pub(crate) fn decode(slice: &[u8], max_blocks: Option<u32>) -> Result<Self, error::Format> {
         let data = schema::Biscuit::decode(slice).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
 
+        if let Some(maximum) = max_blocks {
+            check_block_count(maximum, 1 + data.blocks.len())?;
+        }
+
         let mut keys = vec![];
 
         for key in data.keys {
-            if key.len() == 32 {
-                if let Some(k) = CompressedRistretto::from_slice(&key[..]).decompress() {
-                    keys.push(PublicKey(k));
-                } else {
-                    return Err(error::Format::DeserializationError(
-                        "deserialization error: cannot decompress key point".to_string(),
-                    ));
-                }
-            } else {
-                return Err(error::Format::DeserializationError(format!(
-                    "deserialization error: invalid size for key = {} bytes",
-                    key.len()
-                )));
-            }
+            keys.push(proto_key_to_public_key(&key)?);
         }
 
         let signature = proto_sig_to_token_sig(data.signature)?;
+        let cosignatures = data
+            .cosignatures
+            .iter()
+            .map(proto_cosignature_to_cosignature)
+            .collect::<Result<_, _>>()?;
+        let delegation = data
+            .delegation
+            .as_ref()
+            .map(Delegation::from_proto)
+            .transpose()?;
+
+        // the keys and signature parameters are indexed by block, even
+        // before the signature is verified
+        if keys.is_empty() {
+            return Err(error::Format::EmptyKeys);
+        }
 
-        let deser = SerializedBiscuit {
+        if !(keys.len() == 1 + data.blocks.len() && signature.parameters.len() == keys.len()) {
+            return Err(error::Format::Signature(error::Signature::InvalidFormat));
+        }
+
+        Ok(SerializedBiscuit {
             authority: data.authority,
             blocks: data.blocks,
             keys,
             signature,
-        };
-
-        match deser.verify() {
-            Ok(()) => Ok(deser),
-            Err(e) => Err(e),
-        }
+            cosignatures,
+            delegation,
+        })
     }
 
     /// serializes the token
@@ -85,6 +222,12 @@ impl SerializedBiscuit {
                 .map(|k| Vec::from(&k.0.compress().to_bytes()[..]))
                 .collect(),
             signature: token_sig_to_proto_sig(&self.signature),
+            cosignatures: self
+                .cosignatures
+                .iter()
+                .map(cosignature_to_proto_cosignature)
+                .collect(),
+            delegation: self.delegation.as_ref().map(Delegation::to_proto),
         }
     }
 
@@ -123,6 +266,8 @@ impl SerializedBiscuit {
             blocks: vec![],
             keys: vec![keypair.public()],
             signature,
+            cosignatures: vec![],
+            delegation: None,
         })
     }
 
@@ -150,6 +295,8 @@ impl SerializedBiscuit {
             blocks: self.blocks.clone(),
             keys: self.keys.clone(),
             signature,
+            cosignatures: self.cosignatures.clone(),
+            delegation: self.delegation.clone(),
         };
 
         t.blocks.push(v);
@@ -159,27 +306,166 @@ impl SerializedBiscuit {
     }
 
     /// checks the signature on a deserialized token
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(blocks = self.blocks.len() + 1), err)
+    )]
     pub fn verify(&self) -> Result<(), error::Format> {
         if self.keys.is_empty() {
             return Err(error::Format::EmptyKeys);
         }
 
-        let mut blocks = vec![self.authority.clone()];
-        blocks.extend(self.blocks.iter().cloned());
+        if !(self.keys.len() == 1 + self.blocks.len()
+            && self.signature.parameters.len() == self.keys.len())
+        {
+            return Err(error::Format::Signature(error::Signature::InvalidFormat));
+        }
+
+        if let Some(delegation) = self.delegation.as_ref() {
+            delegation.verify()?;
+        }
+
+        let mut verification = SignatureVerification::new();
+        for index in 0..self.keys.len() {
+            verify_block(&mut verification, self, index)?;
+        }
+        verify_signature(&verification, self)
+    }
+
+    /// adds a signature of the authority block with another root key
+    pub fn cosign<T: RngCore + CryptoRng>(&self, rng: &mut T, keypair: &KeyPair) -> Self {
+        let (signature, parameter) = self.signature.cosign(rng, keypair, &self.authority);
+
+        let mut t = self.clone();
+        t.signature = signature;
+        t.cosignatures.push(Cosignature {
+            key: keypair.public(),
+            parameter,
+        });
+        t
+    }
+
+    /// tests that at least `threshold` different keys of the set signed the
+    /// authority block. A threshold of 0 is handled like 1
+    pub fn check_root_keys(
+        &self,
+        keys: &[PublicKey],
+        threshold: usize,
+    ) -> Result<(), error::Format> {
+        if self.keys.is_empty() {
+            return Err(error::Format::EmptyKeys);
+        }
+
+        let mut signers = Vec::new();
+        for key in self.root_keys() {
+            if keys.contains(&key) && !signers.contains(&key) {
+                signers.push(key);
+            }
+        }
 
-        self.signature
-            .verify(&self.keys, &blocks)
-            .map_err(error::Format::Signature)
+        let threshold = threshold.max(1);
+        if signers.len() < threshold {
+            return Err(error::Format::RootKeyThreshold {
+                threshold: threshold as u32,
+                signers: signers.len() as u32,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// keys that signed the authority block: the root key, then the keys of
+    /// the cosignatures
+    pub fn root_keys(&self) -> Vec<PublicKey> {
+        self.keys
+            .iter()
+            .take(1)
+            .chain(self.cosignatures.iter().map(|c| &c.key))
+            .cloned()
+            .collect()
     }
 
     pub fn check_root_key(&self, root: PublicKey) -> Result<(), error::Format> {
         if self.keys.is_empty() {
             return Err(error::Format::EmptyKeys);
         }
-        if self.keys[0] != root {
+        if self.keys[0] != root && self.delegated_expiration(root).is_none() {
             return Err(error::Format::UnknownPublicKey);
         }
 
         Ok(())
     }
+
+    /// if the authority block was signed by a key delegated from `root`,
+    /// returns the expiration date of the delegation
+    pub fn delegated_expiration(&self, root: PublicKey) -> Option<std::time::SystemTime> {
+        self.delegation
+            .as_ref()
+            .filter(|d| d.root == root && Some(&d.key) == self.keys.first())
+            .map(|d| d.expiration)
+    }
+}
+
+/// adds the block at `index` of a deserialized token, 0 being the authority
+/// block, to a step by step signature verification
+///
+/// the signature of a token is aggregated, so a block cannot be verified
+/// alone: the blocks are added in order with this function, then
+/// [`verify_signature`] checks them against the aggregated scalar. This
+/// performs the same operations as [`SerializedBiscuit::verify`]
+///
+/// ```rust
+/// use biscuit_auth::{crypto::{KeyPair, SignatureVerification}, format, token::Biscuit};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap();
+/// let token = token.append_source(&KeyPair::new(), "check if true").unwrap();
+/// let container = token.container().unwrap();
+///
+/// let mut verification = SignatureVerification::new();
+/// format::verify_block(&mut verification, container, 0).unwrap();
+/// // the authority block alone does not match the aggregated signature
+/// assert!(format::verify_signature(&verification, container).is_err());
+///
+/// format::verify_block(&mut verification, container, 1).unwrap();
+/// assert!(format::verify_signature(&verification, container).is_ok());
+/// ```
+pub fn verify_block(
+    verification: &mut SignatureVerification,
+    token: &SerializedBiscuit,
+    index: usize,
+) -> Result<(), error::Format> {
+    let invalid = error::Format::Signature(error::Signature::InvalidFormat);
+    if index != verification.blocks() {
+        return Err(invalid);
+    }
+
+    let block = if index == 0 {
+        &token.authority
+    } else {
+        token.blocks.get(index - 1).ok_or_else(|| invalid.clone())?
+    };
+    let public_key = token.keys.get(index).ok_or_else(|| invalid.clone())?;
+    let parameter = token.signature.parameters.get(index).ok_or(invalid)?;
+
+    verification.add_block(public_key, parameter, block);
+    Ok(())
+}
+
+/// checks the blocks added with [`verify_block`], and the cosignatures of the
+/// authority block, against the aggregated signature of the token
+///
+/// the verification only succeeds once all the blocks of the token were added
+pub fn verify_signature(
+    verification: &SignatureVerification,
+    token: &SerializedBiscuit,
+) -> Result<(), error::Format> {
+    let mut verification = verification.clone();
+    for cosignature in token.cosignatures.iter() {
+        verification.add_block(&cosignature.key, &cosignature.parameter, &token.authority);
+    }
+
+    verification
+        .finish(&token.signature.z)
+        .map_err(error::Format::Signature)
 }
@@ -8,6 +8,10 @@ pub struct Biscuit {
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
     #[prost(message, required, tag="4")]
     pub signature: Signature,
+    #[prost(message, repeated, tag="5")]
+    pub cosignatures: ::prost::alloc::vec::Vec<Cosignature>,
+    #[prost(message, optional, tag="6")]
+    pub delegation: ::core::option::Option<Delegation>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SealedBiscuit {
@@ -17,6 +21,8 @@ pub struct SealedBiscuit {
     pub blocks: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
     #[prost(bytes="vec", required, tag="3")]
     pub signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, optional, tag="4")]
+    pub key_id: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Signature {
@@ -26,6 +32,37 @@ pub struct Signature {
     pub z: ::prost::alloc::vec::Vec<u8>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Cosignature {
+    #[prost(bytes="vec", required, tag="1")]
+    pub key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="vec", required, tag="2")]
+    pub parameter: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Delegation {
+    #[prost(bytes="vec", required, tag="1")]
+    pub root: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="vec", required, tag="2")]
+    pub key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, required, tag="3")]
+    pub expiration: u64,
+    #[prost(bytes="vec", required, tag="4")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RevocationList {
+    #[prost(bytes="vec", required, tag="1")]
+    pub issuer: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, required, tag="2")]
+    pub sequence: u64,
+    #[prost(uint64, required, tag="3")]
+    pub expiration: u64,
+    #[prost(bytes="vec", repeated, tag="4")]
+    pub ids: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(bytes="vec", required, tag="5")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Block {
     #[prost(uint32, required, tag="1")]
     pub index: u32,
@@ -47,6 +84,8 @@ pub struct Block {
     pub rules_v1: ::prost::alloc::vec::Vec<RuleV1>,
     #[prost(message, repeated, tag="10")]
     pub checks_v1: ::prost::alloc::vec::Vec<CheckV1>,
+    #[prost(bytes="vec", optional, tag="11")]
+    pub nonce: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FactV0 {
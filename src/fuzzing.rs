@@ -0,0 +1,136 @@
+//! entry points of the fuzz targets in the `fuzz` directory
+//!
+//! each function takes the raw input of the fuzzer and only panics when it
+//! finds a bug: invalid inputs are expected to return errors, which are
+//! ignored. They cover the surfaces exposed to attackers, the Datalog
+//! parser, the base64 and binary deserialization of tokens, and the
+//! decoding of the protobuf blocks, so any harness can run them:
+//!
+//! ```rust
+//! biscuit_auth::fuzzing::parse_datalog(b"check if resource(#ambient, $0)");
+//! biscuit_auth::fuzzing::deserialize_token(&[0xff; 32]);
+//! ```
+use crate::format::{borrowed, convert::proto_block_to_token_block, schema, TermLimits};
+use crate::token::{
+    builder::{Check, Fact, Policy, Rule},
+    default_symbol_table,
+    verifier::{Verifier, VerifierLimits},
+    Biscuit,
+};
+use prost::Message;
+
+/// parses the input as Datalog source, and as each kind of element
+pub fn parse_datalog(data: &[u8]) {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let _ = crate::parser::parse_source(source);
+    let _ = crate::parser::parse_block_source(source);
+    let _ = source.parse::<Fact>();
+    let _ = source.parse::<Rule>();
+    let _ = source.parse::<Check>();
+    let _ = source.parse::<Policy>();
+
+    if let Ok(mut verifier) = Verifier::new() {
+        let _ = verifier.add_code(source);
+    }
+}
+
+/// decodes the input as a block, and checks that the zero copy decoder
+/// agrees with the protobuf one
+pub fn decode_block(data: &[u8]) {
+    let block = match borrowed::decode_block(data, &TermLimits::default()) {
+        Ok(block) => block,
+        Err(_) => return,
+    };
+
+    let proto = schema::Block::decode(data)
+        .map_err(|e| e.to_string())
+        .and_then(|proto| proto_block_to_token_block(&proto).map_err(|e| e.to_string()))
+        .expect("the protobuf decoder rejected a block accepted by the zero copy decoder");
+    assert_eq!(block.index, proto.index);
+    assert_eq!(block.symbols.symbols(), proto.symbols.symbols());
+    assert_eq!(block.facts, proto.facts);
+    assert_eq!(block.rules, proto.rules);
+    assert_eq!(block.checks, proto.checks);
+    assert_eq!(block.context, proto.context);
+
+    let mut symbols = default_symbol_table();
+    symbols.extend(block.symbols.symbols().iter().cloned());
+    for fact in block.facts.iter() {
+        let _ = symbols.print_fact(fact);
+    }
+    for rule in block.rules.iter() {
+        let _ = symbols.print_rule(rule);
+    }
+    for check in block.checks.iter() {
+        let _ = symbols.print_check(check);
+    }
+}
+
+/// deserializes the input as a token, then prints and verifies it
+pub fn deserialize_token(data: &[u8]) {
+    if let Ok(token) = Biscuit::from(data) {
+        exercise(&token);
+    }
+}
+
+/// decodes the input as a base64 token, then prints and verifies it
+pub fn deserialize_base64(data: &[u8]) {
+    if let Ok(token) = Biscuit::from_base64(data) {
+        exercise(&token);
+    }
+}
+
+fn exercise(token: &Biscuit) {
+    let serialized = token
+        .to_vec()
+        .expect("a deserialized token can be serialized");
+    Biscuit::from(&serialized).expect("a serialized token can be deserialized");
+
+    let _ = token.print();
+    let root = match token.root_keys().first() {
+        Some(root) => *root,
+        None => return,
+    };
+    if let Ok(mut verifier) = token.verify(root) {
+        let _ = verifier.allow();
+        let _ = verifier.verify_with_limits(VerifierLimits::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    #[test]
+    fn valid_and_invalid_inputs() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(#authority, \"file1\", #read)")
+            .unwrap();
+        let token = builder.build().unwrap();
+        let token = token
+            .append_source(&KeyPair::new(), "check if resource(#ambient, \"file1\")")
+            .unwrap();
+        let data = token.to_vec().unwrap();
+
+        deserialize_token(&data);
+        deserialize_token(&data[..data.len() / 2]);
+        deserialize_base64(token.to_base64().unwrap().as_bytes());
+        deserialize_base64(b"En0KEwoE");
+
+        let proto = schema::Biscuit::decode(&data[..]).unwrap();
+        decode_block(&proto.authority);
+        decode_block(&proto.blocks[0]);
+        decode_block(&proto.blocks[0][1..]);
+
+        parse_datalog(b"right($0, #read) <- resource(#ambient, $0), $0.starts_with(\"/\")");
+        parse_datalog(b"allow if [1, 2].contains(");
+        parse_datalog(&[0xc3, 0x28]);
+    }
+}
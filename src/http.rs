@@ -0,0 +1,437 @@
+//! parsing and formatting of the `Authorization` header
+//!
+//! tokens are sent as `Authorization: Bearer <base64 token>`, or with the
+//! `Biscuit` scheme when the bearer tokens of a service are of another kind.
+//! The scheme is matched without regard to case, as specified by RFC 7235,
+//! and the header is refused if it is larger than the limit.
+//!
+//! Tokens too large for a single cookie can be split with [`split_cookies`]
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, http::{self, Scheme}, token::Biscuit};
+//!
+//! let root = KeyPair::new();
+//! let token = Biscuit::builder(&root).build().unwrap();
+//!
+//! let header = http::authorization(Scheme::Bearer, &token).unwrap();
+//! assert!(header.starts_with("Bearer "));
+//!
+//! let (scheme, token) = http::parse_token(header.as_bytes(), http::MAX_HEADER_SIZE).unwrap();
+//! assert_eq!(scheme, Scheme::Bearer);
+//! assert!(token.check_root_key(root.public()).is_ok());
+//! ```
+use crate::error;
+use crate::token::Biscuit;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt};
+
+/// default maximum size of the header value, in bytes
+pub const MAX_HEADER_SIZE: usize = 8192;
+
+/// authentication scheme of the header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    Bearer,
+    Biscuit,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Bearer => "Bearer",
+            Scheme::Biscuit => "Biscuit",
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// error returned when parsing the header
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum HeaderError {
+    /// the header is not made of a scheme and a token separated by a space
+    #[error("malformed Authorization header")]
+    Malformed,
+    /// the scheme is not `Bearer` or `Biscuit`
+    #[error("unsupported authentication scheme")]
+    UnsupportedScheme,
+    #[error("the header is {size} bytes, larger than the maximum of {maximum}")]
+    TooLarge { size: usize, maximum: usize },
+    /// the token could not be deserialized
+    #[error("invalid token: {0}")]
+    Token(error::Token),
+}
+
+/// extracts the scheme and the base64 encoded token from the value of an
+/// `Authorization` header
+pub fn parse_authorization(value: &[u8], max_size: usize) -> Result<(Scheme, &str), HeaderError> {
+    if value.len() > max_size {
+        return Err(HeaderError::TooLarge {
+            size: value.len(),
+            maximum: max_size,
+        });
+    }
+
+    let value = std::str::from_utf8(value).map_err(|_| HeaderError::Malformed)?;
+    let (scheme, token) = value.trim().split_once(' ').ok_or(HeaderError::Malformed)?;
+    let token = token.trim_start();
+    if token.is_empty() || !token.bytes().all(is_token_byte) {
+        return Err(HeaderError::Malformed);
+    }
+
+    // all the schemes are compared, so the time does not depend on which
+    // one matched
+    let bearer = scheme_eq(scheme, Scheme::Bearer);
+    let biscuit = scheme_eq(scheme, Scheme::Biscuit);
+    match (bearer, biscuit) {
+        (true, _) => Ok((Scheme::Bearer, token)),
+        (_, true) => Ok((Scheme::Biscuit, token)),
+        _ => Err(HeaderError::UnsupportedScheme),
+    }
+}
+
+/// extracts and deserializes the token from the value of an `Authorization`
+/// header
+///
+/// the signature is checked, but not the root key
+pub fn parse_token(value: &[u8], max_size: usize) -> Result<(Scheme, Biscuit), HeaderError> {
+    let (scheme, token) = parse_authorization(value, max_size)?;
+    let token = Biscuit::from_base64(token).map_err(HeaderError::Token)?;
+    Ok((scheme, token))
+}
+
+/// value of an `Authorization` header carrying the token
+pub fn authorization(scheme: Scheme, token: &Biscuit) -> Result<String, error::Token> {
+    Ok(format!("{} {}", scheme, token.to_base64()?))
+}
+
+/// characters of the URL safe base64 alphabet, with padding
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'='
+}
+
+/// case insensitive comparison taking the same time for all the schemes of
+/// the same length
+fn scheme_eq(scheme: &str, expected: Scheme) -> bool {
+    let scheme = scheme.as_bytes();
+    let expected = expected.as_str().as_bytes();
+    if scheme.len() != expected.len() {
+        return false;
+    }
+
+    scheme.iter().zip(expected.iter()).fold(0u8, |acc, (a, b)| {
+        acc | (a.to_ascii_lowercase() ^ b.to_ascii_lowercase())
+    }) == 0
+}
+
+/// size of a cookie, name and value included, that all browsers accept
+pub const MAX_COOKIE_SIZE: usize = 4096;
+
+/// chunk index and count in hexadecimal, 16 hexadecimal characters of the
+/// digest of the token, and a dot
+const CHUNK_HEADER_SIZE: usize = 2 + 2 + 16 + 1;
+const MAX_CHUNKS: usize = 255;
+
+/// error returned by the cookie helpers
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum CookieError {
+    /// there is no cookie carrying the token
+    #[error("missing token cookie")]
+    Missing,
+    /// a chunk does not start with a valid header
+    #[error("malformed token cookie")]
+    Malformed,
+    /// the chunk at this index is missing
+    #[error("missing token cookie chunk {0}")]
+    Incomplete(usize),
+    /// the chunks do not belong to the same token
+    #[error("the token cookie chunks do not match")]
+    Integrity,
+    /// the budget cannot hold the name of the cookie and a chunk header
+    #[error("the cookie budget is too small")]
+    BudgetTooSmall,
+    /// the token needs more than 255 chunks
+    #[error("the token needs {0} cookies, more than the maximum of 255")]
+    TooManyChunks(usize),
+    /// the token could not be serialized or deserialized
+    #[error("invalid token: {0}")]
+    Token(error::Token),
+}
+
+/// returns `true` if the token fits in a single cookie named `name`, of at
+/// most `budget` bytes, name and value included
+pub fn fits_in_cookie(token: &Biscuit, name: &str, budget: usize) -> Result<bool, error::Token> {
+    // padded base64
+    let encoded = token.serialized_size()?.div_ceil(3) * 4;
+    Ok(name.len() + 1 + encoded <= budget)
+}
+
+/// cookies carrying the token, as `(name, value)` pairs
+///
+/// if the token fits in `budget`, a single cookie named `name` is
+/// returned. Otherwise, it is split in chunks named `name.0`, `name.1`...
+/// each starting with a header holding the number of chunks and a digest
+/// of the token, so [`join_cookies`] detects missing chunks, or chunks left
+/// from another token. When switching between one and several cookies, the
+/// ones that are not sent anymore should be deleted
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, http, token::Biscuit};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder(&root);
+/// for i in 0..100 {
+///     builder.add_authority_fact(format!("right(#authority, \"/file{}\", #read)", i).as_str()).unwrap();
+/// }
+/// let token = builder.build().unwrap();
+/// assert!(!http::fits_in_cookie(&token, "biscuit", 1024).unwrap());
+///
+/// let cookies = http::split_cookies(&token, "biscuit", 1024).unwrap();
+/// assert!(cookies.len() > 1);
+/// assert!(cookies.iter().all(|(name, value)| name.len() + 1 + value.len() <= 1024));
+///
+/// let joined = http::join_cookies(
+///     "biscuit",
+///     cookies.iter().map(|(name, value)| (name.as_str(), value.as_str())),
+/// )
+/// .unwrap();
+/// assert_eq!(joined.to_vec().unwrap(), token.to_vec().unwrap());
+/// ```
+pub fn split_cookies(
+    token: &Biscuit,
+    name: &str,
+    budget: usize,
+) -> Result<Vec<(String, String)>, CookieError> {
+    let encoded = token.to_base64().map_err(CookieError::Token)?;
+    if name.len() + 1 + encoded.len() <= budget {
+        return Ok(vec![(name.to_string(), encoded)]);
+    }
+
+    // the name suffix is counted with the largest index
+    let overhead = name.len() + ".255".len() + 1 + CHUNK_HEADER_SIZE;
+    if budget <= overhead {
+        return Err(CookieError::BudgetTooSmall);
+    }
+    // base64 is ASCII, so the chunks are valid strings
+    let chunks = encoded
+        .as_bytes()
+        .chunks(budget - overhead)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap())
+        .collect::<Vec<_>>();
+    if chunks.len() > MAX_CHUNKS {
+        return Err(CookieError::TooManyChunks(chunks.len()));
+    }
+
+    let digest = digest(&encoded);
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            (
+                format!("{}.{}", name, index),
+                format!("{:02x}{:02x}{}.{}", index, chunks.len(), digest, chunk),
+            )
+        })
+        .collect())
+}
+
+/// reassembles and deserializes a token from the cookies of a request,
+/// given as `(name, value)` pairs
+///
+/// cookies with other names are ignored. The signature of the token is
+/// checked, but not its root key
+pub fn join_cookies<'a, I>(name: &str, cookies: I) -> Result<Biscuit, CookieError>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let prefix = format!("{}.", name);
+    let mut chunks = HashMap::new();
+    for (cookie_name, value) in cookies {
+        if cookie_name == name {
+            return Biscuit::from_base64(value).map_err(CookieError::Token);
+        }
+        if let Some(index) = cookie_name
+            .strip_prefix(prefix.as_str())
+            .and_then(|index| index.parse::<usize>().ok())
+        {
+            chunks.insert(index, value);
+        }
+    }
+
+    let (count, digest) = match chunks.get(&0) {
+        Some(first) => chunk_header(first)?,
+        None if chunks.is_empty() => return Err(CookieError::Missing),
+        None => return Err(CookieError::Incomplete(0)),
+    };
+
+    let mut encoded = String::new();
+    for index in 0..count {
+        let chunk = chunks.get(&index).ok_or(CookieError::Incomplete(index))?;
+        if chunk_header(chunk)? != (count, digest) || chunk[..2] != format!("{:02x}", index) {
+            return Err(CookieError::Integrity);
+        }
+        encoded.push_str(&chunk[CHUNK_HEADER_SIZE..]);
+    }
+    if self::digest(&encoded) != digest {
+        return Err(CookieError::Integrity);
+    }
+
+    Biscuit::from_base64(encoded).map_err(CookieError::Token)
+}
+
+/// number of chunks and digest from the header of a chunk
+fn chunk_header(chunk: &str) -> Result<(usize, &str), CookieError> {
+    // the chunk comes from the client, it is only sliced once the header
+    // is known to be ASCII
+    let header = chunk.as_bytes();
+    if header.len() < CHUNK_HEADER_SIZE
+        || !header[..CHUNK_HEADER_SIZE - 1]
+            .iter()
+            .all(u8::is_ascii_hexdigit)
+        || header[CHUNK_HEADER_SIZE - 1] != b'.'
+    {
+        return Err(CookieError::Malformed);
+    }
+    let count = usize::from_str_radix(&chunk[2..4], 16).map_err(|_| CookieError::Malformed)?;
+
+    Ok((count, &chunk[4..CHUNK_HEADER_SIZE - 1]))
+}
+
+/// first 8 bytes of the SHA-256 digest, in hexadecimal
+fn digest(encoded: &str) -> String {
+    hex::encode(&Sha256::digest(encoded.as_bytes())[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_authorization(b"Bearer abc-_=", MAX_HEADER_SIZE),
+            Ok((Scheme::Bearer, "abc-_="))
+        );
+        assert_eq!(
+            parse_authorization(b"bearer  abc", MAX_HEADER_SIZE),
+            Ok((Scheme::Bearer, "abc"))
+        );
+        assert_eq!(
+            parse_authorization(b"BISCUIT abc", MAX_HEADER_SIZE),
+            Ok((Scheme::Biscuit, "abc"))
+        );
+        assert_eq!(
+            parse_authorization(b"Basic abc", MAX_HEADER_SIZE),
+            Err(HeaderError::UnsupportedScheme)
+        );
+        assert_eq!(
+            parse_authorization(b"Bearers abc", MAX_HEADER_SIZE),
+            Err(HeaderError::UnsupportedScheme)
+        );
+        assert_eq!(
+            parse_authorization(b"Bearer", MAX_HEADER_SIZE),
+            Err(HeaderError::Malformed)
+        );
+        assert_eq!(
+            parse_authorization(b"Bearer a b", MAX_HEADER_SIZE),
+            Err(HeaderError::Malformed)
+        );
+        assert_eq!(
+            parse_authorization(b"Bearer \xff", MAX_HEADER_SIZE),
+            Err(HeaderError::Malformed)
+        );
+        assert_eq!(
+            parse_authorization(b"Bearer abcdef", 10),
+            Err(HeaderError::TooLarge {
+                size: 13,
+                maximum: 10
+            })
+        );
+    }
+
+    #[test]
+    fn cookies() {
+        use crate::crypto::KeyPair;
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        for i in 0..100 {
+            builder
+                .add_authority_fact(format!("right(#authority, \"/file{}\", #read)", i).as_str())
+                .unwrap();
+        }
+        let token = builder.build().unwrap();
+        let size = token.to_base64().unwrap().len();
+        let join = |cookies: &[(String, String)]| {
+            join_cookies(
+                "b",
+                cookies
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .chain(std::iter::once(("other", "value"))),
+            )
+            .map(|token| token.to_vec().unwrap())
+        };
+
+        assert!(fits_in_cookie(&token, "b", size + 2).unwrap());
+        assert!(!fits_in_cookie(&token, "b", size + 1).unwrap());
+        let single = split_cookies(&token, "b", size + 2).unwrap();
+        assert_eq!(single, vec![("b".to_string(), token.to_base64().unwrap())]);
+        assert_eq!(join(&single), Ok(token.to_vec().unwrap()));
+
+        let cookies = split_cookies(&token, "b", 512).unwrap();
+        assert!(cookies.len() > 1);
+        assert!(cookies.iter().all(|(n, v)| n.len() + 1 + v.len() <= 512));
+        assert_eq!(join(&cookies), Ok(token.to_vec().unwrap()));
+
+        let mut reversed = cookies.clone();
+        reversed.reverse();
+        assert_eq!(join(&reversed), Ok(token.to_vec().unwrap()));
+
+        assert_eq!(join(&cookies[1..]), Err(CookieError::Incomplete(0)));
+        assert_eq!(
+            join(&cookies[..cookies.len() - 1]),
+            Err(CookieError::Incomplete(cookies.len() - 1))
+        );
+        assert_eq!(join(&[]), Err(CookieError::Missing));
+
+        // a chunk from another token
+        let other = Biscuit::builder(&root).build().unwrap();
+        let other = split_cookies(&other, "b", 100).unwrap();
+        let mut mixed = cookies.clone();
+        mixed[1] = other[1].clone();
+        assert_eq!(join(&mixed), Err(CookieError::Integrity));
+
+        // a modified chunk
+        let mut modified = cookies.clone();
+        let last = modified[1].1.pop();
+        modified[1]
+            .1
+            .push(if last == Some('A') { 'B' } else { 'A' });
+        assert_eq!(join(&modified), Err(CookieError::Integrity));
+
+        // the header of a chunk is not ASCII
+        let malformed = |value: &str| join(&[("b.0".to_string(), value.to_string())]);
+        assert_eq!(
+            malformed("0\u{e9}00000000000000000.abc"),
+            Err(CookieError::Malformed)
+        );
+        assert_eq!(
+            malformed("0100000000000000\u{e9}00.abc"),
+            Err(CookieError::Malformed)
+        );
+        assert_eq!(
+            malformed("0100000000000000000g.abc"),
+            Err(CookieError::Malformed)
+        );
+
+        assert_eq!(
+            split_cookies(&token, "b", 20),
+            Err(CookieError::BudgetTooSmall)
+        );
+    }
+}
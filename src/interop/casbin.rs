@@ -0,0 +1,1115 @@
+//! translation of Casbin models and policies
+//!
+//! the model's matcher becomes the queries of the verifier policies, and
+//! the lines of the policy file become facts: `p, alice, data1, read` is
+//! translated to `p(#ambient, "alice", "data1", "read")`, and the role
+//! definitions like `g, alice, admin, domain1` to
+//! `g(#ambient, "alice", "admin", "domain1")`, with rules computing the
+//! inherited roles. The request is given with [`request`], with the values
+//! in the order of the request definition.
+//!
+//! the supported models are those of the Casbin ACL and RBAC examples,
+//! with or without domains and deny policies:
+//! - matchers combining with `&&`, `||` and parentheses the `==` and `!=`
+//!   comparisons of request and policy fields or strings, the role
+//!   functions like `g(r.sub, p.sub, r.dom)`, and `keyMatch`, `keyMatch2`
+//!   and `regexMatch`
+//! - the `some(where (p.eft == allow))`, `!some(where (p.eft == deny))` and
+//!   `some(where (p.eft == allow)) && !some(where (p.eft == deny))` effects
+//!
+//! other constructs, like attributes of request fields (ABAC), custom
+//! functions or priority effects, are reported as [`CasbinError`]s.
+//!
+//! ```rust
+//! use biscuit_auth::{interop::casbin, token::verifier::Verifier};
+//!
+//! let model = r#"
+//! [request_definition]
+//! r = sub, dom, obj, act
+//!
+//! [policy_definition]
+//! p = sub, dom, obj, act
+//!
+//! [role_definition]
+//! g = _, _, _
+//!
+//! [policy_effect]
+//! e = some(where (p.eft == allow))
+//!
+//! [matchers]
+//! m = g(r.sub, p.sub, r.dom) && r.dom == p.dom && r.obj == p.obj && r.act == p.act
+//! "#;
+//!
+//! let policy = r#"
+//! p, admin, domain1, data1, read
+//! p, admin, domain1, data1, write
+//! g, alice, admin, domain1
+//! "#;
+//!
+//! let translation = casbin::translate(model, policy).unwrap();
+//!
+//! let mut verifier = Verifier::new().unwrap();
+//! verifier.add_fact(casbin::request(&["alice", "domain1", "data1", "write"])).unwrap();
+//! translation.apply(&mut verifier);
+//! assert!(verifier.verify().is_ok());
+//!
+//! let mut verifier = Verifier::new().unwrap();
+//! verifier.add_fact(casbin::request(&["alice", "domain2", "data1", "write"])).unwrap();
+//! translation.apply(&mut verifier);
+//! assert!(verifier.verify().is_err());
+//! ```
+use super::Translation;
+use crate::token::builder::{
+    constrained_rule, fact, pred, rule, s, var, Binary, Expression, Fact, Op, Policy, PolicyKind,
+    Predicate, Rule, Term, Unary,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// construct that could not be translated
+#[derive(Clone, Debug, PartialEq)]
+pub struct CasbinError {
+    /// file containing the construct
+    pub file: CasbinFile,
+    /// line of the construct, starting at 1
+    pub line: usize,
+    pub kind: CasbinErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CasbinFile {
+    Model,
+    Policy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CasbinErrorKind {
+    /// the model or policy is not valid
+    Syntax(String),
+    /// valid Casbin that has no translation, with a description of the construct
+    Unsupported(String),
+}
+
+impl fmt::Display for CasbinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = match self.file {
+            CasbinFile::Model => "model",
+            CasbinFile::Policy => "policy",
+        };
+        match &self.kind {
+            CasbinErrorKind::Syntax(message) => {
+                write!(f, "{} line {}: syntax error: {}", file, self.line, message)
+            }
+            CasbinErrorKind::Unsupported(construct) => {
+                write!(f, "{} line {}: unsupported: {}", file, self.line, construct)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CasbinError {}
+
+/// request fact, with the values in the order of the request definition
+pub fn request(values: &[&str]) -> Fact {
+    let mut terms = vec![s("ambient")];
+    terms.extend(values.iter().map(|value| Term::Str(value.to_string())));
+    fact("request", &terms)
+}
+
+/// translates a Casbin model and its policy file, returning all the errors
+/// if some constructs could not be translated
+pub fn translate(model: &str, policy: &str) -> Result<Translation, Vec<CasbinError>> {
+    let model = Model::parse(model)?;
+    let mut translation = Translation::default();
+
+    let (matcher_line, matcher) = model.matcher.clone();
+    let model_error = |kind| {
+        vec![CasbinError {
+            file: CasbinFile::Model,
+            line: matcher_line,
+            kind,
+        }]
+    };
+    let tokens = lex(&matcher).map_err(model_error)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        model: &model,
+    };
+    let matcher = parser.matcher().map_err(model_error)?;
+    let conjunctions = matcher.dnf();
+
+    // policy fields holding patterns, converted when creating the facts
+    let mut patterns = BTreeMap::new();
+    let mut reflexive = BTreeSet::new();
+    let mut queries = |eft: Option<&str>| -> Result<Vec<Rule>, Vec<CasbinError>> {
+        conjunctions
+            .iter()
+            .map(|atoms| model.query(atoms, eft, &mut patterns, &mut reflexive))
+            .collect::<Result<_, _>>()
+            .map_err(model_error)
+    };
+
+    let eft = model.policy.iter().any(|field| field == "eft");
+    match model.effect {
+        Effect::Allow => {
+            let queries = queries(if eft { Some("allow") } else { None })?;
+            translation.policies.push(Policy {
+                queries,
+                kind: PolicyKind::Allow,
+            });
+        }
+        Effect::AllowAndNotDeny | Effect::NotDeny => {
+            if !eft {
+                return Err(vec![CasbinError {
+                    file: CasbinFile::Model,
+                    line: model.effect_line,
+                    kind: CasbinErrorKind::Syntax(
+                        "the effect uses p.eft, which is not in the policy definition".to_string(),
+                    ),
+                }]);
+            }
+            let deny = queries(Some("deny"))?;
+            let allow = if model.effect == Effect::NotDeny {
+                vec![constrained_rule(
+                    "query",
+                    &[] as &[Term],
+                    &[] as &[Predicate],
+                    &[Expression {
+                        ops: vec![Op::Value(Term::Bool(true))],
+                    }],
+                )]
+            } else {
+                queries(Some("allow"))?
+            };
+            translation.policies.push(Policy {
+                queries: deny,
+                kind: PolicyKind::Deny,
+            });
+            translation.policies.push(Policy {
+                queries: allow,
+                kind: PolicyKind::Allow,
+            });
+        }
+    }
+
+    for (name, arity) in model.roles.iter() {
+        translation.rules.extend(links(name, *arity));
+    }
+    for (name, request, domain) in reflexive {
+        // the role functions are also true for the subject itself
+        let mut request_terms = vec![s("ambient")];
+        request_terms.extend(
+            model
+                .request
+                .iter()
+                .map(|field| var(&format!("r_{}", field))),
+        );
+        let mut head = vec![s("ambient"), var(&request), var(&request)];
+        if let Some(domain) = domain {
+            head.push(var(&domain));
+        }
+        translation.rules.push(rule(
+            &format!("{}_link", name),
+            &head,
+            &[pred("request", &request_terms)],
+        ));
+    }
+
+    translation.facts = model.facts(policy, &patterns)?;
+    Ok(translation)
+}
+
+/// rules computing the roles inherited through a role definition
+fn links(name: &str, arity: usize) -> Vec<Rule> {
+    let link = format!("{}_link", name);
+    let terms = |names: &[&str]| {
+        let mut terms = vec![s("ambient")];
+        terms.extend(names.iter().map(|name| var(name)));
+        if arity == 3 {
+            terms.push(var("domain"));
+        }
+        terms
+    };
+
+    vec![
+        rule(
+            &link,
+            &terms(&["user", "role"]),
+            &[pred(name, &terms(&["user", "role"]))],
+        ),
+        rule(
+            &link,
+            &terms(&["user", "role"]),
+            &[
+                pred(name, &terms(&["user", "parent"])),
+                pred(&link, &terms(&["parent", "role"])),
+            ],
+        ),
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Effect {
+    /// `some(where (p.eft == allow))`
+    Allow,
+    /// `!some(where (p.eft == deny))`
+    NotDeny,
+    /// `some(where (p.eft == allow)) && !some(where (p.eft == deny))`
+    AllowAndNotDeny,
+}
+
+/// conversion of the values of a policy field used as a pattern
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pattern {
+    KeyMatch,
+    KeyMatch2,
+    Regex,
+}
+
+impl Pattern {
+    fn regex(self, value: &str) -> String {
+        match self {
+            Pattern::Regex => value.to_string(),
+            Pattern::KeyMatch => format!(
+                "^{}$",
+                value
+                    .split('*')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(".*")
+            ),
+            Pattern::KeyMatch2 => {
+                let segments = value
+                    .split('/')
+                    .map(|segment| {
+                        if segment == "*" {
+                            ".*".to_string()
+                        } else if segment.starts_with(':') {
+                            "[^/]+".to_string()
+                        } else {
+                            regex::escape(segment)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("^{}$", segments.join("/"))
+            }
+        }
+    }
+}
+
+struct Model {
+    request: Vec<String>,
+    policy: Vec<String>,
+    /// role definitions, with their arity
+    roles: BTreeMap<String, usize>,
+    effect: Effect,
+    effect_line: usize,
+    matcher: (usize, String),
+}
+
+impl Model {
+    fn parse(source: &str) -> Result<Self, Vec<CasbinError>> {
+        let mut errors = Vec::new();
+        let error = |line, kind| CasbinError {
+            file: CasbinFile::Model,
+            line,
+            kind,
+        };
+
+        let mut section = String::new();
+        let mut request = None;
+        let mut policy = None;
+        let mut roles = BTreeMap::new();
+        let mut effect = None;
+        let mut matcher = None;
+        for (i, line) in source.lines().enumerate() {
+            let number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_string();
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => {
+                    errors.push(error(
+                        number,
+                        CasbinErrorKind::Syntax("expected `key = value`".to_string()),
+                    ));
+                    continue;
+                }
+            };
+            let fields = || {
+                value
+                    .split(',')
+                    .map(|field| field.trim().to_string())
+                    .collect::<Vec<_>>()
+            };
+
+            match (section.as_str(), key) {
+                ("request_definition", "r") => request = Some(fields()),
+                ("policy_definition", "p") => policy = Some(fields()),
+                ("role_definition", name) => {
+                    let arity = fields().len();
+                    if arity == 2 || arity == 3 {
+                        roles.insert(name.to_string(), arity);
+                    } else {
+                        errors.push(error(
+                            number,
+                            CasbinErrorKind::Unsupported(format!(
+                                "role definition with {} fields",
+                                arity
+                            )),
+                        ));
+                    }
+                }
+                ("policy_effect", "e") => {
+                    let normalized = value.split_whitespace().collect::<String>();
+                    effect = match normalized.as_str() {
+                        "some(where(p.eft==allow))" => Some((number, Effect::Allow)),
+                        "!some(where(p.eft==deny))" => Some((number, Effect::NotDeny)),
+                        "some(where(p.eft==allow))&&!some(where(p.eft==deny))" => {
+                            Some((number, Effect::AllowAndNotDeny))
+                        }
+                        _ => {
+                            errors.push(error(
+                                number,
+                                CasbinErrorKind::Unsupported(format!("policy effect `{}`", value)),
+                            ));
+                            continue;
+                        }
+                    };
+                }
+                ("matchers", "m") => matcher = Some((number, value.to_string())),
+                ("request_definition", _)
+                | ("policy_definition", _)
+                | ("policy_effect", _)
+                | ("matchers", _) => errors.push(error(
+                    number,
+                    CasbinErrorKind::Unsupported(format!("multiple definitions, with `{}`", key)),
+                )),
+                _ => errors.push(error(
+                    number,
+                    CasbinErrorKind::Syntax(format!("unknown section `{}`", section)),
+                )),
+            }
+        }
+
+        // an unsupported definition is not reported as missing too
+        let last = source.lines().count().max(1);
+        let reported = !errors.is_empty();
+        for (missing, name) in [
+            (request.is_none(), "request definition"),
+            (policy.is_none(), "policy definition"),
+            (effect.is_none(), "policy effect"),
+            (matcher.is_none(), "matcher"),
+        ]
+        .iter()
+        {
+            if *missing && !reported {
+                errors.push(error(
+                    last,
+                    CasbinErrorKind::Syntax(format!("missing {}", name)),
+                ));
+            }
+        }
+
+        match (request, policy, effect, matcher) {
+            (Some(request), Some(policy), Some((effect_line, effect)), Some(matcher))
+                if errors.is_empty() =>
+            {
+                Ok(Model {
+                    request,
+                    policy,
+                    roles,
+                    effect,
+                    effect_line,
+                    matcher,
+                })
+            }
+            _ => Err(errors),
+        }
+    }
+
+    /// verifier query for one conjunction of the matcher
+    fn query(
+        &self,
+        atoms: &[Atom],
+        eft: Option<&str>,
+        patterns: &mut BTreeMap<String, Pattern>,
+        reflexive: &mut BTreeSet<(String, String, Option<String>)>,
+    ) -> Result<Rule, CasbinErrorKind> {
+        // `r.x == p.y` joins on the request variable instead of comparing
+        let mut joins = BTreeMap::new();
+        for atom in atoms {
+            if let Atom::Equal(left, right, false) = atom {
+                match (left, right) {
+                    (Operand::Request(r), Operand::Policy(p))
+                    | (Operand::Policy(p), Operand::Request(r)) => {
+                        joins.entry(p.clone()).or_insert_with(|| r.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let term = |operand: &Operand| match operand {
+            Operand::Request(field) => var(&format!("r_{}", field)),
+            Operand::Policy(field) => match joins.get(field) {
+                Some(request) => var(&format!("r_{}", request)),
+                None => var(&format!("p_{}", field)),
+            },
+            Operand::Literal(value) => Term::Str(value.clone()),
+        };
+
+        let mut predicates = Vec::new();
+        let mut expressions = Vec::new();
+        let mut request_terms = vec![s("ambient")];
+        request_terms.extend(
+            self.request
+                .iter()
+                .map(|field| var(&format!("r_{}", field))),
+        );
+        predicates.push(pred("request", &request_terms));
+
+        let mut uses_policy = eft.is_some();
+        for atom in atoms {
+            match atom {
+                Atom::Equal(left, right, negated) => {
+                    uses_policy |= left.is_policy() || right.is_policy();
+                    let joined = !negated
+                        && match (left, right) {
+                            (Operand::Request(r), Operand::Policy(p))
+                            | (Operand::Policy(p), Operand::Request(r)) => joins.get(p) == Some(r),
+                            _ => false,
+                        };
+                    if joined {
+                        continue;
+                    }
+                    let mut ops = vec![
+                        Op::Value(term(left)),
+                        Op::Value(term(right)),
+                        Op::Binary(Binary::Equal),
+                    ];
+                    if *negated {
+                        ops.push(Op::Unary(Unary::Parens));
+                        ops.push(Op::Unary(Unary::Negate));
+                    }
+                    expressions.push(Expression { ops });
+                }
+                Atom::Call(name, arguments) if self.roles.contains_key(name) => {
+                    uses_policy |= arguments.iter().any(Operand::is_policy);
+                    if arguments.len() != self.roles[name] {
+                        return Err(CasbinErrorKind::Syntax(format!(
+                            "`{}` takes {} arguments",
+                            name, self.roles[name]
+                        )));
+                    }
+                    let request_field = |operand: &Operand| match operand {
+                        Operand::Request(field) => Some(format!("r_{}", field)),
+                        _ => None,
+                    };
+                    let user = request_field(&arguments[0]).ok_or_else(|| {
+                        CasbinErrorKind::Unsupported(format!(
+                            "`{}` with a first argument that is not a request field",
+                            name
+                        ))
+                    })?;
+                    let domain = match arguments.get(2) {
+                        None => None,
+                        Some(domain) => Some(request_field(domain).ok_or_else(|| {
+                            CasbinErrorKind::Unsupported(format!(
+                                "`{}` with a domain that is not a request field",
+                                name
+                            ))
+                        })?),
+                    };
+                    reflexive.insert((name.clone(), user, domain));
+
+                    let mut terms = vec![s("ambient")];
+                    terms.extend(arguments.iter().map(&term));
+                    predicates.push(pred(&format!("{}_link", name), &terms));
+                }
+                Atom::Call(name, arguments) => {
+                    let pattern = match name.as_str() {
+                        "keyMatch" => Pattern::KeyMatch,
+                        "keyMatch2" => Pattern::KeyMatch2,
+                        "regexMatch" => Pattern::Regex,
+                        _ => {
+                            return Err(CasbinErrorKind::Unsupported(format!(
+                                "function `{}`",
+                                name
+                            )))
+                        }
+                    };
+                    let (value, regex) = match arguments.as_slice() {
+                        [value, regex] => (value, regex),
+                        _ => {
+                            return Err(CasbinErrorKind::Syntax(format!(
+                                "`{}` takes 2 arguments",
+                                name
+                            )))
+                        }
+                    };
+                    let regex = match regex {
+                        Operand::Literal(value) => Term::Str(pattern.regex(value)),
+                        Operand::Policy(field) if !joins.contains_key(field) => {
+                            uses_policy = true;
+                            if *patterns.entry(field.clone()).or_insert(pattern) != pattern {
+                                return Err(CasbinErrorKind::Unsupported(format!(
+                                    "p.{} used with different pattern functions",
+                                    field
+                                )));
+                            }
+                            term(regex)
+                        }
+                        _ => {
+                            return Err(CasbinErrorKind::Unsupported(format!(
+                                "`{}` with a pattern that is not a policy field or a string",
+                                name
+                            )))
+                        }
+                    };
+                    uses_policy |= value.is_policy();
+                    expressions.push(Expression {
+                        ops: vec![
+                            Op::Value(term(value)),
+                            Op::Value(regex),
+                            Op::Binary(Binary::Regex),
+                        ],
+                    });
+                }
+            }
+        }
+
+        if uses_policy {
+            let mut terms = vec![s("ambient")];
+            for field in self.policy.iter() {
+                terms.push(match eft {
+                    Some(eft) if field == "eft" => Term::Str(eft.to_string()),
+                    _ => term(&Operand::Policy(field.clone())),
+                });
+            }
+            predicates.insert(1, pred("p", &terms));
+        }
+
+        Ok(constrained_rule(
+            "query",
+            &[] as &[Term],
+            &predicates,
+            &expressions,
+        ))
+    }
+
+    /// facts for the lines of a policy file
+    fn facts(
+        &self,
+        source: &str,
+        patterns: &BTreeMap<String, Pattern>,
+    ) -> Result<Vec<Fact>, Vec<CasbinError>> {
+        let mut facts = Vec::new();
+        let mut errors = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut values = line.split(',').map(|value| {
+                let value = value.trim();
+                if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                    &value[1..value.len() - 1]
+                } else {
+                    value
+                }
+            });
+            let ptype = values.next().unwrap_or_default();
+            let values = values.collect::<Vec<_>>();
+            let error = |message: String| CasbinError {
+                file: CasbinFile::Policy,
+                line: i + 1,
+                kind: CasbinErrorKind::Syntax(message),
+            };
+
+            let mut terms = vec![s("ambient")];
+            if ptype == "p" {
+                // `eft` can be omitted, and defaults to allow
+                let eft = self.policy.iter().position(|field| field == "eft");
+                let expected = self.policy.len();
+                if values.len() != expected && !(eft.is_some() && values.len() + 1 == expected) {
+                    errors.push(error(format!(
+                        "expected {} values, found {}",
+                        expected,
+                        values.len()
+                    )));
+                    continue;
+                }
+                let mut values = values.into_iter();
+                for (index, field) in self.policy.iter().enumerate() {
+                    let value = values.next().unwrap_or("allow");
+                    terms.push(Term::Str(match patterns.get(field) {
+                        Some(pattern) => pattern.regex(value),
+                        None => value.to_string(),
+                    }));
+                    if Some(index) == eft && value != "allow" && value != "deny" {
+                        errors.push(error(format!("invalid effect `{}`", value)));
+                    }
+                }
+            } else if let Some(arity) = self.roles.get(ptype) {
+                if values.len() != *arity {
+                    errors.push(error(format!(
+                        "expected {} values, found {}",
+                        arity,
+                        values.len()
+                    )));
+                    continue;
+                }
+                terms.extend(values.iter().map(|value| Term::Str(value.to_string())));
+            } else {
+                errors.push(error(format!("unknown policy type `{}`", ptype)));
+                continue;
+            }
+            facts.push(fact(ptype, &terms));
+        }
+
+        if errors.is_empty() {
+            Ok(facts)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: &[&str] = &["==", "!=", "&&", "||", "!", "(", ")", ","];
+
+fn lex(source: &str) -> Result<Vec<Tok>, CasbinErrorKind> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, c)) => value.push(c),
+                        None => {
+                            return Err(CasbinErrorKind::Syntax("unterminated string".to_string()))
+                        }
+                    }
+                }
+                tokens.push(Tok::Str(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Tok::Ident(source[start..end].to_string()));
+            }
+            _ => {
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| source[start..].starts_with(**p))
+                    .ok_or_else(|| CasbinErrorKind::Unsupported(format!("operator `{}`", c)))?;
+                for _ in 0..punct.len() {
+                    chars.next();
+                }
+                tokens.push(Tok::Punct(punct));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    /// field of the request definition
+    Request(String),
+    /// field of the policy definition
+    Policy(String),
+    Literal(String),
+}
+
+impl Operand {
+    fn is_policy(&self) -> bool {
+        matches!(self, Operand::Policy(_))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Atom {
+    /// comparison, negated for `!=`
+    Equal(Operand, Operand, bool),
+    Call(String, Vec<Operand>),
+}
+
+enum Matcher {
+    Atom(Atom),
+    And(Box<Matcher>, Box<Matcher>),
+    Or(Box<Matcher>, Box<Matcher>),
+}
+
+impl Matcher {
+    /// disjunctive normal form: each conjunction becomes a query
+    fn dnf(&self) -> Vec<Vec<Atom>> {
+        match self {
+            Matcher::Atom(atom) => vec![vec![atom.clone()]],
+            Matcher::Or(left, right) => {
+                let mut conjunctions = left.dnf();
+                conjunctions.extend(right.dnf());
+                conjunctions
+            }
+            Matcher::And(left, right) => {
+                let right = right.dnf();
+                left.dnf()
+                    .into_iter()
+                    .flat_map(|left| {
+                        right.iter().map(move |right| {
+                            let mut conjunction = left.clone();
+                            conjunction.extend(right.iter().cloned());
+                            conjunction
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Tok>,
+    position: usize,
+    model: &'a Model,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Punct(p)) if *p == punct) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn matcher(&mut self) -> Result<Matcher, CasbinErrorKind> {
+        let matcher = self.or()?;
+        match self.peek() {
+            None => Ok(matcher),
+            Some(_) => Err(CasbinErrorKind::Syntax(
+                "unexpected token in the matcher".to_string(),
+            )),
+        }
+    }
+
+    fn or(&mut self) -> Result<Matcher, CasbinErrorKind> {
+        let mut matcher = self.and()?;
+        while self.eat("||") {
+            matcher = Matcher::Or(Box::new(matcher), Box::new(self.and()?));
+        }
+        Ok(matcher)
+    }
+
+    fn and(&mut self) -> Result<Matcher, CasbinErrorKind> {
+        let mut matcher = self.atom()?;
+        while self.eat("&&") {
+            matcher = Matcher::And(Box::new(matcher), Box::new(self.atom()?));
+        }
+        Ok(matcher)
+    }
+
+    fn atom(&mut self) -> Result<Matcher, CasbinErrorKind> {
+        if self.eat("!") {
+            return Err(CasbinErrorKind::Unsupported("negation".to_string()));
+        }
+        if self.eat("(") {
+            let matcher = self.or()?;
+            if !self.eat(")") {
+                return Err(CasbinErrorKind::Syntax("expected `)`".to_string()));
+            }
+            return Ok(matcher);
+        }
+
+        if let Some(Tok::Ident(name)) = self.peek().cloned() {
+            if matches!(self.tokens.get(self.position + 1), Some(Tok::Punct("("))) {
+                self.position += 2;
+                let mut arguments = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        arguments.push(self.operand()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return Err(CasbinErrorKind::Syntax("expected `,`".to_string()));
+                        }
+                    }
+                }
+                return Ok(Matcher::Atom(Atom::Call(name, arguments)));
+            }
+        }
+
+        let left = self.operand()?;
+        let negated = if self.eat("==") {
+            false
+        } else if self.eat("!=") {
+            true
+        } else {
+            return Err(CasbinErrorKind::Unsupported(
+                "condition that is not a comparison or a function".to_string(),
+            ));
+        };
+        let right = self.operand()?;
+        Ok(Matcher::Atom(Atom::Equal(left, right, negated)))
+    }
+
+    fn operand(&mut self) -> Result<Operand, CasbinErrorKind> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(Tok::Str(value)) => Ok(Operand::Literal(value)),
+            Some(Tok::Ident(name)) => {
+                let mut parts = name.splitn(2, '.');
+                let (definition, fields) = match (parts.next(), parts.next()) {
+                    (Some("r"), Some(_)) => ("r", &self.model.request),
+                    (Some("p"), Some(_)) => ("p", &self.model.policy),
+                    _ => {
+                        return Err(CasbinErrorKind::Unsupported(format!(
+                            "identifier `{}`",
+                            name
+                        )))
+                    }
+                };
+                let field = &name[2..];
+                if field.contains('.') {
+                    return Err(CasbinErrorKind::Unsupported(format!(
+                        "attribute access `{}`",
+                        name
+                    )));
+                }
+                if !fields.iter().any(|f| f == field) {
+                    return Err(CasbinErrorKind::Syntax(format!(
+                        "`{}` is not in the {} definition",
+                        field,
+                        if definition == "r" {
+                            "request"
+                        } else {
+                            "policy"
+                        }
+                    )));
+                }
+                Ok(if definition == "r" {
+                    Operand::Request(field.to_string())
+                } else {
+                    Operand::Policy(field.to_string())
+                })
+            }
+            _ => Err(CasbinErrorKind::Syntax("expected a value".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::verifier::Verifier;
+
+    fn enforce(translation: &Translation, request: &[&str]) -> bool {
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_fact(super::request(request)).unwrap();
+        translation.apply(&mut verifier);
+        verifier.verify().is_ok()
+    }
+
+    #[test]
+    fn acl() {
+        let translation = translate(
+            r#"
+[request_definition]
+r = sub, obj, act
+
+[policy_definition]
+p = sub, obj, act
+
+[policy_effect]
+e = some(where (p.eft == allow))
+
+[matchers]
+m = r.sub == p.sub && keyMatch(r.obj, p.obj) && r.act == p.act || r.sub == "root"
+"#,
+            r#"
+p, alice, /data1/*, read
+p, bob, /data2/report, write
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            translation.to_string(),
+            r#"p(#ambient, "alice", "^/data1/.*$", "read");
+p(#ambient, "bob", "^/data2/report$", "write");
+allow if request(#ambient, $r_sub, $r_obj, $r_act), p(#ambient, $r_sub, $p_obj, $r_act), $r_obj.matches($p_obj) or request(#ambient, $r_sub, $r_obj, $r_act), $r_sub == "root";
+"#
+        );
+        assert!(enforce(&translation, &["alice", "/data1/file", "read"]));
+        assert!(!enforce(&translation, &["alice", "/data1/file", "write"]));
+        assert!(!enforce(&translation, &["alice", "/data2/report", "read"]));
+        assert!(enforce(&translation, &["bob", "/data2/report", "write"]));
+        assert!(!enforce(
+            &translation,
+            &["bob", "/data2/report.bak", "write"]
+        ));
+        assert!(enforce(&translation, &["root", "/anything", "delete"]));
+    }
+
+    #[test]
+    fn rbac_with_domains_and_deny() {
+        let translation = translate(
+            r#"
+[request_definition]
+r = sub, dom, obj, act
+
+[policy_definition]
+p = sub, dom, obj, act, eft
+
+[role_definition]
+g = _, _, _
+
+[policy_effect]
+e = some(where (p.eft == allow)) && !some(where (p.eft == deny))
+
+[matchers]
+m = g(r.sub, p.sub, r.dom) && r.dom == p.dom && keyMatch2(r.obj, p.obj) && r.act == p.act
+"#,
+            r#"
+p, admin, tenant1, /users/:id, read
+p, admin, tenant1, /users/:id, write
+p, reader, tenant1, /users/:id, read
+p, intern, tenant1, /users/:id, write, deny
+g, alice, admin, tenant1
+g, bob, reader, tenant1
+g, carol, admin, tenant1
+g, carol, intern, tenant1
+g, dave, admin, tenant2
+"#,
+        )
+        .unwrap();
+
+        assert!(enforce(
+            &translation,
+            &["alice", "tenant1", "/users/42", "write"]
+        ));
+        assert!(enforce(
+            &translation,
+            &["bob", "tenant1", "/users/42", "read"]
+        ));
+        assert!(!enforce(
+            &translation,
+            &["bob", "tenant1", "/users/42", "write"]
+        ));
+        assert!(!enforce(
+            &translation,
+            &["bob", "tenant1", "/users/42/keys", "read"]
+        ));
+        assert!(enforce(
+            &translation,
+            &["carol", "tenant1", "/users/42", "read"]
+        ));
+        assert!(!enforce(
+            &translation,
+            &["carol", "tenant1", "/users/42", "write"]
+        ));
+        assert!(!enforce(
+            &translation,
+            &["dave", "tenant1", "/users/42", "read"]
+        ));
+        // policies can also name users directly
+        assert!(enforce(
+            &translation,
+            &["admin", "tenant1", "/users/42", "read"]
+        ));
+
+        // the printed translation can be loaded as a policy file
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_code(&translation.to_string()).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let model = |effect: &str, matcher: &str| {
+            format!(
+                "[request_definition]\nr = sub, obj, act\n[policy_definition]\np = sub, obj, act\n\
+                [role_definition]\ng = _, _\n[policy_effect]\ne = {}\n[matchers]\nm = {}\n",
+                effect, matcher
+            )
+        };
+        let allow = "some(where (p.eft == allow))";
+        let error = |model: &str, policy: &str| {
+            translate(model, policy)
+                .unwrap_err()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            error(&model("priority(p.eft) || deny", "r.sub == p.sub"), ""),
+            vec!["model line 8: unsupported: policy effect `priority(p.eft) || deny`"]
+        );
+        assert_eq!(
+            error(&model(allow, "r.sub.Owner == r.obj"), ""),
+            vec!["model line 10: unsupported: attribute access `r.sub.Owner`"]
+        );
+        assert_eq!(
+            error(&model(allow, "r.user == p.sub"), ""),
+            vec!["model line 10: syntax error: `user` is not in the request definition"]
+        );
+        assert_eq!(
+            error(&model(allow, "!(r.sub == p.sub)"), ""),
+            vec!["model line 10: unsupported: negation"]
+        );
+        assert_eq!(
+            error(&model(allow, "ipMatch(r.sub, p.sub)"), ""),
+            vec!["model line 10: unsupported: function `ipMatch`"]
+        );
+        assert_eq!(
+            error(&model("!some(where (p.eft == deny))", "r.sub == p.sub"), ""),
+            vec!["model line 8: syntax error: the effect uses p.eft, which is not in the policy definition"]
+        );
+        assert_eq!(
+            error(
+                &model(allow, "g(r.sub, p.sub) && r.obj == p.obj"),
+                "p, alice, data1\ng, alice\nq, alice, data1, read"
+            ),
+            vec![
+                "policy line 1: syntax error: expected 3 values, found 2",
+                "policy line 2: syntax error: expected 2 values, found 1",
+                "policy line 3: syntax error: unknown policy type `q`",
+            ]
+        );
+    }
+}
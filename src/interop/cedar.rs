@@ -0,0 +1,1072 @@
+//! translation of Cedar policies
+//!
+//! `permit` policies become the queries of an allow policy, and `forbid`
+//! policies the queries of a deny policy tested first, so that like in
+//! Cedar a matching `forbid` overrides any `permit`, and a request that
+//! matches no policy is denied.
+//!
+//! the request is described by facts added to the verifier, with entities
+//! written as their Cedar literal, like `User::"alice"` (see [`entity`]):
+//! - `principal(#ambient, $entity)`, `action(#ambient, $entity)` and
+//!   `resource(#ambient, $entity)`
+//! - `context(#ambient, $name, $value)` for the fields of the context
+//! - `attribute(#ambient, $entity, $name, $value)` for entity attributes
+//! - `parent(#ambient, $entity, $parent)` for the entity hierarchy used by
+//!   `in`
+//!
+//! nested record fields are named by their path, like `"address.city"`,
+//! and sets are Datalog sets.
+//!
+//! the policy scope, `when` conditions combining comparisons with `&&` and
+//! `||`, and the `in`, `has`, `like`, `is` and `.contains()` operators are
+//! supported. `unless` conditions, negations, templates, extension functions
+//! and arithmetic are reported as [`CedarError`]s.
+//!
+//! ```rust
+//! use biscuit_auth::{interop::cedar, token::verifier::Verifier};
+//!
+//! let policies = cedar::translate(r#"
+//! permit (
+//!     principal in Group::"editors",
+//!     action in [Action::"read", Action::"write"],
+//!     resource
+//! ) when { resource.owner == principal || context.public == true };
+//!
+//! forbid (principal, action == Action::"write", resource)
+//! when { resource.locked };
+//! "#).unwrap();
+//!
+//! let mut verifier = Verifier::new().unwrap();
+//! verifier.add_code(r#"
+//!     principal(#ambient, "User::\"alice\"");
+//!     parent(#ambient, "User::\"alice\"", "Group::\"editors\"");
+//!     action(#ambient, "Action::\"read\"");
+//!     resource(#ambient, "Document::\"report\"");
+//!     attribute(#ambient, "Document::\"report\"", "owner", "User::\"alice\"");
+//!     attribute(#ambient, "Document::\"report\"", "locked", true);
+//! "#).unwrap();
+//! policies.apply(&mut verifier);
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::Translation;
+use crate::token::builder::{
+    constrained_rule, pred, rule, s, var, Binary, Expression, Op, Policy, PolicyKind, Predicate,
+    Term, Unary,
+};
+use std::{collections::BTreeSet, fmt};
+
+/// construct that could not be translated
+#[derive(Clone, Debug, PartialEq)]
+pub struct CedarError {
+    /// line of the construct, starting at 1
+    pub line: usize,
+    pub kind: CedarErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CedarErrorKind {
+    /// the source is not valid Cedar
+    Syntax(String),
+    /// valid Cedar that has no translation, with a description of the construct
+    Unsupported(String),
+}
+
+impl fmt::Display for CedarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            CedarErrorKind::Syntax(message) => {
+                write!(f, "line {}: syntax error: {}", self.line, message)
+            }
+            CedarErrorKind::Unsupported(construct) => {
+                write!(f, "line {}: unsupported: {}", self.line, construct)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CedarError {}
+
+/// entity as written in the request facts, like `User::"alice"`
+pub fn entity(entity_type: &str, id: &str) -> Term {
+    Term::Str(format!("{}::{:?}", entity_type, id))
+}
+
+/// translates Cedar policies, returning all the errors if some constructs
+/// could not be translated
+pub fn translate(source: &str) -> Result<Translation, Vec<CedarError>> {
+    let tokens = lex(source).map_err(|e| vec![e])?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        variables: 0,
+        hierarchy: false,
+    };
+
+    let mut errors = Vec::new();
+    let mut permit = Vec::new();
+    let mut forbid = Vec::new();
+    while parser.peek().is_some() {
+        match parser.policy() {
+            Ok((PolicyKind::Allow, queries)) => permit.extend(queries),
+            Ok((PolicyKind::Deny, queries)) => forbid.extend(queries),
+            Err(e) => {
+                errors.push(e);
+                parser.recover();
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut translation = Translation::default();
+    if parser.hierarchy {
+        // `ancestor(#ambient, $entity, $ancestor)`: the entity itself or one
+        // of its ancestors in the hierarchy. The facts are ambient so that
+        // attenuation blocks cannot declare them
+        for scope in ["principal", "action", "resource"].iter() {
+            translation.rules.push(rule(
+                "ancestor",
+                &[s("ambient"), var("entity"), var("entity")],
+                &[pred(scope, &[s("ambient"), var("entity")])],
+            ));
+        }
+        translation.rules.push(rule(
+            "ancestor",
+            &[s("ambient"), var("entity"), var("ancestor")],
+            &[pred(
+                "parent",
+                &[s("ambient"), var("entity"), var("ancestor")],
+            )],
+        ));
+        translation.rules.push(rule(
+            "ancestor",
+            &[s("ambient"), var("entity"), var("ancestor")],
+            &[
+                pred("parent", &[s("ambient"), var("entity"), var("parent")]),
+                pred("ancestor", &[s("ambient"), var("parent"), var("ancestor")]),
+            ],
+        ));
+    }
+
+    let queries = |fragments: Vec<Fragment>| {
+        fragments
+            .into_iter()
+            .map(|(predicates, expressions)| {
+                constrained_rule("query", &[] as &[Term], &predicates, &expressions)
+            })
+            .collect()
+    };
+    if !forbid.is_empty() {
+        translation.policies.push(Policy {
+            queries: queries(forbid),
+            kind: PolicyKind::Deny,
+        });
+    }
+    if !permit.is_empty() {
+        translation.policies.push(Policy {
+            queries: queries(permit),
+            kind: PolicyKind::Allow,
+        });
+    }
+
+    Ok(translation)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Punct(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+const PUNCTUATION: &[&str] = &[
+    "::", "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", "{", "}", ",",
+    ";", ".", "@", "-", "+", "*",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>, CedarError> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    let error = |line, kind| CedarError { line, kind };
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            '\n' => {
+                chars.next();
+                line += 1;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '/' if source[start..].starts_with("//") => {
+                while chars.peek().map(|(_, c)| *c != '\n').unwrap_or(false) {
+                    chars.next();
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 'r')) => value.push('\r'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, '0')) => value.push('\0'),
+                            Some((_, c @ '"')) | Some((_, c @ '\\')) | Some((_, c @ '\'')) => {
+                                value.push(c)
+                            }
+                            Some((_, '*')) => {
+                                return Err(error(
+                                    line,
+                                    CedarErrorKind::Unsupported(
+                                        "escaped `*` in a pattern".to_string(),
+                                    ),
+                                ))
+                            }
+                            _ => {
+                                return Err(error(
+                                    line,
+                                    CedarErrorKind::Syntax("invalid escape in string".to_string()),
+                                ))
+                            }
+                        },
+                        Some((_, c)) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            value.push(c)
+                        }
+                        None => {
+                            return Err(error(
+                                line,
+                                CedarErrorKind::Syntax("unterminated string".to_string()),
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token {
+                    tok: Tok::Str(value),
+                    line,
+                });
+            }
+            c if c.is_ascii_digit() => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let value = source[start..end].parse().map_err(|_| {
+                    error(
+                        line,
+                        CedarErrorKind::Syntax("integer too large".to_string()),
+                    )
+                })?;
+                tokens.push(Token {
+                    tok: Tok::Int(value),
+                    line,
+                });
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token {
+                    tok: Tok::Ident(source[start..end].to_string()),
+                    line,
+                });
+            }
+            '?' => {
+                return Err(error(
+                    line,
+                    CedarErrorKind::Unsupported("policy template".to_string()),
+                ))
+            }
+            _ => {
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| source[start..].starts_with(**p))
+                    .ok_or_else(|| {
+                        error(
+                            line,
+                            CedarErrorKind::Syntax(format!("unexpected character `{}`", c)),
+                        )
+                    })?;
+                for _ in 0..punct.len() {
+                    chars.next();
+                }
+                tokens.push(Token {
+                    tok: Tok::Punct(punct),
+                    line,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// predicates and expressions of a query
+type Fragment = (Vec<Predicate>, Vec<Expression>);
+
+fn merge(mut left: Fragment, right: &Fragment) -> Fragment {
+    for predicate in right.0.iter() {
+        if !left.0.contains(predicate) {
+            left.0.push(predicate.clone());
+        }
+    }
+    left.1.extend(right.1.iter().cloned());
+    left
+}
+
+/// value in a condition
+enum Operand {
+    Literal(Term),
+    /// `principal`, `action` or `resource`
+    Scope(&'static str),
+    /// path in the context record
+    Context(Vec<String>),
+    /// path in the attributes of `principal`, `action` or `resource`
+    Attribute(&'static str, Vec<String>),
+    /// `set.contains(element)`
+    Contains(Box<Operand>, Box<Operand>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// variables created for the current policy
+    variables: usize,
+    /// whether the `ancestor` rules are needed
+    hierarchy: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.position).map(|t| &t.tok)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|t| t.line)
+            .unwrap_or(1)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.peek().cloned();
+        self.position += 1;
+        tok
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Punct(p)) if *p == punct)
+    }
+
+    fn is_ident(&self, ident: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(i)) if i == ident)
+    }
+
+    fn syntax(&self, message: &str) -> CedarError {
+        CedarError {
+            line: self.line(),
+            kind: CedarErrorKind::Syntax(message.to_string()),
+        }
+    }
+
+    fn unsupported(&self, construct: String) -> CedarError {
+        CedarError {
+            line: self.line(),
+            kind: CedarErrorKind::Unsupported(construct),
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), CedarError> {
+        if self.is_punct(punct) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.syntax(&format!("expected `{}`", punct)))
+        }
+    }
+
+    /// skips to the end of the current policy after an error
+    fn recover(&mut self) {
+        while let Some(tok) = self.next() {
+            if tok == Tok::Punct(";") {
+                break;
+            }
+        }
+    }
+
+    fn variable(&mut self) -> Term {
+        let variable = var(&self.variables.to_string());
+        self.variables += 1;
+        variable
+    }
+
+    fn policy(&mut self) -> Result<(PolicyKind, Vec<Fragment>), CedarError> {
+        self.variables = 0;
+        while self.is_punct("@") {
+            self.position += 1;
+            match (self.next(), self.next(), self.next(), self.next()) {
+                (
+                    Some(Tok::Ident(_)),
+                    Some(Tok::Punct("(")),
+                    Some(Tok::Str(_)),
+                    Some(Tok::Punct(")")),
+                ) => {}
+                _ => return Err(self.syntax("invalid annotation")),
+            }
+        }
+
+        let kind = match self.next() {
+            Some(Tok::Ident(effect)) if effect == "permit" => PolicyKind::Allow,
+            Some(Tok::Ident(effect)) if effect == "forbid" => PolicyKind::Deny,
+            _ => {
+                self.position -= 1;
+                return Err(self.syntax("expected `permit` or `forbid`"));
+            }
+        };
+
+        let mut scope = (Vec::new(), Vec::new());
+        self.expect("(")?;
+        self.scope("principal", &mut scope)?;
+        self.expect(",")?;
+        self.scope("action", &mut scope)?;
+        self.expect(",")?;
+        self.scope("resource", &mut scope)?;
+        self.expect(")")?;
+
+        let mut queries = vec![scope];
+        loop {
+            if self.is_ident("unless") {
+                return Err(self.unsupported("`unless` condition".to_string()));
+            } else if !self.is_ident("when") {
+                break;
+            }
+            self.position += 1;
+            self.expect("{")?;
+            let mut disjuncts = vec![self.conjunction()?];
+            while self.is_punct("||") {
+                self.position += 1;
+                disjuncts.push(self.conjunction()?);
+            }
+            self.expect("}")?;
+
+            queries = queries
+                .iter()
+                .flat_map(|query| {
+                    disjuncts
+                        .iter()
+                        .map(move |disjunct| merge(query.clone(), disjunct))
+                })
+                .collect();
+        }
+        self.expect(";")?;
+
+        Ok((kind, queries))
+    }
+
+    /// constraint on `principal`, `action` or `resource` in the policy scope
+    fn scope(&mut self, name: &'static str, fragment: &mut Fragment) -> Result<(), CedarError> {
+        if !self.is_ident(name) {
+            return Err(self.syntax(&format!("expected `{}`", name)));
+        }
+        self.position += 1;
+
+        if self.is_punct("==") {
+            self.position += 1;
+            let entity = self.entity()?;
+            fragment.0.push(pred(name, &[s("ambient"), entity]));
+        } else if self.is_ident("is") && name != "action" {
+            self.position += 1;
+            let entity_type = self.path()?;
+            fragment.0.push(scope(name));
+            fragment.1.push(Expression {
+                ops: vec![
+                    Op::Value(var(name)),
+                    Op::Value(Term::Str(format!("{}::", entity_type))),
+                    Op::Binary(Binary::Prefix),
+                ],
+            });
+            if self.is_ident("in") {
+                self.position += 1;
+                let entity = self.entity()?;
+                self.hierarchy = true;
+                fragment
+                    .0
+                    .push(pred("ancestor", &[s("ambient"), var(name), entity]));
+            }
+        } else if self.is_ident("in") {
+            self.position += 1;
+            self.hierarchy = true;
+            fragment.0.push(scope(name));
+            if name == "action" && self.is_punct("[") {
+                self.position += 1;
+                let mut entities = BTreeSet::new();
+                loop {
+                    entities.insert(self.entity()?);
+                    if self.is_punct(",") {
+                        self.position += 1;
+                    } else {
+                        break;
+                    }
+                }
+                self.expect("]")?;
+                let ancestor = self.variable();
+                fragment.0.push(pred(
+                    "ancestor",
+                    &[s("ambient"), var(name), ancestor.clone()],
+                ));
+                fragment.1.push(Expression {
+                    ops: vec![
+                        Op::Value(Term::Set(entities)),
+                        Op::Value(ancestor),
+                        Op::Binary(Binary::Contains),
+                    ],
+                });
+            } else {
+                let entity = self.entity()?;
+                fragment
+                    .0
+                    .push(pred("ancestor", &[s("ambient"), var(name), entity]));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// entity type, with its namespace
+    fn path(&mut self) -> Result<String, CedarError> {
+        let mut path = match self.next() {
+            Some(Tok::Ident(name)) => name,
+            _ => {
+                self.position -= 1;
+                return Err(self.syntax("expected an entity type"));
+            }
+        };
+        while self.is_punct("::") {
+            match self.tokens.get(self.position + 1).map(|t| &t.tok) {
+                Some(Tok::Ident(name)) => {
+                    path.push_str("::");
+                    path.push_str(name);
+                    self.position += 2;
+                }
+                _ => break,
+            }
+        }
+        Ok(path)
+    }
+
+    /// entity literal, like `User::"alice"`
+    fn entity(&mut self) -> Result<Term, CedarError> {
+        let entity_type = self.path()?;
+        self.expect("::")?;
+        match self.next() {
+            Some(Tok::Str(id)) => Ok(entity(&entity_type, &id)),
+            _ => {
+                self.position -= 1;
+                Err(self.syntax("expected an entity identifier"))
+            }
+        }
+    }
+
+    /// relations joined with `&&`
+    fn conjunction(&mut self) -> Result<Fragment, CedarError> {
+        let mut fragment = (Vec::new(), Vec::new());
+        loop {
+            self.relation(&mut fragment)?;
+            if self.is_punct("&&") {
+                self.position += 1;
+            } else {
+                return Ok(fragment);
+            }
+        }
+    }
+
+    fn relation(&mut self, fragment: &mut Fragment) -> Result<(), CedarError> {
+        if self.is_punct("!") {
+            return Err(self.unsupported("negation".to_string()));
+        }
+        if self.is_punct("(") {
+            self.position += 1;
+            loop {
+                self.relation(fragment)?;
+                if self.is_punct("&&") {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+            if self.is_punct("||") {
+                return Err(self.unsupported("`||` inside parentheses".to_string()));
+            }
+            return self.expect(")");
+        }
+
+        let left = self.operand()?;
+        let line = self.line();
+        let unsupported = |construct: &str| CedarError {
+            line,
+            kind: CedarErrorKind::Unsupported(construct.to_string()),
+        };
+
+        let binary = match self.peek() {
+            Some(Tok::Punct("==")) => Some(Binary::Equal),
+            Some(Tok::Punct("!=")) => Some(Binary::Equal),
+            Some(Tok::Punct("<")) => Some(Binary::LessThan),
+            Some(Tok::Punct("<=")) => Some(Binary::LessOrEqual),
+            Some(Tok::Punct(">")) => Some(Binary::GreaterThan),
+            Some(Tok::Punct(">=")) => Some(Binary::GreaterOrEqual),
+            _ => None,
+        };
+        if let Some(binary) = binary {
+            let negated = self.is_punct("!=");
+            self.position += 1;
+            let right = self.operand()?;
+            let left = self.term(left, fragment).map_err(|e| unsupported(&e))?;
+            let right = self.term(right, fragment).map_err(|e| unsupported(&e))?;
+            let mut ops = vec![Op::Value(left), Op::Value(right), Op::Binary(binary)];
+            if negated {
+                ops.push(Op::Unary(Unary::Parens));
+                ops.push(Op::Unary(Unary::Negate));
+            }
+            fragment.1.push(Expression { ops });
+            return Ok(());
+        }
+
+        match self.peek() {
+            Some(Tok::Ident(op)) if op == "in" => {
+                self.position += 1;
+                let name = match left {
+                    Operand::Scope(name) => name,
+                    _ => {
+                        return Err(unsupported(
+                            "`in` with an operand other than principal, action or resource",
+                        ))
+                    }
+                };
+                let right = self.operand()?;
+                fragment.0.push(scope(name));
+                self.hierarchy = true;
+                match right {
+                    Operand::Literal(Term::Set(entities)) => {
+                        let ancestor = self.variable();
+                        fragment.0.push(pred(
+                            "ancestor",
+                            &[s("ambient"), var(name), ancestor.clone()],
+                        ));
+                        fragment.1.push(Expression {
+                            ops: vec![
+                                Op::Value(Term::Set(entities)),
+                                Op::Value(ancestor),
+                                Op::Binary(Binary::Contains),
+                            ],
+                        });
+                    }
+                    right => {
+                        let right = self.term(right, fragment).map_err(|e| unsupported(&e))?;
+                        fragment
+                            .0
+                            .push(pred("ancestor", &[s("ambient"), var(name), right]));
+                    }
+                }
+                Ok(())
+            }
+            Some(Tok::Ident(op)) if op == "has" => {
+                self.position += 1;
+                let attribute = match self.next() {
+                    Some(Tok::Ident(name)) | Some(Tok::Str(name)) => name,
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.syntax("expected an attribute name"));
+                    }
+                };
+                let operand = match left {
+                    Operand::Scope(name) => Operand::Attribute(name, vec![attribute]),
+                    Operand::Attribute(name, mut path) => {
+                        path.push(attribute);
+                        Operand::Attribute(name, path)
+                    }
+                    Operand::Context(mut path) => {
+                        path.push(attribute);
+                        Operand::Context(path)
+                    }
+                    _ => return Err(unsupported("`has` on a value that is not a record")),
+                };
+                self.term(operand, fragment).map_err(|e| unsupported(&e))?;
+                Ok(())
+            }
+            Some(Tok::Ident(op)) if op == "like" => {
+                self.position += 1;
+                let pattern = match self.next() {
+                    Some(Tok::Str(pattern)) => pattern,
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.syntax("expected a pattern"));
+                    }
+                };
+                let pattern = format!(
+                    "^{}$",
+                    pattern
+                        .split('*')
+                        .map(regex::escape)
+                        .collect::<Vec<_>>()
+                        .join(".*")
+                );
+                let left = self.term(left, fragment).map_err(|e| unsupported(&e))?;
+                fragment.1.push(Expression {
+                    ops: vec![
+                        Op::Value(left),
+                        Op::Value(Term::Str(pattern)),
+                        Op::Binary(Binary::Regex),
+                    ],
+                });
+                Ok(())
+            }
+            Some(Tok::Ident(op)) if op == "is" => {
+                self.position += 1;
+                let entity_type = self.path()?;
+                let left = self.term(left, fragment).map_err(|e| unsupported(&e))?;
+                fragment.1.push(Expression {
+                    ops: vec![
+                        Op::Value(left),
+                        Op::Value(Term::Str(format!("{}::", entity_type))),
+                        Op::Binary(Binary::Prefix),
+                    ],
+                });
+                Ok(())
+            }
+            Some(Tok::Punct("+")) | Some(Tok::Punct("-")) | Some(Tok::Punct("*")) => {
+                Err(unsupported("arithmetic"))
+            }
+            _ => match left {
+                Operand::Literal(Term::Bool(true)) => Ok(()),
+                Operand::Contains(set, element) => {
+                    let set = self.term(*set, fragment).map_err(|e| unsupported(&e))?;
+                    let element = self.term(*element, fragment).map_err(|e| unsupported(&e))?;
+                    fragment.1.push(Expression {
+                        ops: vec![
+                            Op::Value(set),
+                            Op::Value(element),
+                            Op::Binary(Binary::Contains),
+                        ],
+                    });
+                    Ok(())
+                }
+                // boolean attribute
+                Operand::Attribute(name, path) => {
+                    fragment.0.push(scope(name));
+                    fragment.0.push(pred(
+                        "attribute",
+                        &[
+                            s("ambient"),
+                            var(name),
+                            Term::Str(path.join(".")),
+                            Term::Bool(true),
+                        ],
+                    ));
+                    Ok(())
+                }
+                Operand::Context(path) if !path.is_empty() => {
+                    fragment.0.push(pred(
+                        "context",
+                        &[s("ambient"), Term::Str(path.join(".")), Term::Bool(true)],
+                    ));
+                    Ok(())
+                }
+                _ => Err(unsupported("condition that is not a boolean")),
+            },
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, CedarError> {
+        let mut operand = match self.next() {
+            Some(Tok::Str(value)) => Operand::Literal(Term::Str(value)),
+            Some(Tok::Int(value)) => Operand::Literal(Term::Integer(value)),
+            Some(Tok::Punct("-")) => match self.next() {
+                Some(Tok::Int(value)) => Operand::Literal(Term::Integer(-value)),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.unsupported("arithmetic".to_string()));
+                }
+            },
+            Some(Tok::Punct("[")) => {
+                let mut elements = BTreeSet::new();
+                while !self.is_punct("]") {
+                    match self.operand()? {
+                        Operand::Literal(term) => {
+                            elements.insert(term);
+                        }
+                        _ => {
+                            return Err(self.unsupported(
+                                "set with elements that are not literals".to_string(),
+                            ))
+                        }
+                    }
+                    if self.is_punct(",") {
+                        self.position += 1;
+                    } else {
+                        break;
+                    }
+                }
+                self.expect("]")?;
+                Operand::Literal(Term::Set(elements))
+            }
+            Some(Tok::Punct("{")) => return Err(self.unsupported("record literal".to_string())),
+            Some(Tok::Ident(name)) => match name.as_str() {
+                "true" | "false" => Operand::Literal(Term::Bool(name == "true")),
+                "principal" => Operand::Scope("principal"),
+                "action" => Operand::Scope("action"),
+                "resource" => Operand::Scope("resource"),
+                "context" => Operand::Context(Vec::new()),
+                "if" => return Err(self.unsupported("`if` expression".to_string())),
+                _ if self.is_punct("(") => {
+                    return Err(self.unsupported(format!("extension function `{}`", name)))
+                }
+                _ if self.is_punct("::") => {
+                    self.position -= 1;
+                    Operand::Literal(self.entity()?)
+                }
+                _ => return Err(self.syntax(&format!("unknown variable `{}`", name))),
+            },
+            _ => {
+                self.position -= 1;
+                return Err(self.syntax("expected a value"));
+            }
+        };
+
+        // attribute accesses and method calls
+        loop {
+            let attribute = if self.is_punct(".") {
+                self.position += 1;
+                match self.next() {
+                    Some(Tok::Ident(name)) if self.is_punct("(") => {
+                        if name != "contains" {
+                            self.position -= 1;
+                            return Err(self.unsupported(format!("method `{}`", name)));
+                        }
+                        self.position += 1;
+                        let element = self.operand()?;
+                        self.expect(")")?;
+                        operand = Operand::Contains(Box::new(operand), Box::new(element));
+                        continue;
+                    }
+                    Some(Tok::Ident(name)) => name,
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.syntax("expected an attribute name"));
+                    }
+                }
+            } else if self.is_punct("[") {
+                self.position += 1;
+                match (self.next(), self.next()) {
+                    (Some(Tok::Str(name)), Some(Tok::Punct("]"))) => name,
+                    _ => return Err(self.syntax("expected an attribute name")),
+                }
+            } else {
+                return Ok(operand);
+            };
+
+            operand =
+                match operand {
+                    Operand::Scope(name) => Operand::Attribute(name, vec![attribute]),
+                    Operand::Attribute(name, mut path) => {
+                        path.push(attribute);
+                        Operand::Attribute(name, path)
+                    }
+                    Operand::Context(mut path) => {
+                        path.push(attribute);
+                        Operand::Context(path)
+                    }
+                    _ => return Err(self.unsupported(
+                        "attribute of a value that is not principal, action, resource or context"
+                            .to_string(),
+                    )),
+                };
+        }
+    }
+
+    /// Datalog term for an operand, adding the predicates it needs
+    fn term(&mut self, operand: Operand, fragment: &mut Fragment) -> Result<Term, String> {
+        match operand {
+            Operand::Literal(term) => Ok(term),
+            Operand::Scope(name) => {
+                fragment.0.push(scope(name));
+                Ok(var(name))
+            }
+            Operand::Context(path) => {
+                if path.is_empty() {
+                    return Err("reference to the whole context".to_string());
+                }
+                let value = self.variable();
+                fragment.0.push(pred(
+                    "context",
+                    &[s("ambient"), Term::Str(path.join(".")), value.clone()],
+                ));
+                Ok(value)
+            }
+            Operand::Attribute(name, path) => {
+                let value = self.variable();
+                fragment.0.push(scope(name));
+                fragment.0.push(pred(
+                    "attribute",
+                    &[
+                        s("ambient"),
+                        var(name),
+                        Term::Str(path.join(".")),
+                        value.clone(),
+                    ],
+                ));
+                Ok(value)
+            }
+            Operand::Contains(_, _) => Err("result of `.contains()` used as a value".to_string()),
+        }
+    }
+}
+
+/// predicate binding the variable named after `principal`, `action` or
+/// `resource`
+fn scope(name: &str) -> Predicate {
+    pred(name, &[s("ambient"), var(name)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::verifier::Verifier;
+
+    #[test]
+    fn translation() {
+        let translation = translate(
+            r#"
+// photo sharing
+@id("view")
+permit (
+    principal in Group::"friends",
+    action == Action::"view",
+    resource is Photo in Album::"vacation"
+) when {
+    resource.visibility != "private" || resource.owner == principal
+} when { context.time >= 8 && ["jpeg", "png"].contains(resource.format) };
+
+permit (principal == User::"alice", action, resource)
+when { resource has tags && resource.tags.contains("work") };
+
+forbid (principal, action in [Action::"edit", Action::"delete"], resource)
+when { resource.name like "*.lock" };
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            translation.to_string(),
+            r#"ancestor(#ambient, $entity, $entity) <- principal(#ambient, $entity);
+ancestor(#ambient, $entity, $entity) <- action(#ambient, $entity);
+ancestor(#ambient, $entity, $entity) <- resource(#ambient, $entity);
+ancestor(#ambient, $entity, $ancestor) <- parent(#ambient, $entity, $ancestor);
+ancestor(#ambient, $entity, $ancestor) <- parent(#ambient, $entity, $parent), ancestor(#ambient, $parent, $ancestor);
+deny if action(#ambient, $action), ancestor(#ambient, $action, $0), resource(#ambient, $resource), attribute(#ambient, $resource, "name", $1), ["Action::\"delete\"", "Action::\"edit\""].contains($0), $1.matches("^.*\\.lock$");
+allow if principal(#ambient, $principal), ancestor(#ambient, $principal, "Group::\"friends\""), action(#ambient, "Action::\"view\""), resource(#ambient, $resource), ancestor(#ambient, $resource, "Album::\"vacation\""), attribute(#ambient, $resource, "visibility", $0), context(#ambient, "time", $2), attribute(#ambient, $resource, "format", $3), $resource.starts_with("Photo::"), !($0 == "private"), $2 >= 8, ["jpeg", "png"].contains($3) or principal(#ambient, $principal), ancestor(#ambient, $principal, "Group::\"friends\""), action(#ambient, "Action::\"view\""), resource(#ambient, $resource), ancestor(#ambient, $resource, "Album::\"vacation\""), attribute(#ambient, $resource, "owner", $1), context(#ambient, "time", $2), attribute(#ambient, $resource, "format", $3), $resource.starts_with("Photo::"), $1 == $principal, $2 >= 8, ["jpeg", "png"].contains($3) or principal(#ambient, "User::\"alice\""), resource(#ambient, $resource), attribute(#ambient, $resource, "tags", $0), attribute(#ambient, $resource, "tags", $1), $1.contains("work");
+"#
+        );
+
+        let verify = |facts: &str| {
+            let mut verifier = Verifier::new().unwrap();
+            verifier
+                .add_code(&format!(
+                    r#"{}
+                    parent(#ambient, "User::\"bob\"", "Group::\"family\"");
+                    parent(#ambient, "Group::\"family\"", "Group::\"friends\"");
+                    parent(#ambient, "Photo::\"beach\"", "Album::\"vacation\"");
+                    attribute(#ambient, "Photo::\"beach\"", "visibility", "friends");
+                    attribute(#ambient, "Photo::\"beach\"", "format", "png");
+                    attribute(#ambient, "Photo::\"beach\"", "owner", "User::\"carol\"");
+                    attribute(#ambient, "Photo::\"beach\"", "name", "beach.png");
+                    attribute(#ambient, "Photo::\"beach\"", "tags", ["holidays"]);
+                    context(#ambient, "time", 10);"#,
+                    facts
+                ))
+                .unwrap();
+            translation.apply(&mut verifier);
+            verifier.verify().is_ok()
+        };
+        let request = |principal: &str, action: &str| {
+            format!(
+                r#"principal(#ambient, "User::\"{}\"");
+                action(#ambient, "Action::\"{}\"");
+                resource(#ambient, "Photo::\"beach\"");"#,
+                principal, action
+            )
+        };
+
+        assert!(verify(&request("bob", "view")));
+        assert!(!verify(&request("bob", "edit")));
+        assert!(!verify(&request("dave", "view")));
+        assert!(!verify(&request("alice", "view")));
+        assert!(verify(&format!(
+            r#"{} attribute(#ambient, "Photo::\"beach\"", "tags", ["work"]);"#,
+            request("alice", "view")
+        )));
+
+        // the printed translation can be loaded as a policy file
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_code(&translation.to_string()).unwrap();
+    }
+
+    #[test]
+    fn unsupported() {
+        let errors = translate(
+            r#"permit (principal, action, resource) unless { principal.suspended };
+permit (principal, action, resource) when { !(principal.age > 18) };
+permit (principal, action, resource) when { context.ip.isInRange(ip("10.0.0.0/8")) };
+permit (principal, action, resource) when { principal.age + 1 > 18 };
+permit (principal, action, resource) when { principal.manager in Group::"admins" };
+permit (principal, action, resource) when { principal.age };
+permit (principal, action, resource) when { context.level == 2 };
+permit (principal, action resource);
+"#,
+        )
+        .unwrap_err();
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "line 1: unsupported: `unless` condition",
+                "line 2: unsupported: negation",
+                "line 3: unsupported: method `isInRange`",
+                "line 4: unsupported: arithmetic",
+                "line 5: unsupported: `in` with an operand other than principal, action or resource",
+                "line 8: syntax error: expected `,`",
+            ]
+        );
+
+        assert_eq!(
+            translate("permit (principal == ?principal, action, resource);").unwrap_err(),
+            vec![CedarError {
+                line: 1,
+                kind: CedarErrorKind::Unsupported("policy template".to_string()),
+            }]
+        );
+    }
+}
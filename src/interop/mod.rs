@@ -0,0 +1,53 @@
+//! translation of policies written for other authorization systems
+//!
+//! each translator produces a [`Translation`], holding the facts, rules,
+//! checks and policies to add to a verifier. Its `Display` implementation
+//! prints them as Datalog, to review the result or save it as a policy file
+//! loaded with [`Verifier::add_code`](crate::token::verifier::Verifier::add_code)
+use crate::token::builder::{Check, Fact, Policy, Rule};
+use crate::token::verifier::Verifier;
+use std::fmt;
+
+#[cfg(feature = "casbin")]
+pub mod casbin;
+#[cfg(feature = "cedar")]
+pub mod cedar;
+#[cfg(feature = "rego")]
+pub mod rego;
+
+/// verifier facts, rules, checks and policies translated from another system
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Translation {
+    pub facts: Vec<Fact>,
+    pub rules: Vec<Rule>,
+    pub checks: Vec<Check>,
+    pub policies: Vec<Policy>,
+}
+
+impl Translation {
+    /// adds the facts, rules, checks and policies to the verifier
+    pub fn apply(&self, verifier: &mut Verifier) {
+        verifier.extend(self.facts.iter().cloned());
+        verifier.extend(self.rules.iter().cloned());
+        verifier.extend(self.checks.iter().cloned());
+        verifier.extend(self.policies.iter().cloned());
+    }
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for fact in self.facts.iter() {
+            writeln!(f, "{};", fact)?;
+        }
+        for rule in self.rules.iter() {
+            writeln!(f, "{};", rule)?;
+        }
+        for check in self.checks.iter() {
+            writeln!(f, "{};", check)?;
+        }
+        for policy in self.policies.iter() {
+            writeln!(f, "{};", policy)?;
+        }
+        Ok(())
+    }
+}
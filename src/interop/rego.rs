@@ -0,0 +1,1000 @@
+//! experimental translation of a subset of Rego
+//!
+//! this helps moving authorization rules from an OPA sidecar to the
+//! verifier. The translated policies read the request from `input` facts,
+//! holding the path of a field of the Rego input document and its value:
+//! `input.user.role == "admin"` is matched against
+//! `input(#ambient, "user.role", "admin")`, and each element of an array
+//! read with `input.roles[_]` is a separate fact.
+//!
+//! the supported subset:
+//! - `allow` rules become an allow policy, `deny` rules a deny policy tested
+//!   first, and other rules become Datalog rules producing `name(#ambient)`,
+//!   that attenuation blocks cannot forge
+//! - bodies are conjunctions of comparisons (`==`, `=`, `!=`, `<`, `<=`,
+//!   `>`, `>=`) between `input` references and string, integer or boolean
+//!   literals, references to other rules, boolean `input` fields, and the
+//!   `startswith`, `endswith`, `contains` and `regex.match` functions
+//! - `package`, `import rego.v1`, `import future.keywords` and
+//!   `default allow := false` are accepted and ignored
+//!
+//! other constructs, like `not`, `some`, comprehensions, `data` references or
+//! rules producing values, are reported as [`RegoError`]s.
+//!
+//! ```rust
+//! use biscuit_auth::{interop::rego, token::verifier::Verifier};
+//!
+//! let policies = rego::translate(r#"
+//! package authz
+//!
+//! default allow := false
+//!
+//! allow if {
+//!     input.method == "GET"
+//!     startswith(input.path, "/public/")
+//! }
+//!
+//! allow if is_admin
+//!
+//! is_admin if input.user.roles[_] == "admin"
+//! "#).unwrap();
+//!
+//! let mut verifier = Verifier::new().unwrap();
+//! verifier.add_code(r#"
+//!     input(#ambient, "method", "POST");
+//!     input(#ambient, "path", "/private/file");
+//!     input(#ambient, "user.roles", "admin");
+//! "#).unwrap();
+//! policies.apply(&mut verifier);
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::Translation;
+use crate::token::builder::{
+    constrained_rule, pred, s, var, Binary, Expression, Op, Policy, PolicyKind, Predicate, Term,
+    Unary,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// construct that could not be translated
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegoError {
+    /// line of the construct, starting at 1
+    pub line: usize,
+    pub kind: RegoErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegoErrorKind {
+    /// the source is not valid Rego
+    Syntax(String),
+    /// valid Rego that has no translation, with a description of the construct
+    Unsupported(String),
+}
+
+impl fmt::Display for RegoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            RegoErrorKind::Syntax(message) => {
+                write!(f, "line {}: syntax error: {}", self.line, message)
+            }
+            RegoErrorKind::Unsupported(construct) => {
+                write!(f, "line {}: unsupported: {}", self.line, construct)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegoError {}
+
+/// translates Rego rules, returning all the errors if some constructs
+/// could not be translated
+pub fn translate(source: &str) -> Result<Translation, Vec<RegoError>> {
+    let tokens = lex(source).map_err(|e| vec![e])?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        errors: Vec::new(),
+        bodies: BTreeMap::new(),
+        references: Vec::new(),
+    };
+    parser.module();
+
+    let Parser {
+        mut errors,
+        bodies,
+        references,
+        ..
+    } = parser;
+    for (name, line) in references {
+        if !bodies.contains_key(&name) {
+            errors.push(RegoError {
+                line,
+                kind: RegoErrorKind::Unsupported(format!(
+                    "reference to `{}`, which is not a rule of this module",
+                    name
+                )),
+            });
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.line);
+        return Err(errors);
+    }
+
+    let mut translation = Translation::default();
+    let mut allow = Vec::new();
+    let mut deny = Vec::new();
+    for (name, bodies) in bodies {
+        for (predicates, expressions) in bodies {
+            match name.as_str() {
+                "allow" => allow.push(constrained_rule(
+                    "query",
+                    &[] as &[Term],
+                    &predicates,
+                    &expressions,
+                )),
+                "deny" => deny.push(constrained_rule(
+                    "query",
+                    &[] as &[Term],
+                    &predicates,
+                    &expressions,
+                )),
+                _ => translation.rules.push(constrained_rule(
+                    &name,
+                    &[s("ambient")],
+                    &predicates,
+                    &expressions,
+                )),
+            }
+        }
+    }
+    if !deny.is_empty() {
+        translation.policies.push(Policy {
+            queries: deny,
+            kind: PolicyKind::Deny,
+        });
+    }
+    if !allow.is_empty() {
+        translation.policies.push(Policy {
+            queries: allow,
+            kind: PolicyKind::Allow,
+        });
+    }
+
+    Ok(translation)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Punct(&'static str),
+    Newline,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+const PUNCTUATION: &[&str] = &[
+    ":=", "==", "!=", "<=", ">=", "<", ">", "=", "{", "}", "[", "]", "(", ")", ".", ",", ";", "|",
+    "+", "-", "*", "/", "&",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>, RegoError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    let syntax = |line, message: String| RegoError {
+        line,
+        kind: RegoErrorKind::Syntax(message),
+    };
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            '\n' => {
+                chars.next();
+                tokens.push(Token {
+                    tok: Tok::Newline,
+                    line,
+                });
+                line += 1;
+            }
+            ' ' | '\t' | '\r' => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().map(|(_, c)| *c != '\n').unwrap_or(false) {
+                    chars.next();
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, c @ '"')) | Some((_, c @ '\\')) | Some((_, c @ '/')) => {
+                                value.push(c)
+                            }
+                            _ => return Err(syntax(line, "invalid escape in string".to_string())),
+                        },
+                        Some((_, '\n')) | None => {
+                            return Err(syntax(line, "unterminated string".to_string()))
+                        }
+                        Some((_, c)) => value.push(c),
+                    }
+                }
+                tokens.push(Token {
+                    tok: Tok::Str(value),
+                    line,
+                });
+            }
+            '`' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '`')) => break,
+                        Some((_, c)) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            value.push(c)
+                        }
+                        None => return Err(syntax(line, "unterminated raw string".to_string())),
+                    }
+                }
+                tokens.push(Token {
+                    tok: Tok::Str(value),
+                    line,
+                });
+            }
+            c if c.is_ascii_digit() => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                if chars.peek().map(|(_, c)| *c == '.').unwrap_or(false) {
+                    return Err(RegoError {
+                        line,
+                        kind: RegoErrorKind::Unsupported("floating point number".to_string()),
+                    });
+                }
+                let value: i64 = source[start..end]
+                    .parse()
+                    .map_err(|_| syntax(line, "integer too large".to_string()))?;
+                // negative literals, when the minus is not a subtraction
+                let negative = matches!(
+                    tokens.last(),
+                    Some(Token {
+                        tok: Tok::Punct("-"),
+                        ..
+                    })
+                ) && !matches!(
+                    tokens.get(tokens.len().wrapping_sub(2)),
+                    Some(Token {
+                        tok: Tok::Ident(_) | Tok::Str(_) | Tok::Int(_) | Tok::Punct(")" | "]"),
+                        ..
+                    })
+                );
+                if negative {
+                    tokens.pop();
+                }
+                tokens.push(Token {
+                    tok: Tok::Int(if negative { -value } else { value }),
+                    line,
+                });
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token {
+                    tok: Tok::Ident(source[start..end].to_string()),
+                    line,
+                });
+            }
+            _ => {
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| source[start..].starts_with(**p))
+                    .ok_or_else(|| syntax(line, format!("unexpected character `{}`", c)))?;
+                for _ in 0..punct.len() {
+                    chars.next();
+                }
+                tokens.push(Token {
+                    tok: Tok::Punct(punct),
+                    line,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// predicates and expressions of a rule body
+type Body = (Vec<Predicate>, Vec<Expression>);
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    errors: Vec<RegoError>,
+    /// bodies of each rule, by name
+    bodies: BTreeMap<String, Vec<Body>>,
+    /// rules referenced in bodies, with the line
+    references: Vec<(String, usize)>,
+}
+
+/// value in an expression
+enum Operand {
+    Literal(Term),
+    /// path in the input document
+    Input(String),
+    /// other rule of the module
+    Rule(String),
+    Call(String, Vec<Operand>),
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.position).map(|t| &t.tok)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|t| t.line)
+            .unwrap_or(1)
+    }
+
+    fn error(&mut self, line: usize, kind: RegoErrorKind) {
+        self.errors.push(RegoError { line, kind });
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Tok::Newline) {
+            self.position += 1;
+        }
+    }
+
+    /// tokens until the end of the line, or of the block started on this line
+    fn statement(&mut self) -> Vec<Token> {
+        let mut depth = 0usize;
+        let mut tokens = Vec::new();
+        while let Some(token) = self.tokens.get(self.position) {
+            match token.tok {
+                Tok::Newline if depth == 0 => break,
+                Tok::Punct("{") | Tok::Punct("[") | Tok::Punct("(") => depth += 1,
+                Tok::Punct("}") | Tok::Punct("]") | Tok::Punct(")") => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            tokens.push(token.clone());
+            self.position += 1;
+        }
+        tokens
+    }
+
+    fn module(&mut self) {
+        loop {
+            self.skip_newlines();
+            let line = self.line();
+            let statement = self.statement();
+            let first = match statement.first() {
+                Some(token) => token.tok.clone(),
+                None => break,
+            };
+
+            match first {
+                Tok::Ident(keyword) if keyword == "package" => {}
+                Tok::Ident(keyword) if keyword == "import" => {
+                    let path = path_text(&statement[1..]);
+                    if path != "rego.v1" && !path.starts_with("future.keywords") {
+                        self.error(
+                            line,
+                            RegoErrorKind::Unsupported(format!("import of `{}`", path)),
+                        );
+                    }
+                }
+                Tok::Ident(keyword) if keyword == "default" => {
+                    match statement
+                        .get(1..)
+                        .map(|t| t.iter().map(|t| &t.tok).collect::<Vec<_>>())
+                    {
+                        Some(tokens)
+                            if tokens.len() == 3
+                                && matches!(tokens[1], Tok::Punct("=") | Tok::Punct(":="))
+                                && tokens[2] == &Tok::Ident("false".to_string()) => {}
+                        _ => self.error(
+                            line,
+                            RegoErrorKind::Unsupported(
+                                "default value other than false".to_string(),
+                            ),
+                        ),
+                    }
+                }
+                Tok::Ident(name) => self.rule(name, line, &statement[1..]),
+                _ => self.error(line, RegoErrorKind::Syntax("expected a rule".to_string())),
+            }
+        }
+    }
+
+    fn rule(&mut self, name: String, line: usize, tokens: &[Token]) {
+        let mut tokens = tokens;
+        // `allow = true { ... }` is the same as `allow { ... }`
+        if let [Token {
+            tok: Tok::Punct("=") | Tok::Punct(":="),
+            ..
+        }, Token {
+            tok: Tok::Ident(value),
+            ..
+        }, rest @ ..] = tokens
+        {
+            if value != "true" || rest.is_empty() {
+                return self.error(
+                    line,
+                    RegoErrorKind::Unsupported("rule producing a value".to_string()),
+                );
+            }
+            tokens = rest;
+        }
+        if let Some(Token {
+            tok: Tok::Ident(keyword),
+            ..
+        }) = tokens.first()
+        {
+            if keyword == "if" {
+                tokens = &tokens[1..];
+            } else if keyword == "contains" {
+                return self.error(
+                    line,
+                    RegoErrorKind::Unsupported("multi-value rule".to_string()),
+                );
+            }
+        }
+
+        let expressions = match tokens.first().map(|t| &t.tok) {
+            Some(Tok::Punct("{")) => match tokens.last() {
+                Some(Token {
+                    tok: Tok::Punct("}"),
+                    ..
+                }) if tokens.len() >= 2 => split_body(&tokens[1..tokens.len() - 1]),
+                _ => {
+                    return self.error(
+                        line,
+                        RegoErrorKind::Unsupported(
+                            "rule with several bodies or an else branch".to_string(),
+                        ),
+                    )
+                }
+            },
+            Some(Tok::Punct("[")) => {
+                return self.error(
+                    line,
+                    RegoErrorKind::Unsupported("partial set or object rule".to_string()),
+                )
+            }
+            Some(Tok::Punct("(")) => {
+                return self.error(line, RegoErrorKind::Unsupported("function".to_string()))
+            }
+            Some(_) => vec![tokens.to_vec()],
+            None => {
+                return self.error(
+                    line,
+                    RegoErrorKind::Syntax(format!("missing body for rule `{}`", name)),
+                )
+            }
+        };
+
+        let mut body = (Vec::new(), Vec::new());
+        let mut variables = 0;
+        let errors = self.errors.len();
+        for expression in expressions {
+            let line = expression.first().map(|t| t.line).unwrap_or(line);
+            if let Err(kind) = self.expression(&expression, &mut body, &mut variables) {
+                self.error(line, kind);
+            }
+        }
+        if self.errors.len() == errors {
+            self.bodies.entry(name).or_default().push(body);
+        }
+    }
+
+    fn expression(
+        &mut self,
+        tokens: &[Token],
+        body: &mut Body,
+        variables: &mut usize,
+    ) -> Result<(), RegoErrorKind> {
+        if let Some(Tok::Ident(keyword)) = tokens.first().map(|t| &t.tok) {
+            match keyword.as_str() {
+                "not" => return Err(RegoErrorKind::Unsupported("negation".to_string())),
+                "some" | "every" => {
+                    return Err(RegoErrorKind::Unsupported(format!(
+                        "`{}` iteration",
+                        keyword
+                    )))
+                }
+                _ => {}
+            }
+        }
+        if tokens
+            .iter()
+            .any(|t| t.tok == Tok::Ident("with".to_string()))
+        {
+            return Err(RegoErrorKind::Unsupported("`with` modifier".to_string()));
+        }
+
+        let mut depth = 0usize;
+        let mut comparison = None;
+        for (i, token) in tokens.iter().enumerate() {
+            match token.tok {
+                Tok::Punct("{") | Tok::Punct("[") | Tok::Punct("(") => depth += 1,
+                Tok::Punct("}") | Tok::Punct("]") | Tok::Punct(")") => {
+                    depth = depth.saturating_sub(1)
+                }
+                Tok::Punct(op @ ":=")
+                | Tok::Punct(op @ "==")
+                | Tok::Punct(op @ "=")
+                | Tok::Punct(op @ "!=")
+                | Tok::Punct(op @ "<")
+                | Tok::Punct(op @ "<=")
+                | Tok::Punct(op @ ">")
+                | Tok::Punct(op @ ">=")
+                    if depth == 0 =>
+                {
+                    comparison = Some((i, op));
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        match comparison {
+            Some((_, ":=")) => Err(RegoErrorKind::Unsupported(
+                "local variable assignment".to_string(),
+            )),
+            Some((i, op)) => {
+                let left = self.operand(&tokens[..i])?;
+                let right = self.operand(&tokens[i + 1..])?;
+                let left = term(left, body, variables)?;
+                let right = term(right, body, variables)?;
+                let mut ops = vec![Op::Value(left), Op::Value(right)];
+                match op {
+                    "==" | "=" => ops.push(Op::Binary(Binary::Equal)),
+                    "!=" => {
+                        ops.push(Op::Binary(Binary::Equal));
+                        ops.push(Op::Unary(Unary::Parens));
+                        ops.push(Op::Unary(Unary::Negate));
+                    }
+                    "<" => ops.push(Op::Binary(Binary::LessThan)),
+                    "<=" => ops.push(Op::Binary(Binary::LessOrEqual)),
+                    ">" => ops.push(Op::Binary(Binary::GreaterThan)),
+                    _ => ops.push(Op::Binary(Binary::GreaterOrEqual)),
+                }
+                body.1.push(Expression { ops });
+                Ok(())
+            }
+            None => match self.operand(tokens)? {
+                Operand::Literal(Term::Bool(true)) => Ok(()),
+                Operand::Input(path) => {
+                    body.0.push(input(path, Term::Bool(true)));
+                    Ok(())
+                }
+                Operand::Rule(name) => {
+                    body.0.push(pred(&name, &[s("ambient")]));
+                    Ok(())
+                }
+                Operand::Call(name, arguments) => call(name, arguments, body, variables),
+                Operand::Literal(_) => Err(RegoErrorKind::Unsupported(
+                    "literal used as a condition".to_string(),
+                )),
+            },
+        }
+    }
+
+    fn operand(&mut self, tokens: &[Token]) -> Result<Operand, RegoErrorKind> {
+        let (first, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Err(RegoErrorKind::Syntax("missing operand".to_string())),
+        };
+
+        let root = match &first.tok {
+            Tok::Str(value) if rest.is_empty() => {
+                return Ok(Operand::Literal(Term::Str(value.clone())))
+            }
+            Tok::Int(value) if rest.is_empty() => {
+                return Ok(Operand::Literal(Term::Integer(*value)))
+            }
+            Tok::Ident(name) if rest.is_empty() && (name == "true" || name == "false") => {
+                return Ok(Operand::Literal(Term::Bool(name == "true")))
+            }
+            Tok::Ident(name) if name == "null" => {
+                return Err(RegoErrorKind::Unsupported("null".to_string()))
+            }
+            Tok::Ident(name) => name.clone(),
+            Tok::Punct("[") | Tok::Punct("{") => {
+                return Err(RegoErrorKind::Unsupported(
+                    "composite value or comprehension".to_string(),
+                ))
+            }
+            _ => return Err(RegoErrorKind::Syntax("unexpected token".to_string())),
+        };
+
+        // reference: `a.b`, `a["b"]` or `a[_]`
+        let mut segments = Vec::new();
+        let mut i = 0;
+        loop {
+            match rest.get(i..).unwrap_or(&[]) {
+                [Token {
+                    tok: Tok::Punct("."),
+                    ..
+                }, Token {
+                    tok: Tok::Ident(segment),
+                    ..
+                }, ..] => {
+                    segments.push(Some(segment.clone()));
+                    i += 2;
+                }
+                [Token {
+                    tok: Tok::Punct("["),
+                    ..
+                }, index, Token {
+                    tok: Tok::Punct("]"),
+                    ..
+                }, ..] => {
+                    match &index.tok {
+                        Tok::Ident(name) if name == "_" => segments.push(None),
+                        Tok::Str(key) => segments.push(Some(key.clone())),
+                        Tok::Int(_) => {
+                            return Err(RegoErrorKind::Unsupported("array index".to_string()))
+                        }
+                        _ => {
+                            return Err(RegoErrorKind::Unsupported(
+                                "reference with a variable".to_string(),
+                            ))
+                        }
+                    }
+                    i += 3;
+                }
+                [Token {
+                    tok: Tok::Punct("["),
+                    ..
+                }, ..] => {
+                    return Err(RegoErrorKind::Unsupported(
+                        "reference with a variable".to_string(),
+                    ))
+                }
+                _ => break,
+            }
+        }
+        let rest = &rest[i..];
+
+        if let Some(Tok::Punct("(")) = rest.first().map(|t| &t.tok) {
+            let name = std::iter::once(root)
+                .chain(
+                    segments
+                        .into_iter()
+                        .map(|s| s.unwrap_or_else(|| "_".to_string())),
+                )
+                .collect::<Vec<_>>()
+                .join(".");
+            match rest.last().map(|t| &t.tok) {
+                Some(Tok::Punct(")")) => {}
+                _ => return Err(RegoErrorKind::Syntax("unclosed call".to_string())),
+            }
+            let mut arguments = Vec::new();
+            for argument in split_arguments(&rest[1..rest.len() - 1]) {
+                arguments.push(self.operand(&argument)?);
+            }
+            return Ok(Operand::Call(name, arguments));
+        }
+
+        match rest.first().map(|t| &t.tok) {
+            None => {}
+            Some(Tok::Punct("+"))
+            | Some(Tok::Punct("-"))
+            | Some(Tok::Punct("*"))
+            | Some(Tok::Punct("/")) => {
+                return Err(RegoErrorKind::Unsupported("arithmetic".to_string()))
+            }
+            Some(Tok::Punct("|")) | Some(Tok::Punct("&")) => {
+                return Err(RegoErrorKind::Unsupported("set operation".to_string()))
+            }
+            Some(_) => return Err(RegoErrorKind::Syntax("unexpected token".to_string())),
+        }
+
+        match root.as_str() {
+            "input" => {
+                if segments.is_empty() {
+                    return Err(RegoErrorKind::Unsupported(
+                        "reference to the whole input document".to_string(),
+                    ));
+                }
+                Ok(Operand::Input(
+                    segments.into_iter().flatten().collect::<Vec<_>>().join("."),
+                ))
+            }
+            "data" => Err(RegoErrorKind::Unsupported(
+                "reference to data documents".to_string(),
+            )),
+            _ if segments.is_empty() => {
+                self.references.push((root.clone(), first.line));
+                Ok(Operand::Rule(root))
+            }
+            _ => Err(RegoErrorKind::Unsupported(format!(
+                "reference to `{}`",
+                root
+            ))),
+        }
+    }
+}
+
+/// Datalog term for an operand, adding the `input` predicate it needs
+fn term(operand: Operand, body: &mut Body, variables: &mut usize) -> Result<Term, RegoErrorKind> {
+    match operand {
+        Operand::Literal(term) => Ok(term),
+        Operand::Input(path) => {
+            let variable = var(&variables.to_string());
+            *variables += 1;
+            body.0.push(input(path, variable.clone()));
+            Ok(variable)
+        }
+        Operand::Rule(name) => Err(RegoErrorKind::Unsupported(format!(
+            "value of the rule `{}`",
+            name
+        ))),
+        Operand::Call(name, _) => Err(RegoErrorKind::Unsupported(format!(
+            "value of the function `{}`",
+            name
+        ))),
+    }
+}
+
+fn call(
+    name: String,
+    arguments: Vec<Operand>,
+    body: &mut Body,
+    variables: &mut usize,
+) -> Result<(), RegoErrorKind> {
+    let mut arguments = arguments.into_iter();
+    let (first, second) = match (arguments.next(), arguments.next(), arguments.next()) {
+        (Some(first), Some(second), None) => (first, second),
+        _ => return Err(RegoErrorKind::Unsupported(format!("function `{}`", name))),
+    };
+
+    let ops = match name.as_str() {
+        "startswith" | "endswith" => vec![
+            Op::Value(term(first, body, variables)?),
+            Op::Value(term(second, body, variables)?),
+            Op::Binary(if name == "startswith" {
+                Binary::Prefix
+            } else {
+                Binary::Suffix
+            }),
+        ],
+        // strings do not have a `contains` operation, so the substring is
+        // searched with a regular expression
+        "contains" => match second {
+            Operand::Literal(Term::Str(substring)) => vec![
+                Op::Value(term(first, body, variables)?),
+                Op::Value(Term::Str(regex::escape(&substring))),
+                Op::Binary(Binary::Regex),
+            ],
+            _ => {
+                return Err(RegoErrorKind::Unsupported(
+                    "`contains` with a substring that is not a literal".to_string(),
+                ))
+            }
+        },
+        "regex.match" => match first {
+            Operand::Literal(Term::Str(pattern)) => vec![
+                Op::Value(term(second, body, variables)?),
+                Op::Value(Term::Str(pattern)),
+                Op::Binary(Binary::Regex),
+            ],
+            _ => {
+                return Err(RegoErrorKind::Unsupported(
+                    "`regex.match` with a pattern that is not a literal".to_string(),
+                ))
+            }
+        },
+        _ => return Err(RegoErrorKind::Unsupported(format!("function `{}`", name))),
+    };
+
+    body.1.push(Expression { ops });
+    Ok(())
+}
+
+fn input(path: String, value: Term) -> Predicate {
+    pred("input", &[s("ambient"), Term::Str(path), value])
+}
+
+/// dotted path of an import
+fn path_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|t| match &t.tok {
+            Tok::Ident(name) => name.clone(),
+            Tok::Punct(p) => p.to_string(),
+            Tok::Str(s) => format!("{:?}", s),
+            Tok::Int(i) => i.to_string(),
+            Tok::Newline => String::new(),
+        })
+        .collect()
+}
+
+/// expressions of a body, separated by new lines or `;`
+fn split_body(tokens: &[Token]) -> Vec<Vec<Token>> {
+    split(tokens, |tok| matches!(tok, Tok::Newline | Tok::Punct(";")))
+}
+
+fn split_arguments(tokens: &[Token]) -> Vec<Vec<Token>> {
+    split(tokens, |tok| *tok == Tok::Punct(","))
+}
+
+fn split(tokens: &[Token], separator: impl Fn(&Tok) -> bool) -> Vec<Vec<Token>> {
+    let mut parts = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0usize;
+    for token in tokens {
+        match token.tok {
+            Tok::Punct("{") | Tok::Punct("[") | Tok::Punct("(") => depth += 1,
+            Tok::Punct("}") | Tok::Punct("]") | Tok::Punct(")") => depth = depth.saturating_sub(1),
+            ref tok if depth == 0 && separator(tok) => {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if token.tok != Tok::Newline {
+            current.push(token.clone());
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::verifier::Verifier;
+
+    #[test]
+    fn translation() {
+        let translation = translate(
+            r#"
+package example
+
+import rego.v1
+
+default allow := false
+
+# deny writes outside of business hours
+deny if {
+    input.method != "GET"; input.hour < 8
+}
+
+allow {
+    input.method == "GET"
+    endswith(input.path, ".txt")
+}
+
+allow = true {
+    admin
+    regex.match(`^/admin/`, input.path)
+}
+
+admin if input.roles[_] == "admin"
+admin if input.user["is_root"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            translation.to_string(),
+            r#"admin(#ambient) <- input(#ambient, "roles", $0), $0 == "admin";
+admin(#ambient) <- input(#ambient, "user.is_root", true);
+deny if input(#ambient, "method", $0), input(#ambient, "hour", $1), !($0 == "GET"), $1 < 8;
+allow if input(#ambient, "method", $0), input(#ambient, "path", $1), $0 == "GET", $1.ends_with(".txt") or admin(#ambient), input(#ambient, "path", $0), $0.matches("^/admin/");
+"#
+        );
+
+        let verify = |facts: &str| {
+            let mut verifier = Verifier::new().unwrap();
+            verifier.add_code(facts).unwrap();
+            translation.apply(&mut verifier);
+            verifier.verify().is_ok()
+        };
+        assert!(verify(
+            r#"input(#ambient, "method", "GET"); input(#ambient, "path", "/file.txt");"#
+        ));
+        assert!(!verify(
+            r#"input(#ambient, "method", "GET"); input(#ambient, "path", "/file.pdf");"#
+        ));
+        assert!(verify(
+            r#"input(#ambient, "method", "POST"); input(#ambient, "hour", 10);
+            input(#ambient, "path", "/admin/users"); input(#ambient, "roles", "user");
+            input(#ambient, "roles", "admin");"#
+        ));
+        assert!(!verify(
+            r#"input(#ambient, "method", "POST"); input(#ambient, "hour", 6);
+            input(#ambient, "path", "/admin/users"); input(#ambient, "roles", "admin");"#
+        ));
+
+        // the printed translation can be loaded as a policy file
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_code(&translation.to_string()).unwrap();
+    }
+
+    #[test]
+    fn unsupported() {
+        let errors = translate(
+            r#"package example
+import data.roles
+default allow := true
+allow {
+    not input.blocked
+    some role in input.roles
+}
+allow if count(input.roles) > 2
+allow if data.admins[input.user]
+deny[msg] { msg := "no" }
+allow if missing
+"#,
+        )
+        .unwrap_err();
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "line 2: unsupported: import of `data.roles`",
+                "line 3: unsupported: default value other than false",
+                "line 5: unsupported: negation",
+                "line 6: unsupported: `some` iteration",
+                "line 8: unsupported: value of the function `count`",
+                "line 9: unsupported: reference with a variable",
+                "line 10: unsupported: partial set or object rule",
+                "line 11: unsupported: reference to `missing`, which is not a rule of this module",
+            ]
+        );
+
+        assert_eq!(
+            translate("allow if input.amount > 1.5").unwrap_err(),
+            vec![RegoError {
+                line: 1,
+                kind: RegoErrorKind::Unsupported("floating point number".to_string()),
+            }]
+        );
+        assert_eq!(
+            translate("allow if {\n  input.path == \"/\\q\"\n}").unwrap_err(),
+            vec![RegoError {
+                line: 2,
+                kind: RegoErrorKind::Syntax("invalid escape in string".to_string()),
+            }]
+        );
+    }
+}
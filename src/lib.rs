@@ -55,8 +55,8 @@
 //!     biscuit.to_vec()?
 //!   };
 //!
-//!   // this token is only 244 bytes, holding the authority data and the signature
-//!   assert_eq!(token1.len(), 244);
+//!   // this token is only 262 bytes, holding the authority data and the signature
+//!   assert_eq!(token1.len(), 262);
 //!
 //!   // now let's add some restrictions to this token
 //!   // we want to limit access to `/a/file1.txt` and to read operations
@@ -93,8 +93,8 @@
 //!     biscuit.to_vec()?
 //!   };
 //!
-//!   // this new token fits in 373 bytes
-//!   assert_eq!(token2.len(), 373);
+//!   // this new token fits in 409 bytes
+//!   assert_eq!(token2.len(), 409);
 //!
 //!   /************** VERIFICATION ****************/
 //!
@@ -246,12 +246,38 @@ extern crate rand_core;
 extern crate regex;
 extern crate sha2;
 
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "axum")]
+pub mod axum;
 pub mod crypto;
 pub mod datalog;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+#[cfg(feature = "discovery")]
+pub mod discovery;
 pub mod error;
 pub mod format;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod http;
+pub mod interop;
+#[cfg(feature = "oidc")]
+pub mod oidc;
+#[cfg(feature = "otel")]
+pub mod otel;
 pub mod parser;
+#[cfg(feature = "samples")]
+pub mod samples;
+#[cfg(feature = "sql")]
+pub mod sql;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod token;
+#[cfg(feature = "tonic")]
+pub mod tonic;
 
 #[cfg(cargo_c)]
 mod capi;
@@ -260,3 +286,8 @@ mod capi;
 pub use capi::*;
 
 mod time;
+
+#[cfg(feature = "macros")]
+pub use biscuit_macros::{biscuit, block, check, fact, rule};
+#[cfg(feature = "derive")]
+pub use biscuit_macros::{TryFromFact, TryIntoFact};
@@ -0,0 +1,253 @@
+//! facts from the claims of OpenID Connect ID tokens
+//!
+//! a [`ClaimsMapper`] converts the claims of an authenticated user to facts,
+//! so that every service derives the same facts from the same identity:
+//!
+//! ```text
+//! {"sub": "alice", "groups": ["admin"], "scope": "openid read"}
+//! ```
+//!
+//! becomes, in a verifier:
+//!
+//! ```text
+//! user(#ambient, "alice");
+//! group(#ambient, "admin");
+//! scope(#ambient, "openid");
+//! scope(#ambient, "read");
+//! ```
+//!
+//! and the same facts with `#authority` in the authority block of a token
+use crate::error;
+use crate::token::builder::{fact, s, string, BiscuitBuilder, Fact, Term};
+use crate::token::verifier::Verifier;
+use serde_json::Value;
+
+/// converts claims to facts
+///
+/// [`ClaimsMapper::new`] maps the standard claims:
+/// - `sub` to `user`
+/// - `groups` to `group`
+/// - `scope` and `scp`, as lists or space separated strings, to `scope`
+///
+/// other claims are added with [`ClaimsMapper::claim`]. A claim name
+/// containing dots, like `realm_access.roles`, is looked up in the nested
+/// objects if the claims have no key with this exact name
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClaimsMapper {
+    mappings: Vec<Mapping>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Mapping {
+    claim: String,
+    predicate: String,
+    separator: Option<char>,
+}
+
+impl ClaimsMapper {
+    /// mapper for the standard claims
+    pub fn new() -> Self {
+        ClaimsMapper::empty()
+            .claim("sub", "user")
+            .claim("groups", "group")
+            .separated_claim("scope", "scope", ' ')
+            .separated_claim("scp", "scope", ' ')
+    }
+
+    /// mapper without any claim
+    pub fn empty() -> Self {
+        ClaimsMapper {
+            mappings: Vec::new(),
+        }
+    }
+
+    /// maps the claim to facts named `predicate`, one for each element if
+    /// the claim is a list
+    pub fn claim(mut self, claim: &str, predicate: &str) -> Self {
+        self.mappings.push(Mapping {
+            claim: claim.to_string(),
+            predicate: predicate.to_string(),
+            separator: None,
+        });
+        self
+    }
+
+    /// like [`ClaimsMapper::claim`], but a string claim is also split on
+    /// `separator`
+    pub fn separated_claim(mut self, claim: &str, predicate: &str, separator: char) -> Self {
+        self.mappings.push(Mapping {
+            claim: claim.to_string(),
+            predicate: predicate.to_string(),
+            separator: Some(separator),
+        });
+        self
+    }
+
+    /// generates the facts, with `symbol` as first term
+    ///
+    /// missing and `null` claims are ignored. Claims that are objects, or
+    /// numbers that are not 64 bits integers, return an error
+    pub fn facts(&self, claims: &Value, symbol: &str) -> Result<Vec<Fact>, error::Token> {
+        if !claims.is_object() {
+            return Err(error::Token::ConversionError(
+                "the claims are not a JSON object".to_string(),
+            ));
+        }
+
+        let mut facts = Vec::new();
+        for mapping in self.mappings.iter() {
+            let value = match lookup(claims, &mapping.claim) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let mut terms = Vec::new();
+            match (value, mapping.separator) {
+                (Value::String(value), Some(separator)) => {
+                    terms.extend(value.split(separator).filter(|v| !v.is_empty()).map(string))
+                }
+                (Value::Array(elements), _) => {
+                    for element in elements {
+                        terms.extend(term(&mapping.claim, element)?);
+                    }
+                }
+                (value, _) => terms.extend(term(&mapping.claim, value)?),
+            }
+
+            facts.extend(
+                terms
+                    .into_iter()
+                    .map(|t| fact(&mapping.predicate, &[s(symbol), t])),
+            );
+        }
+        Ok(facts)
+    }
+
+    /// adds the facts, with `#authority`, to the authority block of a token
+    pub fn add_to_builder(
+        &self,
+        builder: &mut BiscuitBuilder,
+        claims: &Value,
+    ) -> Result<(), error::Token> {
+        builder.add_authority_facts(self.facts(claims, "authority")?)
+    }
+
+    /// adds the facts, with `#ambient`, to a verifier
+    pub fn add_to_verifier(
+        &self,
+        verifier: &mut Verifier,
+        claims: &Value,
+    ) -> Result<(), error::Token> {
+        verifier.add_facts(self.facts(claims, "ambient")?)
+    }
+}
+
+impl Default for ClaimsMapper {
+    fn default() -> Self {
+        ClaimsMapper::new()
+    }
+}
+
+fn lookup<'a>(claims: &'a Value, claim: &str) -> Option<&'a Value> {
+    claims.get(claim).or_else(|| {
+        claim
+            .split('.')
+            .try_fold(claims, |value, key| value.get(key))
+    })
+}
+
+fn term(claim: &str, value: &Value) -> Result<Option<Term>, error::Token> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(string(s))),
+        Value::Bool(b) => Ok(Some(Term::Bool(*b))),
+        Value::Number(n) => n.as_i64().map(|i| Some(Term::Integer(i))).ok_or_else(|| {
+            error::Token::ConversionError(format!(
+                "{} is not a 64 bits integer, in the {} claim",
+                n, claim
+            ))
+        }),
+        Value::Array(_) | Value::Object(_) => Err(error::Token::ConversionError(format!(
+            "the {} claim contains a nested list or object",
+            claim
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::Biscuit;
+    use serde_json::json;
+
+    #[test]
+    fn standard_claims() {
+        let claims = json!({
+            "iss": "https://accounts.example.com",
+            "sub": "alice",
+            "groups": ["admin", "dev"],
+            "scope": "openid  read",
+            "realm_access": { "roles": ["editor"] },
+            "https://example.com/tenant": 12,
+            "email_verified": true,
+        });
+
+        let mapper = ClaimsMapper::new()
+            .claim("realm_access.roles", "role")
+            .claim("https://example.com/tenant", "tenant")
+            .claim("email_verified", "email_verified")
+            .claim("missing", "missing");
+        let facts = mapper
+            .facts(&claims, "ambient")
+            .unwrap()
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            facts,
+            vec![
+                "user(#ambient, \"alice\")",
+                "group(#ambient, \"admin\")",
+                "group(#ambient, \"dev\")",
+                "scope(#ambient, \"openid\")",
+                "scope(#ambient, \"read\")",
+                "role(#ambient, \"editor\")",
+                "tenant(#ambient, 12)",
+                "email_verified(#ambient, true)",
+            ]
+        );
+
+        assert!(ClaimsMapper::new()
+            .facts(&json!("alice"), "ambient")
+            .is_err());
+        assert!(ClaimsMapper::empty()
+            .claim("address", "address")
+            .facts(&json!({ "address": { "country": "FR" } }), "ambient")
+            .is_err());
+    }
+
+    #[test]
+    fn builder_and_verifier() {
+        let root = KeyPair::new();
+        let claims = json!({ "sub": "alice", "scp": ["read"] });
+
+        let mut builder = Biscuit::builder(&root);
+        ClaimsMapper::new()
+            .add_to_builder(&mut builder, &claims)
+            .unwrap();
+        let token = builder.build().unwrap();
+
+        let mut verifier = token.verify(root.public()).unwrap();
+        ClaimsMapper::empty()
+            .claim("sub", "session_user")
+            .add_to_verifier(&mut verifier, &claims)
+            .unwrap();
+        verifier
+            .add_code(
+                "allow if user(#authority, $user), session_user(#ambient, $user), scope(#authority, \"read\");",
+            )
+            .unwrap();
+        verifier.verify().unwrap();
+    }
+}
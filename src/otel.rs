@@ -0,0 +1,298 @@
+//! OpenTelemetry attributes for authorization outcomes
+//!
+//! with the `otel` feature, each call to [`Verifier::verify`](crate::token::verifier::Verifier::verify)
+//! and its variants records its outcome on the current OpenTelemetry span,
+//! so the services do not need to write it themselves:
+//! - the decision, `allow` or `deny`, and the error code of a denial
+//! - the index, label and source of the policy that matched
+//! - the execution metrics of the verification
+//! - the revocation ids of the token
+//! - a [`FAILED_CHECK_EVENT`] event for each failed check
+//!
+//! nothing is recorded if the current span is not recording.
+use crate::error;
+use crate::token::verifier::{VerifierMetrics, VerifierResult};
+use opentelemetry::{trace::get_active_span, Array, KeyValue, StringValue, Value};
+
+/// `allow` or `deny`
+pub const DECISION: &str = "biscuit.authorization.decision";
+/// error code of a denial, as returned by [`error::Token::code`]
+pub const ERROR: &str = "biscuit.authorization.error";
+/// index of the policy that matched
+pub const POLICY_ID: &str = "biscuit.authorization.policy.id";
+/// label of the policy that matched, if it has one
+pub const POLICY_LABEL: &str = "biscuit.authorization.policy.label";
+/// source of the policy that matched
+pub const POLICY: &str = "biscuit.authorization.policy";
+/// failed checks, like `verifier:0` or `block:1:0`
+pub const FAILED_CHECKS: &str = "biscuit.authorization.failed_checks";
+/// number of facts at the end of the verification
+pub const FACTS: &str = "biscuit.authorization.facts";
+/// number of facts generated by the rules
+pub const FACTS_GENERATED: &str = "biscuit.authorization.facts_generated";
+/// number of iterations of the rules
+pub const ITERATIONS: &str = "biscuit.authorization.iterations";
+/// duration of the verification, in microseconds
+pub const ELAPSED_US: &str = "biscuit.authorization.elapsed_us";
+/// hex encoded revocation ids of the token blocks
+pub const REVOCATION_IDS: &str = "biscuit.token.revocation_ids";
+
+/// event added for each failed check, with the [`BLOCK_ID`], [`CHECK_ID`]
+/// and [`CHECK`] attributes
+pub const FAILED_CHECK_EVENT: &str = "biscuit.check.failed";
+/// block of the failed check, absent for the checks of the verifier
+pub const BLOCK_ID: &str = "biscuit.check.block_id";
+/// index of the failed check in its block or in the verifier
+pub const CHECK_ID: &str = "biscuit.check.id";
+/// source of the failed check
+pub const CHECK: &str = "biscuit.check";
+
+pub(crate) fn record(
+    result: &Result<VerifierResult, error::Token>,
+    metrics: Option<&VerifierMetrics>,
+    revocation_ids: &[(Vec<u8>, Vec<u8>)],
+) {
+    get_active_span(|span| {
+        if !span.is_recording() {
+            return;
+        }
+
+        let mut attributes = Vec::new();
+        let mut policy = |id: usize, label: &Option<String>, source: &str| {
+            attributes.push(KeyValue::new(POLICY_ID, id as i64));
+            if let Some(label) = label {
+                attributes.push(KeyValue::new(POLICY_LABEL, label.clone()));
+            }
+            attributes.push(KeyValue::new(POLICY, source.to_string()));
+        };
+        match result {
+            Ok(result) => policy(result.policy_id, &result.label, &result.policy),
+            Err(error::Token::FailedLogic(error::Logic::Deny {
+                policy_id,
+                label,
+                policy: source,
+            })) => policy(*policy_id, label, source),
+            Err(_) => {}
+        }
+
+        attributes.push(KeyValue::new(
+            DECISION,
+            if result.is_ok() { "allow" } else { "deny" },
+        ));
+        if let Err(e) = result {
+            attributes.push(KeyValue::new(ERROR, e.code()));
+        }
+
+        if let Err(error::Token::FailedLogic(error::Logic::FailedChecks(checks))) = result {
+            let mut ids = Vec::new();
+            for check in checks {
+                let (block_id, check_id, rule) = match check {
+                    error::FailedCheck::Verifier(c) => (None, c.check_id, &c.rule),
+                    error::FailedCheck::Block(c) => (Some(c.block_id), c.check_id, &c.rule),
+                };
+                let mut event = Vec::new();
+                match block_id {
+                    Some(block_id) => {
+                        ids.push(StringValue::from(format!(
+                            "block:{}:{}",
+                            block_id, check_id
+                        )));
+                        event.push(KeyValue::new(BLOCK_ID, i64::from(block_id)));
+                    }
+                    None => ids.push(StringValue::from(format!("verifier:{}", check_id))),
+                }
+                event.push(KeyValue::new(CHECK_ID, i64::from(check_id)));
+                event.push(KeyValue::new(CHECK, rule.clone()));
+                span.add_event(FAILED_CHECK_EVENT, event);
+            }
+            attributes.push(KeyValue::new(FAILED_CHECKS, Value::Array(Array::from(ids))));
+        }
+
+        if let Some(metrics) = metrics {
+            attributes.push(KeyValue::new(FACTS, metrics.facts as i64));
+            attributes.push(KeyValue::new(
+                FACTS_GENERATED,
+                metrics.facts_generated as i64,
+            ));
+            attributes.push(KeyValue::new(ITERATIONS, i64::from(metrics.iterations)));
+            attributes.push(KeyValue::new(
+                ELAPSED_US,
+                metrics.elapsed.as_micros() as i64,
+            ));
+        }
+
+        if !revocation_ids.is_empty() {
+            let ids = revocation_ids
+                .iter()
+                .map(|(id, _)| StringValue::from(hex::encode(id)))
+                .collect::<Vec<_>>();
+            attributes.push(KeyValue::new(
+                REVOCATION_IDS,
+                Value::Array(Array::from(ids)),
+            ));
+        }
+
+        span.set_attributes(attributes);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::KeyPair, token::Biscuit};
+    use opentelemetry::{
+        trace::{Span, SpanContext, Status, TraceContextExt},
+        Context,
+    };
+    use std::{
+        borrow::Cow,
+        sync::{Arc, Mutex},
+        time::SystemTime,
+    };
+
+    #[derive(Default)]
+    struct Recorded {
+        attributes: Vec<KeyValue>,
+        events: Vec<(String, Vec<KeyValue>)>,
+    }
+
+    /// span keeping what is recorded on it
+    struct TestSpan(Arc<Mutex<Recorded>>);
+
+    impl Span for TestSpan {
+        fn add_event_with_timestamp<T>(
+            &mut self,
+            name: T,
+            _timestamp: SystemTime,
+            attributes: Vec<KeyValue>,
+        ) where
+            T: Into<Cow<'static, str>>,
+        {
+            let name = name.into().to_string();
+            self.0.lock().unwrap().events.push((name, attributes));
+        }
+
+        fn span_context(&self) -> &SpanContext {
+            &SpanContext::NONE
+        }
+
+        fn is_recording(&self) -> bool {
+            true
+        }
+
+        fn set_attribute(&mut self, attribute: KeyValue) {
+            self.0.lock().unwrap().attributes.push(attribute);
+        }
+
+        fn set_status(&mut self, _status: Status) {}
+
+        fn update_name<T>(&mut self, _new_name: T)
+        where
+            T: Into<Cow<'static, str>>,
+        {
+        }
+
+        fn add_link(&mut self, _span_context: SpanContext, _attributes: Vec<KeyValue>) {}
+
+        fn end_with_timestamp(&mut self, _timestamp: SystemTime) {}
+    }
+
+    fn verify(token: &Biscuit, root: &KeyPair, policies: &str) -> Recorded {
+        let recorded = Arc::new(Mutex::new(Recorded::default()));
+        let guard = Context::current_with_span(TestSpan(recorded.clone())).attach();
+
+        let mut verifier = token.verify(root.public()).unwrap();
+        verifier.add_code(policies).unwrap();
+        let _ = verifier.verify();
+
+        drop(guard);
+        Arc::try_unwrap(recorded)
+            .ok()
+            .unwrap()
+            .into_inner()
+            .unwrap()
+    }
+
+    fn attribute<'a>(recorded: &'a Recorded, key: &str) -> Option<&'a Value> {
+        recorded
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| &kv.value)
+    }
+
+    #[test]
+    fn attributes() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(#authority, \"file1\", #read)")
+            .unwrap();
+        let token = builder.build().unwrap();
+        let mut builder = token.create_block();
+        builder
+            .add_check("check if operation(#ambient, #read)")
+            .unwrap();
+        let token = token.append(&KeyPair::new(), builder).unwrap();
+
+        let recorded = verify(
+            &token,
+            &root,
+            "operation(#ambient, #read); allow if right(#authority, \"file1\", #read);",
+        );
+        assert_eq!(attribute(&recorded, DECISION), Some(&Value::from("allow")));
+        assert_eq!(attribute(&recorded, POLICY_ID), Some(&Value::from(0)));
+        assert_eq!(
+            attribute(&recorded, POLICY),
+            Some(&Value::from("allow if right(#authority, \"file1\", #read)"))
+        );
+        assert_eq!(attribute(&recorded, ERROR), None);
+        assert!(attribute(&recorded, FACTS).is_some());
+        assert!(attribute(&recorded, ELAPSED_US).is_some());
+        let ids = token
+            .revocation_ids()
+            .iter()
+            .map(|id| StringValue::from(hex::encode(id)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attribute(&recorded, REVOCATION_IDS),
+            Some(&Value::Array(Array::from(ids)))
+        );
+        assert!(recorded.events.is_empty());
+
+        let recorded = verify(
+            &token,
+            &root,
+            "check if resource(#ambient, \"file1\"); operation(#ambient, #write); allow if true;",
+        );
+        assert_eq!(attribute(&recorded, DECISION), Some(&Value::from("deny")));
+        assert_eq!(
+            attribute(&recorded, ERROR),
+            Some(&Value::from("logic.checks.failed"))
+        );
+        assert_eq!(
+            attribute(&recorded, FAILED_CHECKS),
+            Some(&Value::Array(Array::from(vec![
+                StringValue::from("verifier:0"),
+                StringValue::from("block:1:0"),
+            ])))
+        );
+        assert_eq!(recorded.events.len(), 2);
+        assert_eq!(recorded.events[1].0, FAILED_CHECK_EVENT);
+        assert_eq!(
+            recorded.events[1].1,
+            vec![
+                KeyValue::new(BLOCK_ID, 1),
+                KeyValue::new(CHECK_ID, 0),
+                KeyValue::new(CHECK, "check if operation(#ambient, #read)"),
+            ]
+        );
+
+        let recorded = verify(&token, &root, "operation(#ambient, #read); deny if true;");
+        assert_eq!(attribute(&recorded, DECISION), Some(&Value::from("deny")));
+        assert_eq!(
+            attribute(&recorded, POLICY),
+            Some(&Value::from("deny if true"))
+        );
+    }
+}
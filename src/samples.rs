@@ -0,0 +1,757 @@
+//! runner for the sample suite of the biscuit specification
+//!
+//! the suite is a directory holding the tokens, and a `samples.json` file
+//! describing them:
+//!
+//! ```json
+//! {
+//!   "root_public_key": "<hex>",
+//!   "testcases": [{
+//!     "title": "basic token",
+//!     "filename": "test1_basic.bc",
+//!     "validations": {
+//!       "": {
+//!         "authorizer_code": "resource(#ambient, \"file1\"); allow if true;",
+//!         "result": {"Ok": 0}
+//!       }
+//!     }
+//!   }]
+//! }
+//! ```
+//!
+//! each validation loads the token, adds the `authorizer_code` to the
+//! verifier, and compares the outcome with the expected `result`: `Ok`
+//! with the index of the matching policy, or `Err`. A validation can also
+//! be written as an array of the verifier's world and the result. A `null`
+//! world means that the token is expected to be rejected. Validations
+//! without `authorizer_code` only check whether the token loads.
+//!
+//! [`generate`] mints such a suite from a seed, for other implementations
+//! to check that they accept the tokens of this crate, and
+//! [`Suite::write`] saves it to a directory.
+//!
+//! ```rust,no_run
+//! let report = biscuit_auth::samples::load("samples").unwrap().run();
+//! for failure in report.failures() {
+//!     println!("{}", failure);
+//! }
+//! assert!(report.passed());
+//! ```
+use crate::crypto::{KeyPair, PublicKey};
+use crate::error;
+use crate::token::{builder::BlockBuilder, Biscuit};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::{json, Map, Value};
+use std::{fmt, path::Path};
+
+/// name of the file describing the suite
+pub const DESCRIPTION: &str = "samples.json";
+
+/// error returned when loading a suite
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum SampleError {
+    #[error("cannot read {path}: {message}")]
+    Io { path: String, message: String },
+    #[error("malformed sample description: {0}")]
+    Malformed(String),
+}
+
+/// outcome of a validation
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// the allow policy at this index matched
+    Allowed(usize),
+    /// a check failed, or no allow policy matched
+    Denied,
+    /// the token could not be deserialized, or its signature is invalid
+    InvalidToken,
+}
+
+/// sample suite, with its tokens
+#[derive(Clone, Debug)]
+pub struct Suite {
+    pub root: PublicKey,
+    pub cases: Vec<Case>,
+}
+
+/// token and the validations run on it
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub title: String,
+    pub filename: String,
+    pub token: Vec<u8>,
+    pub validations: Vec<Validation>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Validation {
+    /// name of the validation, empty for the default one
+    pub name: String,
+    /// facts, rules, checks and policies of the verifier
+    pub authorizer_code: Option<String>,
+    pub expected: Outcome,
+}
+
+/// result of a validation
+#[derive(Clone, Debug)]
+pub struct ValidationResult {
+    pub case: String,
+    pub validation: String,
+    pub expected: Outcome,
+    pub actual: Outcome,
+    /// error returned while loading the token or verifying it
+    pub error: Option<error::Token>,
+    pub passed: bool,
+}
+
+impl fmt::Display for ValidationResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.case)?;
+        if !self.validation.is_empty() {
+            write!(f, " ({})", self.validation)?;
+        }
+        write!(f, ": expected {:?}, got {:?}", self.expected, self.actual)?;
+        if let Some(e) = &self.error {
+            write!(f, " ({})", e)?;
+        }
+        Ok(())
+    }
+}
+
+/// results of all the validations of a suite
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub results: Vec<ValidationResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ValidationResult> {
+        self.results.iter().filter(|r| !r.passed)
+    }
+}
+
+/// loads the suite from a directory containing `samples.json`
+pub fn load<P: AsRef<Path>>(directory: P) -> Result<Suite, SampleError> {
+    let directory = directory.as_ref();
+    let read = |name: &str| {
+        let path = directory.join(name);
+        std::fs::read(&path).map_err(|e| SampleError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })
+    };
+
+    Suite::from_json(&read(DESCRIPTION)?, read)
+}
+
+/// mints a suite covering the features and the failure modes of tokens,
+/// with keys derived from `seed`
+///
+/// the same seed always gives the same tokens, so the suite can be written
+/// with [`Suite::write`] and used as test vectors by other implementations
+pub fn generate(seed: u64) -> Suite {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let root = KeyPair::new_with_rng(&mut rng);
+    let mut g = Generator {
+        rng,
+        root,
+        cases: Vec::new(),
+    };
+
+    let rights = r#"right(#authority, "file1", #read); right(#authority, "file2", #read); right(#authority, "file1", #write);"#;
+    let basic = g.token(
+        rights,
+        &[r#"check if resource(#ambient, $0), operation(#ambient, #read), right(#authority, $0, #read);"#],
+    );
+    g.case(
+        "basic token",
+        basic.clone(),
+        &[
+            (
+                "",
+                r#"resource(#ambient, "file1"); operation(#ambient, #read); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "write",
+                r#"resource(#ambient, "file1"); operation(#ambient, #write); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    let other_root = KeyPair::new_with_rng(&mut g.rng);
+    let mut builder = Biscuit::builder(&other_root);
+    builder.add_right("file1", "read");
+    let token = builder
+        .build_with_rng(&mut g.rng)
+        .unwrap()
+        .to_vec()
+        .unwrap();
+    g.case(
+        "different root key",
+        token,
+        &[("", "allow if true;", Outcome::InvalidToken)],
+    );
+
+    g.case(
+        "malformed token",
+        basic[..basic.len() / 2].to_vec(),
+        &[("", "allow if true;", Outcome::InvalidToken)],
+    );
+
+    for (title, block) in [
+        (
+            "invalid block fact authority",
+            r#"right(#authority, "file1", #write);"#,
+        ),
+        (
+            "invalid block fact ambient",
+            r#"resource(#ambient, "file2");"#,
+        ),
+        (
+            "invalid block rule",
+            "right(#authority, $0, #write) <- resource(#ambient, $0);",
+        ),
+    ]
+    .iter()
+    {
+        let token = g.token(rights, &[block]);
+        g.case(
+            title,
+            token,
+            &[("", "allow if true;", Outcome::InvalidToken)],
+        );
+    }
+
+    let token = g.token(
+        "",
+        &["check if time(#ambient, $date), $date <= 2018-12-20T00:00:00+00:00;"],
+    );
+    g.case(
+        "expired token",
+        token,
+        &[
+            (
+                "",
+                "time(#ambient, 2020-12-21T09:23:12+00:00); allow if true;",
+                Outcome::Denied,
+            ),
+            (
+                "before expiration",
+                "time(#ambient, 2018-12-01T00:00:00+00:00); allow if true;",
+                Outcome::Allowed(0),
+            ),
+        ],
+    );
+
+    let token = g.token(
+        "right(#authority, $1, #read) <- resource(#ambient, $1), owner(#ambient, $0, $1);",
+        &["check if right(#authority, $0, #read), resource(#ambient, $0);"],
+    );
+    g.case(
+        "authority rules",
+        token,
+        &[
+            (
+                "",
+                r#"resource(#ambient, "file1"); owner(#ambient, "alice", "file1"); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "not owner",
+                r#"resource(#ambient, "file2"); owner(#ambient, "alice", "file1"); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    let token = g.token(
+        rights,
+        &[r#"valid_date("file1") <- time(#ambient, $0), resource(#ambient, "file1"), $0 <= 2030-12-31T12:59:59+00:00; check if valid_date($0), resource(#ambient, $0);"#],
+    );
+    g.case(
+        "block rules",
+        token,
+        &[
+            (
+                "",
+                r#"resource(#ambient, "file1"); time(#ambient, 2020-12-21T09:23:12+00:00); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "no rule",
+                r#"resource(#ambient, "file2"); time(#ambient, 2020-12-21T09:23:12+00:00); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    let token = g.token(
+        "",
+        &[r#"check if resource(#ambient, $0), $0.matches("file[0-9]+.txt");"#],
+    );
+    g.case(
+        "regex constraint",
+        token,
+        &[
+            (
+                "",
+                r#"resource(#ambient, "file123.txt"); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "no match",
+                r#"resource(#ambient, "file1"); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    let token = g.token(
+        r#"must_be_present(#authority, "hello");"#,
+        &[r#"check if must_be_present(#authority, "world") or must_be_present(#authority, "hello");"#],
+    );
+    g.case(
+        "multi queries checks",
+        token,
+        &[("", "allow if true;", Outcome::Allowed(0))],
+    );
+
+    let token = g.token(
+        "",
+        &[r#"check if true; check if 1 + 2 * 3 == 7; check if "hello world".starts_with("hello"), "hello world".ends_with("world"); check if [1, 2].contains(2); check if 2019-12-04T09:46:41+00:00 < 2020-12-04T09:46:41+00:00;"#],
+    );
+    g.case(
+        "expressions",
+        token,
+        &[("", "allow if true;", Outcome::Allowed(0))],
+    );
+
+    let token = g.token(rights, &[r#"check if resource(#ambient, "file1");"#]);
+    g.case(
+        "attenuation",
+        token,
+        &[
+            (
+                "",
+                r#"resource(#ambient, "file1"); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "other resource",
+                r#"resource(#ambient, "file2"); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    let token = g.token(r#"user(#authority, "alice");"#, &[]);
+    g.case(
+        "policies",
+        token,
+        &[
+            (
+                "",
+                r#"allow if user(#authority, "bob"); allow if user(#authority, "alice");"#,
+                Outcome::Allowed(1),
+            ),
+            (
+                "deny",
+                r#"deny if user(#authority, "alice"); allow if true;"#,
+                Outcome::Denied,
+            ),
+            ("no policy", "", Outcome::Denied),
+        ],
+    );
+
+    let token = g.token(r#"right(#authority, "file1", #read);"#, &[]);
+    g.case(
+        "verifier checks",
+        token,
+        &[
+            (
+                "",
+                r#"check if right(#authority, "file1", #read); allow if true;"#,
+                Outcome::Allowed(0),
+            ),
+            (
+                "missing right",
+                r#"check if right(#authority, "file2", #read); allow if true;"#,
+                Outcome::Denied,
+            ),
+        ],
+    );
+
+    Suite {
+        root: g.root.public(),
+        cases: g.cases,
+    }
+}
+
+struct Generator {
+    rng: StdRng,
+    root: KeyPair,
+    cases: Vec<Case>,
+}
+
+impl Generator {
+    /// serialized token, with one attenuation block per source
+    fn token(&mut self, authority: &str, blocks: &[&str]) -> Vec<u8> {
+        let mut source = BlockBuilder::new(0);
+        source.add_code(authority).unwrap();
+        let mut builder = Biscuit::builder(&self.root);
+        builder.extend(source.facts);
+        builder.extend(source.rules);
+        builder.extend(source.checks);
+        let mut token = builder.build_with_rng(&mut self.rng).unwrap();
+
+        for block in blocks {
+            let mut builder = token.create_block();
+            builder.add_code(block).unwrap();
+            let keypair = KeyPair::new_with_rng(&mut self.rng);
+            token = token
+                .append_with_rng(&mut self.rng, &keypair, builder)
+                .unwrap();
+        }
+        token.to_vec().unwrap()
+    }
+
+    fn case(&mut self, title: &str, token: Vec<u8>, validations: &[(&str, &str, Outcome)]) {
+        self.cases.push(Case {
+            title: title.to_string(),
+            filename: format!(
+                "test{}_{}.bc",
+                self.cases.len() + 1,
+                title.replace(' ', "_")
+            ),
+            token,
+            validations: validations
+                .iter()
+                .map(|(name, code, expected)| Validation {
+                    name: name.to_string(),
+                    authorizer_code: Some(code.to_string()),
+                    expected: expected.clone(),
+                })
+                .collect(),
+        });
+    }
+}
+
+impl Suite {
+    /// parses the description of the suite, loading each token file with
+    /// `load_token`
+    pub fn from_json<F>(description: &[u8], mut load_token: F) -> Result<Self, SampleError>
+    where
+        F: FnMut(&str) -> Result<Vec<u8>, SampleError>,
+    {
+        let malformed = |message: &str| SampleError::Malformed(message.to_string());
+        let value: Value = serde_json::from_slice(description)
+            .map_err(|e| SampleError::Malformed(e.to_string()))?;
+
+        let root = value["root_public_key"]
+            .as_str()
+            .and_then(|key| hex::decode(key).ok())
+            .and_then(|key| PublicKey::from_bytes(&key))
+            .ok_or_else(|| malformed("invalid root_public_key"))?;
+
+        let mut cases = Vec::new();
+        for case in value["testcases"]
+            .as_array()
+            .ok_or_else(|| malformed("missing testcases"))?
+        {
+            let title = case["title"].as_str().unwrap_or_default().to_string();
+            let filename = case["filename"]
+                .as_str()
+                .ok_or_else(|| malformed("missing filename"))?
+                .to_string();
+            let validations = case["validations"]
+                .as_object()
+                .ok_or_else(|| malformed("missing validations"))?
+                .iter()
+                .map(|(name, validation)| Validation::from_json(name, validation))
+                .collect::<Result<_, _>>()?;
+
+            cases.push(Case {
+                title,
+                token: load_token(&filename)?,
+                filename,
+                validations,
+            });
+        }
+
+        Ok(Suite { root, cases })
+    }
+
+    /// runs all the validations
+    pub fn run(&self) -> Report {
+        let results = self
+            .cases
+            .iter()
+            .flat_map(|case| {
+                case.validations
+                    .iter()
+                    .map(move |validation| validation.run(case, self.root))
+            })
+            .collect();
+
+        Report { results }
+    }
+
+    /// description of the suite, in the format read by [`Suite::from_json`]
+    pub fn to_json(&self) -> String {
+        let testcases = self
+            .cases
+            .iter()
+            .map(|case| {
+                let validations = case
+                    .validations
+                    .iter()
+                    .map(|v| (v.name.clone(), v.to_json()))
+                    .collect::<Map<_, _>>();
+                json!({
+                    "title": case.title,
+                    "filename": case.filename,
+                    "validations": validations,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::to_string_pretty(&json!({
+            "root_public_key": hex::encode(self.root.to_bytes()),
+            "testcases": testcases,
+        }))
+        .unwrap()
+    }
+
+    /// writes `samples.json` and the tokens to a directory, creating it if
+    /// needed
+    pub fn write<P: AsRef<Path>>(&self, directory: P) -> Result<(), SampleError> {
+        let directory = directory.as_ref();
+        let write = |name: &str, data: &[u8]| {
+            let path = directory.join(name);
+            std::fs::write(&path, data).map_err(|e| SampleError::Io {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            })
+        };
+
+        std::fs::create_dir_all(directory).map_err(|e| SampleError::Io {
+            path: directory.display().to_string(),
+            message: e.to_string(),
+        })?;
+        for case in self.cases.iter() {
+            write(&case.filename, &case.token)?;
+        }
+        write(DESCRIPTION, self.to_json().as_bytes())
+    }
+}
+
+impl Validation {
+    fn from_json(name: &str, value: &Value) -> Result<Self, SampleError> {
+        let (world, result, authorizer_code) = match value {
+            Value::Array(v) if v.len() == 2 => (Some(&v[0]), &v[1], None),
+            Value::Object(o) => (
+                o.get("world"),
+                o.get("result").unwrap_or(&Value::Null),
+                o.get("authorizer_code")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            ),
+            _ => {
+                return Err(SampleError::Malformed(format!(
+                    "invalid validation {:?}",
+                    name
+                )))
+            }
+        };
+
+        let expected = if let Some(policy) = result.get("Ok") {
+            policy.as_u64().map(|id| Outcome::Allowed(id as usize))
+        } else if result.get("Err").is_some() {
+            // a null world: the token was not loaded in a verifier
+            if world.map(Value::is_null).unwrap_or(false) {
+                Some(Outcome::InvalidToken)
+            } else {
+                Some(Outcome::Denied)
+            }
+        } else {
+            None
+        }
+        .ok_or_else(|| SampleError::Malformed(format!("invalid result for {:?}", name)))?;
+
+        Ok(Validation {
+            name: name.to_string(),
+            authorizer_code,
+            expected,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "result": match self.expected {
+                Outcome::Allowed(policy_id) => json!({ "Ok": policy_id }),
+                Outcome::Denied => json!({ "Err": "denied" }),
+                Outcome::InvalidToken => json!({ "Err": "invalid token" }),
+            },
+        });
+        if let Some(code) = &self.authorizer_code {
+            value["authorizer_code"] = json!(code);
+        }
+        if self.expected == Outcome::InvalidToken {
+            value["world"] = Value::Null;
+        }
+        value
+    }
+
+    fn run(&self, case: &Case, root: PublicKey) -> ValidationResult {
+        let (actual, error) = self.verify(&case.token, root);
+
+        let passed = match (&self.authorizer_code, &self.expected, &actual) {
+            (Some(_), expected, actual) => expected == actual,
+            (None, Outcome::InvalidToken, actual) => *actual == Outcome::InvalidToken,
+            (None, _, actual) => *actual != Outcome::InvalidToken,
+        };
+
+        ValidationResult {
+            case: case.title.clone(),
+            validation: self.name.clone(),
+            expected: self.expected.clone(),
+            actual,
+            error,
+            passed,
+        }
+    }
+
+    fn verify(&self, data: &[u8], root: PublicKey) -> (Outcome, Option<error::Token>) {
+        let mut verifier = match Biscuit::from(data).and_then(|token| token.verify(root)) {
+            Ok(verifier) => verifier,
+            Err(e) => return (Outcome::InvalidToken, Some(e)),
+        };
+
+        let result = match &self.authorizer_code {
+            Some(code) => verifier.add_code(code),
+            None => verifier.allow(),
+        }
+        .and_then(|_| verifier.verify());
+        match result {
+            Ok(result) => (Outcome::Allowed(result.policy_id), None),
+            Err(e) => (Outcome::Denied, Some(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::builder::{fact, s, string};
+    use serde_json::json;
+
+    #[test]
+    fn run() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(fact("right", &[s("authority"), string("file1")]))
+            .unwrap();
+        let token = builder.build().unwrap().to_vec().unwrap();
+
+        let description = json!({
+            "root_public_key": hex::encode(root.public().to_bytes()),
+            "testcases": [
+                {
+                    "title": "basic token",
+                    "filename": "test1_basic.bc",
+                    "validations": {
+                        "": {
+                            "authorizer_code": "resource(#ambient, \"file1\"); deny if false; allow if resource(#ambient, $r), right(#authority, $r);",
+                            "result": {"Ok": 1}
+                        },
+                        "other resource": {
+                            "authorizer_code": "resource(#ambient, \"file2\"); allow if resource(#ambient, $r), right(#authority, $r);",
+                            "result": {"Err": ["NoMatchingPolicy"]}
+                        },
+                        "legacy": [{"facts": []}, {"Ok": 0}]
+                    }
+                },
+                {
+                    "title": "invalid token",
+                    "filename": "test2_invalid.bc",
+                    "validations": {
+                        "": [null, {"Err": ["Format"]}],
+                        "wrong expectation": {
+                            "authorizer_code": "allow if true;",
+                            "result": {"Ok": 0}
+                        }
+                    }
+                }
+            ]
+        })
+        .to_string();
+
+        let suite = Suite::from_json(description.as_bytes(), |filename| match filename {
+            "test1_basic.bc" => Ok(token.clone()),
+            _ => Ok(vec![0u8; 16]),
+        })
+        .unwrap();
+        assert_eq!(
+            suite.cases[1].validations[0].expected,
+            Outcome::InvalidToken
+        );
+
+        let report = suite.run();
+        assert_eq!(report.results.len(), 5);
+        let failures = report.failures().collect::<Vec<_>>();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].validation, "wrong expectation");
+        assert_eq!(failures[0].actual, Outcome::InvalidToken);
+        assert!(failures[0].to_string().starts_with(
+            "invalid token (wrong expectation): expected Allowed(0), got InvalidToken"
+        ));
+
+        assert!(matches!(load("/nonexistent"), Err(SampleError::Io { .. })));
+    }
+
+    #[test]
+    fn generated() {
+        let suite = generate(1234);
+        assert_eq!(suite.to_json(), generate(1234).to_json());
+        assert_eq!(suite.cases[0].token, generate(1234).cases[0].token);
+        let report = suite.run();
+        assert!(
+            report.passed(),
+            "{:?}",
+            report.failures().collect::<Vec<_>>()
+        );
+
+        let tokens = suite
+            .cases
+            .iter()
+            .map(|case| (case.filename.clone(), case.token.clone()))
+            .collect::<std::collections::HashMap<_, _>>();
+        let loaded = Suite::from_json(suite.to_json().as_bytes(), |filename| {
+            Ok(tokens[filename].clone())
+        })
+        .unwrap();
+        assert_eq!(loaded.root, suite.root);
+        let report = loaded.run();
+        assert_eq!(report.results.len(), suite.run().results.len());
+        assert!(
+            report.passed(),
+            "{:?}",
+            report.failures().collect::<Vec<_>>()
+        );
+
+        let directory =
+            std::env::temp_dir().join(format!("biscuit-samples-{}", std::process::id()));
+        suite.write(&directory).unwrap();
+        let report = load(&directory).unwrap().run();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(report.passed());
+    }
+}
@@ -0,0 +1,610 @@
+//! compilation of authorization queries to SQL filters
+//!
+//! list endpoints cannot run the verifier on every row stored in a database.
+//! Instead, a query written over a predicate representing the rows, like:
+//!
+//! ```text
+//! allowed($id) <- document($id, $owner, $team), user(#ambient, $user), member(#authority, $user, $team)
+//! ```
+//!
+//! is compiled to a `WHERE` clause. The other predicates are evaluated by the
+//! verifier, and each way they can match becomes an alternative in the
+//! clause, constraining the columns of the rows:
+//!
+//! ```text
+//! ("team" = $1) OR ("team" = $2)
+//! ```
+//!
+//! expressions over the row variables are translated to SQL too, and values
+//! are passed as parameters, never inlined in the clause. The syntax
+//! specific to each database is provided by a [`Dialect`].
+use crate::error;
+use crate::token::builder::{pred, Binary, Expression, Fact, Op, Predicate, Rule, Term, Unary};
+use crate::token::verifier::Verifier;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+
+/// syntax of a database
+pub trait Dialect {
+    /// quotes a column name
+    fn identifier(&self, name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    /// placeholder of the parameter at `index`, starting at 1
+    fn placeholder(&self, _index: usize) -> String {
+        "?".to_string()
+    }
+
+    /// condition matching `value` with the regular expression `pattern`,
+    /// or `None` if the database does not support regular expressions
+    fn regex(&self, _value: &str, _pattern: &str) -> Option<String> {
+        None
+    }
+}
+
+/// PostgreSQL, with `$1` placeholders
+pub struct Postgres;
+
+impl Dialect for Postgres {
+    fn placeholder(&self, index: usize) -> String {
+        format!("${}", index)
+    }
+
+    fn regex(&self, value: &str, pattern: &str) -> Option<String> {
+        Some(format!("{} ~ {}", value, pattern))
+    }
+}
+
+/// SQLite, without regular expressions
+pub struct Sqlite;
+
+impl Dialect for Sqlite {}
+
+/// MySQL, with identifiers quoted by backticks
+pub struct MySql;
+
+impl Dialect for MySql {
+    fn identifier(&self, name: &str) -> String {
+        format!("`{}`", name.replace('`', "``"))
+    }
+
+    fn regex(&self, value: &str, pattern: &str) -> Option<String> {
+        Some(format!("{} REGEXP {}", value, pattern))
+    }
+}
+
+/// predicate stored in a database table
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    /// name of the predicate in the queries
+    pub predicate: String,
+    /// column matching each term of the predicate
+    pub columns: Vec<String>,
+}
+
+impl Table {
+    pub fn new(predicate: &str, columns: &[&str]) -> Self {
+        Table {
+            predicate: predicate.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// parameter of a SQL filter
+#[derive(Clone, Debug, PartialEq)]
+pub enum Parameter {
+    Integer(i64),
+    /// strings and symbols
+    Text(String),
+    /// seconds since the Unix epoch
+    Date(u64),
+    Bytes(Vec<u8>),
+    Bool(bool),
+}
+
+/// `WHERE` clause and its parameters, in the order of the placeholders
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub clause: String,
+    pub parameters: Vec<Parameter>,
+}
+
+/// error returned when compiling a query
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum SqlError {
+    /// the query could not be parsed or run
+    #[error("invalid query: {0}")]
+    Token(error::Token),
+    #[error("the query has no {0} predicate")]
+    MissingTable(String),
+    /// self joins are not supported
+    #[error("the query has more than one {0} predicate")]
+    MultipleTables(String),
+    #[error("the {predicate} predicate has {found} terms, but the table has {expected} columns")]
+    Arity {
+        predicate: String,
+        expected: usize,
+        found: usize,
+    },
+    /// the query uses an operation that cannot be translated to SQL
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+}
+
+/// compiles the constraints of `query` over the rows of `table` to a SQL filter
+///
+/// the predicates other than the table's are evaluated by the verifier, so
+/// its facts and rules must be added before calling this function
+pub fn filter<R: TryInto<Rule>, D: Dialect>(
+    verifier: &mut Verifier,
+    query: R,
+    table: &Table,
+    dialect: &D,
+) -> Result<Filter, SqlError>
+where
+    R::Error: Into<error::Token>,
+{
+    let Rule(_, body, expressions) = query.try_into().map_err(|e| SqlError::Token(e.into()))?;
+
+    let (rows, predicates): (Vec<Predicate>, Vec<Predicate>) =
+        body.into_iter().partition(|p| p.name == table.predicate);
+    let row = match rows.len() {
+        0 => return Err(SqlError::MissingTable(table.predicate.clone())),
+        1 => rows.into_iter().next().unwrap(),
+        _ => return Err(SqlError::MultipleTables(table.predicate.clone())),
+    };
+    if row.ids.len() != table.columns.len() {
+        return Err(SqlError::Arity {
+            predicate: table.predicate.clone(),
+            expected: table.columns.len(),
+            found: row.ids.len(),
+        });
+    }
+
+    let mut bound = BTreeSet::new();
+    for predicate in predicates.iter() {
+        bound.extend(variables(&predicate.ids));
+    }
+
+    // expressions over the bound variables only are evaluated by the verifier
+    let (local, pushed): (Vec<Expression>, Vec<Expression>) =
+        expressions.into_iter().partition(|e| {
+            !predicates.is_empty()
+                && e.ops.iter().all(|op| match op {
+                    Op::Value(Term::Variable(v)) => bound.contains(v),
+                    _ => true,
+                })
+        });
+
+    let mut used = variables(&row.ids);
+    for expression in pushed.iter() {
+        used.extend(expression.ops.iter().filter_map(|op| match op {
+            Op::Value(Term::Variable(v)) => Some(v.clone()),
+            _ => None,
+        }));
+    }
+    let needed = used
+        .into_iter()
+        .filter(|v| bound.contains(v))
+        .collect::<Vec<_>>();
+
+    let bindings = if predicates.is_empty() {
+        vec![HashMap::new()]
+    } else {
+        let head = pred(
+            "sql_bindings",
+            &needed
+                .iter()
+                .map(|v| Term::Variable(v.clone()))
+                .collect::<Vec<_>>(),
+        );
+        let facts: Vec<Fact> = verifier
+            .query(Rule(head, predicates, local))
+            .map_err(SqlError::Token)?;
+        // sorted, so the clause does not depend on the order of the facts
+        facts
+            .into_iter()
+            .map(|f| f.0.ids)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|terms| needed.iter().cloned().zip(terms).collect::<HashMap<_, _>>())
+            .collect()
+    };
+
+    let mut compiler = Compiler {
+        dialect,
+        parameters: Vec::new(),
+    };
+    let mut alternatives = Vec::new();
+    for binding in bindings.iter() {
+        alternatives.push(compiler.alternative(table, &row, &pushed, binding)?);
+    }
+
+    let clause = match alternatives.len() {
+        0 => "1 = 0".to_string(),
+        1 => alternatives.remove(0),
+        _ => alternatives
+            .iter()
+            .map(|a| format!("({})", a))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    };
+
+    Ok(Filter {
+        clause,
+        parameters: compiler.parameters,
+    })
+}
+
+fn variables(terms: &[Term]) -> BTreeSet<String> {
+    terms
+        .iter()
+        .filter_map(|t| match t {
+            Term::Variable(v) => Some(v.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// operand of a SQL expression, values are kept until they are used to
+/// build `LIKE` patterns and `IN` lists
+enum Operand {
+    Sql(String),
+    Value(Term),
+}
+
+struct Compiler<'a, D> {
+    dialect: &'a D,
+    parameters: Vec<Parameter>,
+}
+
+impl<'a, D: Dialect> Compiler<'a, D> {
+    fn alternative(
+        &mut self,
+        table: &Table,
+        row: &Predicate,
+        expressions: &[Expression],
+        binding: &HashMap<String, Term>,
+    ) -> Result<String, SqlError> {
+        let mut conditions = Vec::new();
+        let mut columns: HashMap<&str, String> = HashMap::new();
+
+        for (term, column) in row.ids.iter().zip(table.columns.iter()) {
+            let column = self.dialect.identifier(column);
+            let value = match term {
+                Term::Variable(v) => match binding.get(v) {
+                    Some(value) => value,
+                    None => {
+                        match columns.get(v.as_str()) {
+                            Some(other) => conditions.push(format!("{} = {}", column, other)),
+                            None => {
+                                columns.insert(v.as_str(), column);
+                            }
+                        }
+                        continue;
+                    }
+                },
+                value => value,
+            };
+            let placeholder = self.parameter(value)?;
+            conditions.push(format!("{} = {}", column, placeholder));
+        }
+
+        for expression in expressions {
+            conditions.push(self.expression(expression, binding, &columns)?);
+        }
+
+        if conditions.is_empty() {
+            Ok("1 = 1".to_string())
+        } else {
+            Ok(conditions.join(" AND "))
+        }
+    }
+
+    fn expression(
+        &mut self,
+        expression: &Expression,
+        binding: &HashMap<String, Term>,
+        columns: &HashMap<&str, String>,
+    ) -> Result<String, SqlError> {
+        let mut stack = Vec::new();
+
+        for op in expression.ops.iter() {
+            match op {
+                Op::Value(Term::Variable(v)) => match (binding.get(v), columns.get(v.as_str())) {
+                    (Some(value), _) => stack.push(Operand::Value(value.clone())),
+                    (None, Some(column)) => stack.push(Operand::Sql(column.clone())),
+                    (None, None) => {
+                        return Err(SqlError::Unsupported(format!(
+                            "the variable ${} is not bound",
+                            v
+                        )))
+                    }
+                },
+                Op::Value(term) => stack.push(Operand::Value(term.clone())),
+                Op::Unary(unary) => {
+                    let value = stack.pop().ok_or_else(|| malformed(expression))?;
+                    match unary {
+                        Unary::Parens => stack.push(value),
+                        Unary::Negate => {
+                            let value = self.operand(value)?;
+                            stack.push(Operand::Sql(format!("NOT {}", value)))
+                        }
+                        Unary::Length => return Err(SqlError::Unsupported("length".to_string())),
+                    }
+                }
+                Op::Binary(binary) => {
+                    let right = stack.pop().ok_or_else(|| malformed(expression))?;
+                    let left = stack.pop().ok_or_else(|| malformed(expression))?;
+                    let sql = self.binary(binary, left, right)?;
+                    stack.push(Operand::Sql(sql));
+                }
+            }
+        }
+
+        match (stack.pop(), stack.is_empty()) {
+            (Some(result), true) => self.operand(result),
+            _ => Err(malformed(expression)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        binary: &Binary,
+        left: Operand,
+        right: Operand,
+    ) -> Result<String, SqlError> {
+        let operator = match binary {
+            Binary::LessThan => "<",
+            Binary::GreaterThan => ">",
+            Binary::LessOrEqual => "<=",
+            Binary::GreaterOrEqual => ">=",
+            Binary::Equal => "=",
+            Binary::Add => "+",
+            Binary::Sub => "-",
+            Binary::Mul => "*",
+            Binary::Div => "/",
+            Binary::And => "AND",
+            Binary::Or => "OR",
+            Binary::Prefix | Binary::Suffix => {
+                let pattern = match right {
+                    Operand::Value(Term::Str(s)) => {
+                        let escaped = escape_like(&s);
+                        if *binary == Binary::Prefix {
+                            format!("{}%", escaped)
+                        } else {
+                            format!("%{}", escaped)
+                        }
+                    }
+                    _ => {
+                        return Err(SqlError::Unsupported(
+                            "starts_with and ends_with with a pattern that is not a string"
+                                .to_string(),
+                        ))
+                    }
+                };
+                let left = self.operand(left)?;
+                let placeholder = self.parameter(&Term::Str(pattern))?;
+                return Ok(format!("{} LIKE {} ESCAPE '\\'", left, placeholder));
+            }
+            Binary::Regex => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+                return self.dialect.regex(&left, &right).ok_or_else(|| {
+                    SqlError::Unsupported("regular expressions in this dialect".to_string())
+                });
+            }
+            Binary::Contains => {
+                let set = match left {
+                    Operand::Value(Term::Set(set)) => set,
+                    _ => {
+                        return Err(SqlError::Unsupported(
+                            "contains on a value that is not a set".to_string(),
+                        ))
+                    }
+                };
+                if let Operand::Value(Term::Set(_)) = right {
+                    return Err(SqlError::Unsupported("contains with a set".to_string()));
+                }
+                if set.is_empty() {
+                    return Ok("1 = 0".to_string());
+                }
+                let value = self.operand(right)?;
+                let placeholders = set
+                    .iter()
+                    .map(|term| self.parameter(term))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return Ok(format!("{} IN ({})", value, placeholders.join(", ")));
+            }
+            Binary::Intersection | Binary::Union => {
+                return Err(SqlError::Unsupported("set operations".to_string()))
+            }
+        };
+
+        let left = self.operand(left)?;
+        let right = self.operand(right)?;
+        Ok(format!("({} {} {})", left, operator, right))
+    }
+
+    fn operand(&mut self, operand: Operand) -> Result<String, SqlError> {
+        match operand {
+            Operand::Sql(sql) => Ok(sql),
+            Operand::Value(term) => self.parameter(&term),
+        }
+    }
+
+    /// adds a parameter and returns its placeholder
+    fn parameter(&mut self, term: &Term) -> Result<String, SqlError> {
+        let parameter = match term {
+            Term::Integer(i) => Parameter::Integer(*i),
+            Term::Str(s) | Term::Symbol(s) => Parameter::Text(s.clone()),
+            Term::Date(d) => Parameter::Date(*d),
+            Term::Bytes(b) => Parameter::Bytes(b.clone()),
+            Term::Bool(b) => Parameter::Bool(*b),
+            Term::Set(_) => return Err(SqlError::Unsupported("set values".to_string())),
+            Term::Variable(v) => {
+                return Err(SqlError::Unsupported(format!(
+                    "the variable ${} is not bound",
+                    v
+                )))
+            }
+        };
+        self.parameters.push(parameter);
+        Ok(self.dialect.placeholder(self.parameters.len()))
+    }
+}
+
+fn malformed(expression: &Expression) -> SqlError {
+    SqlError::Unsupported(format!("malformed expression {}", expression))
+}
+
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verifier() -> Verifier {
+        let mut verifier = Verifier::new().unwrap();
+        verifier
+            .add_code(
+                r#"user(#ambient, "alice");
+                member(#authority, "alice", "team1");
+                member(#authority, "alice", "team2");
+                member(#authority, "bob", "team3");"#,
+            )
+            .unwrap();
+        verifier
+    }
+
+    #[test]
+    fn filter_bindings() {
+        let table = Table::new("document", &["id", "owner", "team", "size"]);
+        let mut verifier = verifier();
+
+        let result = filter(
+            &mut verifier,
+            "allowed($id) <- document($id, $owner, $team, $size), user(#ambient, $user), member(#authority, $user, $team), $size < 1000",
+            &table,
+            &Postgres,
+        )
+        .unwrap();
+        assert_eq!(
+            result.clause,
+            r#"("team" = $1 AND ("size" < $2)) OR ("team" = $3 AND ("size" < $4))"#
+        );
+        assert_eq!(
+            result.parameters,
+            vec![
+                Parameter::Text("team1".to_string()),
+                Parameter::Integer(1000),
+                Parameter::Text("team2".to_string()),
+                Parameter::Integer(1000),
+            ]
+        );
+
+        let result = filter(
+            &mut verifier,
+            r#"allowed($id) <- document($id, $user, $team, 0), user(#ambient, $user), $user == "alice""#,
+            &table,
+            &Sqlite,
+        )
+        .unwrap();
+        assert_eq!(result.clause, r#""owner" = ? AND "size" = ?"#);
+        assert_eq!(
+            result.parameters,
+            vec![Parameter::Text("alice".to_string()), Parameter::Integer(0)]
+        );
+
+        // no binding matches
+        let result = filter(
+            &mut verifier,
+            "allowed($id) <- document($id, $owner, $team, $size), member(#authority, \"carol\", $team)",
+            &table,
+            &Postgres,
+        )
+        .unwrap();
+        assert_eq!(result.clause, "1 = 0");
+        assert!(result.parameters.is_empty());
+    }
+
+    #[test]
+    fn filter_expressions() {
+        let table = Table::new("file", &["path", "owner"]);
+        let mut verifier = verifier();
+
+        let result = filter(
+            &mut verifier,
+            r#"allowed($path) <- file($path, $path), $path.starts_with("/tmp_%/"), !(["a", "b"].contains($path))"#,
+            &table,
+            &MySql,
+        )
+        .unwrap();
+        assert_eq!(
+            result.clause,
+            r#"`owner` = `path` AND `path` LIKE ? ESCAPE '\' AND NOT `path` IN (?, ?)"#
+        );
+        assert_eq!(
+            result.parameters,
+            vec![
+                Parameter::Text("/tmp\\_\\%/%".to_string()),
+                Parameter::Text("a".to_string()),
+                Parameter::Text("b".to_string()),
+            ]
+        );
+
+        let result = filter(
+            &mut verifier,
+            r#"allowed($path) <- file($path, $owner), $path.matches("^/home/")"#,
+            &table,
+            &Postgres,
+        )
+        .unwrap();
+        assert_eq!(result.clause, r#""path" ~ $1"#);
+
+        assert_eq!(
+            filter(
+                &mut verifier,
+                r#"allowed($path) <- file($path, $owner), $path.matches("^/home/")"#,
+                &table,
+                &Sqlite,
+            ),
+            Err(SqlError::Unsupported(
+                "regular expressions in this dialect".to_string()
+            ))
+        );
+        assert_eq!(
+            filter(
+                &mut verifier,
+                "allowed($path) <- user(#ambient, $path)",
+                &table,
+                &Sqlite,
+            ),
+            Err(SqlError::MissingTable("file".to_string()))
+        );
+        assert_eq!(
+            filter(
+                &mut verifier,
+                "allowed($path) <- file($path)",
+                &table,
+                &Sqlite
+            ),
+            Err(SqlError::Arity {
+                predicate: "file".to_string(),
+                expected: 2,
+                found: 1,
+            })
+        );
+    }
+}
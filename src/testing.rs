@@ -0,0 +1,282 @@
+//! assertions on the authorization of tokens, for policy tests
+//!
+//! [`assert_authorized!`](crate::assert_authorized) and
+//! [`assert_denied!`](crate::assert_denied) load a token in a verifier,
+//! set it up with Datalog source or a closure, and verify it. When the
+//! assertion fails, the panic message contains the error, the failed checks
+//! and the facts and rules of the verifier.
+//!
+//! ```rust
+//! use biscuit_auth::{assert_authorized, assert_denied, crypto::KeyPair, token::{Biscuit, verifier::Verifier}};
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_right("file1", "read");
+//! let token = builder.build().unwrap();
+//! let token = token
+//!     .append_source(&KeyPair::new(), "check if operation(#ambient, #read)")
+//!     .unwrap();
+//!
+//! assert_authorized!(token, "operation(#ambient, #read); allow if true");
+//! assert_authorized!(token, root = root.public(), |verifier: &mut Verifier| {
+//!     verifier.add_operation("read");
+//!     verifier.allow().unwrap();
+//! });
+//! assert_denied!(
+//!     token,
+//!     "operation(#ambient, #write); allow if true",
+//!     expect_check = "check if operation(#ambient, #read)"
+//! );
+//! ```
+//!
+//! without a `root`, the token is verified with its own root key, the first
+//! of [`Biscuit::root_keys`]
+use crate::crypto::PublicKey;
+use crate::error;
+use crate::token::{
+    verifier::{Verifier, VerifierResult},
+    Biscuit,
+};
+
+/// facts, rules, checks and policies added to the verifier before the
+/// verification
+pub trait Setup {
+    fn setup(self, verifier: &mut Verifier) -> Result<(), error::Token>;
+}
+
+/// Datalog source, as accepted by [`Verifier::add_code`]
+impl Setup for &str {
+    fn setup(self, verifier: &mut Verifier) -> Result<(), error::Token> {
+        verifier.add_code(self)
+    }
+}
+
+impl<F: FnOnce(&mut Verifier)> Setup for F {
+    fn setup(self, verifier: &mut Verifier) -> Result<(), error::Token> {
+        self(verifier);
+        Ok(())
+    }
+}
+
+/// verifies the token, returning a report of the failure if it is denied
+///
+/// this is the implementation of [`assert_authorized!`](crate::assert_authorized)
+pub fn authorized<S: Setup>(
+    token: &Biscuit,
+    root: Option<PublicKey>,
+    setup: S,
+) -> Result<VerifierResult, String> {
+    let (verifier, result) = verify(token, root, setup)?;
+    result.map_err(|e| report("expected the token to be authorized", &e, &verifier))
+}
+
+/// verifies the token, returning a report if it is authorized, or if the
+/// `expected_check` is not among the failed checks
+///
+/// this is the implementation of [`assert_denied!`](crate::assert_denied)
+pub fn denied<S: Setup>(
+    token: &Biscuit,
+    root: Option<PublicKey>,
+    setup: S,
+    expected_check: Option<&str>,
+) -> Result<error::Token, String> {
+    let (verifier, result) = verify(token, root, setup)?;
+    let e = match result {
+        Ok(result) => {
+            return Err(format!(
+                "expected the token to be denied, it was authorized by policy {}: {}\n\nworld:\n{}",
+                result.policy_id,
+                result.policy,
+                verifier.print_world()
+            ))
+        }
+        Err(e) => e,
+    };
+
+    if let Some(expected) = expected_check {
+        if !failed_checks(&e).contains(&expected) {
+            return Err(report(
+                &format!("expected the check \"{}\" to fail", expected),
+                &e,
+                &verifier,
+            ));
+        }
+    }
+    Ok(e)
+}
+
+fn verify<S: Setup>(
+    token: &Biscuit,
+    root: Option<PublicKey>,
+    setup: S,
+) -> Result<(Verifier, Result<VerifierResult, error::Token>), String> {
+    let root = root
+        .or_else(|| token.root_keys().first().copied())
+        .ok_or_else(|| "the token has no root key, it must be provided".to_string())?;
+    let mut verifier = token
+        .verify(root)
+        .map_err(|e| format!("cannot load the token: {}", e))?;
+    setup
+        .setup(&mut verifier)
+        .map_err(|e| format!("cannot set up the verifier: {}", e))?;
+
+    let result = verifier.verify();
+    Ok((verifier, result))
+}
+
+fn failed_checks(e: &error::Token) -> Vec<&str> {
+    match e {
+        error::Token::FailedLogic(error::Logic::FailedChecks(checks)) => checks
+            .iter()
+            .map(|check| match check {
+                error::FailedCheck::Block(c) => c.rule.as_str(),
+                error::FailedCheck::Verifier(c) => c.rule.as_str(),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn report(message: &str, e: &error::Token, verifier: &Verifier) -> String {
+    let mut report = format!("{}, got: {}\n", message, e);
+    if let error::Token::FailedLogic(error::Logic::FailedChecks(checks)) = e {
+        report.push_str("\nfailed checks:\n");
+        for check in checks {
+            match check {
+                error::FailedCheck::Block(c) => {
+                    report.push_str(&format!("  block {}: {}\n", c.block_id, c.rule))
+                }
+                error::FailedCheck::Verifier(c) => {
+                    report.push_str(&format!("  verifier: {}\n", c.rule))
+                }
+            }
+        }
+    }
+    report.push_str("\nworld:\n");
+    report.push_str(&verifier.print_world());
+    report
+}
+
+/// asserts that a token is authorized by a verifier
+///
+/// the verifier is set up with Datalog source or a closure taking a
+/// `&mut Verifier`, see the [`testing`](crate::testing) module:
+///
+/// ```rust,ignore
+/// assert_authorized!(token, "allow if true");
+/// assert_authorized!(token, root = root.public(), "allow if true");
+/// ```
+#[macro_export]
+macro_rules! assert_authorized {
+    ($token:expr, root = $root:expr, $setup:expr $(,)?) => {
+        if let Err(report) = $crate::testing::authorized(&$token, Some($root), $setup) {
+            panic!("{}", report);
+        }
+    };
+    ($token:expr, $setup:expr $(,)?) => {
+        if let Err(report) = $crate::testing::authorized(&$token, None, $setup) {
+            panic!("{}", report);
+        }
+    };
+}
+
+/// asserts that a token is denied by a verifier, optionally because of a
+/// failed check, given as printed in the error
+///
+/// ```rust,ignore
+/// assert_denied!(token, "allow if false");
+/// assert_denied!(token, "allow if true", expect_check = "check if operation(#ambient, #read)");
+/// assert_denied!(token, root = root.public(), "allow if false");
+/// ```
+#[macro_export]
+macro_rules! assert_denied {
+    ($token:expr, root = $root:expr, $setup:expr, expect_check = $check:expr $(,)?) => {
+        if let Err(report) = $crate::testing::denied(&$token, Some($root), $setup, Some($check)) {
+            panic!("{}", report);
+        }
+    };
+    ($token:expr, root = $root:expr, $setup:expr $(,)?) => {
+        if let Err(report) = $crate::testing::denied(&$token, Some($root), $setup, None) {
+            panic!("{}", report);
+        }
+    };
+    ($token:expr, $setup:expr, expect_check = $check:expr $(,)?) => {
+        if let Err(report) = $crate::testing::denied(&$token, None, $setup, Some($check)) {
+            panic!("{}", report);
+        }
+    };
+    ($token:expr, $setup:expr $(,)?) => {
+        if let Err(report) = $crate::testing::denied(&$token, None, $setup, None) {
+            panic!("{}", report);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    #[test]
+    fn assertions() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let token = builder.build().unwrap();
+        let token = token
+            .append_source(&KeyPair::new(), "check if operation(#ambient, #read)")
+            .unwrap();
+
+        assert_authorized!(token, "operation(#ambient, #read); allow if true");
+        assert_authorized!(token, root = root.public(), |v: &mut Verifier| {
+            v.add_operation("read");
+            v.allow().unwrap();
+        });
+        assert_denied!(token, "operation(#ambient, #read); deny if true");
+        assert_denied!(
+            token,
+            root = root.public(),
+            "operation(#ambient, #write); allow if true",
+            expect_check = "check if operation(#ambient, #read)",
+        );
+
+        let report =
+            authorized(&token, None, "operation(#ambient, #write); allow if true").unwrap_err();
+        assert!(report.starts_with("expected the token to be authorized"));
+        assert!(report.contains("block 1: check if operation(#ambient, #read)"));
+        assert!(report.contains("operation(#ambient, #write)"));
+
+        let report = denied(
+            &token,
+            None,
+            "allow if true",
+            Some("check if operation(#ambient, #write)"),
+        )
+        .unwrap_err();
+        assert!(report
+            .starts_with("expected the check \"check if operation(#ambient, #write)\" to fail"));
+
+        let report = denied(
+            &token,
+            None,
+            "operation(#ambient, #read); allow if true",
+            None,
+        )
+        .unwrap_err();
+        assert!(report.starts_with("expected the token to be denied"));
+
+        assert!(
+            authorized(&token, Some(KeyPair::new().public()), "allow if true")
+                .unwrap_err()
+                .starts_with("cannot load the token")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected the token to be authorized")]
+    fn failed_assertion() {
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        assert_authorized!(token, "deny if true");
+    }
+}
@@ -0,0 +1,59 @@
+//! binding of tokens to the key of their holder
+//!
+//! like the `cnf` claim of [RFC 8705](https://www.rfc-editor.org/rfc/rfc8705),
+//! a token minted with [`BiscuitBuilder::bind_to_certificate`](crate::token::builder::BiscuitBuilder::bind_to_certificate)
+//! or [`BiscuitBuilder::bind_to_public_key`](crate::token::builder::BiscuitBuilder::bind_to_public_key)
+//! carries the SHA-256 thumbprint of the client's TLS certificate or public
+//! key in an authority fact:
+//!
+//! ```text
+//! cnf(#authority, "x5t#S256", hex:...);
+//! check if cnf(#authority, $method, $thumbprint), client_thumbprint(#ambient, $method, $thumbprint);
+//! ```
+//!
+//! the check only succeeds if the verifier adds the thumbprint of the key
+//! presented by the client, with [`Verifier::add_client_certificate`](crate::token::verifier::Verifier::add_client_certificate)
+//! or [`Verifier::add_client_public_key`](crate::token::verifier::Verifier::add_client_public_key),
+//! so a token stolen from a client cannot be replayed by another one
+use super::builder::{fact, pred, rule, s, string, var, Check, Fact, Term};
+use sha2::{Digest, Sha256};
+
+/// method of the thumbprint of a DER encoded X.509 certificate
+pub const CERTIFICATE: &str = "x5t#S256";
+/// method of the thumbprint of a DER encoded SubjectPublicKeyInfo
+pub const PUBLIC_KEY: &str = "spki#S256";
+
+/// SHA-256 thumbprint of a DER encoded certificate or public key
+pub fn thumbprint(der: &[u8]) -> Vec<u8> {
+    Sha256::digest(der).to_vec()
+}
+
+pub(crate) fn cnf(method: &str, der: &[u8]) -> Fact {
+    fact(
+        "cnf",
+        &[s("authority"), string(method), Term::Bytes(thumbprint(der))],
+    )
+}
+
+pub(crate) fn check() -> Check {
+    Check {
+        queries: vec![rule(
+            "bound",
+            &[var("method")],
+            &[
+                pred("cnf", &[s("authority"), var("method"), var("thumbprint")]),
+                pred(
+                    "client_thumbprint",
+                    &[s("ambient"), var("method"), var("thumbprint")],
+                ),
+            ],
+        )],
+    }
+}
+
+pub(crate) fn client_thumbprint(method: &str, der: &[u8]) -> Fact {
+    fact(
+        "client_thumbprint",
+        &[s("ambient"), string(method), Term::Bytes(thumbprint(der))],
+    )
+}
@@ -1,13 +1,15 @@
 //! helper functions and structure to create tokens and blocks
-use super::{Biscuit, Block};
-use crate::crypto::KeyPair;
+use super::{binding, delegation::Delegation, possession, Biscuit, Block};
+use crate::crypto::{KeyPair, PublicKey};
 use crate::datalog::{self, SymbolTable, ID};
 use crate::error;
+use crate::parser;
 use rand_core::{CryptoRng, RngCore};
 use std::{
     collections::BTreeSet,
     convert::{TryFrom, TryInto},
     fmt,
+    iter::FromIterator,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -34,20 +36,53 @@ impl BlockBuilder {
         }
     }
 
-    pub fn add_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token> {
-        let fact = fact.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token>
+    where
+        F::Error: Into<error::Token>,
+    {
+        let fact = fact.try_into().map_err(Into::into)?;
         self.facts.push(fact);
         Ok(())
     }
 
-    pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token> {
-        let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
+    /// adds facts to the block
+    ///
+    /// if a fact fails to convert, the facts before it are kept
+    pub fn add_facts<I, F>(&mut self, facts: I) -> Result<(), error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        F::Error: Into<error::Token>,
+    {
+        let facts = facts.into_iter();
+        self.facts.reserve(facts.size_hint().0);
+        for fact in facts {
+            self.facts.push(fact.try_into().map_err(Into::into)?);
+        }
+        Ok(())
+    }
+
+    /// adds a rule to the block
+    ///
+    /// this fails if the head of the rule contains variables that are not
+    /// used in its body, instead of rejecting the token at verification time
+    pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        let rule = rule.try_into().map_err(Into::into)?;
+        if rule.validate_variables().is_err() {
+            return Err(error::Logic::InvalidBlockRule(self.index, rule.to_string()).into());
+        }
         self.rules.push(rule);
         Ok(())
     }
 
-    pub fn add_check<C: TryInto<Check>>(&mut self, check: C) -> Result<(), error::Token> {
-        let check = check.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_check<C: TryInto<Check>>(&mut self, check: C) -> Result<(), error::Token>
+    where
+        C::Error: Into<error::Token>,
+    {
+        let check = check.try_into().map_err(Into::into)?;
         self.checks.push(check);
         Ok(())
     }
@@ -56,8 +91,73 @@ impl BlockBuilder {
         self.context = Some(context);
     }
 
+    /// adds the facts, rules and checks from a Datalog block source
+    ///
+    /// elements are separated by `;`, and the source can set the block's
+    /// context with `context "text";`
+    ///
+    /// ```rust
+    /// # use biscuit_auth::token::builder::BlockBuilder;
+    /// let mut block = BlockBuilder::new(1);
+    /// block
+    ///     .add_code(
+    ///         r#"
+    ///           context "read only";
+    ///           check if operation(#ambient, #read);
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn add_code(&mut self, source: &str) -> Result<(), error::Token> {
+        let (_, result) =
+            parser::parse_block_source(source).map_err(|e| parser::source_error(source, e))?;
+
+        for (_, fact) in result.facts {
+            self.add_fact(fact)?;
+        }
+        for (_, rule) in result.rules {
+            self.add_rule(rule)?;
+        }
+        for (_, check) in result.checks {
+            self.add_check(check)?;
+        }
+        if let Some(context) = result.context {
+            self.set_context(context);
+        }
+
+        Ok(())
+    }
+
+    /// adds the facts, rules and checks of another block builder
+    ///
+    /// this can be used to define common sets of checks (expiration, allowed
+    /// operations...) once, as fragments that can be merged in any block:
+    ///
+    /// ```rust
+    /// # use biscuit_auth::token::builder::BlockBuilder;
+    /// # use std::time::{Duration, SystemTime};
+    /// fn read_only(ttl: Duration) -> BlockBuilder {
+    ///     let mut fragment = BlockBuilder::new(0);
+    ///     fragment.check_operation("read");
+    ///     fragment.expiration_date(SystemTime::now() + ttl);
+    ///     fragment
+    /// }
+    /// ```
+    ///
+    /// the index of the other builder is ignored, and its context is only
+    /// used if this builder does not have one
+    pub fn merge(&mut self, mut other: BlockBuilder) {
+        self.facts.append(&mut other.facts);
+        self.rules.append(&mut other.rules);
+        self.checks.append(&mut other.checks);
+
+        if self.context.is_none() {
+            self.context = other.context;
+        }
+    }
+
     pub fn build(self, mut symbols: SymbolTable) -> Block {
-        let symbols_start = symbols.symbols.len();
+        let symbols_start = symbols.len();
 
         let mut facts = Vec::new();
         for fact in self.facts {
@@ -73,9 +173,7 @@ impl BlockBuilder {
         for check in self.checks {
             checks.push(check.convert(&mut symbols));
         }
-        let new_syms = SymbolTable {
-            symbols: symbols.symbols.split_off(symbols_start),
-        };
+        let new_syms = symbols.split_off(symbols_start);
 
         Block {
             index: self.index,
@@ -85,6 +183,7 @@ impl BlockBuilder {
             checks,
             context: self.context,
             version: super::MAX_SCHEMA_VERSION,
+            nonce: None,
         }
     }
 
@@ -156,15 +255,20 @@ impl BlockBuilder {
         let _ = self.add_check(check);
     }
 
-    pub fn expiration_date(&mut self, exp: SystemTime) {
+    /// adds a check on the ambient `time` fact
+    ///
+    /// the date can be a `SystemTime`, a `chrono::DateTime`, or with the `time`
+    /// feature, a `time::OffsetDateTime`
+    pub fn expiration_date<T: Into<SystemTime>>(&mut self, exp: T) {
+        let exp = exp.into();
         let check = constrained_rule(
             "expiration",
             &[var("date")],
             &[pred("time", &[s("ambient"), var("date")])],
             &[Expression {
                 ops: vec![
-                    Op::Value(var("date")),
-                    Op::Value(date(&exp)),
+                    var("date").into(),
+                    exp.into(),
                     Op::Binary(Binary::LessOrEqual),
                 ],
             }],
@@ -176,6 +280,131 @@ impl BlockBuilder {
     pub fn revocation_id(&mut self, id: i64) {
         let _ = self.add_fact(fact("revocation_id", &[int(id)]));
     }
+
+    /// restricts the token to resources starting with this prefix
+    ///
+    /// adds `check if resource(#ambient, $resource), $resource.starts_with(prefix)`
+    pub fn restrict_resource_prefix(&mut self, prefix: &str) {
+        self.resource_prefix(prefix);
+    }
+
+    /// restricts the token to one of these resources
+    ///
+    /// adds `check if resource(#ambient, $resource), [resources].contains($resource)`
+    pub fn restrict_resources(&mut self, resources: &[&str]) {
+        let check = constrained_rule(
+            "resources",
+            &[var("resource")],
+            &[pred("resource", &[s("ambient"), var("resource")])],
+            &[Expression {
+                ops: vec![
+                    Op::Value(Term::Set(resources.iter().map(|r| string(r)).collect())),
+                    Op::Value(var("resource")),
+                    Op::Binary(Binary::Contains),
+                ],
+            }],
+        );
+
+        let _ = self.add_check(check);
+    }
+
+    /// restricts the token to one of these operations
+    ///
+    /// adds `check if operation(#ambient, $operation), [#operations].contains($operation)`
+    pub fn restrict_operations(&mut self, operations: &[&str]) {
+        let check = constrained_rule(
+            "operations",
+            &[var("operation")],
+            &[pred("operation", &[s("ambient"), var("operation")])],
+            &[Expression {
+                ops: vec![
+                    Op::Value(Term::Set(operations.iter().map(|o| s(o)).collect())),
+                    Op::Value(var("operation")),
+                    Op::Binary(Binary::Contains),
+                ],
+            }],
+        );
+
+        let _ = self.add_check(check);
+    }
+
+    /// restricts the token to a validity period, bounds included
+    ///
+    /// adds `check if time(#ambient, $date), $date >= start, $date <= end`.
+    /// Like [`BlockBuilder::expiration_date`], the dates can be a `SystemTime`,
+    /// a `chrono::DateTime`, or with the `time` feature, a `time::OffsetDateTime`
+    pub fn valid_between<T: Into<SystemTime>, U: Into<SystemTime>>(&mut self, start: T, end: U) {
+        let check = constrained_rule(
+            "validity",
+            &[var("date")],
+            &[pred("time", &[s("ambient"), var("date")])],
+            &[
+                Expression {
+                    ops: vec![
+                        var("date").into(),
+                        start.into().into(),
+                        Op::Binary(Binary::GreaterOrEqual),
+                    ],
+                },
+                Expression {
+                    ops: vec![
+                        var("date").into(),
+                        end.into().into(),
+                        Op::Binary(Binary::LessOrEqual),
+                    ],
+                },
+            ],
+        );
+
+        let _ = self.add_check(check);
+    }
+}
+
+impl Extend<Fact> for BlockBuilder {
+    fn extend<T: IntoIterator<Item = Fact>>(&mut self, iter: T) {
+        self.facts.extend(iter);
+    }
+}
+
+impl Extend<Rule> for BlockBuilder {
+    fn extend<T: IntoIterator<Item = Rule>>(&mut self, iter: T) {
+        self.rules.extend(iter);
+    }
+}
+
+impl Extend<Check> for BlockBuilder {
+    fn extend<T: IntoIterator<Item = Check>>(&mut self, iter: T) {
+        self.checks.extend(iter);
+    }
+}
+
+/// creates a block builder with index 0
+///
+/// the index must be set to the block's position in the token before calling
+/// [`Biscuit::append`](`crate::token::Biscuit::append`), it is usually easier to
+/// extend the result of [`Biscuit::create_block`](`crate::token::Biscuit::create_block`)
+impl FromIterator<Fact> for BlockBuilder {
+    fn from_iter<T: IntoIterator<Item = Fact>>(iter: T) -> Self {
+        let mut builder = BlockBuilder::new(0);
+        builder.extend(iter);
+        builder
+    }
+}
+
+impl FromIterator<Rule> for BlockBuilder {
+    fn from_iter<T: IntoIterator<Item = Rule>>(iter: T) -> Self {
+        let mut builder = BlockBuilder::new(0);
+        builder.extend(iter);
+        builder
+    }
+}
+
+impl FromIterator<Check> for BlockBuilder {
+    fn from_iter<T: IntoIterator<Item = Check>>(iter: T) -> Self {
+        let mut builder = BlockBuilder::new(0);
+        builder.extend(iter);
+        builder
+    }
 }
 
 #[derive(Clone)]
@@ -187,39 +416,71 @@ pub struct BiscuitBuilder<'a> {
     pub rules: Vec<datalog::Rule>,
     pub checks: Vec<datalog::Check>,
     pub context: Option<String>,
+    delegation: Option<Delegation>,
 }
 
 impl<'a> BiscuitBuilder<'a> {
     pub fn new(root: &'a KeyPair, base_symbols: SymbolTable) -> BiscuitBuilder<'a> {
         BiscuitBuilder {
             root,
-            symbols_start: base_symbols.symbols.len(),
+            symbols_start: base_symbols.len(),
             symbols: base_symbols,
             facts: vec![],
             rules: vec![],
             checks: vec![],
             context: None,
+            delegation: None,
         }
     }
 
-    pub fn add_authority_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token> {
-        let fact = fact.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_authority_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token>
+    where
+        F::Error: Into<error::Token>,
+    {
+        let fact = fact.try_into().map_err(Into::into)?;
 
         let f = fact.convert(&mut self.symbols);
         self.facts.push(f);
         Ok(())
     }
 
-    pub fn add_authority_rule<Ru: TryInto<Rule>>(&mut self, rule: Ru) -> Result<(), error::Token> {
-        let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
+    /// adds facts to the authority block
+    ///
+    /// if a fact fails to convert, the facts before it are kept
+    pub fn add_authority_facts<I, F>(&mut self, facts: I) -> Result<(), error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        F::Error: Into<error::Token>,
+    {
+        let facts = facts.into_iter();
+        self.facts.reserve(facts.size_hint().0);
+        for fact in facts {
+            let fact = fact.try_into().map_err(Into::into)?;
+            self.facts.push(fact.convert(&mut self.symbols));
+        }
+        Ok(())
+    }
+
+    pub fn add_authority_rule<Ru: TryInto<Rule>>(&mut self, rule: Ru) -> Result<(), error::Token>
+    where
+        Ru::Error: Into<error::Token>,
+    {
+        let rule = rule.try_into().map_err(Into::into)?;
+        if rule.validate_variables().is_err() {
+            return Err(error::Logic::InvalidBlockRule(0, rule.to_string()).into());
+        }
 
         let r = rule.convert(&mut self.symbols);
         self.rules.push(r);
         Ok(())
     }
 
-    pub fn add_authority_check<C: TryInto<Check>>(&mut self, rule: C) -> Result<(), error::Token> {
-        let check: Check = rule.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_authority_check<C: TryInto<Check>>(&mut self, rule: C) -> Result<(), error::Token>
+    where
+        C::Error: Into<error::Token>,
+    {
+        let check: Check = rule.try_into().map_err(Into::into)?;
         let c = check.convert(&mut self.symbols);
         self.checks.push(c);
         Ok(())
@@ -234,6 +495,59 @@ impl<'a> BiscuitBuilder<'a> {
         self.context = Some(context);
     }
 
+    /// adds the facts, rules and checks of a block builder to the authority block
+    ///
+    /// see [`BlockBuilder::merge`] for the use of block builders as reusable fragments
+    pub fn merge(&mut self, other: BlockBuilder) {
+        self.extend(other.facts);
+        self.extend(other.rules);
+        self.extend(other.checks);
+
+        if self.context.is_none() {
+            self.context = other.context;
+        }
+    }
+
+    /// adds a certificate delegating a root key to the key of this builder
+    ///
+    /// the token is then accepted by verifiers using the root key of the
+    /// delegation, see [`Delegation`]
+    pub fn set_delegation(&mut self, delegation: Delegation) {
+        self.delegation = Some(delegation);
+    }
+
+    /// binds the token to a client TLS certificate, given in DER
+    ///
+    /// adds a `cnf` fact with the thumbprint of the certificate, and a check
+    /// requiring the verifier to provide the same thumbprint with
+    /// [`Verifier::add_client_certificate`](crate::token::verifier::Verifier::add_client_certificate),
+    /// see [`binding`](crate::token::binding)
+    pub fn bind_to_certificate(&mut self, certificate: &[u8]) {
+        let _ = self.add_authority_fact(binding::cnf(binding::CERTIFICATE, certificate));
+        let _ = self.add_authority_check(binding::check());
+    }
+
+    /// binds the token to a client public key, given as a DER encoded
+    /// SubjectPublicKeyInfo
+    ///
+    /// the verifier provides the key with
+    /// [`Verifier::add_client_public_key`](crate::token::verifier::Verifier::add_client_public_key)
+    pub fn bind_to_public_key(&mut self, public_key: &[u8]) {
+        let _ = self.add_authority_fact(binding::cnf(binding::PUBLIC_KEY, public_key));
+        let _ = self.add_authority_check(binding::check());
+    }
+
+    /// binds the token to the key of its holder
+    ///
+    /// adds a `holder_key` fact, and a check requiring a proof of possession
+    /// of the private key, verified by
+    /// [`Verifier::verify_possession`](crate::token::verifier::Verifier::verify_possession),
+    /// see [`possession`](crate::token::possession)
+    pub fn bind_to_holder(&mut self, key: PublicKey) {
+        let _ = self.add_authority_fact(possession::holder_key(key));
+        let _ = self.add_authority_check(possession::check());
+    }
+
     pub fn build(self) -> Result<Biscuit, error::Token> {
         self.build_with_rng(&mut rand::rngs::OsRng)
     }
@@ -242,9 +556,7 @@ impl<'a> BiscuitBuilder<'a> {
         mut self,
         rng: &'a mut R,
     ) -> Result<Biscuit, error::Token> {
-        let new_syms = SymbolTable {
-            symbols: self.symbols.symbols.split_off(self.symbols_start),
-        };
+        let new_syms = self.symbols.split_off(self.symbols_start);
 
         let authority_block = Block {
             index: 0,
@@ -254,9 +566,41 @@ impl<'a> BiscuitBuilder<'a> {
             checks: self.checks,
             context: self.context,
             version: super::MAX_SCHEMA_VERSION,
+            nonce: None,
         };
 
-        Biscuit::new_with_rng(rng, self.root, self.symbols, authority_block)
+        let token = Biscuit::new_with_rng(rng, self.root, self.symbols, authority_block)?;
+        match self.delegation {
+            Some(delegation) => token.with_delegation(delegation),
+            None => Ok(token),
+        }
+    }
+}
+
+impl<'a> Extend<Fact> for BiscuitBuilder<'a> {
+    fn extend<T: IntoIterator<Item = Fact>>(&mut self, iter: T) {
+        for fact in iter {
+            let f = fact.convert(&mut self.symbols);
+            self.facts.push(f);
+        }
+    }
+}
+
+impl<'a> Extend<Rule> for BiscuitBuilder<'a> {
+    fn extend<T: IntoIterator<Item = Rule>>(&mut self, iter: T) {
+        for rule in iter {
+            let r = rule.convert(&mut self.symbols);
+            self.rules.push(r);
+        }
+    }
+}
+
+impl<'a> Extend<Check> for BiscuitBuilder<'a> {
+    fn extend<T: IntoIterator<Item = Check>>(&mut self, iter: T) {
+        for check in iter {
+            let c = check.convert(&mut self.symbols);
+            self.checks.push(c);
+        }
     }
 }
 
@@ -326,7 +670,7 @@ impl fmt::Display for Term {
         match self {
             Term::Variable(i) => write!(f, "${}", i),
             Term::Integer(i) => write!(f, "{}", i),
-            Term::Str(s) => write!(f, "\"{}\"", s),
+            Term::Str(s) => write!(f, "{}", datalog::quote_string(s)),
             Term::Symbol(s) => write!(f, "#{}", s),
             Term::Date(d) => {
                 let t = UNIX_EPOCH + Duration::from_secs(*d);
@@ -355,9 +699,14 @@ pub struct Predicate {
 }
 
 impl Predicate {
+    /// namespace of the predicate, like `billing` in `billing:right(...)`
+    pub fn namespace(&self) -> Option<&str> {
+        datalog::namespace(&self.name)
+    }
+
     pub fn convert(&self, symbols: &mut SymbolTable) -> datalog::Predicate {
         let name = symbols.insert(&self.name);
-        let mut ids = vec![];
+        let mut ids = datalog::Terms::new();
 
         for id in self.ids.iter() {
             ids.push(id.convert(symbols));
@@ -468,8 +817,7 @@ impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut syms = super::default_symbol_table();
         let expr = self.convert(&mut syms);
-        let s = expr.print(&syms).unwrap();
-        write!(f, "{}", s)
+        expr.write(f, &syms)
     }
 }
 
@@ -498,6 +846,24 @@ impl Op {
     }
 }
 
+impl From<Term> for Op {
+    fn from(t: Term) -> Self {
+        Op::Value(t)
+    }
+}
+
+impl From<SystemTime> for Op {
+    fn from(t: SystemTime) -> Self {
+        Op::Value(t.into())
+    }
+}
+
+impl From<Duration> for Op {
+    fn from(d: Duration) -> Self {
+        Op::Value(d.into())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule(pub Predicate, pub Vec<Predicate>, pub Vec<Expression>);
 
@@ -561,6 +927,10 @@ impl Rule {
             }
         }
 
+        if head_variables.is_empty() {
+            return Ok(());
+        }
+
         Err(format!(
             "rule head contains variables that are not used in predicates of the rule's body: {}",
             head_variables
@@ -821,6 +1191,74 @@ pub fn set(s: BTreeSet<Term>) -> Term {
     Term::Set(s)
 }
 
+impl From<i64> for Term {
+    fn from(i: i64) -> Self {
+        Term::Integer(i)
+    }
+}
+
+impl From<bool> for Term {
+    fn from(b: bool) -> Self {
+        Term::Bool(b)
+    }
+}
+
+impl From<String> for Term {
+    fn from(s: String) -> Self {
+        Term::Str(s)
+    }
+}
+
+impl From<&str> for Term {
+    fn from(s: &str) -> Self {
+        Term::Str(s.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Term {
+    fn from(b: Vec<u8>) -> Self {
+        Term::Bytes(b)
+    }
+}
+
+impl From<&[u8]> for Term {
+    fn from(b: &[u8]) -> Self {
+        Term::Bytes(b.to_vec())
+    }
+}
+
+impl From<SystemTime> for Term {
+    fn from(t: SystemTime) -> Self {
+        date(&t)
+    }
+}
+
+/// durations are stored as an integer number of seconds
+impl From<Duration> for Term {
+    fn from(d: Duration) -> Self {
+        Term::Integer(i64::try_from(d.as_secs()).unwrap_or(i64::MAX))
+    }
+}
+
+impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for Term {
+    fn from(t: chrono::DateTime<Tz>) -> Self {
+        Term::Date(t.timestamp().max(0) as u64)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<::time::OffsetDateTime> for Term {
+    fn from(t: ::time::OffsetDateTime) -> Self {
+        Term::Date(t.unix_timestamp().max(0) as u64)
+    }
+}
+
+impl<T: Into<Term>> From<BTreeSet<T>> for Term {
+    fn from(s: BTreeSet<T>) -> Self {
+        Term::Set(s.into_iter().map(Into::into).collect())
+    }
+}
+
 impl TryFrom<Term> for i64 {
     type Error = error::Token;
     fn try_from(value: Term) -> Result<Self, Self::Error> {
@@ -887,6 +1325,41 @@ impl TryFrom<Term> for SystemTime {
     }
 }
 
+impl TryFrom<Term> for chrono::DateTime<chrono::Utc> {
+    type Error = error::Token;
+    fn try_from(value: Term) -> Result<Self, Self::Error> {
+        use chrono::TimeZone;
+
+        match value {
+            Term::Date(d) => i64::try_from(d)
+                .ok()
+                .and_then(|d| chrono::Utc.timestamp_opt(d, 0).single())
+                .ok_or_else(|| error::Token::ConversionError(format!("date out of range: {}", d))),
+            _ => Err(error::Token::ConversionError(format!(
+                "expected date, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<Term> for ::time::OffsetDateTime {
+    type Error = error::Token;
+    fn try_from(value: Term) -> Result<Self, Self::Error> {
+        match value {
+            Term::Date(d) => i64::try_from(d)
+                .ok()
+                .and_then(|d| ::time::OffsetDateTime::from_unix_timestamp(d).ok())
+                .ok_or_else(|| error::Token::ConversionError(format!("date out of range: {}", d))),
+            _ => Err(error::Token::ConversionError(format!(
+                "expected date, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
 impl<T: Ord + TryFrom<Term, Error = error::Token>> TryFrom<Term> for BTreeSet<T> {
     type Error = error::Token;
     fn try_from(value: Term) -> Result<Self, Self::Error> {
@@ -900,8 +1373,25 @@ impl<T: Ord + TryFrom<Term, Error = error::Token>> TryFrom<Term> for BTreeSet<T>
     }
 }
 
+/// conversion of a tuple into the list of terms of a fact
+///
+/// this is used to build facts from tuples, as in
+/// `Fact::try_from(("right", ("file1", "read")))`
+pub trait IntoTerms {
+    fn into_terms(self) -> Result<Vec<Term>, error::Token>;
+}
+
+impl<T: IntoTerms> TryFrom<(&str, T)> for Fact {
+    type Error = error::Token;
+
+    fn try_from((name, terms): (&str, T)) -> Result<Self, Self::Error> {
+        Ok(Fact::new(name.to_string(), &terms.into_terms()?))
+    }
+}
+
 macro_rules! tuple_try_from(
     ($ty1:ident, $ty2:ident, $($ty:ident),*) => (
+        tuple_try_from_impl!($ty1);
         tuple_try_from!(__impl $ty1, $ty2; $($ty),*);
         );
     (__impl $($ty: ident),+; $ty1:ident, $($ty2:ident),*) => (
@@ -916,7 +1406,7 @@ macro_rules! tuple_try_from(
 
 macro_rules! tuple_try_from_impl(
     ($($ty: ident),+) => (
-        impl<$($ty: TryFrom<Term, Error = error::Token>),+> TryFrom<Fact> for ($($ty),+) {
+        impl<$($ty: TryFrom<Term, Error = error::Token>),+> TryFrom<Fact> for ($($ty,)+) {
             type Error = error::Token;
             fn try_from(fact: Fact) -> Result<Self, Self::Error> {
                 let mut terms = fact.0.ids;
@@ -924,12 +1414,28 @@ macro_rules! tuple_try_from_impl(
 
                 Ok((
                         $(
-                            it.next().ok_or(error::Token::ConversionError("not enough terms in fact".to_string())).and_then($ty::try_from)?
-                         ),+
+                            it.next().ok_or(error::Token::ConversionError("not enough terms in fact".to_string())).and_then($ty::try_from)?,
+                         )+
                    ))
 
             }
         }
+
+        impl<$($ty: TryInto<Term>),+> IntoTerms for ($($ty,)+)
+        where
+            $(<$ty as TryInto<Term>>::Error: Into<error::Token>),+
+        {
+            #[allow(non_snake_case)]
+            fn into_terms(self) -> Result<Vec<Term>, error::Token> {
+                let ($($ty,)+) = self;
+
+                Ok(vec![
+                    $(
+                        $ty.try_into().map_err(Into::into)?,
+                     )+
+                ])
+            }
+        }
         );
     );
 
@@ -0,0 +1,143 @@
+//! cache of signature verification results
+use super::verifier::{Clock, SystemClock};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+type Key = [u8; 32];
+
+/// remembers which serialized tokens had a valid signature
+///
+/// tokens are identified by a SHA256 digest of their serialized form, so a
+/// token presented again with the exact same bytes, before the TTL expires,
+/// is deserialized with [`Biscuit::from_with_cache`](crate::token::Biscuit::from_with_cache)
+/// without verifying the signature of every block again.
+///
+/// the cache holds at most `capacity` tokens, the oldest ones are evicted
+/// first. It can be shared between threads.
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, cache::SignatureCache}};
+/// use std::time::Duration;
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap().to_vec().unwrap();
+///
+/// let cache = SignatureCache::new(1000, Duration::from_secs(60));
+/// let biscuit = Biscuit::from_with_cache(&token, &cache).unwrap();
+/// assert_eq!(cache.len(), 1);
+/// ```
+pub struct SignatureCache {
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock + Send + Sync>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// `None` if the TTL goes past the latest representable time
+    expiration: HashMap<Key, Option<SystemTime>>,
+    /// digests in insertion order
+    order: VecDeque<Key>,
+}
+
+impl SignatureCache {
+    /// creates a cache holding at most `capacity` tokens for `ttl`
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        SignatureCache {
+            capacity,
+            ttl,
+            clock: Arc::new(SystemClock),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// sets the source of the current time used for expiration
+    pub fn set_clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// number of tokens in the cache, including expired ones that were not evicted yet
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().expiration.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// removes all the tokens from the cache
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.expiration.clear();
+        entries.order.clear();
+    }
+
+    pub(crate) fn digest(data: &[u8]) -> Key {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&Sha256::digest(data));
+        key
+    }
+
+    /// returns `true` if a token with this digest was verified and has not expired
+    pub(crate) fn contains(&self, key: &Key) -> bool {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.expiration.get(key) {
+            Some(None) => true,
+            Some(Some(expiration)) if *expiration > now => true,
+            Some(Some(_)) => {
+                entries.expiration.remove(key);
+                entries.order.retain(|k| k != key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// records that the token with this digest has a valid signature
+    pub(crate) fn insert(&self, key: Key) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+
+        let expiration = now.checked_add(self.ttl);
+        if entries.expiration.insert(key, expiration).is_some() {
+            entries.order.retain(|k| *k != key);
+        }
+        entries.order.push_back(key);
+
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.expiration.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SignatureCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignatureCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,281 @@
+//! capabilities: an action on a resource, with constraints
+//!
+//! a [`Capability`] is granted in the authority block with
+//! [`Capability::grant`], narrowed in attenuation blocks with [`narrow`],
+//! and required by the verifier with
+//! [`Verifier::require_capability`](crate::token::verifier::Verifier::require_capability).
+//! The resource is either exact, or a prefix when it ends with `*`.
+//!
+//! granting `Capability::new("read", "/docs/*")` adds:
+//!
+//! ```text
+//! capability(#authority, #read, "/docs/", #prefix);
+//! ```
+//!
+//! or, with constraints, a rule only producing this fact while they hold:
+//!
+//! ```text
+//! capability(#authority, #read, "/docs/", #prefix) <- time(#ambient, $time), $time <= 2030-01-01T00:00:00+00:00;
+//! ```
+//!
+//! narrowing adds a check on the `operation` and `resource` of the request:
+//!
+//! ```text
+//! check if operation(#ambient, #read), resource(#ambient, $resource), $resource.starts_with("/docs/");
+//! ```
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, capability::{self, Capability}}};
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! Capability::new("read", "/docs/*").grant(&mut builder);
+//! Capability::new("write", "/docs/*").grant(&mut builder);
+//! let token = builder.build().unwrap();
+//!
+//! let mut block = token.create_block();
+//! capability::narrow(&mut block, &[Capability::new("read", "/docs/public/*")]);
+//! let token = token.append(&KeyPair::new(), block).unwrap();
+//!
+//! let mut verifier = token.verify(root.public()).unwrap();
+//! verifier.require_capability("read", "/docs/public/a");
+//! verifier.allow().unwrap();
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::builder::{
+    pred, s, string, var, Binary, BiscuitBuilder, BlockBuilder, Check, Expression, Fact, Op,
+    Predicate, Rule, Term,
+};
+use std::time::SystemTime;
+
+/// resources a capability applies to
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResourcePattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl ResourcePattern {
+    /// a pattern ending with `*` is a prefix
+    pub fn new(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => ResourcePattern::Prefix(prefix.to_string()),
+            None => ResourcePattern::Exact(pattern.to_string()),
+        }
+    }
+}
+
+/// condition limiting the use of a capability
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constraint {
+    /// the capability can be used until this date, included
+    ExpiresAt(SystemTime),
+    /// the capability can be used from this date, included
+    NotBefore(SystemTime),
+}
+
+/// action on resources, with constraints
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capability {
+    pub action: String,
+    pub resource: ResourcePattern,
+    pub constraints: Vec<Constraint>,
+}
+
+impl Capability {
+    pub fn new(action: &str, resource: &str) -> Self {
+        Capability {
+            action: action.to_string(),
+            resource: ResourcePattern::new(resource),
+            constraints: Vec::new(),
+        }
+    }
+
+    pub fn expires_at<T: Into<SystemTime>>(mut self, date: T) -> Self {
+        self.constraints.push(Constraint::ExpiresAt(date.into()));
+        self
+    }
+
+    pub fn not_before<T: Into<SystemTime>>(mut self, date: T) -> Self {
+        self.constraints.push(Constraint::NotBefore(date.into()));
+        self
+    }
+
+    /// grants the capability in the authority block
+    pub fn grant(&self, builder: &mut BiscuitBuilder) {
+        let (pattern, kind) = match &self.resource {
+            ResourcePattern::Exact(resource) => (resource, "exact"),
+            ResourcePattern::Prefix(prefix) => (prefix, "prefix"),
+        };
+        let head = pred(
+            "capability",
+            &[s("authority"), s(&self.action), string(pattern), s(kind)],
+        );
+
+        if self.constraints.is_empty() {
+            let _ = builder.add_authority_fact(Fact(head));
+        } else {
+            let (predicates, expressions) = self.constraints();
+            let _ = builder.add_authority_rule(Rule(head, predicates, expressions));
+        }
+    }
+
+    /// query matching the operation and resource of the request
+    fn query(&self) -> Rule {
+        let mut predicates = vec![pred("operation", &[s("ambient"), s(&self.action)])];
+        let mut expressions = Vec::new();
+        match &self.resource {
+            ResourcePattern::Exact(resource) => {
+                predicates.push(pred("resource", &[s("ambient"), string(resource)]))
+            }
+            ResourcePattern::Prefix(prefix) => {
+                predicates.push(pred("resource", &[s("ambient"), var("resource")]));
+                expressions.push(Expression {
+                    ops: vec![
+                        Op::Value(var("resource")),
+                        Op::Value(string(prefix)),
+                        Op::Binary(Binary::Prefix),
+                    ],
+                });
+            }
+        }
+
+        let (constraint_predicates, constraint_expressions) = self.constraints();
+        predicates.extend(constraint_predicates);
+        expressions.extend(constraint_expressions);
+        Rule(
+            pred("capability", &[s(&self.action)]),
+            predicates,
+            expressions,
+        )
+    }
+
+    fn constraints(&self) -> (Vec<Predicate>, Vec<Expression>) {
+        if self.constraints.is_empty() {
+            return (vec![], vec![]);
+        }
+
+        let expressions = self
+            .constraints
+            .iter()
+            .map(|constraint| {
+                let (date, comparison) = match constraint {
+                    Constraint::ExpiresAt(date) => (date, Binary::LessOrEqual),
+                    Constraint::NotBefore(date) => (date, Binary::GreaterOrEqual),
+                };
+                Expression {
+                    ops: vec![
+                        Op::Value(var("time")),
+                        Op::Value(Term::from(*date)),
+                        Op::Binary(comparison),
+                    ],
+                }
+            })
+            .collect();
+        (
+            vec![pred("time", &[s("ambient"), var("time")])],
+            expressions,
+        )
+    }
+}
+
+/// restricts the token to these capabilities, in an attenuation block
+///
+/// the verifier must provide the `operation` and `resource` facts, as done
+/// by [`Verifier::require_capability`](crate::token::verifier::Verifier::require_capability),
+/// and the time if the capabilities have constraints
+pub fn narrow(builder: &mut BlockBuilder, capabilities: &[Capability]) {
+    let _ = builder.add_check(Check {
+        queries: capabilities.iter().map(Capability::query).collect(),
+    });
+}
+
+/// `check if capability(#authority, #action, resource, #exact) or
+/// capability(#authority, #action, $prefix, #prefix), resource.starts_with($prefix)`
+pub(crate) fn require(action: &str, resource: &str) -> Check {
+    let exact = Rule(
+        pred("capability", &[s(action)]),
+        vec![pred(
+            "capability",
+            &[s("authority"), s(action), string(resource), s("exact")],
+        )],
+        vec![],
+    );
+    let prefix = Rule(
+        pred("capability", &[s(action)]),
+        vec![pred(
+            "capability",
+            &[s("authority"), s(action), var("prefix"), s("prefix")],
+        )],
+        vec![Expression {
+            ops: vec![
+                Op::Value(string(resource)),
+                Op::Value(var("prefix")),
+                Op::Binary(Binary::Prefix),
+            ],
+        }],
+    );
+
+    Check {
+        queries: vec![exact, prefix],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::Biscuit;
+    use std::time::Duration;
+
+    #[test]
+    fn grant_narrow_require() {
+        let root = KeyPair::new();
+        let now = SystemTime::now();
+        let mut builder = Biscuit::builder(&root);
+        Capability::new("read", "/docs/*").grant(&mut builder);
+        Capability::new("write", "/docs/draft").grant(&mut builder);
+        Capability::new("delete", "/docs/*")
+            .expires_at(now - Duration::from_secs(60))
+            .grant(&mut builder);
+        let token = builder.build().unwrap();
+        assert_eq!(
+            token.print_block_source(0).unwrap().lines().next(),
+            Some("capability(#authority, #read, \"/docs/\", #prefix);")
+        );
+
+        let allowed = |token: &Biscuit, action: &str, resource: &str| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.set_time();
+            verifier.require_capability(action, resource);
+            verifier.allow().unwrap();
+            verifier.verify().is_ok()
+        };
+        assert!(allowed(&token, "read", "/docs/a"));
+        assert!(allowed(&token, "write", "/docs/draft"));
+        assert!(!allowed(&token, "write", "/docs/a"));
+        assert!(!allowed(&token, "read", "/other"));
+        // expired
+        assert!(!allowed(&token, "delete", "/docs/a"));
+
+        let mut block = token.create_block();
+        narrow(
+            &mut block,
+            &[
+                Capability::new("read", "/docs/public/*"),
+                Capability::new("write", "/docs/draft").not_before(now + Duration::from_secs(60)),
+            ],
+        );
+        let attenuated = token.append(&KeyPair::new(), block).unwrap();
+        assert!(allowed(&attenuated, "read", "/docs/public/a"));
+        assert!(!allowed(&attenuated, "read", "/docs/a"));
+        // not yet valid
+        assert!(!allowed(&attenuated, "write", "/docs/draft"));
+
+        // narrowing cannot widen the token
+        let mut block = token.create_block();
+        narrow(&mut block, &[Capability::new("read", "/*")]);
+        let attenuated = token.append(&KeyPair::new(), block).unwrap();
+        assert!(!allowed(&attenuated, "read", "/other"));
+    }
+}
@@ -0,0 +1,146 @@
+//! delegation of the root key to an intermediate key
+use crate::crypto::{self, KeyPair, PublicKey};
+use crate::error;
+use crate::format::schema;
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    convert::TryInto,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// certificate signed by a root key, allowing an intermediate key to issue
+/// tokens until a date
+///
+/// the root key can stay offline, while a service holding the intermediate
+/// key issues short lived tokens carrying the certificate in their wrapper.
+/// Verifiers only know the root public key: [`Biscuit::verify`](crate::token::Biscuit::verify)
+/// accepts tokens signed by the intermediate key, and adds a check on the
+/// ambient time, so the verifier must call
+/// [`Verifier::set_time`](crate::token::verifier::Verifier::set_time)
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, delegation::Delegation}};
+/// use std::time::{Duration, SystemTime};
+///
+/// let root = KeyPair::new();
+/// let intermediate = KeyPair::new();
+/// let delegation = Delegation::new(
+///     &root,
+///     intermediate.public(),
+///     SystemTime::now() + Duration::from_secs(3600),
+/// );
+///
+/// let mut builder = Biscuit::builder(&intermediate);
+/// builder.set_delegation(delegation);
+/// let token = builder.build().unwrap();
+///
+/// let token = Biscuit::from(&token.to_vec().unwrap()).unwrap();
+/// let mut verifier = token.verify(root.public()).unwrap();
+/// verifier.set_time();
+/// verifier.allow().unwrap();
+/// assert!(verifier.verify().is_ok());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delegation {
+    /// key that signed the certificate
+    pub root: PublicKey,
+    /// key allowed to sign authority blocks
+    pub key: PublicKey,
+    /// date after which tokens signed by `key` are refused, with a
+    /// precision of one second
+    pub expiration: SystemTime,
+    signature: (Scalar, Scalar),
+}
+
+impl Delegation {
+    /// signs a certificate allowing `key` to issue tokens until `expiration`
+    pub fn new(root: &KeyPair, key: PublicKey, expiration: SystemTime) -> Self {
+        Delegation::new_with_rng(&mut rand::rngs::OsRng, root, key, expiration)
+    }
+
+    /// signs a certificate, using the provided CSPRNG
+    pub fn new_with_rng<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        root: &KeyPair,
+        key: PublicKey,
+        expiration: SystemTime,
+    ) -> Self {
+        let expiration = UNIX_EPOCH + Duration::from_secs(seconds(expiration));
+        let signature = root.sign(rng, &message(root.public(), key, expiration));
+
+        Delegation {
+            root: root.public(),
+            key,
+            expiration,
+            signature,
+        }
+    }
+
+    /// checks the signature of the certificate
+    pub fn verify(&self) -> Result<(), error::Format> {
+        if crypto::verify(
+            &self.root.0,
+            &message(self.root, self.key, self.expiration),
+            &self.signature,
+        ) {
+            Ok(())
+        } else {
+            Err(error::Format::InvalidDelegation)
+        }
+    }
+
+    pub(crate) fn to_proto(&self) -> schema::Delegation {
+        let (d, z) = &self.signature;
+        let mut signature = d.to_bytes().to_vec();
+        signature.extend_from_slice(&z.to_bytes());
+
+        schema::Delegation {
+            root: self.root.to_bytes().to_vec(),
+            key: self.key.to_bytes().to_vec(),
+            expiration: seconds(self.expiration),
+            signature,
+        }
+    }
+
+    pub(crate) fn from_proto(input: &schema::Delegation) -> Result<Self, error::Format> {
+        let root = PublicKey::from_bytes(&input.root).ok_or(error::Format::InvalidDelegation)?;
+        let key = PublicKey::from_bytes(&input.key).ok_or(error::Format::InvalidDelegation)?;
+        if input.signature.len() != 64 {
+            return Err(error::Format::InvalidDelegation);
+        }
+        let scalar = |bytes: &[u8]| {
+            Scalar::from_canonical_bytes(bytes.try_into().unwrap())
+                .ok_or(error::Format::InvalidDelegation)
+        };
+
+        let expiration = UNIX_EPOCH
+            .checked_add(Duration::from_secs(input.expiration))
+            .ok_or(error::Format::InvalidDelegation)?;
+
+        Ok(Delegation {
+            root,
+            key,
+            expiration,
+            signature: (
+                scalar(&input.signature[..32])?,
+                scalar(&input.signature[32..])?,
+            ),
+        })
+    }
+}
+
+pub(crate) fn seconds(date: SystemTime) -> u64 {
+    date.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// content signed by the root key
+fn message(root: PublicKey, key: PublicKey, expiration: SystemTime) -> Vec<u8> {
+    let mut message = b"biscuit-delegation".to_vec();
+    message.extend_from_slice(&root.to_bytes());
+    message.extend_from_slice(&key.to_bytes());
+    message.extend_from_slice(&seconds(expiration).to_le_bytes());
+    message
+}
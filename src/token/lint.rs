@@ -0,0 +1,411 @@
+//! static analysis of the Datalog content of a token
+//!
+//! [`lint`] looks for mistakes made when minting or attenuating a token,
+//! without a verifier: checks that can never pass, facts that no rule or
+//! check of the token uses, checks repeated in several blocks, and terms
+//! large enough to bloat the token.
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, lint::{lint, Severity}}};
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_check("check if 1 > 2").unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! let findings = lint(&token).unwrap();
+//! assert_eq!(findings.len(), 1);
+//! assert_eq!(findings[0].severity, Severity::Error);
+//! println!("{}", findings[0]);
+//! ```
+//!
+//! the verifier can provide facts and rules the token does not know about,
+//! so facts used by the verifier's policies are still reported as unused,
+//! with a lower severity for the authority block
+use super::{Biscuit, Block};
+use crate::datalog::{Check, Op, Predicate, SymbolTable, ID};
+use crate::error;
+use crate::format::TermLimits;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// sizes above which [`lint`] reports a term as oversized
+///
+/// they are far below the limits enforced when decoding a block, and are
+/// meant to catch data that should not be carried in a token
+pub const TERM_LIMITS: TermLimits = TermLimits {
+    max_string_len: 1024,
+    max_bytes_len: 1024,
+    max_set_len: 100,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// probably intended, like authority facts only used by the verifier
+    Info,
+    Warning,
+    /// the token cannot be authorized as it is
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lint {
+    /// every query of the check has an expression without variables that
+    /// does not evaluate to `true`, or the check has no queries
+    UnsatisfiableCheck { check_id: usize, check: String },
+    /// no rule or check of the token has a predicate with the same name and
+    /// number of terms
+    UnusedFact { fact_id: usize, fact: String },
+    /// the same check already appears in an earlier block, or earlier in
+    /// the same block
+    DuplicateCheck {
+        check_id: usize,
+        check: String,
+        first_block_id: usize,
+        first_check_id: usize,
+    },
+    /// a string, byte array or set is larger than the [`TermLimits`]
+    OversizedTerm {
+        /// "string", "bytes" or "set"
+        kind: &'static str,
+        len: usize,
+        max: usize,
+        /// printed fact, rule or check containing the term
+        element: String,
+    },
+}
+
+/// problem found by [`lint`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// position of the block, 0 being the authority block
+    pub block_id: usize,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: block {}: ", self.severity, self.block_id)?;
+        match &self.lint {
+            Lint::UnsatisfiableCheck { check_id, check } => {
+                write!(f, "check {} can never pass: {}", check_id, check)
+            }
+            Lint::UnusedFact { fact_id, fact } => {
+                write!(f, "fact {} is not used by the token: {}", fact_id, fact)
+            }
+            Lint::DuplicateCheck {
+                check_id,
+                check,
+                first_block_id,
+                first_check_id,
+            } => write!(
+                f,
+                "check {} duplicates check {} of block {}: {}",
+                check_id, first_check_id, first_block_id, check
+            ),
+            Lint::OversizedTerm {
+                kind,
+                len,
+                max,
+                element,
+            } => write!(
+                f,
+                "{} of length {} is larger than {}: {}",
+                kind, len, max, element
+            ),
+        }
+    }
+}
+
+/// inspects the token with the default [`TERM_LIMITS`]
+///
+/// findings are sorted by block, then by kind
+pub fn lint(token: &Biscuit) -> Result<Vec<Finding>, error::Token> {
+    lint_with_limits(token, &TERM_LIMITS)
+}
+
+/// inspects the token, reporting terms larger than `limits`
+pub fn lint_with_limits(
+    token: &Biscuit,
+    limits: &TermLimits,
+) -> Result<Vec<Finding>, error::Token> {
+    let symbols = token.symbols()?;
+    let mut blocks = vec![token.authority()?];
+    blocks.extend(token.blocks()?);
+
+    let used = used_predicates(&blocks);
+    let mut checks = HashMap::new();
+    let mut findings = Vec::new();
+
+    for (block_id, block) in blocks.iter().enumerate() {
+        let mut finding = |severity, lint| {
+            findings.push(Finding {
+                severity,
+                block_id,
+                lint,
+            })
+        };
+
+        for (check_id, check) in block.checks.iter().enumerate() {
+            let printed = symbols.print_check(check);
+            if !satisfiable(check) {
+                finding(
+                    Severity::Error,
+                    Lint::UnsatisfiableCheck {
+                        check_id,
+                        check: printed.clone(),
+                    },
+                );
+            }
+
+            match checks.get(&printed) {
+                Some(&(first_block_id, first_check_id)) => finding(
+                    Severity::Warning,
+                    Lint::DuplicateCheck {
+                        check_id,
+                        check: printed,
+                        first_block_id,
+                        first_check_id,
+                    },
+                ),
+                None => {
+                    checks.insert(printed, (block_id, check_id));
+                }
+            }
+        }
+
+        for (fact_id, fact) in block.facts.iter().enumerate() {
+            if !used.contains(&signature(&fact.predicate)) {
+                let severity = if block_id == 0 {
+                    Severity::Info
+                } else {
+                    Severity::Warning
+                };
+                finding(
+                    severity,
+                    Lint::UnusedFact {
+                        fact_id,
+                        fact: symbols.print_fact(fact),
+                    },
+                );
+            }
+        }
+
+        for (element, terms) in elements(block, symbols) {
+            for term in terms {
+                if let Some((kind, len, max)) = oversized(term, limits) {
+                    finding(
+                        Severity::Warning,
+                        Lint::OversizedTerm {
+                            kind,
+                            len,
+                            max,
+                            element: element.clone(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+/// name and number of terms of a predicate
+fn signature(predicate: &Predicate) -> (u64, usize) {
+    (predicate.name, predicate.ids.len())
+}
+
+fn used_predicates(blocks: &[&Block]) -> HashSet<(u64, usize)> {
+    let rules = blocks.iter().flat_map(|block| {
+        block
+            .rules
+            .iter()
+            .chain(block.checks.iter().flat_map(|check| check.queries.iter()))
+    });
+    rules
+        .flat_map(|rule| rule.body.iter().map(signature))
+        .collect()
+}
+
+/// a query can match if none of its expressions without variables
+/// evaluates to something other than `true`
+fn satisfiable(check: &Check) -> bool {
+    let no_values = HashMap::new();
+    check.queries.iter().any(|query| {
+        query.expressions.iter().all(|expression| {
+            let constant = expression
+                .ops
+                .iter()
+                .all(|op| !matches!(op, Op::Value(ID::Variable(_))));
+            !constant || expression.evaluate(&no_values) == Some(ID::Bool(true))
+        })
+    })
+}
+
+/// facts, rules and checks of the block, printed, with their terms
+fn elements<'a>(block: &'a Block, symbols: &SymbolTable) -> Vec<(String, Vec<&'a ID>)> {
+    let mut elements = Vec::new();
+    for fact in block.facts.iter() {
+        elements.push((
+            symbols.print_fact(fact),
+            fact.predicate.ids.iter().collect(),
+        ));
+    }
+
+    let rules = block
+        .rules
+        .iter()
+        .map(|rule| (symbols.print_rule(rule), vec![rule]));
+    let checks = block.checks.iter().map(|check| {
+        (
+            symbols.print_check(check),
+            check.queries.iter().collect::<Vec<_>>(),
+        )
+    });
+    for (printed, rules) in rules.chain(checks) {
+        let terms = rules
+            .into_iter()
+            .flat_map(|rule| {
+                std::iter::once(&rule.head)
+                    .chain(rule.body.iter())
+                    .flat_map(|predicate| predicate.ids.iter())
+                    .chain(rule.expressions.iter().flat_map(|e| {
+                        e.ops.iter().filter_map(|op| match op {
+                            Op::Value(id) => Some(id),
+                            _ => None,
+                        })
+                    }))
+            })
+            .collect();
+        elements.push((printed, terms));
+    }
+    elements
+}
+
+fn oversized(term: &ID, limits: &TermLimits) -> Option<(&'static str, usize, usize)> {
+    let (kind, len, max) = match term {
+        ID::Str(s) => ("string", s.len(), limits.max_string_len),
+        ID::Bytes(b) => ("bytes", b.len(), limits.max_bytes_len),
+        ID::Set(set) => ("set", set.len(), limits.max_set_len),
+        _ => return None,
+    };
+    if len > max {
+        Some((kind, len, max))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    #[test]
+    fn findings() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder
+            .add_authority_fact("user(#authority, \"alice\")")
+            .unwrap();
+        builder
+            .add_authority_check("check if resource(#ambient, $0), right(#authority, $0, #read)")
+            .unwrap();
+        builder
+            .add_authority_check("check if operation(#ambient, #read), 1 > 2")
+            .unwrap();
+        let token = builder.build().unwrap();
+
+        let long = "a".repeat(2000);
+        let token = token
+            .append_source(
+                &KeyPair::new(),
+                &format!(
+                    "note(\"{}\");\n\
+                     check if resource(#ambient, $0), right(#authority, $0, #read);\n\
+                     check if time(#ambient, $0), $0 < 2030-01-01T00:00:00Z",
+                    long
+                ),
+            )
+            .unwrap();
+
+        let findings = lint(&token).unwrap();
+        let lints = findings
+            .iter()
+            .map(|f| (f.severity, f.block_id, &f.lint))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lints,
+            vec![
+                (
+                    Severity::Error,
+                    0,
+                    &Lint::UnsatisfiableCheck {
+                        check_id: 1,
+                        check: "check if operation(#ambient, #read), 1 > 2".to_string(),
+                    }
+                ),
+                (
+                    Severity::Info,
+                    0,
+                    &Lint::UnusedFact {
+                        fact_id: 1,
+                        fact: "user(#authority, \"alice\")".to_string(),
+                    }
+                ),
+                (
+                    Severity::Warning,
+                    1,
+                    &Lint::DuplicateCheck {
+                        check_id: 0,
+                        check: "check if resource(#ambient, $0), right(#authority, $0, #read)"
+                            .to_string(),
+                        first_block_id: 0,
+                        first_check_id: 0,
+                    }
+                ),
+                (
+                    Severity::Warning,
+                    1,
+                    &Lint::UnusedFact {
+                        fact_id: 0,
+                        fact: format!("note(\"{}\")", long),
+                    }
+                ),
+                (
+                    Severity::Warning,
+                    1,
+                    &Lint::OversizedTerm {
+                        kind: "string",
+                        len: 2000,
+                        max: 1024,
+                        element: format!("note(\"{}\")", long),
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "error: block 0: check 1 can never pass: check if operation(#ambient, #read), 1 > 2"
+        );
+
+        let limits = TermLimits {
+            max_string_len: 4096,
+            ..TERM_LIMITS
+        };
+        assert_eq!(lint_with_limits(&token, &limits).unwrap().len(), 4);
+    }
+}
@@ -1,25 +1,42 @@
 //! main structures to interact with Biscuit tokens
 use super::crypto::{KeyPair, PublicKey};
-use super::datalog::{Check, Fact, Rule, SymbolTable, World, ID};
+use super::datalog::{Binary, Check, Fact, Op, Predicate, Rule, SymbolTable, World, ID};
 use super::error;
-use super::format::SerializedBiscuit;
+use super::format::{SerializedBiscuit, TermLimits};
 use builder::{BiscuitBuilder, BlockBuilder};
-use prost::Message;
+use once_cell::sync::OnceCell;
 use rand_core::{CryptoRng, RngCore};
 #[cfg(test)]
 use std::collections::HashMap;
 use std::collections::HashSet;
 
-use crate::format::{convert::proto_block_to_token_block, schema};
+use crate::format::borrowed;
 use verifier::Verifier;
 
+pub mod binding;
 pub mod builder;
+pub mod cache;
+pub mod capability;
+pub mod delegation;
+pub mod lint;
+pub mod pool;
+pub mod possession;
+pub mod rbac;
+pub mod revocation;
 pub mod sealed;
+pub mod stdlib;
+pub mod template;
+pub mod tenant;
+pub mod unverified;
 pub mod verifier;
+pub mod view;
 
 /// maximum supported version of the serialization format
 pub const MAX_SCHEMA_VERSION: u32 = 1;
 
+/// size in bytes of the random nonce added to each block
+pub const NONCE_SIZE: usize = 16;
+
 /// some symbols are predefined and available in every implementation, to avoid
 /// transmitting them with every token
 pub fn default_symbol_table() -> SymbolTable {
@@ -40,6 +57,11 @@ pub fn default_symbol_table() -> SymbolTable {
 /// It contains multiple `Block` elements, the associated symbol table,
 /// and a serialized version of this data
 ///
+/// when a token is deserialized, only the signatures are checked: the blocks
+/// are decoded the first time their content is needed, like when creating a
+/// [`Verifier`], so [`Biscuit::revocation_identifiers`] or
+/// [`Biscuit::check_root_key`] do not pay for parsing the Datalog content
+///
 /// ```rust
 /// extern crate biscuit_auth as biscuit;
 ///
@@ -69,10 +91,62 @@ pub fn default_symbol_table() -> SymbolTable {
 /// ```
 #[derive(Clone, Debug)]
 pub struct Biscuit {
-    pub(crate) authority: Block,
-    pub(crate) blocks: Vec<Block>,
-    pub(crate) symbols: SymbolTable,
+    pub(crate) authority: LazyBlock,
+    pub(crate) blocks: Vec<LazyBlock>,
+    /// symbol table the token was deserialized with, before adding the
+    /// symbols of its blocks
+    base_symbols: SymbolTable,
+    symbols: OnceCell<SymbolTable>,
     container: Option<SerializedBiscuit>,
+    /// computed when the token is created or deserialized
+    revocation_ids: RevocationIds,
+    /// maximum number of blocks, authority block included, set by
+    /// [`DecodeOptions::max_blocks`] and checked by [`Biscuit::append`]
+    max_blocks: Option<u32>,
+}
+
+/// options shared by the constructors deserializing a token
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, DecodeOptions}};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap().to_vec().unwrap();
+///
+/// let options = DecodeOptions::new().max_blocks(10);
+/// let biscuit = Biscuit::from_with_options(&token, options).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct DecodeOptions<'a> {
+    /// limits on the size of the terms, checked when a block is decoded
+    pub term_limits: TermLimits,
+    /// maximum number of blocks, authority block included, checked before
+    /// the signatures and when appending a block. No limit by default
+    pub max_blocks: Option<u32>,
+    /// cache of signature verification results
+    pub signature_cache: Option<&'a cache::SignatureCache>,
+}
+
+impl<'a> DecodeOptions<'a> {
+    pub fn new() -> Self {
+        DecodeOptions::default()
+    }
+
+    pub fn term_limits(mut self, term_limits: TermLimits) -> Self {
+        self.term_limits = term_limits;
+        self
+    }
+
+    pub fn max_blocks(mut self, max_blocks: u32) -> Self {
+        self.max_blocks = Some(max_blocks);
+        self
+    }
+
+    pub fn signature_cache(mut self, cache: &'a cache::SignatureCache) -> Self {
+        self.signature_cache = Some(cache);
+        self
+    }
 }
 
 impl Biscuit {
@@ -98,10 +172,10 @@ impl Biscuit {
         rng: &mut T,
         root: &KeyPair,
         mut symbols: SymbolTable,
-        authority: Block,
+        mut authority: Block,
     ) -> Result<Biscuit, error::Token> {
-        let h1 = symbols.symbols.iter().collect::<HashSet<_>>();
-        let h2 = authority.symbols.symbols.iter().collect::<HashSet<_>>();
+        let h1 = symbols.symbols().iter().collect::<HashSet<_>>();
+        let h2 = authority.symbols.symbols().iter().collect::<HashSet<_>>();
 
         if !h1.is_disjoint(&h2) {
             return Err(error::Token::SymbolTableOverlap);
@@ -111,20 +185,24 @@ impl Biscuit {
             return Err(error::Token::InvalidAuthorityIndex(authority.index));
         }
 
-        symbols
-            .symbols
-            .extend(authority.symbols.symbols.iter().cloned());
+        authority.generate_nonce(rng);
+        let base_symbols = symbols.clone();
+        symbols.extend(authority.symbols.symbols().iter().cloned());
 
         let blocks = vec![];
 
         let container =
             SerializedBiscuit::new(rng, root, &authority).map_err(error::Token::Format)?;
+        let authority = LazyBlock::decoded(container.authority.clone(), authority);
 
         Ok(Biscuit {
             authority,
             blocks,
-            symbols,
+            base_symbols,
+            symbols: OnceCell::from(symbols),
+            revocation_ids: RevocationIds::new(&container),
             container: Some(container),
+            max_blocks: None,
         })
     }
 
@@ -134,64 +212,91 @@ impl Biscuit {
     }
 
     /// deserializes a token and validates the signature using the root public key, with a custom symbol table
-    pub fn from_with_symbols(slice: &[u8], mut symbols: SymbolTable) -> Result<Self, error::Token> {
-        let container = SerializedBiscuit::from_slice(slice).map_err(error::Token::Format)?;
-
-        let authority: Block = schema::Block::decode(&container.authority[..])
-            .map_err(|e| {
-                error::Token::Format(error::Format::BlockDeserializationError(format!(
-                    "error deserializing authority block: {:?}",
-                    e
-                )))
-            })
-            .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(size = slice.len()), err)
+    )]
+    pub fn from_with_symbols(slice: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
+        Biscuit::from_with_symbols_and_options(slice, symbols, DecodeOptions::default())
+    }
 
-        if authority.index != 0 {
-            return Err(error::Token::InvalidAuthorityIndex(authority.index));
-        }
+    /// deserializes a token and validates the signature using the root public key,
+    /// with custom limits on the size of its terms
+    ///
+    /// the blocks are decoded, and the limits checked, the first time their
+    /// content is accessed: the error is returned by the methods reading the
+    /// blocks, like [`Biscuit::verify`]
+    pub fn from_with_term_limits(slice: &[u8], limits: TermLimits) -> Result<Self, error::Token> {
+        Biscuit::from_with_options(slice, DecodeOptions::new().term_limits(limits))
+    }
 
-        let mut blocks = vec![];
+    /// deserializes a token and validates the signature using the root public key,
+    /// refusing it if it has more than `max_blocks` blocks, authority block
+    /// included
+    ///
+    /// the number of blocks is checked before the signatures, and
+    /// [`Biscuit::append`] refuses to go over the limit
+    pub fn from_with_max_blocks(slice: &[u8], max_blocks: u32) -> Result<Self, error::Token> {
+        Biscuit::from_with_options(slice, DecodeOptions::new().max_blocks(max_blocks))
+    }
 
-        let mut index = 1;
-        for block in container.blocks.iter() {
-            let deser: Block = schema::Block::decode(&block[..])
-                .map_err(|e| {
-                    error::Token::Format(error::Format::BlockDeserializationError(format!(
-                        "error deserializing block: {:?}",
-                        e
-                    )))
-                })
-                .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+    /// deserializes a token, skipping the signature verification if the same
+    /// token was verified recently
+    ///
+    /// see [`cache::SignatureCache`]
+    pub fn from_with_cache(
+        slice: &[u8],
+        cache: &cache::SignatureCache,
+    ) -> Result<Self, error::Token> {
+        Biscuit::from_with_options(slice, DecodeOptions::new().signature_cache(cache))
+    }
 
-            if deser.index != index {
-                return Err(error::Token::InvalidBlockIndex(error::InvalidBlockIndex {
-                    expected: index,
-                    found: deser.index,
-                }));
-            }
-            blocks.push(deser);
+    /// deserializes a token and validates the signature using the root public key,
+    /// with the limits and cache set in `options`
+    pub fn from_with_options(slice: &[u8], options: DecodeOptions) -> Result<Self, error::Token> {
+        Biscuit::from_with_symbols_and_options(slice, default_symbol_table(), options)
+    }
 
-            index += 1;
+    fn from_with_symbols_and_options(
+        slice: &[u8],
+        symbols: SymbolTable,
+        options: DecodeOptions,
+    ) -> Result<Self, error::Token> {
+        let container = match options.signature_cache {
+            Some(cache) => {
+                SerializedBiscuit::from_slice_with_cache(slice, cache, options.max_blocks)
+            }
+            None => SerializedBiscuit::from_slice_with_max_blocks(slice, options.max_blocks),
         }
+        .map_err(error::Token::Format)?;
 
-        symbols
-            .symbols
-            .extend(authority.symbols.symbols.iter().cloned());
-
-        for block in blocks.iter() {
-            symbols
-                .symbols
-                .extend(block.symbols.symbols.iter().cloned());
-        }
+        let mut biscuit = Biscuit::from_container(container, symbols, options.term_limits);
+        biscuit.max_blocks = options.max_blocks;
+        Ok(biscuit)
+    }
 
-        let container = Some(container);
+    fn from_container(
+        container: SerializedBiscuit,
+        symbols: SymbolTable,
+        limits: TermLimits,
+    ) -> Self {
+        let authority = LazyBlock::new(0, container.authority.clone(), limits);
+        let blocks = container
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| LazyBlock::new(1 + i as u32, block.clone(), limits))
+            .collect();
 
-        Ok(Biscuit {
+        Biscuit {
             authority,
             blocks,
-            symbols,
-            container,
-        })
+            base_symbols: symbols,
+            symbols: OnceCell::new(),
+            revocation_ids: RevocationIds::new(&container),
+            container: Some(container),
+            max_blocks: None,
+        }
     }
 
     /// deserializes a token and validates the signature using the root public key
@@ -208,6 +313,16 @@ impl Biscuit {
         Biscuit::from_with_symbols(&decoded, symbols)
     }
 
+    /// deserializes a token and validates the signature using the root public key,
+    /// with the limits and cache set in `options`
+    pub fn from_base64_with_options<T: AsRef<[u8]>>(
+        slice: T,
+        options: DecodeOptions,
+    ) -> Result<Self, error::Token> {
+        let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
+        Biscuit::from_with_options(&decoded, options)
+    }
+
     /// deserializes a sealed token and checks its signature with the secret, using a custom symbol table
     pub fn from_sealed(slice: &[u8], secret: &[u8]) -> Result<Self, error::Token> {
         Biscuit::from_sealed_with_symbols(slice, secret, default_symbol_table())
@@ -217,65 +332,74 @@ impl Biscuit {
     pub fn from_sealed_with_symbols(
         slice: &[u8],
         secret: &[u8],
-        mut symbols: SymbolTable,
+        symbols: SymbolTable,
     ) -> Result<Self, error::Token> {
         let container =
             sealed::SealedBiscuit::from_slice(slice, secret).map_err(error::Token::Format)?;
 
-        let authority: Block = schema::Block::decode(&container.authority[..])
-            .map_err(|e| {
-                error::Token::Format(error::Format::BlockDeserializationError(format!(
-                    "error deserializing authority block: {:?}",
-                    e
-                )))
-            })
-            .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
-
-        if authority.index != 0 {
-            return Err(error::Token::InvalidAuthorityIndex(authority.index));
-        }
+        Ok(Biscuit::from_sealed_container(container, symbols))
+    }
 
-        let mut blocks = vec![];
+    /// deserializes a sealed token and checks its signature with the secret
+    /// matching the key id it was sealed with
+    ///
+    /// tokens sealed without a key id, or with a key id absent from the set,
+    /// are refused with [`error::Format::UnknownSealKey`]
+    pub fn from_sealed_with_keys(
+        slice: &[u8],
+        keys: &sealed::SealKeys,
+    ) -> Result<Self, error::Token> {
+        let container = sealed::SealedBiscuit::from_slice_with_keys(slice, keys)
+            .map_err(error::Token::Format)?;
 
-        let mut index = 1;
-        for block in container.blocks.iter() {
-            let deser: Block = schema::Block::decode(&block[..])
-                .map_err(|e| {
-                    error::Token::Format(error::Format::BlockDeserializationError(format!(
-                        "error deserializing block: {:?}",
-                        e
-                    )))
-                })
-                .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+        Ok(Biscuit::from_sealed_container(
+            container,
+            default_symbol_table(),
+        ))
+    }
 
-            if deser.index != index {
-                return Err(error::Token::InvalidBlockIndex(error::InvalidBlockIndex {
-                    expected: index,
-                    found: deser.index,
-                }));
-            }
-            blocks.push(deser);
+    fn from_sealed_container(container: sealed::SealedBiscuit, symbols: SymbolTable) -> Self {
+        let limits = TermLimits::default();
+        let authority = LazyBlock::new(0, container.authority, limits);
+        let blocks = container
+            .blocks
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| LazyBlock::new(1 + i as u32, block, limits))
+            .collect();
 
-            index += 1;
+        Biscuit {
+            authority,
+            blocks,
+            base_symbols: symbols,
+            symbols: OnceCell::new(),
+            container: None,
+            revocation_ids: RevocationIds::default(),
+            max_blocks: None,
         }
+    }
 
-        symbols
-            .symbols
-            .extend(authority.symbols.symbols.iter().cloned());
-
-        for block in blocks.iter() {
-            symbols
-                .symbols
-                .extend(block.symbols.symbols.iter().cloned());
-        }
+    /// decodes the authority block, if it was not done already
+    pub(crate) fn authority(&self) -> Result<&Block, error::Token> {
+        self.authority.get()
+    }
 
-        let container = None;
+    /// decodes the blocks following the authority block, if it was not done already
+    pub(crate) fn blocks(&self) -> Result<Vec<&Block>, error::Token> {
+        self.blocks.iter().map(LazyBlock::get).collect()
+    }
 
-        Ok(Biscuit {
-            authority,
-            blocks,
-            symbols,
-            container,
+    /// symbol table of the token, including the symbols defined by its blocks
+    ///
+    /// this decodes all the blocks
+    pub(crate) fn symbols(&self) -> Result<&SymbolTable, error::Token> {
+        self.symbols.get_or_try_init(|| {
+            let mut symbols = self.base_symbols.clone();
+            symbols.extend(self.authority()?.symbols.symbols().iter().cloned());
+            for block in self.blocks()? {
+                symbols.extend(block.symbols.symbols().iter().cloned());
+            }
+            Ok(symbols)
         })
     }
 
@@ -320,6 +444,14 @@ impl Biscuit {
         sealed.to_vec().map_err(error::Token::Format)
     }
 
+    /// serializes a sealed version of the token, recording the key id of
+    /// the secret so it can be checked with [`Biscuit::from_sealed_with_keys`]
+    pub fn seal_with_key_id(&self, key_id: u32, secret: &[u8]) -> Result<Vec<u8>, error::Token> {
+        let sealed = sealed::SealedBiscuit::from_token_with_key_id(self, key_id, secret)
+            .map_err(error::Token::Format)?;
+        sealed.to_vec().map_err(error::Token::Format)
+    }
+
     /// returns the internal representation of the token
     pub fn container(&self) -> Option<&SerializedBiscuit> {
         self.container.as_ref()
@@ -336,10 +468,115 @@ impl Biscuit {
 
     /// creates a verifier from this token
     ///
-    /// this will also call [`Biscuit::check_root_key`]
+    /// this will also call [`Biscuit::check_root_key`]. If the token was
+    /// issued by a key delegated from `root`, the verifier gets a check on
+    /// the expiration of the [`Delegation`](delegation::Delegation)
     pub fn verify(&self, root: PublicKey) -> Result<Verifier, error::Token> {
         self.check_root_key(root)?;
-        Verifier::from_token(self).map_err(error::Token::FailedLogic)
+        let mut verifier = Verifier::from_token(self)?;
+        verifier.add_delegation_check(self, root);
+        Ok(verifier)
+    }
+
+    /// certificate delegating a root key to the key of the authority block
+    pub fn delegation(&self) -> Option<&delegation::Delegation> {
+        self.container.as_ref()?.delegation.as_ref()
+    }
+
+    fn with_delegation(mut self, delegation: delegation::Delegation) -> Result<Self, error::Token> {
+        let container = self.container.as_mut().ok_or(error::Token::Sealed)?;
+        if container.keys.first() != Some(&delegation.key) {
+            return Err(error::Format::InvalidDelegation.into());
+        }
+        delegation.verify()?;
+
+        container.delegation = Some(delegation);
+        Ok(self)
+    }
+
+    /// expiration of the delegation from `root`, if the token uses one
+    pub(crate) fn delegated_expiration(&self, root: PublicKey) -> Option<std::time::SystemTime> {
+        self.container.as_ref()?.delegated_expiration(root)
+    }
+
+    /// tests that at least `threshold` different keys of the set signed the
+    /// authority block, with the root key or with [`Biscuit::cosign`]
+    pub fn check_root_keys(
+        &self,
+        keys: &[PublicKey],
+        threshold: usize,
+    ) -> Result<(), error::Token> {
+        self.container
+            .as_ref()
+            .map(|c| {
+                c.check_root_keys(keys, threshold)
+                    .map_err(error::Token::Format)
+            })
+            .unwrap_or(Err(error::Token::Sealed))
+    }
+
+    /// creates a verifier from a token signed by several root keys
+    ///
+    /// this will also call [`Biscuit::check_root_keys`], so no single key of
+    /// the set can issue tokens when `threshold` is higher than 1
+    ///
+    /// ```rust
+    /// use biscuit_auth::{crypto::KeyPair, token::Biscuit};
+    ///
+    /// let root1 = KeyPair::new();
+    /// let root2 = KeyPair::new();
+    /// let root3 = KeyPair::new();
+    /// let keys = [root1.public(), root2.public(), root3.public()];
+    ///
+    /// let token = Biscuit::builder(&root1).build().unwrap();
+    /// assert!(token.verify_with_root_keys(&keys, 2).is_err());
+    ///
+    /// // the token is sent to the holder of another root key
+    /// let token = token.cosign(&root3).unwrap();
+    /// let token = Biscuit::from(&token.to_vec().unwrap()).unwrap();
+    /// assert!(token.verify_with_root_keys(&keys, 2).is_ok());
+    /// ```
+    pub fn verify_with_root_keys(
+        &self,
+        keys: &[PublicKey],
+        threshold: usize,
+    ) -> Result<Verifier, error::Token> {
+        self.check_root_keys(keys, threshold)?;
+        Verifier::from_token(self)
+    }
+
+    /// adds a signature of the authority block with another root key
+    ///
+    /// this does not change the content of the token, and can be done after
+    /// adding blocks
+    pub fn cosign(&self, root: &KeyPair) -> Result<Self, error::Token> {
+        self.cosign_with_rng(&mut rand::rngs::OsRng, root)
+    }
+
+    /// adds a signature of the authority block with another root key, using
+    /// the provided CSPRNG
+    pub fn cosign_with_rng<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        root: &KeyPair,
+    ) -> Result<Self, error::Token> {
+        let container = match self.container.as_ref() {
+            None => return Err(error::Token::Sealed),
+            Some(c) => c.cosign(rng, root),
+        };
+
+        let mut token = self.clone();
+        token.container = Some(container);
+        Ok(token)
+    }
+
+    /// keys that signed the authority block: the root key, then the keys
+    /// added with [`Biscuit::cosign`]. Sealed tokens do not keep the keys
+    pub fn root_keys(&self) -> Vec<PublicKey> {
+        self.container
+            .as_ref()
+            .map(SerializedBiscuit::root_keys)
+            .unwrap_or_default()
     }
 
     /// creates a verifier from this token
@@ -347,54 +584,51 @@ impl Biscuit {
         if self.container.is_some() {
             Err(error::Token::InternalError)
         } else {
-            Verifier::from_token(self).map_err(error::Token::FailedLogic)
+            Verifier::from_token(self)
         }
     }
 
-    pub(crate) fn generate_world(&self, symbols: &mut SymbolTable) -> Result<World, error::Logic> {
+    pub(crate) fn generate_world(&self, symbols: &mut SymbolTable) -> Result<World, error::Token> {
         let mut world = World::new();
 
         let authority_index = symbols.get("authority").unwrap();
         let ambient_index = symbols.get("ambient").unwrap();
 
-        for fact in self.authority.facts.iter().cloned() {
+        let authority = self.authority()?;
+        for fact in authority.facts.iter().cloned() {
             if fact.predicate.ids[0] == ID::Symbol(ambient_index) {
-                return Err(error::Logic::InvalidAuthorityFact(
-                    symbols.print_fact(&fact),
-                ));
+                return Err(error::Logic::InvalidAuthorityFact(symbols.print_fact(&fact)).into());
             }
 
             world.facts.insert(fact);
         }
 
-        let mut revocation_ids = self.revocation_identifiers();
         let revocation_id_sym = symbols.get("revocation_id").unwrap();
-        for (i, id) in revocation_ids.drain(..).enumerate() {
+        for (i, id) in self.revocation_ids().iter().enumerate() {
             world.facts.insert(Fact::new(
                 revocation_id_sym,
-                &[ID::Integer(i as i64), ID::Bytes(id)],
+                &[ID::Integer(i as i64), ID::Bytes(id.clone())],
             ));
         }
 
-        let mut unique_revocation_ids = self.unique_revocation_identifiers();
         let unique_revocation_id_sym = symbols.insert("unique_revocation_id");
-        for (i, id) in unique_revocation_ids.drain(..).enumerate() {
+        for (i, id) in self.unique_revocation_ids().iter().enumerate() {
             world.facts.insert(Fact::new(
                 unique_revocation_id_sym,
-                &[ID::Integer(i as i64), ID::Bytes(id)],
+                &[ID::Integer(i as i64), ID::Bytes(id.clone())],
             ));
         }
 
-        for rule in self.authority.rules.iter().cloned() {
+        for rule in authority.rules.iter().cloned() {
             if let Err(_message) = builder::Rule::convert_from(&rule, symbols).validate_variables()
             {
-                return Err(error::Logic::InvalidBlockRule(0, symbols.print_rule(&rule)));
+                return Err(error::Logic::InvalidBlockRule(0, symbols.print_rule(&rule)).into());
             }
 
             world.privileged_rules.push(rule);
         }
 
-        for (i, block) in self.blocks.iter().enumerate() {
+        for (i, block) in self.blocks()?.into_iter().enumerate() {
             // blocks cannot provide authority or ambient facts
             for fact in block.facts.iter().cloned() {
                 if fact.predicate.ids[0] == ID::Symbol(authority_index)
@@ -403,7 +637,8 @@ impl Biscuit {
                     return Err(error::Logic::InvalidBlockFact(
                         i as u32,
                         symbols.print_fact(&fact),
-                    ));
+                    )
+                    .into());
                 }
 
                 world.facts.insert(fact);
@@ -417,7 +652,8 @@ impl Biscuit {
                     return Err(error::Logic::InvalidBlockRule(
                         i as u32,
                         symbols.print_rule(&rule),
-                    ));
+                    )
+                    .into());
                 }
 
                 if let Err(_message) =
@@ -426,7 +662,8 @@ impl Biscuit {
                     return Err(error::Logic::InvalidBlockRule(
                         i as u32,
                         symbols.print_rule(&rule),
-                    ));
+                    )
+                    .into());
                 }
 
                 world.rules.push(rule);
@@ -436,17 +673,17 @@ impl Biscuit {
         Ok(world)
     }
 
-    pub(crate) fn checks(&self) -> Vec<Vec<Check>> {
+    pub(crate) fn checks(&self) -> Result<Vec<Vec<Check>>, error::Token> {
         let mut result = Vec::new();
-        let v = self.authority.checks.to_vec();
+        let v = self.authority()?.checks.to_vec();
         result.push(v);
 
-        for block in self.blocks.iter() {
+        for block in self.blocks()? {
             let v = block.checks.to_vec();
             result.push(v);
         }
 
-        result
+        Ok(result)
     }
 
     /// checks the checks of a token, in the context of the request it comes with
@@ -475,9 +712,7 @@ impl Biscuit {
         queries: HashMap<String, Rule>,
     ) -> Result<HashMap<String, Vec<Fact>>, error::Token> {
         let mut symbols = symbols.clone();
-        let mut world = self
-            .generate_world(&mut symbols)
-            .map_err(error::Token::FailedLogic)?;
+        let mut world = self.generate_world(&mut symbols)?;
 
         for fact in ambient_facts.drain(..) {
             world.facts.insert(fact);
@@ -501,7 +736,7 @@ impl Biscuit {
         let mut errors = vec![];
 
         // authority checks provided by the authority block
-        for (i, check) in self.authority.checks.iter().enumerate() {
+        for (i, check) in self.authority()?.checks.iter().enumerate() {
             let mut successful = false;
 
             for query in check.queries.iter() {
@@ -517,6 +752,7 @@ impl Biscuit {
                     block_id: 0,
                     check_id: i as u32,
                     rule: symbols.print_check(check),
+                    span: None,
                 }));
             }
         }
@@ -537,11 +773,12 @@ impl Biscuit {
                 errors.push(error::FailedCheck::Verifier(error::FailedVerifierCheck {
                     check_id: i as u32,
                     rule: symbols.print_check(check),
+                    span: None,
                 }));
             }
         }
 
-        for (i, block) in self.blocks.iter().enumerate() {
+        for (i, block) in self.blocks()?.into_iter().enumerate() {
             for (j, check) in block.checks.iter().enumerate() {
                 let mut successful = false;
 
@@ -558,6 +795,7 @@ impl Biscuit {
                         block_id: i as u32,
                         check_id: j as u32,
                         rule: symbols.print_check(check),
+                        span: None,
                     }));
                 }
             }
@@ -607,6 +845,17 @@ impl Biscuit {
         self.append_with_rng(&mut rand::rngs::OsRng, keypair, block_builder)
     }
 
+    /// adds a new block to the token, from a Datalog block source
+    ///
+    /// the source contains facts, rules and checks separated by `;`, and
+    /// can set the block's context with `context "text";`, as accepted by
+    /// [`BlockBuilder::add_code`]
+    pub fn append_source(&self, keypair: &KeyPair, source: &str) -> Result<Self, error::Token> {
+        let mut block_builder = self.create_block();
+        block_builder.add_code(source)?;
+        self.append(keypair, block_builder)
+    }
+
     /// adds a new block to the token, using the provided CSPRNG
     ///
     /// since the public key is integrated into the token, the keypair can be
@@ -621,10 +870,12 @@ impl Biscuit {
             return Err(error::Token::Sealed);
         }
 
-        let block = block_builder.build(self.symbols.clone());
+        let symbols = self.symbols()?;
+        let mut block = block_builder.build(symbols.clone());
+        block.generate_nonce(rng);
 
-        let h1 = self.symbols.symbols.iter().collect::<HashSet<_>>();
-        let h2 = block.symbols.symbols.iter().collect::<HashSet<_>>();
+        let h1 = symbols.symbols().iter().collect::<HashSet<_>>();
+        let h2 = block.symbols.symbols().iter().collect::<HashSet<_>>();
 
         if !h1.is_disjoint(&h2) {
             return Err(error::Token::SymbolTableOverlap);
@@ -637,9 +888,14 @@ impl Biscuit {
             }));
         }
 
+        if let Some(maximum) = self.max_blocks {
+            crate::format::check_block_count(maximum, 2 + self.blocks.len())
+                .map_err(error::Token::Format)?;
+        }
+
         let authority = self.authority.clone();
         let mut blocks = self.blocks.clone();
-        let mut symbols = self.symbols.clone();
+        let mut symbols = symbols.clone();
 
         let container = match self.container.as_ref() {
             None => return Err(error::Token::Sealed),
@@ -648,16 +904,22 @@ impl Biscuit {
                 .map_err(error::Token::Format)?,
         };
 
-        symbols
-            .symbols
-            .extend(block.symbols.symbols.iter().cloned());
-        blocks.push(block);
+        symbols.extend(block.symbols.symbols().iter().cloned());
+        let data = container
+            .blocks
+            .last()
+            .cloned()
+            .ok_or(error::Token::InternalError)?;
+        blocks.push(LazyBlock::decoded(data, block));
 
         Ok(Biscuit {
             authority,
             blocks,
-            symbols,
+            base_symbols: self.base_symbols.clone(),
+            symbols: OnceCell::from(symbols),
+            revocation_ids: RevocationIds::new(&container),
             container: Some(container),
+            max_blocks: self.max_blocks,
         })
     }
 
@@ -665,43 +927,199 @@ impl Biscuit {
     ///
     /// the context is a free form text field in which application specific data
     /// can be stored
-    pub fn context(&self) -> Vec<Option<String>> {
-        let mut res = vec![self.authority.context.clone()];
+    ///
+    /// this decodes all the blocks
+    pub fn context(&self) -> Result<Vec<Option<String>>, error::Token> {
+        let mut res = vec![self.authority()?.context.clone()];
 
-        for b in self.blocks.iter() {
+        for b in self.blocks()? {
             res.push(b.context.clone());
         }
 
-        res
+        Ok(res)
     }
 
-    /// returns a list of revocation identifiers for each block, in order
+    /// returns the context of the block at this index, 0 being the authority
+    /// block
     ///
-    /// if a token is generated with the same keys and the same content,
-    /// those identifiers will stay the same
-    pub fn revocation_identifiers(&self) -> Vec<Vec<u8>> {
-        use sha2::{Digest, Sha256};
+    /// returns `Ok(None)` if the block has no context, or if there is no
+    /// block at this index. Only this block is decoded
+    ///
+    /// ```rust
+    /// use biscuit_auth::{crypto::KeyPair, token::Biscuit};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder(&root).build().unwrap();
+    /// let mut block = token.create_block();
+    /// block.set_context("issued by billing-svc v2".to_string());
+    /// let token = token.append(&KeyPair::new(), block).unwrap();
+    ///
+    /// assert_eq!(token.block_context(0).unwrap(), None);
+    /// assert_eq!(token.block_context(1).unwrap(), Some("issued by billing-svc v2"));
+    /// ```
+    pub fn block_context(&self, index: usize) -> Result<Option<&str>, error::Token> {
+        match self.lazy_block(index) {
+            Some(block) => Ok(block.get()?.context.as_deref()),
+            None => Ok(None),
+        }
+    }
 
-        let mut res = Vec::new();
-        let mut h = Sha256::new();
+    /// returns the earliest expiration date set by the checks of the token,
+    /// or `Ok(None)` if it does not expire
+    ///
+    /// the checks recognized are the ones generated by
+    /// [`BlockBuilder::expiration_date`] and [`BlockBuilder::valid_between`],
+    /// written as `check if time(#ambient, $date), $date <= 2021-01-01T00:00:00Z`
+    /// or with `<`. A check with several queries only sets an expiration if
+    /// all of its queries do. The expiration of the [`delegation`](Biscuit::delegation)
+    /// is included.
+    ///
+    /// this decodes all the blocks
+    ///
+    /// ```rust
+    /// use biscuit_auth::{crypto::KeyPair, token::Biscuit};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder(&root).build().unwrap();
+    /// assert_eq!(token.expiration_time().unwrap(), None);
+    ///
+    /// let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    /// let mut block = token.create_block();
+    /// block.expiration_date(expiration);
+    /// let token = token.append(&KeyPair::new(), block).unwrap();
+    /// assert_eq!(token.expiration_time().unwrap(), Some(expiration));
+    /// ```
+    pub fn expiration_time(&self) -> Result<Option<std::time::SystemTime>, error::Token> {
+        let symbols = self.symbols()?;
+        let mut expiration = match (symbols.get("time"), symbols.get("ambient")) {
+            (Some(time), Some(ambient)) => std::iter::once(self.authority()?)
+                .chain(self.blocks()?)
+                .filter_map(|block| block.expiration(time, ambient))
+                .min()
+                // a date that does not fit in a SystemTime is never reached
+                .and_then(|date| {
+                    std::time::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(date))
+                }),
+            _ => None,
+        };
 
-        if let Some(token) = self.container.as_ref() {
-            h.update(&token.authority);
-            h.update(&token.keys[0].to_bytes());
+        if let Some(delegation) = self.delegation() {
+            expiration = Some(expiration.map_or(delegation.expiration, |date| {
+                date.min(delegation.expiration)
+            }));
+        }
 
-            let h2 = h.clone();
-            res.push(h2.finalize().as_slice().into());
+        Ok(expiration)
+    }
 
-            for (i, block) in token.blocks.iter().enumerate() {
-                h.update(&block);
-                h.update(&token.keys[1 + i].to_bytes());
+    /// returns the content of the block at this index, 0 being the authority
+    /// block, or `Ok(None)` if there is no block at this index
+    ///
+    /// this decodes all the blocks, to resolve the symbols
+    pub fn block(&self, index: usize) -> Result<Option<view::BlockView>, error::Token> {
+        let block = match self.lazy_block(index) {
+            Some(block) => block.get()?,
+            None => return Ok(None),
+        };
+        let public_key = self
+            .container
+            .as_ref()
+            .and_then(|c| c.keys.get(index).cloned());
 
-                let h2 = h.clone();
-                res.push(h2.finalize().as_slice().into());
-            }
+        Ok(Some(view::BlockView::new(
+            index,
+            block,
+            self.symbols()?,
+            public_key,
+        )))
+    }
+
+    /// compares the blocks of two tokens
+    ///
+    /// the blocks are compared in order, by their serialized content and
+    /// their signing key. The blocks following the first difference are
+    /// reported as removed from this token and added in `other`, with their
+    /// content
+    ///
+    /// ```rust
+    /// use biscuit_auth::{crypto::KeyPair, token::Biscuit};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder(&root).build().unwrap();
+    /// let attenuated = token
+    ///     .append_source(&KeyPair::new(), "check if operation(#ambient, #read)")
+    ///     .unwrap();
+    ///
+    /// let diff = token.diff(&attenuated).unwrap();
+    /// assert_eq!(diff.common, 1);
+    /// assert!(diff.removed.is_empty());
+    /// assert_eq!(diff.added[0].checks[0].to_string(), "check if operation(#ambient, #read)");
+    /// println!("{}", diff);
+    /// ```
+    pub fn diff(&self, other: &Biscuit) -> Result<view::TokenDiff, error::Token> {
+        let key = |token: &Biscuit, index: usize| {
+            token
+                .container
+                .as_ref()
+                .and_then(|c| c.keys.get(index).cloned())
+        };
+        let common = (0..self.block_count().min(other.block_count()))
+            .take_while(|i| {
+                self.lazy_block(*i).map(|b| &b.data) == other.lazy_block(*i).map(|b| &b.data)
+                    && key(self, *i) == key(other, *i)
+            })
+            .count();
+
+        let views = |token: &Biscuit| -> Result<Vec<_>, error::Token> {
+            (common..token.block_count())
+                .filter_map(|i| token.block(i).transpose())
+                .collect()
+        };
+
+        Ok(view::TokenDiff {
+            common,
+            removed: views(self)?,
+            added: views(other)?,
+        })
+    }
+
+    /// returns `true` if this token was obtained by appending blocks to `other`
+    ///
+    /// the blocks of `other` must be a strict prefix of the blocks of this
+    /// token, with the same content, keys and signature parameters. This
+    /// does not verify the signatures, which is done when deserializing the
+    /// tokens. Sealed tokens are not attenuations of any token
+    pub fn is_attenuation_of(&self, other: &Biscuit) -> bool {
+        let (token, prefix) = match (self.container.as_ref(), other.container.as_ref()) {
+            (Some(token), Some(prefix)) => (token, prefix),
+            _ => return false,
+        };
+        if token.blocks.len() <= prefix.blocks.len() {
+            return false;
         }
 
-        res
+        token.authority == prefix.authority
+            && token.blocks[..prefix.blocks.len()] == prefix.blocks[..]
+            && token.keys[..prefix.keys.len()] == prefix.keys[..]
+            && token.signature.parameters[..prefix.signature.parameters.len()]
+                == prefix.signature.parameters[..]
+    }
+
+    fn lazy_block(&self, index: usize) -> Option<&LazyBlock> {
+        if index == 0 {
+            Some(&self.authority)
+        } else {
+            self.blocks.get(index - 1)
+        }
+    }
+
+    /// returns a list of revocation identifiers for each block, in order
+    ///
+    /// if a token is generated with the same keys and the same content,
+    /// those identifiers will stay the same
+    pub fn revocation_identifiers(&self) -> Vec<Vec<u8>> {
+        self.revocation_ids.ids.clone()
     }
 
     /// returns a list of unique revocation identifiers for each block, in order
@@ -709,77 +1127,68 @@ impl Biscuit {
     /// those identifiers will be different for every token even if they have the
     /// same content and use the same keys
     pub fn unique_revocation_identifiers(&self) -> Vec<Vec<u8>> {
-        use sha2::{Digest, Sha256};
-
-        let mut res = Vec::new();
-        let mut h = Sha256::new();
-
-        if let Some(token) = self.container.as_ref() {
-            h.update(&token.authority);
-            h.update(&token.keys[0].to_bytes());
-            h.update(&token.signature.parameters[0].compress().to_bytes());
-
-            let h2 = h.clone();
-            res.push(h2.finalize().as_slice().into());
-
-            for (i, block) in token.blocks.iter().enumerate() {
-                h.update(&block);
-                h.update(&token.keys[1 + i].to_bytes());
-                h.update(&token.signature.parameters[1 + i].compress().to_bytes());
+        self.revocation_ids.unique_ids.clone()
+    }
 
-                let h2 = h.clone();
-                res.push(h2.finalize().as_slice().into());
-            }
-        }
+    /// revocation identifiers of each block, like [`Biscuit::revocation_identifiers`]
+    ///
+    /// they are computed when the token is created or deserialized, so this
+    /// does not copy or hash anything. Sealed tokens have no revocation
+    /// identifiers
+    pub fn revocation_ids(&self) -> &[Vec<u8>] {
+        &self.revocation_ids.ids
+    }
 
-        res
+    /// unique revocation identifiers of each block, like
+    /// [`Biscuit::unique_revocation_identifiers`]
+    pub fn unique_revocation_ids(&self) -> &[Vec<u8>] {
+        &self.revocation_ids.unique_ids
     }
 
     /// pretty printer for this token
     pub fn print(&self) -> String {
-        let authority = print_block(&self.symbols, &self.authority);
-        let blocks: Vec<_> = self
-            .blocks
-            .iter()
-            .map(|b| print_block(&self.symbols, b))
-            .collect();
+        let decoded = self
+            .symbols()
+            .and_then(|symbols| Ok((symbols, self.authority()?, self.blocks()?)));
+        let (symbols, authority, blocks) = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => return format!("Biscuit {{ invalid block: {} }}", e),
+        };
+        let authority = print_block(symbols, authority);
+        let blocks: Vec<_> = blocks.iter().map(|b| print_block(symbols, b)).collect();
 
         format!(
             "Biscuit {{\n    symbols: {:?}\n    authority: {}\n    blocks: [\n        {}\n    ]\n}}",
-            self.symbols.symbols,
+            symbols.symbols(),
             authority,
             blocks.join(",\n\t")
         )
     }
 
     /// prints the content of a block as Datalog source code
+    ///
+    /// returns `None` if there is no block at this index, or if the
+    /// blocks cannot be decoded
     pub fn print_block_source(&self, index: usize) -> Option<String> {
-        let block = if index == 0 {
-            &self.authority
-        } else {
-            match self.blocks.get(index - 1) {
-                None => return None,
-                Some(block) => block,
-            }
-        };
+        let symbols = self.symbols().ok()?;
+        let block = self.lazy_block(index)?.get().ok()?;
 
-        let facts: Vec<_> = block
-            .facts
-            .iter()
-            .map(|f| self.symbols.print_fact(f))
-            .collect();
-        let rules: Vec<_> = block
-            .rules
-            .iter()
-            .map(|r| self.symbols.print_rule(r))
-            .collect();
+        let facts: Vec<_> = block.facts.iter().map(|f| symbols.print_fact(f)).collect();
+        let rules: Vec<_> = block.rules.iter().map(|r| symbols.print_rule(r)).collect();
         let checks: Vec<_> = block
             .checks
             .iter()
-            .map(|r| self.symbols.print_check(r))
+            .map(|r| symbols.print_check(r))
             .collect();
 
-        let mut res = facts.join(";\n");
+        let mut res = String::new();
+        if let Some(context) = &block.context {
+            res.push_str(&format!(
+                "context {};\n",
+                crate::datalog::quote_string(context)
+            ));
+        }
+        res.push_str(&facts.join(";\n"));
         if !facts.is_empty() {
             res.push_str(";\n");
         }
@@ -799,6 +1208,45 @@ impl Biscuit {
     pub fn block_count(&self) -> usize {
         1 + self.blocks.len()
     }
+
+    /// reports, for each block, how many symbols it defines and how many it
+    /// reuses from the default symbol table and the previous blocks
+    ///
+    /// blocks built with [`Biscuit::create_block`] only define the symbols
+    /// that are not present in the token yet, so a long attenuation chain
+    /// using the same names does not grow the symbol tables
+    ///
+    /// this decodes all the blocks
+    pub fn symbol_stats(&self) -> Result<Vec<BlockSymbolStats>, error::Token> {
+        let mut available = self.base_symbols.len() as u64;
+        let mut stats = Vec::new();
+
+        let mut blocks = vec![self.authority()?];
+        blocks.extend(self.blocks()?);
+        for block in blocks {
+            let referenced = block.referenced_symbols();
+            stats.push(BlockSymbolStats {
+                defined: block.symbols.len(),
+                defined_bytes: block.symbols.symbols().iter().map(|s| s.len()).sum(),
+                reused: referenced.iter().filter(|s| **s < available).count(),
+            });
+            available += block.symbols.len() as u64;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// symbols of a block, see [`Biscuit::symbol_stats`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockSymbolStats {
+    /// number of symbols added to the symbol table by this block
+    pub defined: usize,
+    /// total length of the symbols added by this block
+    pub defined_bytes: usize,
+    /// number of distinct symbols used by this block that were defined
+    /// before it
+    pub reused: usize,
 }
 
 fn print_block(symbols: &SymbolTable, block: &Block) -> String {
@@ -838,7 +1286,7 @@ fn print_block(symbols: &SymbolTable, block: &Block) -> String {
     format!(
         "Block[{}] {{\n            symbols: {:?}\n            version: {}\n            context: \"{}\"\n            facts: [{}]\n            rules: [{}]\n            checks: [{}]\n        }}",
         block.index,
-        block.symbols.symbols,
+        block.symbols.symbols(),
         block.version,
         block.context.as_deref().unwrap_or(""),
         facts,
@@ -847,39 +1295,130 @@ fn print_block(symbols: &SymbolTable, block: &Block) -> String {
     )
 }
 
-/// a block contained in a token
-#[derive(Clone, Debug)]
-pub struct Block {
-    /// position of the block
-    pub index: u32,
-    /// list of symbols introduced by this block
-    pub symbols: SymbolTable,
-    /// list of facts provided by this block
-    pub facts: Vec<Fact>,
-    /// list of rules provided by this block
-    pub rules: Vec<Rule>,
-    /// checks that the token and ambient data must validate
-    pub checks: Vec<Check>,
-    /// contextual information that can be looked up before the verification
-    /// (as an example, a user id to query rights into a database)
-    pub context: Option<String>,
-    /// format version used to generate this block
-    pub version: u32,
+/// revocation identifiers of all the blocks of a token
+#[derive(Clone, Debug, Default)]
+struct RevocationIds {
+    ids: Vec<Vec<u8>>,
+    unique_ids: Vec<Vec<u8>>,
 }
 
-impl Block {
-    /// creates a new block
-    ///
-    /// blocks should be created through the BlockBuilder interface instead, to avoid mistakes
-    pub fn new(index: u32, base_symbols: SymbolTable) -> Block {
-        Block {
-            index,
-            symbols: base_symbols,
-            facts: vec![],
-            rules: vec![],
+impl RevocationIds {
+    fn new(token: &SerializedBiscuit) -> Self {
+        use sha2::{Digest, Sha256};
+
+        let mut ids = Vec::new();
+        let mut unique_ids = Vec::new();
+        let mut h = Sha256::new();
+        let mut unique = Sha256::new();
+
+        let blocks = std::iter::once(&token.authority).chain(token.blocks.iter());
+        for (i, block) in blocks.enumerate() {
+            let key = token.keys[i].to_bytes();
+
+            h.update(block);
+            h.update(key);
+            ids.push(h.clone().finalize().as_slice().into());
+
+            unique.update(block);
+            unique.update(key);
+            unique.update(token.signature.parameters[i].compress().to_bytes());
+            unique_ids.push(unique.clone().finalize().as_slice().into());
+        }
+
+        RevocationIds { ids, unique_ids }
+    }
+}
+
+/// serialized block, decoded on first access
+#[derive(Clone, Debug)]
+pub(crate) struct LazyBlock {
+    /// position the block must have in the token
+    index: u32,
+    pub(crate) data: Vec<u8>,
+    limits: TermLimits,
+    block: OnceCell<Block>,
+}
+
+impl LazyBlock {
+    pub(crate) fn new(index: u32, data: Vec<u8>, limits: TermLimits) -> Self {
+        LazyBlock {
+            index,
+            data,
+            limits,
+            block: OnceCell::new(),
+        }
+    }
+
+    /// a block that was just serialized to `data`
+    pub(crate) fn decoded(data: Vec<u8>, block: Block) -> Self {
+        LazyBlock {
+            index: block.index,
+            data,
+            limits: TermLimits::default(),
+            block: OnceCell::from(block),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Result<&Block, error::Token> {
+        self.block.get_or_try_init(|| {
+            let block =
+                borrowed::decode_block(&self.data, &self.limits).map_err(error::Token::Format)?;
+
+            if block.index != self.index {
+                return Err(if self.index == 0 {
+                    error::Token::InvalidAuthorityIndex(block.index)
+                } else {
+                    error::Token::InvalidBlockIndex(error::InvalidBlockIndex {
+                        expected: self.index,
+                        found: block.index,
+                    })
+                });
+            }
+
+            Ok(block)
+        })
+    }
+}
+
+/// a block contained in a token
+#[derive(Clone, Debug)]
+pub struct Block {
+    /// position of the block
+    pub index: u32,
+    /// list of symbols introduced by this block
+    pub symbols: SymbolTable,
+    /// list of facts provided by this block
+    pub facts: Vec<Fact>,
+    /// list of rules provided by this block
+    pub rules: Vec<Rule>,
+    /// checks that the token and ambient data must validate
+    pub checks: Vec<Check>,
+    /// contextual information that can be looked up before the verification
+    /// (as an example, a user id to query rights into a database)
+    pub context: Option<String>,
+    /// format version used to generate this block
+    pub version: u32,
+    /// random bytes making the serialized block, and so its revocation
+    /// identifier, different from any other block with the same content
+    ///
+    /// blocks without a nonce get one when they are added to a token
+    pub nonce: Option<Vec<u8>>,
+}
+
+impl Block {
+    /// creates a new block
+    ///
+    /// blocks should be created through the BlockBuilder interface instead, to avoid mistakes
+    pub fn new(index: u32, base_symbols: SymbolTable) -> Block {
+        Block {
+            index,
+            symbols: base_symbols,
+            facts: vec![],
+            rules: vec![],
             checks: vec![],
             context: None,
             version: MAX_SCHEMA_VERSION,
+            nonce: None,
         }
     }
 
@@ -890,11 +1429,107 @@ impl Block {
     pub fn symbol_insert(&mut self, s: &str) -> u64 {
         self.symbols.insert(s)
     }
+
+    /// sets a random nonce if the block has none
+    fn generate_nonce<R: RngCore + CryptoRng>(&mut self, rng: &mut R) {
+        if self.nonce.is_none() {
+            let mut nonce = vec![0u8; NONCE_SIZE];
+            rng.fill_bytes(&mut nonce);
+            self.nonce = Some(nonce);
+        }
+    }
+
+    /// earliest date, in seconds, after which one of the checks of the block
+    /// fails on the ambient `time` fact
+    fn expiration(&self, time: u64, ambient: u64) -> Option<u64> {
+        // date bound of an expression like `$date <= 2021-01-01T00:00:00Z`
+        fn bound(ops: &[Op], variable: u32) -> Option<u64> {
+            match ops {
+                [Op::Value(ID::Variable(v)), Op::Value(ID::Date(date)), Op::Binary(Binary::LessOrEqual | Binary::LessThan)]
+                | [Op::Value(ID::Date(date)), Op::Value(ID::Variable(v)), Op::Binary(Binary::GreaterOrEqual | Binary::GreaterThan)]
+                    if *v == variable =>
+                {
+                    Some(*date)
+                }
+                _ => None,
+            }
+        }
+
+        fn query(r: &Rule, time: u64, ambient: u64) -> Option<u64> {
+            r.body
+                .iter()
+                .filter(|p| p.name == time && p.ids.first() == Some(&ID::Symbol(ambient)))
+                .filter_map(|p| match p.ids.get(1) {
+                    Some(ID::Variable(v)) => Some(*v),
+                    _ => None,
+                })
+                .flat_map(|v| r.expressions.iter().filter_map(move |e| bound(&e.ops, v)))
+                .min()
+        }
+
+        self.checks
+            .iter()
+            .filter_map(|c| {
+                // every query must be bounded, otherwise the check can
+                // still succeed after the dates
+                c.queries
+                    .iter()
+                    .map(|r| query(r, time, ambient))
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .max()
+            })
+            .min()
+    }
+
+    /// symbols used as predicate names, symbol terms or variable names
+    fn referenced_symbols(&self) -> HashSet<u64> {
+        fn id(i: &ID, symbols: &mut HashSet<u64>) {
+            match i {
+                ID::Symbol(s) => {
+                    symbols.insert(*s);
+                }
+                ID::Variable(v) => {
+                    symbols.insert(u64::from(*v));
+                }
+                ID::Set(set) => set.iter().for_each(|i| id(i, symbols)),
+                _ => {}
+            }
+        }
+
+        fn predicate(p: &Predicate, symbols: &mut HashSet<u64>) {
+            symbols.insert(p.name);
+            p.ids.iter().for_each(|i| id(i, symbols));
+        }
+
+        fn rule(r: &Rule, symbols: &mut HashSet<u64>) {
+            predicate(&r.head, symbols);
+            r.body.iter().for_each(|p| predicate(p, symbols));
+            for expression in r.expressions.iter() {
+                for op in expression.ops.iter() {
+                    if let Op::Value(i) = op {
+                        id(i, symbols);
+                    }
+                }
+            }
+        }
+
+        let mut symbols = HashSet::new();
+        self.facts
+            .iter()
+            .for_each(|f| predicate(&f.predicate, &mut symbols));
+        self.rules.iter().for_each(|r| rule(r, &mut symbols));
+        self.checks
+            .iter()
+            .flat_map(|c| c.queries.iter())
+            .for_each(|r| rule(r, &mut symbols));
+        symbols
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::builder::{check, fact, int, pred, rule, s, var};
+    use super::builder::{check, fact, int, pred, rule, s, string, var};
     use super::*;
     use crate::crypto::KeyPair;
     use crate::error::*;
@@ -1020,7 +1655,7 @@ mod tests {
         final_token.check_root_key(root.public()).unwrap();
         println!("final token:\n{}", final_token.print());
         {
-            let mut symbols = final_token.symbols.clone();
+            let mut symbols = final_token.symbols().unwrap().clone();
 
             let facts = vec![
                 fact("resource", &[s("ambient"), s("file1")]),
@@ -1040,7 +1675,7 @@ mod tests {
         }
 
         {
-            let mut symbols = final_token.symbols.clone();
+            let mut symbols = final_token.symbols().unwrap().clone();
 
             let facts = vec![
                 fact("resource", &[s("ambient"), s("file2")]),
@@ -1056,8 +1691,8 @@ mod tests {
             println!("res2: {:#?}", res);
             assert_eq!(res,
               Err(Token::FailedLogic(Logic::FailedChecks(vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 0, check_id: 0, rule: String::from("check if resource(#ambient, $resource), operation(#ambient, #read), right(#authority, $resource, #read)") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource(#ambient, #file1)") })
+                FailedCheck::Block(FailedBlockCheck { block_id: 0, check_id: 0, rule: String::from("check if resource(#ambient, $resource), operation(#ambient, #read), right(#authority, $resource, #read)"), span: None }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource(#ambient, #file1)"), span: None })
               ]))));
         }
     }
@@ -1117,7 +1752,8 @@ mod tests {
                         check_id: 0,
                         rule: String::from(
                             "check if resource(#ambient, $resource), $resource.starts_with(\"/folder1/\")"
-                        )
+                        ),
+                        span: None,
                     }),
                 ])))
             );
@@ -1132,8 +1768,8 @@ mod tests {
             println!("res3: {:?}", res);
             assert_eq!(res,
               Err(Token::FailedLogic(Logic::FailedChecks(vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource(#ambient, $resource), $resource.starts_with(\"/folder1/\")") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource(#ambient, $resource_name), operation(#ambient, #read), right(#authority, $resource_name, #read)") }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource(#ambient, $resource), $resource.starts_with(\"/folder1/\")"), span: None }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource(#ambient, $resource_name), operation(#ambient, #read), right(#authority, $resource_name, #read)"), span: None }),
               ]))));
         }
     }
@@ -1297,7 +1933,8 @@ mod tests {
             Err(Token::FailedLogic(Logic::FailedChecks(vec![
                 FailedCheck::Verifier(FailedVerifierCheck {
                     check_id: 0,
-                    rule: String::from("check if right(#authority, \"file2\", #write)")
+                    rule: String::from("check if right(#authority, \"file2\", #write)"),
+                    span: None,
                 }),
             ])))
         );
@@ -1410,6 +2047,7 @@ mod tests {
                         block_id: 0,
                         check_id: 0,
                         rule: String::from("check if resource(#ambient, #hello)"),
+                        span: None,
                     }),
                 ])))
             );
@@ -1441,6 +2079,7 @@ mod tests {
                     block_id: 0,
                     check_id: 0,
                     rule: String::from("check if name($name)"),
+                    span: None,
                 }),
             ])))
         );
@@ -1457,7 +2096,7 @@ mod tests {
         let mut verifier2 = biscuit2.verify(root.public()).unwrap();
         verifier2.allow().unwrap();
         let res2 = verifier2.verify();
-        assert_eq!(res2, Ok(0));
+        assert_eq!(res2.map(|r| r.policy_id), Ok(0));
     }
 
     #[test]
@@ -1582,4 +2221,2579 @@ mod tests {
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn tuple_conversions() {
+        use std::convert::TryFrom;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(("user", ("alice", 42i64)))
+            .unwrap();
+        builder.add_authority_fact(("user", ("bob", 7i64))).unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut verifier = biscuit1.verify(root.public()).unwrap();
+        let mut res: Vec<(String, i64)> = verifier
+            .query("data($name, $age) <- user($name, $age)")
+            .unwrap();
+        res.sort();
+        assert_eq!(res, vec![("alice".to_string(), 42), ("bob".to_string(), 7)]);
+
+        let names: Vec<(String,)> = verifier.query("data($name) <- user($name, 7)").unwrap();
+        assert_eq!(names, vec![("bob".to_string(),)]);
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let f = builder::Fact::try_from(("expires", ("session", true, now))).unwrap();
+        assert_eq!(
+            <(String, bool, SystemTime)>::try_from(f).unwrap(),
+            ("session".to_string(), true, now)
+        );
+
+        let f = builder::Fact::try_from(("user", ("alice",))).unwrap();
+        assert!(<(String, i64)>::try_from(f).is_err());
+    }
+
+    #[test]
+    fn date_conversions() {
+        use chrono::{TimeZone, Utc};
+        use std::convert::TryFrom;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let expiration = Utc.timestamp_opt(1_900_000_000, 0).unwrap();
+        let term = builder::Term::from(expiration);
+        assert_eq!(term, builder::Term::Date(1_900_000_000));
+        assert_eq!(chrono::DateTime::<Utc>::try_from(term).unwrap(), expiration);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.expiration_date(expiration);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier
+            .add_fact(fact(
+                "time",
+                &[
+                    s("ambient"),
+                    Utc.timestamp_opt(1_800_000_000, 0).unwrap().into(),
+                ],
+            ))
+            .unwrap();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier
+            .add_fact(fact(
+                "time",
+                &[
+                    s("ambient"),
+                    Utc.timestamp_opt(2_000_000_000, 0).unwrap().into(),
+                ],
+            ))
+            .unwrap();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_err());
+
+        #[cfg(feature = "time")]
+        {
+            let t = ::time::OffsetDateTime::from_unix_timestamp(1_900_000_000).unwrap();
+            let term = builder::Term::from(t);
+            assert_eq!(term, builder::Term::Date(1_900_000_000));
+            assert_eq!(::time::OffsetDateTime::try_from(term).unwrap(), t);
+        }
+    }
+
+    #[test]
+    fn duration_and_time_literals() {
+        use super::builder::{constrained_rule, Binary, Expression, Op, Term};
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        assert_eq!(Term::from(Duration::from_secs(3600)), Term::Integer(3600));
+        assert_eq!(
+            Op::from(SystemTime::UNIX_EPOCH + Duration::from_secs(10)),
+            Op::Value(Term::Date(10))
+        );
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_check(constrained_rule(
+                "ttl",
+                &[var("ttl")],
+                &[pred("ttl", &[s("ambient"), var("ttl")])],
+                &[Expression {
+                    ops: vec![
+                        var("ttl").into(),
+                        Duration::from_secs(3600).into(),
+                        Op::Binary(Binary::LessOrEqual),
+                    ],
+                }],
+            ))
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.expiration_date(SystemTime::now() + Duration::from_secs(3600));
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier
+            .add_fact(fact(
+                "ttl",
+                &[s("ambient"), Duration::from_secs(600).into()],
+            ))
+            .unwrap();
+        verifier.set_time();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+    }
+
+    #[test]
+    fn extend_builders() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.extend(
+            ["file1", "file2"]
+                .iter()
+                .map(|f| fact("right", &[s("authority"), string(f), s("read")])),
+        );
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.extend(vec![check(&[pred("resource", &[s("ambient"), var("r")])])]);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.extend(vec![
+            fact("resource", &[s("ambient"), string("file2")]),
+            fact("operation", &[s("ambient"), s("read")]),
+        ]);
+        verifier
+            .add_policy("allow if resource(#ambient, $r), operation(#ambient, $op), right(#authority, $r, $op)")
+            .unwrap();
+        assert_eq!(verifier.verify().map(|r| r.policy_id), Ok(0));
+
+        let block: builder::BlockBuilder =
+            (0..3).map(|i| fact("revocation_id", &[int(i)])).collect();
+        assert_eq!(block.facts.len(), 3);
+
+        let mut verifier: Verifier = vec![fact("user", &[int(1)])].into_iter().collect();
+        let users: Vec<(i64,)> = verifier.query("data($u) <- user($u)").unwrap();
+        assert_eq!(users, vec![(1,)]);
+    }
+
+    #[test]
+    fn merge_fragments() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut read_only = builder::BlockBuilder::new(0);
+        read_only.check_operation("read");
+        read_only.set_context("read only".to_string());
+
+        let mut audience = builder::BlockBuilder::new(0);
+        audience
+            .add_check("check if service(#ambient, \"storage\")")
+            .unwrap();
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.merge(audience.clone());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.authority().unwrap().checks.len(), 1);
+
+        let mut block2 = biscuit1.create_block();
+        block2.merge(read_only);
+        block2.merge(audience);
+        assert_eq!(block2.checks.len(), 2);
+        assert_eq!(block2.context.as_deref(), Some("read only"));
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_operation("read");
+        verifier.add_fact("service(#ambient, \"storage\")").unwrap();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_operation("write");
+        verifier.add_fact("service(#ambient, \"storage\")").unwrap();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_err());
+    }
+
+    #[test]
+    fn append_source() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.add_right("file2", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let source = r#"
+          context "attenuated";
+          // only the first file
+          check if resource(#ambient, "file1");
+          can_read($file) <- right(#authority, $file, #read);
+        "#;
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_source(&keypair2, source).unwrap();
+        assert_eq!(
+            biscuit2.context().unwrap(),
+            vec![None, Some("attenuated".to_string())]
+        );
+
+        let printed = biscuit2.print_block_source(1).unwrap();
+        println!("block 1:\n{}", printed);
+        assert!(printed.starts_with("context \"attenuated\";\n"));
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_resource("file2");
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_err());
+
+        assert!(biscuit1
+            .append_source(&keypair2, "check if resource(#ambient, $0")
+            .is_err());
+    }
+
+    #[test]
+    fn policy_files() {
+        use super::verifier::{DirectoryLoader, PolicyLoader, Verifier};
+
+        let mut files = HashMap::new();
+        files.insert(
+            "common.datalog".to_string(),
+            r#"
+              is_admin($user) <- user(#authority, $user), admin($user);
+              deny if revoked(#authority);
+            "#
+            .to_string(),
+        );
+        files.insert(
+            "main.datalog".to_string(),
+            r#"
+              include "common.datalog";
+              admin("alice");
+              allow if is_admin($user);
+            "#
+            .to_string(),
+        );
+        files.insert(
+            "loop.datalog".to_string(),
+            r#"include "main2.datalog";"#.to_string(),
+        );
+        files.insert(
+            "main2.datalog".to_string(),
+            r#"include "loop.datalog";"#.to_string(),
+        );
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_policy_file("main.datalog", &files).unwrap();
+        verifier.add_fact("user(#authority, \"alice\")").unwrap();
+        // the policies of included files come first
+        assert_eq!(verifier.verify().map(|r| r.policy_id), Ok(1));
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_policy_file("main.datalog", &files).unwrap();
+        verifier.add_fact("user(#authority, \"alice\")").unwrap();
+        verifier.add_fact("revoked(#authority)").unwrap();
+        assert!(verifier.verify().is_err());
+
+        let mut verifier = Verifier::new().unwrap();
+        assert!(matches!(
+            verifier.add_policy_file("loop.datalog", &files),
+            Err(error::Token::LoadError(_))
+        ));
+        assert!(matches!(
+            verifier.add_policy_file("missing.datalog", &files),
+            Err(error::Token::LoadError(_))
+        ));
+        assert!(matches!(
+            verifier.add_code(&files["main.datalog"]),
+            Err(error::Token::LoadError(_))
+        ));
+        assert!(verifier.dump().4.is_empty());
+
+        let loader = DirectoryLoader::new(std::env::temp_dir());
+        assert!(matches!(
+            loader.load("../passwd"),
+            Err(error::Token::LoadError(_))
+        ));
+    }
+
+    #[test]
+    fn policy_templates() {
+        use super::template::PolicyTemplate;
+        use super::verifier::Verifier;
+
+        let tenant_check = PolicyTemplate::new(
+            "check if tenant(#authority, {tenant}), [{tenant}, \"shared\"].contains($t), resource_tenant(#ambient, $t)",
+        )
+        .unwrap();
+        assert!(tenant_check.is_check());
+        assert_eq!(
+            tenant_check.parameters().collect::<Vec<_>>(),
+            vec!["tenant"]
+        );
+
+        let allow = PolicyTemplate::new("allow if role(#authority, {role})").unwrap();
+
+        for (tenant, resource_tenant, expected) in [
+            ("acme", "acme", true),
+            ("acme", "shared", true),
+            ("acme", "globex", false),
+            ("globex", "globex", true),
+        ]
+        .iter()
+        {
+            let mut verifier = Verifier::new().unwrap();
+            verifier
+                .add_check(tenant_check.check(&[("tenant", (*tenant).into())]).unwrap())
+                .unwrap();
+            verifier
+                .add_policy(allow.policy(&[("role", s("admin"))]).unwrap())
+                .unwrap();
+            verifier
+                .add_fact(fact("tenant", &[s("authority"), string(tenant)]))
+                .unwrap();
+            verifier
+                .add_fact(fact(
+                    "resource_tenant",
+                    &[s("ambient"), string(resource_tenant)],
+                ))
+                .unwrap();
+            verifier.add_fact("role(#authority, #admin)").unwrap();
+            assert_eq!(verifier.verify().is_ok(), *expected);
+        }
+
+        let injected = tenant_check
+            .check(&[("tenant", "acme\"), true, \"".into())])
+            .unwrap();
+        assert_eq!(injected.queries[0].1[0].ids[1], string("acme\"), true, \""));
+
+        assert!(matches!(
+            tenant_check.check(&[]),
+            Err(error::Token::ConversionError(_))
+        ));
+        assert!(matches!(
+            tenant_check.check(&[("tenant", "a".into()), ("other", 1.into())]),
+            Err(error::Token::ConversionError(_))
+        ));
+        assert!(matches!(
+            tenant_check.check(&[("tenant", var("x"))]),
+            Err(error::Token::ConversionError(_))
+        ));
+        assert!(matches!(
+            tenant_check.policy(&[("tenant", "a".into())]),
+            Err(error::Token::ConversionError(_))
+        ));
+        assert_eq!(
+            PolicyTemplate::new("allow if {"),
+            Err(error::Token::ParseError(error::ParseError {
+                line: 1,
+                column: 11,
+                fragment: String::new(),
+                expected: vec![],
+                message: None,
+                hint: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn fixed_clock() {
+        use super::verifier::FixedClock;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.expiration_date(expiration);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.set_clock(FixedClock(expiration - Duration::from_secs(60)));
+        verifier.set_time();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.set_clock(FixedClock(expiration + Duration::from_secs(60)));
+        verifier.set_time();
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_err());
+    }
+
+    #[test]
+    fn failed_check_spans() {
+        use super::verifier::Verifier;
+        use crate::error::SourceSpan;
+
+        let mut files = HashMap::new();
+        files.insert(
+            "common.datalog".to_string(),
+            "check if user($user);\n  check if admin($user);".to_string(),
+        );
+        files.insert(
+            "main.datalog".to_string(),
+            "include \"common.datalog\";\nallow if true;".to_string(),
+        );
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier
+            .add_check("check if resource(#ambient, $r)")
+            .unwrap();
+        verifier.add_policy_file("main.datalog", &files).unwrap();
+        verifier
+            .add_code("user(\"alice\");\n    check if operation(#ambient, #read)")
+            .unwrap();
+
+        let spans = match verifier.verify() {
+            Err(Token::FailedLogic(Logic::FailedChecks(errors))) => errors
+                .into_iter()
+                .map(|e| match e {
+                    FailedCheck::Verifier(FailedVerifierCheck { span, .. }) => span,
+                    FailedCheck::Block(_) => panic!("unexpected block check"),
+                })
+                .collect::<Vec<_>>(),
+            res => panic!("unexpected result: {:?}", res),
+        };
+
+        assert_eq!(
+            spans,
+            vec![
+                None,
+                Some(SourceSpan {
+                    file: Some("common.datalog".to_string()),
+                    start: 24,
+                    end: 45,
+                    line: 2,
+                    column: 3,
+                }),
+                Some(SourceSpan {
+                    file: None,
+                    start: 19,
+                    end: 54,
+                    line: 2,
+                    column: 5,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_codes() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let other = KeyPair::new_with_rng(&mut rng);
+
+        let biscuit = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        match biscuit.verify(other.public()) {
+            Err(e) => assert_eq!(e.code(), "format.public_key.unknown"),
+            Ok(_) => panic!("the token should not be verified with another key"),
+        }
+
+        let mut verifier = biscuit.verify(root.public()).unwrap();
+        verifier.deny().unwrap();
+        assert_eq!(verifier.verify().unwrap_err().code(), "logic.policy.deny");
+
+        let err = verifier.add_check("check if").unwrap_err();
+        assert_eq!(err.code(), "datalog.parse");
+        assert_eq!(
+            Token::RunLimit(error::RunLimit::Timeout).code(),
+            "limit.timeout"
+        );
+    }
+
+    #[test]
+    fn verifier_warnings() {
+        use super::verifier::VerifierWarning;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut symbols = default_symbol_table();
+        symbols.insert("unused");
+        let biscuit = Biscuit::builder_with_symbols(&root, symbols)
+            .build_with_rng(&mut rng)
+            .unwrap();
+
+        let mut verifier = biscuit.verify(root.public()).unwrap();
+        verifier.add_fact("resource(#ambient, \"file1\")").unwrap();
+        verifier.add_fact("stray(#ambient, 1)").unwrap();
+        verifier
+            .add_rule("can_read($f) <- resource(#ambient, $f), operation(#ambient, #read)")
+            .unwrap();
+        verifier
+            .add_check("check if resource(#ambient, $f)")
+            .unwrap();
+        verifier.allow().unwrap();
+        verifier.deny().unwrap();
+        verifier.verify().unwrap();
+
+        assert_eq!(
+            verifier.warnings(),
+            vec![
+                VerifierWarning::UnusedFact("stray(#ambient, 1)".to_string()),
+                VerifierWarning::UnusedRule(
+                    "can_read($f) <- resource(#ambient, $f), operation(#ambient, #read)"
+                        .to_string()
+                ),
+                VerifierWarning::UnusedSymbol("unused".to_string()),
+                VerifierWarning::UnreachablePolicy {
+                    policy_id: 1,
+                    shadowed_by: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn failure_dump() {
+        use super::verifier::Verifier;
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_fact("resource(#ambient, \"file1\")").unwrap();
+        verifier
+            .add_check("check if operation(#ambient, #read)")
+            .unwrap();
+        verifier.allow().unwrap();
+
+        assert!(verifier.verify().is_err());
+        assert_eq!(verifier.failure(), None);
+
+        verifier.dump_on_failure(true);
+        let err = verifier.verify().unwrap_err();
+        let failure = verifier.failure().unwrap();
+        assert_eq!(failure.error, err);
+        assert_eq!(
+            failure.facts,
+            vec!["resource(#ambient, \"file1\")".to_string()]
+        );
+        assert_eq!(
+            failure.to_string(),
+            format!("{}; facts: [resource(#ambient, \"file1\")]", err)
+        );
+
+        verifier.add_fact("operation(#ambient, #read)").unwrap();
+        verifier.verify().unwrap();
+        assert_eq!(verifier.failure(), None);
+    }
+
+    #[test]
+    fn check_failure_explanation() {
+        use super::verifier::{CheckFailureExplanation, QueryFailureExplanation, Verifier};
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_fact("resource(#ambient, \"file1\")").unwrap();
+        verifier
+            .add_fact("time(#ambient, 2020-12-21T09:23:12Z)")
+            .unwrap();
+        verifier
+            .add_check(
+                "check if resource(#ambient, $f), operation(#ambient, #read) \
+                 or time(#ambient, $t), $t < 2019-01-01T00:00:00Z",
+            )
+            .unwrap();
+        verifier.allow().unwrap();
+
+        let checks = match verifier.verify() {
+            Err(Token::FailedLogic(Logic::FailedChecks(checks))) => checks,
+            res => panic!("unexpected result: {:?}", res),
+        };
+
+        assert_eq!(
+            verifier.explain(&checks[0]),
+            Some(CheckFailureExplanation {
+                check: "check if resource(#ambient, $f), operation(#ambient, #read) or time(#ambient, $t), $t < 2019-01-01T00:00:00+00:00".to_string(),
+                queries: vec![
+                    QueryFailureExplanation {
+                        query: "resource(#ambient, $f), operation(#ambient, #read)".to_string(),
+                        missing_predicates: vec!["operation(#ambient, #read)".to_string()],
+                        failed_expressions: vec![],
+                    },
+                    QueryFailureExplanation {
+                        query: "time(#ambient, $t), $t < 2019-01-01T00:00:00+00:00".to_string(),
+                        missing_predicates: vec![],
+                        failed_expressions: vec!["$t < 2019-01-01T00:00:00+00:00".to_string()],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[cfg(feature = "serde-error")]
+    #[test]
+    fn serialize_errors() {
+        let e = Token::FailedLogic(Logic::FailedChecks(vec![FailedCheck::Verifier(
+            FailedVerifierCheck {
+                check_id: 0,
+                rule: "check if right(#authority, #read)".to_string(),
+                span: None,
+            },
+        )]));
+        assert_eq!(
+            serde_json::to_string(&e).unwrap(),
+            r##"{"FailedLogic":{"FailedChecks":[{"Verifier":{"check_id":0,"rule":"check if right(#authority, #read)","span":null}}]}}"##
+        );
+
+        let e = Token::Base64(base64::DecodeError::InvalidLength);
+        assert_eq!(
+            serde_json::to_string(&e).unwrap(),
+            r#"{"Base64":"Encoded text cannot have a 6-bit remainder."}"#
+        );
+    }
+
+    #[test]
+    fn localized_messages() {
+        use std::collections::HashMap;
+
+        let mut catalog = HashMap::new();
+        catalog.insert(
+            "logic.checks.failed".to_string(),
+            "vérifications échouées : {checks}".to_string(),
+        );
+        catalog.insert(
+            "logic.check.block.failed".to_string(),
+            "bloc {block_id}, vérification {check_id} ({rule})".to_string(),
+        );
+
+        let e = Token::FailedLogic(Logic::FailedChecks(vec![
+            FailedCheck::Block(FailedBlockCheck {
+                block_id: 1,
+                check_id: 0,
+                rule: "check if resource(#ambient, \"file1\")".to_string(),
+                span: None,
+            }),
+            FailedCheck::Verifier(FailedVerifierCheck {
+                check_id: 0,
+                rule: "check if operation(#ambient, #read)".to_string(),
+                span: None,
+            }),
+        ]));
+
+        assert_eq!(
+            e.localized_message(&catalog),
+            "vérifications échouées : bloc 1, vérification 0 (check if resource(#ambient, \"file1\")), \
+             a check provided by the verifier failed"
+        );
+        let e = Logic::Deny {
+            policy_id: 2,
+            label: None,
+            policy: "deny if true".to_string(),
+        };
+        assert_eq!(
+            Token::FailedLogic(e.clone()).localized_message(&catalog),
+            "check validation failed"
+        );
+        assert_eq!(
+            e.localized_message(&catalog),
+            "denied by policy 2: deny if true"
+        );
+    }
+
+    #[test]
+    fn signature_error_detail() {
+        use crate::format::schema;
+        use prost::Message;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let proto = biscuit.container.as_ref().unwrap().to_proto();
+
+        let corrupt = |f: &dyn Fn(&mut schema::Biscuit)| {
+            let mut proto = proto.clone();
+            f(&mut proto);
+            let mut v = Vec::new();
+            proto.encode(&mut v).unwrap();
+            match Biscuit::from(&v) {
+                Err(Token::Format(Format::Signature(e))) => e,
+                res => panic!("unexpected result: {:?}", res.map(|b| b.print())),
+            }
+        };
+
+        assert_eq!(
+            corrupt(&|p| p.keys[0].truncate(31)),
+            Signature::InvalidKeySize(31)
+        );
+        assert_eq!(
+            corrupt(&|p| p.signature.z.push(0)),
+            Signature::InvalidScalarSize(33)
+        );
+        assert_eq!(
+            corrupt(&|p| p.signature.z = vec![0xff; 32]),
+            Signature::NonCanonicalScalar
+        );
+        assert_eq!(
+            corrupt(&|p| p.authority.push(0)),
+            Signature::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn deprecated_syntax() {
+        use super::verifier::{Verifier, VerifierWarning};
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier
+            .add_code("operation(#ambient, #read);\ncaveat if operation(#ambient, #read);\nallow if true;")
+            .unwrap();
+        verifier.verify().unwrap();
+
+        let warnings = verifier.warnings();
+        assert_eq!(
+            warnings[0],
+            VerifierWarning::Deprecated {
+                message: "`caveat` was renamed to `check`".to_string(),
+                replacement: "check if operation(#ambient, #read)".to_string(),
+                span: SourceSpan {
+                    file: None,
+                    start: 28,
+                    end: 64,
+                    line: 2,
+                    column: 1,
+                },
+            }
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "line 2: `caveat` was renamed to `check`, write `check if operation(#ambient, #read)`"
+        );
+    }
+
+    #[test]
+    fn shared_world() {
+        use super::verifier::{Verifier, VerifierLimits};
+
+        let limits = VerifierLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+
+        let mut base = Verifier::new().unwrap();
+        for i in 0..100 {
+            base.add_fact(fact("member", &[s("ambient"), int(i)]))
+                .unwrap();
+        }
+        base.add_rule("is_member($i) <- member(#ambient, $i)")
+            .unwrap();
+        base.freeze();
+
+        let mut request = base.clone();
+        request.add_fact("user(#ambient, 42)").unwrap();
+        request
+            .add_policy("allow if user(#ambient, $i), is_member($i)")
+            .unwrap();
+        assert_eq!(
+            request
+                .verify_with_limits(limits.clone())
+                .map(|r| r.policy_id),
+            Ok(0)
+        );
+
+        let mut other = base.clone();
+        other.add_fact("user(#ambient, 1000)").unwrap();
+        other
+            .add_policy("allow if user(#ambient, $i), is_member($i)")
+            .unwrap();
+        assert!(other.verify_with_limits(limits.clone()).is_err());
+
+        base.allow().unwrap();
+        base.verify_with_limits(limits).unwrap();
+        let facts = base.dump().0;
+        assert!(facts.iter().all(|f| !f.to_string().starts_with("user(")));
+    }
+
+    #[test]
+    fn expression_depth() {
+        use super::builder::{boolean, constrained_rule, Check, Expression, Op, Unary};
+        use crate::datalog::MAX_EXPRESSION_DEPTH;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        // true, negated many times
+        let mut ops = vec![Op::Value(boolean(true))];
+        ops.extend((0..10_000).map(|_| Op::Unary(Unary::Negate)));
+        let deep = Expression { ops };
+        assert_eq!(deep.to_string(), "<expression nested too deeply>");
+
+        let mut block = biscuit1.create_block();
+        block
+            .add_check(Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    &[int(1)],
+                    &[pred("resource", &[s("ambient"), string("file1")])],
+                    &[deep],
+                )],
+            })
+            .unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        let deser = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+        assert!(deser.print().contains("<expression nested too deeply>"));
+
+        let mut verifier = deser.verify(root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.allow().unwrap();
+        assert_eq!(
+            verifier.verify(),
+            Err(error::Token::RunLimit(error::RunLimit::ExpressionDepth))
+        );
+
+        // an odd number of negations, the expression is as deep as allowed
+        let mut ops = vec![Op::Value(boolean(false))];
+        ops.extend((1..MAX_EXPRESSION_DEPTH).map(|_| Op::Unary(Unary::Negate)));
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_resource("file1");
+        verifier
+            .add_check(Check {
+                queries: vec![constrained_rule(
+                    "query",
+                    &[int(1)],
+                    &[pred("resource", &[s("ambient"), string("file1")])],
+                    &[Expression { ops }],
+                )],
+            })
+            .unwrap();
+        verifier.allow().unwrap();
+        verifier
+            .verify_with_limits(super::verifier::VerifierLimits {
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn term_limits() {
+        use super::builder::{bytes, set};
+        use crate::format::TermLimits;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(fact(
+                "data",
+                &[
+                    s("authority"),
+                    string(&"a".repeat(100)),
+                    bytes(&[0; 50]),
+                    set([int(1), int(2), int(3)].iter().cloned().collect()),
+                ],
+            ))
+            .unwrap();
+        let serialized = builder.build_with_rng(&mut rng).unwrap().to_vec().unwrap();
+
+        let token = Biscuit::from(&serialized).unwrap();
+        token.verify(root.public()).unwrap();
+
+        let limits = TermLimits {
+            max_string_len: 10,
+            ..Default::default()
+        };
+        let token = Biscuit::from_with_term_limits(&serialized, limits).unwrap();
+        assert_eq!(
+            token.verify(root.public()).err().unwrap(),
+            error::Token::Format(error::Format::TermTooLarge {
+                kind: "string".to_string(),
+                size: 100,
+                maximum: 10,
+            })
+        );
+
+        let limits = TermLimits {
+            max_bytes_len: 50,
+            max_set_len: 2,
+            ..Default::default()
+        };
+        let token = Biscuit::from_with_term_limits(&serialized, limits).unwrap();
+        assert_eq!(
+            token.verify(root.public()).err().unwrap(),
+            error::Token::Format(error::Format::TermTooLarge {
+                kind: "set".to_string(),
+                size: 3,
+                maximum: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn expiration_time() {
+        use super::delegation::Delegation;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let date = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/file", "read");
+        builder
+            .add_authority_check("check if time(#ambient, $t), $t > 2020-01-01T00:00:00Z")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.expiration_time().unwrap(), None);
+
+        let mut block = biscuit1.create_block();
+        block.valid_between(date(1_500_000_000), date(1_700_000_000));
+        // not every query is bounded, so this check does not expire
+        block
+            .add_check(
+                "check if time(#ambient, $t), $t < 2000-01-01T00:00:00Z or operation(#ambient, #read)",
+            )
+            .unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        assert_eq!(
+            biscuit2.expiration_time().unwrap(),
+            Some(date(1_700_000_000))
+        );
+
+        let keypair3 = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = biscuit2
+            .append_source(
+                &keypair3,
+                "check if time(#ambient, $t), $t < 2020-09-13T12:26:40Z or time(#ambient, $t), 2020-01-01T00:00:00Z > $t",
+            )
+            .unwrap();
+        let deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.expiration_time().unwrap(), Some(date(1_600_000_000)));
+
+        let intermediate = KeyPair::new_with_rng(&mut rng);
+        let delegation =
+            Delegation::new_with_rng(&mut rng, &root, intermediate.public(), date(1_550_000_000));
+        let mut builder = Biscuit::builder(&intermediate);
+        builder
+            .add_authority_check("check if time(#ambient, $t), $t <= 2020-09-13T12:26:40Z")
+            .unwrap();
+        builder.set_delegation(delegation);
+        let biscuit4 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(
+            biscuit4.expiration_time().unwrap(),
+            Some(date(1_550_000_000))
+        );
+
+        use super::builder::{constrained_rule, Binary, Expression, Op, Term};
+        let mut block = biscuit1.create_block();
+        block
+            .add_check(constrained_rule(
+                "expiration",
+                &[var("date")],
+                &[pred("time", &[s("ambient"), var("date")])],
+                &[Expression {
+                    ops: vec![
+                        var("date").into(),
+                        Op::Value(Term::Date(u64::MAX)),
+                        Op::Binary(Binary::LessOrEqual),
+                    ],
+                }],
+            ))
+            .unwrap();
+        let biscuit5 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        assert_eq!(biscuit5.expiration_time().unwrap(), None);
+    }
+
+    #[test]
+    fn revocation_list() {
+        use super::revocation::{RevocationList, RevocationStore};
+        use super::verifier::FixedClock;
+        use std::sync::Arc;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let issuer = KeyPair::new_with_rng(&mut rng);
+
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let expiration = now + Duration::from_secs(3600);
+        let list = RevocationList::new_with_rng(
+            &mut rng,
+            &issuer,
+            3,
+            expiration,
+            vec![biscuit2.revocation_ids()[1].clone()],
+        );
+
+        let serialized = list.to_vec().unwrap();
+        let list = RevocationList::parse(&serialized).unwrap();
+        assert_eq!(list.sequence, 3);
+        assert_eq!(list.expiration, expiration);
+
+        let mut verified = list.verify(issuer.public(), now, 3).unwrap();
+        verified.set_clock(FixedClock(now));
+        assert_eq!(verified.list(), &list);
+        assert!(verified.is_revoked(&biscuit2.revocation_ids()[1]));
+        assert!(!verified.is_revoked(&biscuit2.revocation_ids()[0]));
+        assert_eq!(
+            list.verify(issuer.public(), expiration + Duration::from_secs(1), 0)
+                .err(),
+            Some(error::Format::ExpiredRevocationList)
+        );
+        assert_eq!(
+            list.verify(root.public(), now, 0).err(),
+            Some(error::Format::InvalidRevocationList)
+        );
+
+        // a list older than the last one accepted is refused
+        assert_eq!(
+            list.verify(issuer.public(), now, 4).err(),
+            Some(error::Format::OutdatedRevocationList {
+                minimum: 4,
+                actual: 3,
+            })
+        );
+
+        // the identifiers are covered by the signature
+        let mut forged = list.clone();
+        forged.ids.clear();
+        assert_eq!(
+            forged.verify(issuer.public(), now, 0).err(),
+            Some(error::Format::InvalidRevocationList)
+        );
+        let mut forged = list.clone();
+        forged.sequence = 4;
+        assert_eq!(
+            forged.verify(issuer.public(), now, 0).err(),
+            Some(error::Format::InvalidRevocationList)
+        );
+        assert_eq!(
+            RevocationList::parse(&serialized[..serialized.len() - 1]).err(),
+            Some(error::Format::InvalidRevocationList)
+        );
+
+        // an expiration date that does not fit in a SystemTime is refused
+        use crate::format::schema;
+        use prost::Message;
+        let mut proto = schema::RevocationList::decode(&serialized[..]).unwrap();
+        proto.expiration = u64::MAX;
+        let mut overflowing = Vec::new();
+        proto.encode(&mut overflowing).unwrap();
+        assert_eq!(
+            RevocationList::parse(&overflowing).err(),
+            Some(error::Format::InvalidRevocationList)
+        );
+
+        // once expired, the list revokes every token
+        let mut expired = verified.clone();
+        expired.set_clock(FixedClock(expiration + Duration::from_secs(1)));
+        assert!(expired.is_revoked(&biscuit2.revocation_ids()[0]));
+
+        let verify = |token: &Biscuit, list: Arc<dyn RevocationStore>| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.set_revocation_store(list);
+            verifier.allow().unwrap();
+            verifier.verify()
+        };
+        let list = Arc::new(verified);
+        assert!(verify(&biscuit1, list.clone()).is_ok());
+        assert_eq!(verify(&biscuit2, list), Err(error::Token::Revoked(1)));
+        assert_eq!(
+            verify(&biscuit1, Arc::new(expired)),
+            Err(error::Token::Revoked(0))
+        );
+    }
+
+    #[test]
+    fn delegation() {
+        use super::delegation::Delegation;
+        use super::verifier::FixedClock;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let intermediate = KeyPair::new_with_rng(&mut rng);
+        let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let delegation =
+            Delegation::new_with_rng(&mut rng, &root, intermediate.public(), expiration);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.set_delegation(delegation.clone());
+        assert_eq!(
+            builder.build_with_rng(&mut rng).err(),
+            Some(error::Token::Format(error::Format::InvalidDelegation))
+        );
+
+        let mut builder = Biscuit::builder(&intermediate);
+        builder.add_right("/file", "read");
+        builder.set_delegation(delegation.clone());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+
+        let serialized = biscuit2.to_vec().unwrap();
+        let deser = Biscuit::from(&serialized).unwrap();
+        assert_eq!(deser.delegation(), Some(&delegation));
+
+        let verify = |token: &Biscuit, time: SystemTime| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.set_clock(FixedClock(time));
+            verifier.set_time();
+            verifier.allow().unwrap();
+            verifier.verify()
+        };
+        assert!(verify(&deser, expiration - Duration::from_secs(60)).is_ok());
+        assert!(verify(&deser, expiration + Duration::from_secs(60)).is_err());
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_token(&deser, root.public()).unwrap();
+        verifier.allow().unwrap();
+        // without the time, the delegation check fails
+        assert!(verifier.verify().is_err());
+
+        // loading the token again does not add another delegation check
+        let checks = verifier.dump().3.len();
+        assert_eq!(
+            verifier.add_token(&deser, root.public()),
+            Err(error::Token::FailedLogic(error::Logic::VerifierNotEmpty))
+        );
+        assert_eq!(verifier.dump().3.len(), checks);
+
+        let other = KeyPair::new_with_rng(&mut rng);
+        assert_eq!(
+            deser.check_root_key(other.public()),
+            Err(error::Token::Format(error::Format::UnknownPublicKey))
+        );
+
+        // the expiration is covered by the signature of the root key
+        let mut container = deser.container().unwrap().clone();
+        let mut forged = container.delegation.clone().unwrap();
+        forged.expiration = expiration + Duration::from_secs(3600);
+        container.delegation = Some(forged);
+        assert_eq!(
+            Biscuit::from(&container.to_vec().unwrap()).err(),
+            Some(error::Token::Format(error::Format::InvalidDelegation))
+        );
+
+        // an expiration that cannot be represented is refused while decoding
+        use crate::format::schema;
+        use prost::Message;
+        let mut proto = schema::Biscuit::decode(&serialized[..]).unwrap();
+        proto.delegation.as_mut().unwrap().expiration = u64::MAX;
+        let mut data = Vec::new();
+        proto.encode(&mut data).unwrap();
+        assert_eq!(
+            Biscuit::from(&data).err(),
+            Some(error::Token::Format(error::Format::InvalidDelegation))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn facts_from_json() {
+        let profile = serde_json::json!({
+            "name": "alice",
+            "age": 30,
+            "admin": false,
+            "manager": null,
+            "roles": ["admin", "dev"],
+            "badges": [1, 2],
+            "address": { "city": "Paris", "post-code": "75001" },
+            "teams": [{ "id": 1, "tags": ["a"] }, { "id": 2 }],
+            "mixed": ["a", 1],
+        });
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_facts_from_json("user", &profile).unwrap();
+        let mut facts = verifier
+            .dump()
+            .0
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        facts.sort();
+        assert_eq!(
+            facts,
+            vec![
+                "user_address_city(#ambient, \"Paris\")",
+                "user_address_post_code(#ambient, \"75001\")",
+                "user_admin(#ambient, false)",
+                "user_age(#ambient, 30)",
+                "user_badges(#ambient, [ 1, 2])",
+                "user_mixed(#ambient, \"a\")",
+                "user_mixed(#ambient, 1)",
+                "user_name(#ambient, \"alice\")",
+                "user_roles(#ambient, [ \"admin\", \"dev\"])",
+                "user_teams_id(#ambient, 1)",
+                "user_teams_id(#ambient, 2)",
+                "user_teams_tags(#ambient, [ \"a\"])",
+            ]
+        );
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier
+            .add_facts_from_json("", &serde_json::json!({ "method": "GET" }))
+            .unwrap();
+        verifier
+            .add_policy("allow if method(#ambient, \"GET\")")
+            .unwrap();
+        verifier.verify().unwrap();
+
+        let mut verifier = Verifier::new().unwrap();
+        assert_eq!(
+            verifier.add_facts_from_json("order", &serde_json::json!({ "id": 1, "total": 9.99 })),
+            Err(error::Token::ConversionError(
+                "9.99 is not a 64 bits integer, in order_total".to_string()
+            ))
+        );
+        assert!(verifier.dump().0.is_empty());
+        assert!(verifier
+            .add_facts_from_json("", &serde_json::json!(["a"]))
+            .is_err());
+    }
+
+    #[test]
+    fn certificate_binding() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        // the thumbprint does not depend on the content of the certificate
+        let certificate = b"client certificate".to_vec();
+        let other = b"other certificate".to_vec();
+
+        let mut builder = Biscuit::builder(&root);
+        builder.bind_to_certificate(&certificate);
+        let token = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(
+            token.print_block_source(0).unwrap(),
+            format!(
+                "cnf(#authority, \"x5t#S256\", hex:{});\n\
+                 check if cnf(#authority, $method, $thumbprint), client_thumbprint(#ambient, $method, $thumbprint);\n",
+                hex::encode(binding::thumbprint(&certificate))
+            )
+        );
+
+        let verify = |client: Option<&[u8]>, public_key: bool| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            match (client, public_key) {
+                (Some(client), false) => verifier.add_client_certificate(client),
+                (Some(client), true) => verifier.add_client_public_key(client),
+                (None, _) => {}
+            }
+            verifier.allow().unwrap();
+            verifier.verify()
+        };
+        assert!(verify(Some(&certificate), false).is_ok());
+        assert!(verify(Some(&other), false).is_err());
+        assert!(verify(Some(&certificate), true).is_err());
+        assert!(verify(None, false).is_err());
+
+        let mut builder = Biscuit::builder(&root);
+        builder.bind_to_public_key(&certificate);
+        let token = builder.build_with_rng(&mut rng).unwrap();
+        let mut verifier = token.verify(root.public()).unwrap();
+        verifier.add_client_public_key(&certificate);
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_ok());
+    }
+
+    #[test]
+    fn proof_of_possession() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let holder = KeyPair::new_with_rng(&mut rng);
+        let thief = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.bind_to_holder(holder.public());
+        let token = builder.build_with_rng(&mut rng).unwrap();
+
+        let nonce = possession::nonce();
+        let proof = possession::Proof::new_with_rng(&mut rng, &holder, &nonce);
+        assert_eq!(
+            possession::Proof::from_bytes(&proof.to_bytes()),
+            Some(proof.clone())
+        );
+
+        let verify = |nonce: &[u8], proof: Option<&possession::Proof>| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            let possessed = proof
+                .map(|proof| verifier.verify_possession(nonce, proof))
+                .unwrap_or(false);
+            verifier.allow().unwrap();
+            (possessed, verifier.verify().is_ok())
+        };
+        assert_eq!(verify(&nonce, Some(&proof)), (true, true));
+        assert_eq!(verify(b"other nonce", Some(&proof)), (false, false));
+        let forged = possession::Proof::new_with_rng(&mut rng, &thief, &nonce);
+        assert_eq!(verify(&nonce, Some(&forged)), (false, false));
+        assert_eq!(verify(&nonce, None), (false, false));
+
+        // an unbound token has no key to prove
+        let token = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let mut verifier = token.verify(root.public()).unwrap();
+        assert!(!verifier.verify_possession(&nonce, &proof));
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("auth:role(#authority, \"admin\")")
+            .unwrap();
+        let token = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(
+            token.print_block_source(0).unwrap(),
+            "auth:role(#authority, \"admin\");\n"
+        );
+        let fact: builder::Fact = "billing:right(\"invoice\", #read)".parse().unwrap();
+        assert_eq!(fact.0.namespace(), Some("billing"));
+
+        let keypair = KeyPair::new_with_rng(&mut rng);
+        let mut block = token.create_block();
+        block.add_fact(fact).unwrap();
+        let forged = token.append_with_rng(&mut rng, &keypair, block).unwrap();
+        let mut block = token.create_block();
+        block
+            .add_rule("billing:right($r, #read) <- resource(#ambient, $r)")
+            .unwrap();
+        let forged_rule = token.append_with_rng(&mut rng, &keypair, block).unwrap();
+
+        let verify = |token: &Biscuit| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.restrict_block_namespaces(&["auth"]);
+            verifier
+                .add_code(
+                    "billing:right(\"report\", #read); allow if auth:role(#authority, \"admin\");",
+                )
+                .unwrap();
+            verifier.verify()
+        };
+        assert!(verify(&token).is_ok());
+        assert_eq!(
+            verify(&forged).err(),
+            Some(error::Token::FailedLogic(error::Logic::ForbiddenNamespace(
+                1,
+                "billing:right".to_string()
+            )))
+        );
+        assert_eq!(
+            verify(&forged_rule).err(),
+            Some(error::Token::FailedLogic(error::Logic::ForbiddenNamespace(
+                1,
+                "billing:right".to_string()
+            )))
+        );
+
+        // same restrictions when the token is added to an existing verifier
+        let mut verifier = Verifier::new().unwrap();
+        verifier.restrict_block_namespaces(&[]);
+        verifier.add_token(&token, root.public()).unwrap();
+        verifier.allow().unwrap();
+        assert_eq!(
+            verifier.verify().err(),
+            Some(error::Token::FailedLogic(error::Logic::ForbiddenNamespace(
+                0,
+                "auth:role".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file2", "read");
+        builder.add_right("file1", "read");
+        let token = builder.build().unwrap();
+        let token = token
+            .append_source(
+                &KeyPair::new(),
+                "check if resource(#ambient, $0), operation(#ambient, #read), right(#authority, $0, #read)",
+            )
+            .unwrap();
+
+        let verifier = |resources: &[&str]| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            for resource in resources {
+                verifier.add_resource(resource);
+            }
+            verifier.add_operation("read");
+            verifier
+                .add_rule("allowed($0) <- resource(#ambient, $0), right(#authority, $0, #read)")
+                .unwrap();
+            verifier
+                .add_policy("deny if resource(#ambient, \"secret\")")
+                .unwrap();
+            verifier.allow().unwrap();
+            verifier
+        };
+
+        let snapshot = verifier(&["file1", "file2"]).to_snapshot_string();
+        assert_eq!(snapshot, verifier(&["file2", "file1"]).to_snapshot_string());
+        assert_eq!(
+            snapshot,
+            r#"// facts
+operation(#ambient, #read);
+resource(#ambient, "file1");
+resource(#ambient, "file2");
+right(#authority, "file1", #read);
+right(#authority, "file2", #read);
+
+// privileged rules
+allowed($0) <- resource(#ambient, $0), right(#authority, $0, #read);
+
+// rules
+
+// verifier checks
+
+// checks of block 1
+check if resource(#ambient, $0), operation(#ambient, #read), right(#authority, $0, #read);
+
+// policies
+deny if resource(#ambient, "secret");
+allow if true;
+"#
+        );
+    }
+
+    #[test]
+    fn threshold_root_keys() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root1 = KeyPair::new_with_rng(&mut rng);
+        let root2 = KeyPair::new_with_rng(&mut rng);
+        let root3 = KeyPair::new_with_rng(&mut rng);
+        let keys = [root1.public(), root2.public(), root3.public()];
+
+        let mut builder = Biscuit::builder(&root1);
+        builder.add_right("/file", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(
+            biscuit1.check_root_keys(&keys, 2),
+            Err(error::Token::Format(error::Format::RootKeyThreshold {
+                threshold: 2,
+                signers: 1,
+            }))
+        );
+
+        // signing twice with the same key does not count
+        let biscuit2 = biscuit1.cosign_with_rng(&mut rng, &root1).unwrap();
+        assert!(biscuit2.check_root_keys(&keys, 2).is_err());
+
+        let biscuit2 = biscuit1.cosign_with_rng(&mut rng, &root2).unwrap();
+        let keypair = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = biscuit2
+            .append_source(&keypair, "check if operation(#ambient, #read)")
+            .unwrap();
+        let biscuit3 = biscuit3.cosign_with_rng(&mut rng, &root3).unwrap();
+
+        let deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.root_keys(), keys.to_vec());
+        assert!(deser.check_root_keys(&keys, 3).is_ok());
+        assert!(deser.check_root_keys(&keys[1..], 2).is_ok());
+        assert!(deser.check_root_keys(&keys[1..], 3).is_err());
+
+        let mut verifier = deser.verify_with_root_keys(&keys, 3).unwrap();
+        verifier.add_resource("/file");
+        verifier.add_operation("read");
+        verifier.allow().unwrap();
+        verifier.verify().unwrap();
+
+        // a cosignature of another authority block is refused
+        let mut container = deser.container().unwrap().clone();
+        container.cosignatures[0] = biscuit1
+            .cosign_with_rng(&mut rng, &root2)
+            .unwrap()
+            .container()
+            .unwrap()
+            .cosignatures[0]
+            .clone();
+        assert!(Biscuit::from(&container.to_vec().unwrap()).is_err());
+    }
+
+    #[test]
+    fn is_attenuation_of() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+        let biscuit3 = biscuit2.append_source(&keypair2, "check if true").unwrap();
+        let deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+
+        assert!(biscuit2.is_attenuation_of(&biscuit1));
+        assert!(deser.is_attenuation_of(&biscuit1));
+        assert!(deser.is_attenuation_of(&biscuit2));
+        assert!(!biscuit1.is_attenuation_of(&biscuit2));
+        assert!(!biscuit2.is_attenuation_of(&biscuit2));
+
+        // same content, appended separately
+        let other = biscuit1.append_source(&keypair2, "check if true").unwrap();
+        assert!(!biscuit3.is_attenuation_of(&other));
+
+        let unrelated = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        assert!(!biscuit2.is_attenuation_of(&unrelated));
+
+        let sealed = Biscuit::from_sealed(&biscuit3.seal(b"secret").unwrap(), b"secret").unwrap();
+        assert!(!sealed.is_attenuation_of(&biscuit1));
+    }
+
+    #[test]
+    fn token_diff() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_source(&keypair2, "check if resource(#ambient, \"file1\")")
+            .unwrap();
+        let working = biscuit2
+            .append_source(&keypair2, "check if operation(#ambient, #read)")
+            .unwrap();
+        let failing = biscuit2
+            .append_source(
+                &keypair2,
+                "allowed(#write); check if operation(#ambient, #write)",
+            )
+            .unwrap();
+
+        let diff = biscuit2.diff(&biscuit2).unwrap();
+        assert_eq!(diff.common, 2);
+        assert!(diff.is_empty());
+
+        let diff = working.diff(&failing).unwrap();
+        assert_eq!(diff.common, 2);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].index, 2);
+        assert_eq!(diff.added[0].facts, vec![fact("allowed", &[s("write")])]);
+        assert_eq!(
+            diff.to_string(),
+            "common blocks: 2\n\
+             - block 2:\n    check if operation(#ambient, #read);\n\
+             + block 2:\n    allowed(#write);\n    check if operation(#ambient, #write);\n"
+        );
+
+        let diff = biscuit1.diff(&working).unwrap();
+        assert_eq!(diff.common, 1);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.added.len(), 2);
+
+        let other = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let diff = other.diff(&working).unwrap();
+        assert_eq!(diff.common, 0);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.added.len(), 3);
+    }
+
+    #[test]
+    fn block_view() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/file", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_source(
+                &keypair2,
+                r#"context "billing";
+                   valid(#ambient, $file) <- resource(#ambient, $file);
+                   check if operation(#ambient, #read)"#,
+            )
+            .unwrap();
+        let deser = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+
+        let authority = deser.block(0).unwrap().unwrap();
+        assert_eq!(authority.index, 0);
+        assert_eq!(
+            authority.facts,
+            vec![fact("right", &[s("authority"), string("/file"), s("read")])]
+        );
+        assert!(authority.rules.is_empty());
+        assert_eq!(authority.public_key, Some(root.public()));
+
+        let block = deser.block(1).unwrap().unwrap();
+        assert!(block.facts.is_empty());
+        assert_eq!(
+            block.rules,
+            vec![rule(
+                "valid",
+                &[s("ambient"), var("file")],
+                &[pred("resource", &[s("ambient"), var("file")])],
+            )]
+        );
+        assert_eq!(block.checks.len(), 1);
+        assert_eq!(
+            block.checks[0].to_string(),
+            "check if operation(#ambient, #read)"
+        );
+        assert_eq!(block.context.as_deref(), Some("billing"));
+        assert_eq!(block.version, MAX_SCHEMA_VERSION);
+        assert_eq!(block.public_key, Some(keypair2.public()));
+
+        assert_eq!(deser.block(2).unwrap(), None);
+
+        let sealed = Biscuit::from_sealed(&deser.seal(b"secret").unwrap(), b"secret").unwrap();
+        assert_eq!(sealed.block(1).unwrap().unwrap().public_key, None);
+    }
+
+    #[test]
+    fn block_context() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+        builder.set_context("issued by auth-svc".to_string());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block = biscuit1.create_block();
+        block.set_context("issued by billing-svc v2".to_string());
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        let biscuit3 = biscuit2.append_source(&keypair2, "check if true").unwrap();
+
+        let deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.block_context(0).unwrap(), Some("issued by auth-svc"));
+        assert_eq!(
+            deser.block_context(1).unwrap(),
+            Some("issued by billing-svc v2")
+        );
+        assert_eq!(deser.block_context(2).unwrap(), None);
+        assert_eq!(deser.block_context(3).unwrap(), None);
+    }
+
+    #[test]
+    fn max_blocks() {
+        use super::cache::SignatureCache;
+        use super::unverified::UnverifiedBiscuit;
+        use std::time::Duration;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut biscuit = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        for _ in 0..3 {
+            let keypair = KeyPair::new_with_rng(&mut rng);
+            biscuit = biscuit
+                .append_with_rng(&mut rng, &keypair, biscuit.create_block())
+                .unwrap();
+        }
+        let serialized = biscuit.to_vec().unwrap();
+
+        assert!(Biscuit::from_with_max_blocks(&serialized, 4).is_ok());
+        assert_eq!(
+            Biscuit::from_with_max_blocks(&serialized, 3).err(),
+            Some(error::Token::Format(error::Format::TooManyBlocks {
+                maximum: 3,
+                actual: 4,
+            }))
+        );
+
+        // the limit is kept when appending
+        let keypair = KeyPair::new_with_rng(&mut rng);
+        let limited = Biscuit::from_with_max_blocks(&serialized, 5).unwrap();
+        let limited = limited
+            .append_with_rng(&mut rng, &keypair, limited.create_block())
+            .unwrap();
+        assert_eq!(
+            limited
+                .append_with_rng(&mut rng, &keypair, limited.create_block())
+                .err(),
+            Some(error::Token::Format(error::Format::TooManyBlocks {
+                maximum: 5,
+                actual: 6,
+            }))
+        );
+
+        // combined with the other options
+        let cache = SignatureCache::new(10, Duration::from_secs(60));
+        let base64 = biscuit.to_base64().unwrap();
+        let options = DecodeOptions::new()
+            .term_limits(TermLimits::default())
+            .signature_cache(&cache);
+        assert!(Biscuit::from_base64_with_options(&base64, options.max_blocks(4)).is_ok());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            Biscuit::from_base64_with_options(&base64, options.max_blocks(3)).err(),
+            Some(error::Token::Format(error::Format::TooManyBlocks {
+                maximum: 3,
+                actual: 4,
+            }))
+        );
+
+        // no limit by default
+        let mut unverified = UnverifiedBiscuit::from(&serialized).unwrap();
+        for _ in 4..=100 {
+            let keypair = KeyPair::new_with_rng(&mut rng);
+            unverified = unverified
+                .append_with_rng(&mut rng, &keypair, unverified.create_block())
+                .unwrap();
+        }
+        let serialized = unverified.to_vec().unwrap();
+        let biscuit = Biscuit::from(&serialized).unwrap();
+        assert_eq!(biscuit.block_count(), 101);
+        assert!(UnverifiedBiscuit::from(&serialized).is_ok());
+        assert!(biscuit
+            .append_with_rng(&mut rng, &keypair, biscuit.create_block())
+            .is_ok());
+    }
+
+    #[test]
+    fn unverified_append() {
+        use super::unverified::UnverifiedBiscuit;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let unverified = UnverifiedBiscuit::from(&biscuit1.to_vec().unwrap()).unwrap();
+        let mut block = unverified.create_block();
+        block.check_operation("read");
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let unverified = unverified
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        assert_eq!(unverified.block_count(), 2);
+
+        let serialized = unverified.to_vec().unwrap();
+        let biscuit2 = UnverifiedBiscuit::from(&serialized)
+            .unwrap()
+            .verify(root.public())
+            .unwrap();
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_operation("write");
+        verifier.allow().unwrap();
+        assert!(verifier.verify().is_err());
+
+        let other_root = KeyPair::new_with_rng(&mut rng);
+        assert_eq!(
+            unverified.clone().verify(other_root.public()).err(),
+            Some(error::Token::Format(error::Format::UnknownPublicKey))
+        );
+
+        // a tampered token can be attenuated, but not verified
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/file", "write");
+        let other = builder.build_with_rng(&mut rng).unwrap();
+        let mut container = biscuit1.container().unwrap().clone();
+        container.authority = other.container().unwrap().authority.clone();
+        let tampered = UnverifiedBiscuit::from(&container.to_vec().unwrap()).unwrap();
+        assert!(Biscuit::from(&container.to_vec().unwrap()).is_err());
+        let tampered = tampered
+            .append_with_rng(&mut rng, &keypair2, BlockBuilder::new(1))
+            .unwrap();
+        assert!(tampered.verify(root.public()).is_err());
+
+        // the number of keys is checked before the token is used
+        use crate::format::schema;
+        use prost::Message;
+        let mut proto = schema::Biscuit::decode(&serialized[..]).unwrap();
+        proto.keys.pop();
+        let mut truncated = Vec::new();
+        proto.encode(&mut truncated).unwrap();
+        assert!(matches!(
+            UnverifiedBiscuit::from(&truncated),
+            Err(error::Token::Format(error::Format::Signature(
+                error::Signature::InvalidFormat
+            )))
+        ));
+        assert!(Biscuit::from(&truncated).is_err());
+    }
+
+    #[test]
+    fn attenuation_helpers() {
+        use super::verifier::FixedClock;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let end = start + Duration::from_secs(3600);
+        let mut block = biscuit1.create_block();
+        block.restrict_resource_prefix("/tenant-42/");
+        block.restrict_operations(&["read", "list"]);
+        block.valid_between(start, end);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+
+        let verify = |resource: &str, operation: &str, time: SystemTime| {
+            let mut verifier = biscuit2.verify(root.public()).unwrap();
+            verifier.set_clock(FixedClock(time));
+            verifier.set_time();
+            verifier.add_resource(resource);
+            verifier.add_operation(operation);
+            verifier.allow().unwrap();
+            verifier.verify().is_ok()
+        };
+
+        let now = start + Duration::from_secs(60);
+        assert!(verify("/tenant-42/file", "read", now));
+        assert!(verify("/tenant-42/file", "list", end));
+        assert!(!verify("/tenant-43/file", "read", now));
+        assert!(!verify("/tenant-42/file", "write", now));
+        assert!(!verify(
+            "/tenant-42/file",
+            "read",
+            start - Duration::from_secs(1)
+        ));
+        assert!(!verify(
+            "/tenant-42/file",
+            "read",
+            end + Duration::from_secs(1)
+        ));
+
+        let mut block = biscuit2.create_block();
+        block.restrict_resources(&["/tenant-42/a", "/tenant-42/b"]);
+        let biscuit3 = biscuit2
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        let source = biscuit3.print_block_source(2).unwrap();
+        assert!(source.contains(r#"["/tenant-42/a", "/tenant-42/b"].contains($resource)"#));
+    }
+
+    #[test]
+    fn sealed_key_id() {
+        use super::sealed::SealKeys;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let mut keys = SealKeys::new();
+        keys.insert(1, b"secret 1");
+        keys.insert(2, b"secret 2");
+
+        let sealed = biscuit1.seal_with_key_id(2, b"secret 2").unwrap();
+        Biscuit::from_sealed_with_keys(&sealed, &keys).unwrap();
+        Biscuit::from_sealed(&sealed, b"secret 2").unwrap();
+
+        let sealed = biscuit1.seal_with_key_id(1, b"secret 2").unwrap();
+        assert_eq!(
+            Biscuit::from_sealed_with_keys(&sealed, &keys).err(),
+            Some(error::Token::Format(error::Format::SealedSignature {
+                expected: Some(1),
+                actual: Some(1),
+            }))
+        );
+
+        let sealed = biscuit1.seal_with_key_id(3, b"secret 3").unwrap();
+        assert_eq!(
+            Biscuit::from_sealed_with_keys(&sealed, &keys).err(),
+            Some(error::Token::Format(error::Format::UnknownSealKey {
+                accepted: vec![1, 2],
+                actual: Some(3),
+            }))
+        );
+
+        // tokens sealed without a key id are refused
+        let sealed = biscuit1.seal(b"secret 1").unwrap();
+        assert_eq!(
+            Biscuit::from_sealed_with_keys(&sealed, &keys).err(),
+            Some(error::Token::Format(error::Format::UnknownSealKey {
+                accepted: vec![1, 2],
+                actual: None,
+            }))
+        );
+
+        // the key id is covered by the signature
+        let mut sealed =
+            super::sealed::SealedBiscuit::from_token_with_key_id(&biscuit1, 2, b"secret 2")
+                .unwrap();
+        sealed.key_id = Some(1);
+        keys.insert(1, b"secret 2");
+        assert!(Biscuit::from_sealed_with_keys(&sealed.to_vec().unwrap(), &keys).is_err());
+
+        // the tagged key id is signed before the blocks
+        use hmac::{Hmac, Mac, NewMac};
+        let sealed = super::sealed::SealedBiscuit::from_token_with_key_id(
+            &biscuit1,
+            0x01020304,
+            b"secret 2",
+        )
+        .unwrap();
+        let mut mac = Hmac::<sha2::Sha256>::new_varkey(b"secret 2").unwrap();
+        mac.update(b"key_id\x04\x03\x02\x01");
+        mac.update(&sealed.authority);
+        mac.verify(&sealed.signature).unwrap();
+    }
+
+    #[test]
+    fn block_nonce() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let attenuated1 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+        let attenuated2 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+
+        let nonce1 = attenuated1.blocks().unwrap()[0].nonce.clone().unwrap();
+        let nonce2 = attenuated2.blocks().unwrap()[0].nonce.clone().unwrap();
+        assert_eq!(nonce1.len(), NONCE_SIZE);
+        assert_ne!(nonce1, nonce2);
+        assert_ne!(
+            attenuated1.revocation_ids()[1],
+            attenuated2.revocation_ids()[1]
+        );
+        assert_eq!(
+            attenuated1.revocation_ids()[0],
+            attenuated2.revocation_ids()[0]
+        );
+
+        let deser = Biscuit::from(&attenuated1.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.blocks().unwrap()[0].nonce, Some(nonce1));
+        assert!(deser.authority().unwrap().nonce.is_some());
+        assert_eq!(deser.revocation_ids(), attenuated1.revocation_ids());
+    }
+
+    #[test]
+    fn revocation_store() {
+        use super::revocation::{MemoryRevocationStore, RevocationStore};
+        use std::sync::Arc;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_source(&keypair2, "check if true").unwrap();
+
+        let store = Arc::new(MemoryRevocationStore::new());
+        let verify = |token: &Biscuit, store: Arc<MemoryRevocationStore>| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.set_revocation_store(store);
+            verifier.allow().unwrap();
+            verifier.verify()
+        };
+
+        assert!(verify(&biscuit2, store.clone()).is_ok());
+
+        store.revoke(&biscuit2.unique_revocation_ids()[1]);
+        assert_eq!(
+            verify(&biscuit2, store.clone()),
+            Err(error::Token::Revoked(1))
+        );
+        // the unique id of the second block does not affect the first one
+        assert!(verify(&biscuit1, store.clone()).is_ok());
+
+        store.revoke(&biscuit1.revocation_ids()[0]);
+        assert_eq!(
+            verify(&biscuit2, store.clone()),
+            Err(error::Token::Revoked(0))
+        );
+        assert!(store.is_revoked(&biscuit2.revocation_ids()[0]));
+        assert_eq!(store.len(), 2);
+
+        assert!(store.unrevoke(&biscuit1.revocation_ids()[0]));
+        assert!(store.unrevoke(&biscuit2.unique_revocation_ids()[1]));
+        assert!(store.is_empty());
+
+        // a verifier loaded with add_token checks the store too
+        store.revoke(&biscuit2.revocation_ids()[1]);
+        let mut verifier = Verifier::new().unwrap();
+        verifier.set_revocation_store(store);
+        verifier.add_token(&biscuit2, root.public()).unwrap();
+        verifier.allow().unwrap();
+        assert_eq!(verifier.verify(), Err(error::Token::Revoked(1)));
+    }
+
+    #[test]
+    fn revocation_ids() {
+        use sha2::{Digest, Sha256};
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_source(&keypair2, "check if resource(#ambient, \"file1\")")
+            .unwrap();
+
+        let container = biscuit2.container.as_ref().unwrap();
+        let mut h = Sha256::new();
+        h.update(&container.authority);
+        h.update(&container.keys[0].to_bytes());
+        assert_eq!(biscuit2.revocation_ids()[0], h.finalize().as_slice());
+
+        assert_eq!(biscuit2.revocation_ids().len(), 2);
+        assert_eq!(biscuit2.unique_revocation_ids().len(), 2);
+        assert_eq!(biscuit2.revocation_ids()[0], biscuit1.revocation_ids()[0]);
+
+        let deser = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.revocation_ids(), biscuit2.revocation_ids());
+        assert_eq!(
+            deser.unique_revocation_ids(),
+            biscuit2.unique_revocation_ids()
+        );
+
+        let sealed = biscuit2.seal(b"secret").unwrap();
+        let sealed = Biscuit::from_sealed(&sealed, b"secret").unwrap();
+        assert!(sealed.revocation_ids().is_empty());
+    }
+
+    #[test]
+    fn add_facts() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_facts(
+                (0..10).map(|i| fact("right", &[s("authority"), int(i), s("read")])),
+            )
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.authority().unwrap().facts.len(), 10);
+
+        let mut block = biscuit1.create_block();
+        block.add_facts(vec!["allowed(1)", "allowed(2)"]).unwrap();
+        assert!(block.add_facts(vec!["allowed(3)", "allowed("]).is_err());
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block)
+            .unwrap();
+        assert_eq!(biscuit2.blocks().unwrap()[0].facts.len(), 3);
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier
+            .add_facts((0..1000).map(|i| fact("resource", &[s("ambient"), int(i)])))
+            .unwrap();
+        verifier
+            .add_check("check if resource(#ambient, 999), right(#authority, 9, #read)")
+            .unwrap();
+        verifier.allow().unwrap();
+        let limits = super::verifier::VerifierLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+        verifier.verify_with_limits(limits).unwrap();
+    }
+
+    #[test]
+    fn symbol_stats() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(#authority, \"file1\", #myread)")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_source(&keypair2, "check if right(#authority, \"file1\", #myread)")
+            .unwrap();
+        let keypair3 = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = biscuit2
+            .append_source(
+                &keypair3,
+                "check if myresource(#ambient, $file), $file.starts_with(\"/tmp\")",
+            )
+            .unwrap();
+
+        let stats = biscuit3.symbol_stats().unwrap();
+        assert_eq!(stats.len(), 3);
+        // "myread"
+        assert_eq!(stats[0].defined, 1);
+        assert_eq!(stats[0].defined_bytes, 6);
+        // "right", "authority"
+        assert_eq!(stats[0].reused, 2);
+        // only the "query" head of the check, "file1" is a string
+        assert_eq!(stats[1].defined, 1);
+        assert_eq!(stats[1].reused, 3);
+        // "myresource" and the "file" variable, "query" is reused
+        assert_eq!(stats[2].defined, 2);
+        assert_eq!(stats[2].defined_bytes, 14);
+        assert_eq!(stats[2].reused, 2);
+
+        // deserializing keeps the same tables
+        let deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+        assert_eq!(deser.symbol_stats().unwrap(), stats);
+    }
+
+    #[test]
+    fn lazy_blocks() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(#authority, \"file1\", #read)")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_source(&keypair2, "check if resource(#ambient, \"file1\")")
+            .unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        let token = Biscuit::from(&serialized).unwrap();
+        token.check_root_key(root.public()).unwrap();
+        assert_eq!(
+            token.revocation_identifiers(),
+            biscuit2.revocation_identifiers()
+        );
+        assert_eq!(token.block_count(), 2);
+        assert!(token.authority.block.get().is_none());
+        assert!(token.blocks[0].block.get().is_none());
+
+        let mut verifier = token.verify(root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.allow().unwrap();
+        verifier.verify().unwrap();
+        assert!(token.authority.block.get().is_some());
+        assert!(token.blocks[0].block.get().is_some());
+    }
+
+    #[test]
+    fn verifier_pool() {
+        use super::pool::VerifierPool;
+        use super::verifier::{Verifier, VerifierLimits};
+        use std::sync::Arc;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<VerifierPool>();
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(#authority, \"file1\", #read)")
+            .unwrap();
+        let token = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut template = Verifier::new().unwrap();
+        template
+            .add_policy("allow if resource(#ambient, $f), operation(#ambient, $op), right(#authority, $f, $op)")
+            .unwrap();
+        let pool = Arc::new(VerifierPool::new(template, 2));
+        assert_eq!(pool.idle(), 2);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let pool = pool.clone();
+                let token = token.clone();
+                let root = root.public();
+                std::thread::spawn(move || {
+                    let mut verifier = pool.get();
+                    verifier.add_token(&token, root).unwrap();
+                    verifier.add_resource(&format!("file{}", i % 2));
+                    verifier.add_operation("read");
+                    verifier
+                        .verify_with_limits(VerifierLimits {
+                            max_time: Duration::from_secs(10),
+                            ..Default::default()
+                        })
+                        .is_ok()
+                })
+            })
+            .collect();
+        let results: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![false, true, false, true]);
+
+        // the request data does not stay in the pool
+        assert_eq!(pool.idle(), 2);
+        let verifier = pool.get();
+        assert_eq!(pool.idle(), 1);
+        assert!(verifier.dump().0.is_empty());
+    }
+
+    #[test]
+    fn verify_batch() {
+        use super::verifier::{Verifier, VerifierLimits};
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let tokens: Vec<Biscuit> = (0..3)
+            .map(|i| {
+                let mut builder = Biscuit::builder(&root);
+                builder
+                    .add_authority_fact(fact(
+                        "right",
+                        &[s("authority"), string(&format!("file{}", i)), s("read")],
+                    ))
+                    .unwrap();
+                builder.build_with_rng(&mut rng).unwrap()
+            })
+            .collect();
+        let other_root = KeyPair::new_with_rng(&mut rng);
+        let foreign = Biscuit::builder(&other_root)
+            .build_with_rng(&mut rng)
+            .unwrap();
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_resource("file1");
+        verifier.add_operation("read");
+        verifier
+            .add_policy("allow if resource(#ambient, $f), operation(#ambient, $op), right(#authority, $f, $op)")
+            .unwrap();
+
+        let mut batch = tokens.clone();
+        batch.push(foreign);
+        let limits = VerifierLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let results = verifier.verify_batch_with_limits(&batch, root.public(), limits);
+
+        assert_eq!(results.len(), 4);
+        assert!(matches!(
+            results[0],
+            Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy))
+        ));
+        assert_eq!(results[1].as_ref().map(|r| r.policy_id), Ok(0));
+        assert!(results[2].is_err());
+        assert_eq!(
+            results[3],
+            Err(error::Token::Format(error::Format::UnknownPublicKey))
+        );
+    }
+
+    #[test]
+    fn signature_cache() {
+        use super::cache::SignatureCache;
+        use super::verifier::FixedClock;
+        use std::time::SystemTime;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let token1 = Biscuit::builder(&root)
+            .build_with_rng(&mut rng)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let token2 = Biscuit::builder(&root)
+            .build_with_rng(&mut rng)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut cache = SignatureCache::new(1, Duration::from_secs(60));
+        cache.set_clock(FixedClock(start));
+
+        let mut corrupted = token1.clone();
+        let len = corrupted.len();
+        corrupted[len - 1] ^= 1;
+        assert!(Biscuit::from_with_cache(&corrupted, &cache).is_err());
+        assert!(cache.is_empty());
+
+        Biscuit::from_with_cache(&token1, &cache).unwrap();
+        let key1 = SignatureCache::digest(&token1);
+        assert!(cache.contains(&key1));
+
+        // the oldest token is evicted when the cache is full
+        Biscuit::from_with_cache(&token2, &cache).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(!cache.contains(&key1));
+        let key2 = SignatureCache::digest(&token2);
+        assert!(cache.contains(&key2));
+
+        cache.set_clock(FixedClock(start + Duration::from_secs(61)));
+        assert!(!cache.contains(&key2));
+        assert!(cache.is_empty());
+
+        // a TTL past the latest representable time never expires
+        let mut cache = SignatureCache::new(1, Duration::MAX);
+        cache.set_clock(FixedClock(start));
+        Biscuit::from_with_cache(&token1, &cache).unwrap();
+        assert!(cache.contains(&key1));
+    }
+
+    #[test]
+    fn verify_deadline() {
+        use super::verifier::{Verifier, VerifierLimits};
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.allow().unwrap();
+        let limits = VerifierLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        assert_eq!(
+            verifier
+                .verify_with_deadline(limits.clone(), deadline)
+                .map(|r| r.policy_id),
+            Ok(0)
+        );
+
+        let deadline = std::time::Instant::now();
+        assert_eq!(
+            verifier.verify_with_deadline(limits, deadline),
+            Err(error::Token::RunLimit(error::RunLimit::Timeout))
+        );
+    }
+
+    #[test]
+    fn verify_result() {
+        use super::verifier::Verifier;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.add_fact(fact("name", &[s("test")])).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_policy("deny if revoked(#authority)").unwrap();
+        verifier
+            .add_labeled_policy(
+                "readers",
+                "allow if right(#authority, $file, #read), name($name)",
+            )
+            .unwrap();
+        let res = verifier.verify().unwrap();
+        assert_eq!(res.policy_id, 1);
+        assert_eq!(res.label.as_deref(), Some("readers"));
+        assert_eq!(
+            res.policy,
+            "allow if right(#authority, $file, #read), name($name)"
+        );
+        assert_eq!(res.contributing_blocks, vec![0, 1]);
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_token(&biscuit2, root.public()).unwrap();
+        verifier.add_policy("allow if name($name)").unwrap();
+        let res = verifier.verify().unwrap();
+        assert_eq!(res.label, None);
+        assert_eq!(res.contributing_blocks, vec![1]);
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_token(&biscuit2, root.public()).unwrap();
+        verifier
+            .add_labeled_policy("named", "deny if name($name)")
+            .unwrap();
+        assert_eq!(
+            verifier.verify(),
+            Err(Token::FailedLogic(Logic::Deny {
+                policy_id: 0,
+                label: Some("named".to_string()),
+                policy: "deny if name($name)".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn verify_metrics() {
+        use super::verifier::Verifier;
+
+        let mut verifier = Verifier::new().unwrap();
+        assert!(verifier.metrics().is_none());
+
+        verifier.add_fact("parent(\"a\", \"b\")").unwrap();
+        verifier.add_fact("parent(\"b\", \"c\")").unwrap();
+        verifier
+            .add_rule("grandparent($a, $c) <- parent($a, $b), parent($b, $c)")
+            .unwrap();
+        verifier
+            .add_check("check if grandparent(\"a\", \"c\")")
+            .unwrap();
+        verifier.allow().unwrap();
+        verifier.verify().unwrap();
+
+        let metrics = verifier.metrics().unwrap();
+        assert_eq!(metrics.facts_generated, 1);
+        assert_eq!(metrics.iterations, 1);
+
+        verifier.add_check("check if parent(\"c\", \"d\")").unwrap();
+        assert!(verifier.verify().is_err());
+        let metrics = verifier.metrics().unwrap();
+        assert_eq!(metrics.facts_generated, 0);
+        assert_eq!(metrics.iterations, 0);
+    }
+
+    #[test]
+    fn suggested_limits() {
+        use super::verifier::{Verifier, VerifierLimits, VerifierMetrics};
+
+        let mut verifier = Verifier::new().unwrap();
+        for i in 0..10 {
+            verifier
+                .add_fact(fact("edge", &[int(i), int(i + 1)]))
+                .unwrap();
+        }
+        verifier.add_rule("path($a, $b) <- edge($a, $b)").unwrap();
+        verifier
+            .add_rule("path($a, $c) <- path($a, $b), edge($b, $c)")
+            .unwrap();
+        verifier.allow().unwrap();
+
+        let generous = VerifierLimits {
+            max_facts: 10_000,
+            max_iterations: 1000,
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let recorded: Vec<VerifierMetrics> = (0..3)
+            .map(|_| {
+                let mut verifier = verifier.clone();
+                verifier.verify_with_limits(generous.clone()).unwrap();
+                verifier.metrics().unwrap().clone()
+            })
+            .collect();
+
+        let limits = VerifierLimits::suggest(&recorded, 0.0).unwrap();
+        assert_eq!(limits.max_facts as usize, recorded[0].facts + 1);
+        assert_eq!(limits.max_iterations, recorded[0].iterations + 1);
+
+        let limits = VerifierLimits {
+            max_time: Duration::from_secs(10),
+            ..limits
+        };
+        assert!(verifier.clone().verify_with_limits(limits.clone()).is_ok());
+        let tight = VerifierLimits {
+            max_facts: limits.max_facts - 1,
+            ..limits
+        };
+        assert_eq!(
+            verifier.clone().verify_with_limits(tight),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyFacts))
+        );
+
+        assert!(VerifierLimits::suggest(&Vec::new(), 0.5).is_none());
+    }
+
+    #[test]
+    fn invalid_rules_fail_early() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let invalid = rule("right", &[var("unbound")], &[pred("resource", &[var("r")])]);
+
+        let mut builder = Biscuit::builder(&root);
+        assert_eq!(
+            builder.add_authority_rule(invalid.clone()),
+            Err(Token::FailedLogic(Logic::InvalidBlockRule(
+                0,
+                invalid.to_string()
+            )))
+        );
+        assert_eq!(
+            builder.add_authority_fact("values([1, \"a\"])"),
+            Err(Token::ParseError(error::ParseError {
+                line: 1,
+                column: 9,
+                fragment: "1".to_string(),
+                expected: vec![],
+                message: Some("set elements must have the same type".to_string()),
+                hint: None,
+            }))
+        );
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        assert_eq!(
+            block2.add_rule(invalid.clone()),
+            Err(Token::FailedLogic(Logic::InvalidBlockRule(
+                1,
+                invalid.to_string()
+            )))
+        );
+        assert!(block2.rules.is_empty());
+    }
+
+    #[test]
+    fn block_rules_without_variables() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2
+            .add_rule("granted(#read) <- operation(#ambient, #read)")
+            .unwrap();
+        block2.add_check("check if granted(#read)").unwrap();
+
+        let unbound = rule(
+            "right",
+            &[var("unbound")],
+            &[pred("resource", &[s("file1")])],
+        );
+        assert_eq!(
+            block2.add_rule(unbound.clone()),
+            Err(Token::FailedLogic(Logic::InvalidBlockRule(
+                1,
+                unbound.to_string()
+            )))
+        );
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+        let biscuit2 = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+
+        let verify = |operation: &str| {
+            let mut verifier = Verifier::new().unwrap();
+            verifier.add_token(&biscuit2, root.public()).unwrap();
+            verifier.add_operation(operation);
+            verifier.allow().unwrap();
+            verifier.verify()
+        };
+        assert!(verify("read").is_ok());
+        assert!(verify("write").is_err());
+    }
 }
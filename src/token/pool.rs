@@ -0,0 +1,113 @@
+//! pool of preloaded verifiers, shared between threads
+use super::verifier::Verifier;
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Mutex,
+};
+
+/// keeps verifiers built from the same policies, ready to be used for a request
+///
+/// the pool is created from a template verifier containing the facts, rules,
+/// checks and policies common to all requests. The template is frozen (see
+/// [`Verifier::freeze`]) so its world is shared by all the verifiers of the
+/// pool instead of being copied.
+///
+/// [`VerifierPool::get`] checks out a verifier, to which the token and the
+/// request's facts can be added. When the [`PooledVerifier`] is dropped,
+/// a fresh copy of the template takes its place in the pool.
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, pool::VerifierPool, verifier::Verifier}};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap();
+///
+/// let mut template = Verifier::new().unwrap();
+/// template.add_policy("allow if operation(#ambient, #read)").unwrap();
+/// let pool = VerifierPool::new(template, 8);
+///
+/// let mut verifier = pool.get();
+/// verifier.add_token(&token, root.public()).unwrap();
+/// verifier.add_operation("read");
+/// assert!(verifier.verify().is_ok());
+/// ```
+pub struct VerifierPool {
+    template: Verifier,
+    size: usize,
+    idle: Mutex<Vec<Verifier>>,
+}
+
+impl VerifierPool {
+    /// creates a pool holding `size` copies of the template
+    pub fn new(mut template: Verifier, size: usize) -> Self {
+        template.freeze();
+        let idle = (0..size).map(|_| template.clone()).collect();
+
+        VerifierPool {
+            template,
+            size,
+            idle: Mutex::new(idle),
+        }
+    }
+
+    /// checks out a verifier
+    ///
+    /// if all the verifiers of the pool are in use, a new one is created
+    /// from the template
+    pub fn get(&self) -> PooledVerifier<'_> {
+        let verifier = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| self.template.clone());
+
+        PooledVerifier {
+            pool: self,
+            verifier: Some(verifier),
+        }
+    }
+
+    /// maximum number of verifiers kept in the pool
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// number of verifiers ready to be checked out
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// a verifier checked out from a [`VerifierPool`]
+pub struct PooledVerifier<'a> {
+    pool: &'a VerifierPool,
+    verifier: Option<Verifier>,
+}
+
+impl<'a> Deref for PooledVerifier<'a> {
+    type Target = Verifier;
+
+    fn deref(&self) -> &Verifier {
+        self.verifier.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for PooledVerifier<'a> {
+    fn deref_mut(&mut self) -> &mut Verifier {
+        self.verifier.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for PooledVerifier<'a> {
+    fn drop(&mut self) {
+        // the verifier contains the data of the request, it is replaced
+        // with a clean one
+        self.verifier = None;
+
+        let mut idle = self.pool.idle.lock().unwrap();
+        if idle.len() < self.pool.size {
+            idle.push(self.pool.template.clone());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! proof of possession of a key referenced in a token
+//!
+//! a token minted with [`BiscuitBuilder::bind_to_holder`](crate::token::builder::BiscuitBuilder::bind_to_holder)
+//! carries the public key of its holder, and a check requiring a proof that
+//! the holder has the private key:
+//!
+//! ```text
+//! holder_key(#authority, hex:...);
+//! check if holder_key(#authority, $key), possession(#ambient, $key);
+//! ```
+//!
+//! for each request, the server sends a fresh nonce, the holder signs it
+//! with [`Proof::new`], and the server verifies the proof with
+//! [`Verifier::verify_possession`](crate::token::verifier::Verifier::verify_possession),
+//! which adds the `possession` fact. A stolen token is not enough to make
+//! requests, as long as nonces are not reused
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, possession::{self, Proof}}};
+//!
+//! let root = KeyPair::new();
+//! let holder = KeyPair::new();
+//!
+//! let mut builder = Biscuit::builder(&root);
+//! builder.bind_to_holder(holder.public());
+//! let token = builder.build().unwrap();
+//!
+//! // the server sends a nonce, the holder answers with a proof
+//! let nonce = possession::nonce();
+//! let proof = Proof::new(&holder, &nonce).to_bytes();
+//!
+//! let mut verifier = token.verify(root.public()).unwrap();
+//! let proof = Proof::from_bytes(&proof).unwrap();
+//! assert!(verifier.verify_possession(&nonce, &proof));
+//! verifier.allow().unwrap();
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::builder::{fact, pred, rule, s, var, Check, Fact, Term};
+use crate::crypto::{self, KeyPair, PublicKey};
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use std::convert::TryInto;
+
+/// signature of a nonce by the holder of a token
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proof {
+    signature: (Scalar, Scalar),
+}
+
+impl Proof {
+    /// signs the nonce sent by the server
+    pub fn new(holder: &KeyPair, nonce: &[u8]) -> Self {
+        Proof::new_with_rng(&mut rand::rngs::OsRng, holder, nonce)
+    }
+
+    /// signs the nonce, using the provided CSPRNG
+    pub fn new_with_rng<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        holder: &KeyPair,
+        nonce: &[u8],
+    ) -> Self {
+        Proof {
+            signature: holder.sign(rng, &message(holder.public(), nonce)),
+        }
+    }
+
+    /// checks that the nonce was signed by the private key of `key`
+    pub fn verify(&self, key: PublicKey, nonce: &[u8]) -> bool {
+        crypto::verify(&key.0, &message(key, nonce), &self.signature)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let (d, z) = &self.signature;
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(d.as_bytes());
+        bytes[32..].copy_from_slice(z.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 64 {
+            return None;
+        }
+        let d = Scalar::from_canonical_bytes(bytes[..32].try_into().unwrap())?;
+        let z = Scalar::from_canonical_bytes(bytes[32..].try_into().unwrap())?;
+
+        Some(Proof { signature: (d, z) })
+    }
+}
+
+/// generates a random nonce for a challenge
+pub fn nonce() -> [u8; 32] {
+    let mut nonce = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+/// content signed by the holder
+fn message(key: PublicKey, nonce: &[u8]) -> Vec<u8> {
+    let mut message = b"biscuit-possession".to_vec();
+    message.extend_from_slice(&key.to_bytes());
+    message.extend_from_slice(nonce);
+    message
+}
+
+pub(crate) fn holder_key(key: PublicKey) -> Fact {
+    fact(
+        "holder_key",
+        &[s("authority"), Term::Bytes(key.to_bytes().to_vec())],
+    )
+}
+
+pub(crate) fn check() -> Check {
+    Check {
+        queries: vec![rule(
+            "possession",
+            &[var("key")],
+            &[
+                pred("holder_key", &[s("authority"), var("key")]),
+                pred("possession", &[s("ambient"), var("key")]),
+            ],
+        )],
+    }
+}
+
+pub(crate) fn possession(key: PublicKey) -> Fact {
+    fact(
+        "possession",
+        &[s("ambient"), Term::Bytes(key.to_bytes().to_vec())],
+    )
+}
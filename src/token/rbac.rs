@@ -0,0 +1,305 @@
+//! role based access control on top of Datalog
+//!
+//! roles, their permissions and the roles of each user are described with
+//! [`Role`] and [`Assignment`], gathered in a [`Rbac`] and compiled to
+//! facts and rules. [`Verifier::require_permission`](crate::token::verifier::Verifier::require_permission)
+//! then checks that the user of the token, from its `user(#authority, $user)`
+//! fact, has a permission:
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, builder::{fact, s, string}, rbac::{Assignment, Rbac, Role}}};
+//!
+//! let rbac = Rbac::new()
+//!     .role(Role::new("viewer").permission("doc:read"))
+//!     .role(Role::new("editor").permission("doc:write").inherits("viewer"))
+//!     .assign(Assignment::new("alice", "editor"));
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_fact(fact("user", &[s("authority"), string("alice")])).unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! let mut verifier = token.verify(root.public()).unwrap();
+//! rbac.apply(&mut verifier);
+//! verifier.require_permission("doc:read");
+//! verifier.allow().unwrap();
+//! assert!(verifier.verify().is_ok());
+//! ```
+//!
+//! the Datalog stays inspectable, `rbac.to_string()` prints it:
+//!
+//! ```text
+//! role_permission(#ambient, "viewer", "doc:read");
+//! role_permission(#ambient, "editor", "doc:write");
+//! role_parent(#ambient, "editor", "viewer");
+//! assignment(#ambient, "alice", "editor");
+//! role_permission(#ambient, $role, $permission) <- role_parent(#ambient, $role, $parent), role_permission(#ambient, $parent, $permission);
+//! permission(#ambient, $user, $permission) <- assignment(#ambient, $user, $role), role_permission(#ambient, $role, $permission);
+//! permission(#ambient, $user, $permission) <- assignment(#authority, $user, $role), role_permission(#ambient, $role, $permission);
+//! ```
+use super::builder::{fact, pred, rule, s, string, var, Check, Fact, Rule};
+use super::verifier::Verifier;
+use std::fmt;
+
+/// permission, like `doc:write`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Permission(pub String);
+
+impl Permission {
+    /// permission to do `action` on the resources of `kind`, as `kind:action`
+    pub fn new(kind: &str, action: &str) -> Self {
+        Permission(format!("{}:{}", kind, action))
+    }
+}
+
+impl From<&str> for Permission {
+    fn from(permission: &str) -> Self {
+        Permission(permission.to_string())
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// role, granting permissions and inheriting those of other roles
+#[derive(Clone, Debug, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    /// roles whose permissions are included
+    pub inherits: Vec<String>,
+}
+
+impl Role {
+    pub fn new(name: &str) -> Self {
+        Role {
+            name: name.to_string(),
+            permissions: Vec::new(),
+            inherits: Vec::new(),
+        }
+    }
+
+    pub fn permission<P: Into<Permission>>(mut self, permission: P) -> Self {
+        self.permissions.push(permission.into());
+        self
+    }
+
+    pub fn inherits(mut self, role: &str) -> Self {
+        self.inherits.push(role.to_string());
+        self
+    }
+
+    /// `role_permission` and `role_parent` facts
+    pub fn facts(&self) -> Vec<Fact> {
+        let permissions = self.permissions.iter().map(|p| {
+            fact(
+                "role_permission",
+                &[s("ambient"), string(&self.name), string(&p.0)],
+            )
+        });
+        let parents = self.inherits.iter().map(|parent| {
+            fact(
+                "role_parent",
+                &[s("ambient"), string(&self.name), string(parent)],
+            )
+        });
+        permissions.chain(parents).collect()
+    }
+}
+
+/// role given to a user
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub user: String,
+    pub role: String,
+}
+
+impl Assignment {
+    pub fn new(user: &str, role: &str) -> Self {
+        Assignment {
+            user: user.to_string(),
+            role: role.to_string(),
+        }
+    }
+
+    /// `assignment(#ambient, user, role)`, for the verifier
+    pub fn fact(&self) -> Fact {
+        fact(
+            "assignment",
+            &[s("ambient"), string(&self.user), string(&self.role)],
+        )
+    }
+
+    /// `assignment(#authority, user, role)`, for the authority block of a
+    /// token carrying the roles of its user
+    pub fn authority_fact(&self) -> Fact {
+        fact(
+            "assignment",
+            &[s("authority"), string(&self.user), string(&self.role)],
+        )
+    }
+}
+
+/// roles and assignments of a service
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rbac {
+    pub roles: Vec<Role>,
+    pub assignments: Vec<Assignment>,
+}
+
+impl Rbac {
+    pub fn new() -> Self {
+        Rbac::default()
+    }
+
+    pub fn role(mut self, role: Role) -> Self {
+        self.roles.push(role);
+        self
+    }
+
+    pub fn assign(mut self, assignment: Assignment) -> Self {
+        self.assignments.push(assignment);
+        self
+    }
+
+    pub fn facts(&self) -> Vec<Fact> {
+        self.roles
+            .iter()
+            .flat_map(Role::facts)
+            .chain(self.assignments.iter().map(Assignment::fact))
+            .collect()
+    }
+
+    /// rules deriving `permission(#ambient, $user, $permission)`
+    pub fn rules() -> Vec<Rule> {
+        let permission = |origin: &str| {
+            rule(
+                "permission",
+                &[s("ambient"), var("user"), var("permission")],
+                &[
+                    pred("assignment", &[s(origin), var("user"), var("role")]),
+                    pred(
+                        "role_permission",
+                        &[s("ambient"), var("role"), var("permission")],
+                    ),
+                ],
+            )
+        };
+
+        vec![
+            rule(
+                "role_permission",
+                &[s("ambient"), var("role"), var("permission")],
+                &[
+                    pred("role_parent", &[s("ambient"), var("role"), var("parent")]),
+                    pred(
+                        "role_permission",
+                        &[s("ambient"), var("parent"), var("permission")],
+                    ),
+                ],
+            ),
+            permission("ambient"),
+            permission("authority"),
+        ]
+    }
+
+    /// adds the facts and rules to the verifier
+    pub fn apply(&self, verifier: &mut Verifier) {
+        verifier.extend(self.facts());
+        verifier.extend(Rbac::rules());
+    }
+}
+
+impl fmt::Display for Rbac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for fact in self.facts() {
+            writeln!(f, "{};", fact)?;
+        }
+        for rule in Rbac::rules() {
+            writeln!(f, "{};", rule)?;
+        }
+        Ok(())
+    }
+}
+
+/// `check if user(#authority, $user), permission(#ambient, $user, permission)`
+pub(crate) fn permission_check(permission: &Permission) -> Check {
+    Check {
+        queries: vec![rule(
+            "permission",
+            &[var("user")],
+            &[
+                pred("user", &[s("authority"), var("user")]),
+                pred(
+                    "permission",
+                    &[s("ambient"), var("user"), string(&permission.0)],
+                ),
+            ],
+        )],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::Biscuit;
+
+    #[test]
+    fn permissions() {
+        let rbac = Rbac::new()
+            .role(Role::new("viewer").permission("doc:read"))
+            .role(
+                Role::new("editor")
+                    .permission(Permission::new("doc", "write"))
+                    .inherits("viewer"),
+            )
+            .role(
+                Role::new("admin")
+                    .permission("user:delete")
+                    .inherits("editor"),
+            )
+            .assign(Assignment::new("alice", "editor"));
+        assert_eq!(
+            rbac.to_string().lines().next(),
+            Some("role_permission(#ambient, \"viewer\", \"doc:read\");")
+        );
+
+        let root = KeyPair::new();
+        let token = |user: &str, roles: &[&str]| {
+            let mut builder = Biscuit::builder(&root);
+            builder
+                .add_authority_fact(fact("user", &[s("authority"), string(user)]))
+                .unwrap();
+            for role in roles {
+                builder
+                    .add_authority_fact(Assignment::new(user, role).authority_fact())
+                    .unwrap();
+            }
+            builder.build().unwrap()
+        };
+        let allowed = |token: &Biscuit, permission: &str| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            rbac.apply(&mut verifier);
+            verifier.require_permission(permission);
+            verifier.allow().unwrap();
+            verifier.verify().is_ok()
+        };
+
+        let alice = token("alice", &[]);
+        assert!(allowed(&alice, "doc:read"));
+        assert!(allowed(&alice, "doc:write"));
+        assert!(!allowed(&alice, "user:delete"));
+
+        let bob = token("bob", &[]);
+        assert!(!allowed(&bob, "doc:read"));
+
+        // roles carried by the token
+        let carol = token("carol", &["admin"]);
+        assert!(allowed(&carol, "doc:read"));
+        assert!(allowed(&carol, "user:delete"));
+    }
+}
@@ -0,0 +1,343 @@
+//! revocation lists consulted by the verifier
+use super::delegation::seconds;
+use super::verifier::{Clock, SystemClock};
+use crate::crypto::{self, KeyPair, PublicKey};
+use crate::error;
+use crate::format::schema;
+use crate::prost::Message;
+use curve25519_dalek::scalar::Scalar;
+use rand_core::{CryptoRng, RngCore};
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fmt,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// source of revoked token identifiers
+///
+/// when a store is set on a verifier with [`Verifier::set_revocation_store`](crate::token::verifier::Verifier::set_revocation_store),
+/// the revocation identifiers and unique revocation identifiers of every
+/// block of the token are looked up before running the Datalog engine, and
+/// the verification fails with [`error::Token::Revoked`](crate::error::Token::Revoked)
+/// if one of them was revoked.
+pub trait RevocationStore: Send + Sync {
+    /// returns `true` if this identifier was revoked
+    fn is_revoked(&self, id: &[u8]) -> bool;
+
+    /// returns the position of the first revoked identifier
+    ///
+    /// stores backed by a database can override this to look up all the
+    /// identifiers of a token in a single request
+    fn first_revoked(&self, ids: &[&[u8]]) -> Option<usize> {
+        ids.iter().position(|id| self.is_revoked(id))
+    }
+}
+
+/// revocation list kept in memory, that can be shared between threads
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, revocation::MemoryRevocationStore}};
+/// use std::sync::Arc;
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap();
+///
+/// let store = Arc::new(MemoryRevocationStore::new());
+/// store.revoke(&token.revocation_ids()[0]);
+///
+/// let mut verifier = token.verify(root.public()).unwrap();
+/// verifier.set_revocation_store(store);
+/// verifier.allow().unwrap();
+/// assert!(verifier.verify().is_err());
+/// ```
+#[derive(Default)]
+pub struct MemoryRevocationStore {
+    ids: RwLock<HashSet<Vec<u8>>>,
+}
+
+impl MemoryRevocationStore {
+    pub fn new() -> Self {
+        MemoryRevocationStore::default()
+    }
+
+    /// adds an identifier to the list
+    pub fn revoke(&self, id: &[u8]) {
+        self.ids.write().unwrap().insert(id.to_vec());
+    }
+
+    /// removes an identifier from the list, returns `false` if it was not present
+    pub fn unrevoke(&self, id: &[u8]) -> bool {
+        self.ids.write().unwrap().remove(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn is_revoked(&self, id: &[u8]) -> bool {
+        self.ids.read().unwrap().contains(id)
+    }
+
+    fn first_revoked(&self, ids: &[&[u8]]) -> Option<usize> {
+        let revoked = self.ids.read().unwrap();
+        ids.iter().position(|id| revoked.contains(*id))
+    }
+}
+
+impl Extend<Vec<u8>> for MemoryRevocationStore {
+    fn extend<T: IntoIterator<Item = Vec<u8>>>(&mut self, iter: T) {
+        self.ids.get_mut().unwrap().extend(iter);
+    }
+}
+
+impl fmt::Debug for MemoryRevocationStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryRevocationStore")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// revocation list signed by its issuer, to be distributed out of band
+///
+/// the issuer publishes a new list with a higher sequence number whenever
+/// tokens are revoked, and before the expiration date of the current one.
+/// Services fetch the list from an untrusted location, check it with
+/// [`RevocationList::verify`], passing the sequence number of the last list
+/// they accepted so an older list cannot be replayed, then use the
+/// [`VerifiedRevocationList`] it returns as the revocation store of their
+/// verifiers
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, revocation::RevocationList}};
+/// use std::{sync::Arc, time::{Duration, SystemTime}};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap();
+///
+/// // the issuer signs the list
+/// let issuer = KeyPair::new();
+/// let list = RevocationList::new(
+///     &issuer,
+///     1,
+///     SystemTime::now() + Duration::from_secs(3600),
+///     vec![token.revocation_ids()[0].clone()],
+/// );
+/// let data = list.to_vec().unwrap();
+///
+/// // the service checks it before using it
+/// let list = RevocationList::parse(&data).unwrap();
+/// let list = list.verify(issuer.public(), SystemTime::now(), 0).unwrap();
+/// // the next list must have a sequence number of at least 1
+/// assert_eq!(list.list().sequence, 1);
+///
+/// let mut verifier = token.verify(root.public()).unwrap();
+/// verifier.set_revocation_store(Arc::new(list));
+/// verifier.allow().unwrap();
+/// assert!(verifier.verify().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RevocationList {
+    /// key that signed the list
+    pub issuer: PublicKey,
+    /// incremented by the issuer for each new list, so that an older list
+    /// cannot replace a newer one
+    pub sequence: u64,
+    /// date after which the list must not be used, with a precision of one
+    /// second
+    pub expiration: SystemTime,
+    /// revoked identifiers
+    pub ids: Vec<Vec<u8>>,
+    signature: (Scalar, Scalar),
+}
+
+impl RevocationList {
+    /// signs a revocation list
+    pub fn new(issuer: &KeyPair, sequence: u64, expiration: SystemTime, ids: Vec<Vec<u8>>) -> Self {
+        RevocationList::new_with_rng(&mut rand::rngs::OsRng, issuer, sequence, expiration, ids)
+    }
+
+    /// signs a revocation list, using the provided CSPRNG
+    pub fn new_with_rng<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        issuer: &KeyPair,
+        sequence: u64,
+        expiration: SystemTime,
+        ids: Vec<Vec<u8>>,
+    ) -> Self {
+        let expiration = UNIX_EPOCH + Duration::from_secs(seconds(expiration));
+        let signature = issuer.sign(rng, &message(issuer.public(), sequence, expiration, &ids));
+
+        RevocationList {
+            issuer: issuer.public(),
+            sequence,
+            expiration,
+            ids,
+            signature,
+        }
+    }
+
+    /// deserializes a revocation list
+    ///
+    /// the signature is not checked, the list can only be used as a
+    /// revocation store once [`RevocationList::verify`] succeeded
+    pub fn parse(slice: &[u8]) -> Result<Self, error::Format> {
+        let proto = schema::RevocationList::decode(slice)
+            .map_err(|_| error::Format::InvalidRevocationList)?;
+
+        let issuer =
+            PublicKey::from_bytes(&proto.issuer).ok_or(error::Format::InvalidRevocationList)?;
+        if proto.signature.len() != 64 {
+            return Err(error::Format::InvalidRevocationList);
+        }
+        let expiration = UNIX_EPOCH
+            .checked_add(Duration::from_secs(proto.expiration))
+            .ok_or(error::Format::InvalidRevocationList)?;
+        let scalar = |bytes: &[u8]| {
+            Scalar::from_canonical_bytes(bytes.try_into().unwrap())
+                .ok_or(error::Format::InvalidRevocationList)
+        };
+
+        Ok(RevocationList {
+            issuer,
+            sequence: proto.sequence,
+            expiration,
+            signature: (
+                scalar(&proto.signature[..32])?,
+                scalar(&proto.signature[32..])?,
+            ),
+            ids: proto.ids,
+        })
+    }
+
+    /// checks that the list was signed by `issuer`, has not expired at the
+    /// date `now`, and that its sequence number is at least `min_sequence`
+    ///
+    /// `min_sequence` should be the sequence number of the last list
+    /// accepted, so a list served again after a newer one was published is
+    /// refused with [`error::Format::OutdatedRevocationList`]
+    pub fn verify(
+        &self,
+        issuer: PublicKey,
+        now: SystemTime,
+        min_sequence: u64,
+    ) -> Result<VerifiedRevocationList, error::Format> {
+        if self.issuer != issuer
+            || !crypto::verify(
+                &self.issuer.0,
+                &message(self.issuer, self.sequence, self.expiration, &self.ids),
+                &self.signature,
+            )
+        {
+            return Err(error::Format::InvalidRevocationList);
+        }
+
+        if now > self.expiration {
+            return Err(error::Format::ExpiredRevocationList);
+        }
+
+        if self.sequence < min_sequence {
+            return Err(error::Format::OutdatedRevocationList {
+                minimum: min_sequence,
+                actual: self.sequence,
+            });
+        }
+
+        Ok(VerifiedRevocationList {
+            ids: self.ids.iter().cloned().collect(),
+            list: self.clone(),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        let (d, z) = &self.signature;
+        let mut signature = d.to_bytes().to_vec();
+        signature.extend_from_slice(&z.to_bytes());
+
+        let proto = schema::RevocationList {
+            issuer: self.issuer.to_bytes().to_vec(),
+            sequence: self.sequence,
+            expiration: seconds(self.expiration),
+            ids: self.ids.clone(),
+            signature,
+        };
+
+        let mut v = Vec::new();
+        proto
+            .encode(&mut v)
+            .map(|_| v)
+            .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
+    }
+}
+
+/// revocation list returned by [`RevocationList::verify`]
+///
+/// it cannot be modified, so the identifiers it contains are the ones
+/// signed by the issuer. Once the list has expired, the store considers
+/// every token revoked, until it is replaced by a newer list
+#[derive(Clone)]
+pub struct VerifiedRevocationList {
+    list: RevocationList,
+    ids: HashSet<Vec<u8>>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl VerifiedRevocationList {
+    pub fn list(&self) -> &RevocationList {
+        &self.list
+    }
+
+    /// replaces the clock used to check the expiration of the list, the
+    /// system clock by default
+    pub fn set_clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.clock.now() > self.list.expiration
+    }
+}
+
+impl RevocationStore for VerifiedRevocationList {
+    fn is_revoked(&self, id: &[u8]) -> bool {
+        self.is_expired() || self.ids.contains(id)
+    }
+
+    fn first_revoked(&self, ids: &[&[u8]]) -> Option<usize> {
+        if self.is_expired() {
+            return if ids.is_empty() { None } else { Some(0) };
+        }
+        ids.iter().position(|id| self.ids.contains(*id))
+    }
+}
+
+impl fmt::Debug for VerifiedRevocationList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerifiedRevocationList")
+            .field("list", &self.list)
+            .finish()
+    }
+}
+
+/// content signed by the issuer
+fn message(issuer: PublicKey, sequence: u64, expiration: SystemTime, ids: &[Vec<u8>]) -> Vec<u8> {
+    let mut message = b"biscuit-revocation-list".to_vec();
+    message.extend_from_slice(&issuer.to_bytes());
+    message.extend_from_slice(&sequence.to_le_bytes());
+    message.extend_from_slice(&seconds(expiration).to_le_bytes());
+    for id in ids.iter() {
+        // the length prefix keeps the identifiers from being split differently
+        message.extend_from_slice(&(id.len() as u64).to_le_bytes());
+        message.extend_from_slice(id);
+    }
+    message
+}
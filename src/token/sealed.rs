@@ -3,90 +3,135 @@ use super::Biscuit;
 use crate::error;
 use hmac::{Hmac, Mac, NewMac};
 use sha2::Sha256;
+use std::collections::HashMap;
 
-use crate::format::{convert::token_block_to_proto_block, schema};
+use crate::format::schema;
 use crate::prost::Message;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// prefix of the key id in the signed data
+const KEY_ID_TAG: &[u8] = b"key_id";
+
 #[derive(Clone, Debug)]
 pub struct SealedBiscuit {
     pub authority: Vec<u8>,
     pub blocks: Vec<Vec<u8>>,
     pub signature: Vec<u8>,
+    /// identifier of the secret the token was sealed with
+    pub key_id: Option<u32>,
 }
 
 impl SealedBiscuit {
     pub fn from_token(token: &Biscuit, secret: &[u8]) -> Result<Self, error::Format> {
-        let mut authority = Vec::new();
-        token_block_to_proto_block(&token.authority)
-            .encode(&mut authority)
-            .map_err(|e| {
-                error::Format::BlockSerializationError(format!(
-                    "error serializing authority block: {:?}",
-                    e
-                ))
-            })?;
-
-        let mut blocks = Vec::new();
-
-        for block in token.blocks.iter() {
-            let mut b = Vec::new();
-            match token_block_to_proto_block(block).encode(&mut b) {
-                Ok(_) => blocks.push(b),
-                Err(e) => {
-                    return Err(error::Format::BlockSerializationError(format!(
-                        "error serializing block: {:?}",
-                        e
-                    )))
-                }
-            }
-        }
+        SealedBiscuit::seal(token, None, secret)
+    }
 
-        let mut mac = HmacSha256::new_varkey(secret).unwrap();
-        mac.update(&authority);
-        for block in blocks.iter() {
-            mac.update(&block);
-        }
+    /// seals the token and records the identifier of the secret, so it can be
+    /// verified with [`SealedBiscuit::from_slice_with_keys`]
+    pub fn from_token_with_key_id(
+        token: &Biscuit,
+        key_id: u32,
+        secret: &[u8],
+    ) -> Result<Self, error::Format> {
+        SealedBiscuit::seal(token, Some(key_id), secret)
+    }
 
-        let signature: Vec<u8> = mac.finalize().into_bytes().to_vec();
+    fn seal(token: &Biscuit, key_id: Option<u32>, secret: &[u8]) -> Result<Self, error::Format> {
+        let authority = token.authority.data.clone();
+        let blocks: Vec<Vec<u8>> = token.blocks.iter().map(|b| b.data.clone()).collect();
 
-        Ok(SealedBiscuit {
+        let mut sealed = SealedBiscuit {
             authority,
             blocks,
-            signature,
-        })
+            signature: Vec::new(),
+            key_id,
+        };
+        sealed.signature = sealed.mac(secret).finalize().into_bytes().to_vec();
+
+        Ok(sealed)
     }
 
+    /// deserializes a sealed token and checks its signature with the secret,
+    /// whatever the key id it was sealed with
     pub fn from_slice(slice: &[u8], secret: &[u8]) -> Result<Self, error::Format> {
+        let deser = SealedBiscuit::decode(slice)?;
+        deser.verify(None, secret)?;
+
+        Ok(deser)
+    }
+
+    /// deserializes a sealed token and checks its signature with the secret
+    /// matching its key id in the set
+    ///
+    /// tokens sealed without a key id, or with a key id absent from the set,
+    /// are refused with [`error::Format::UnknownSealKey`]
+    pub fn from_slice_with_keys(slice: &[u8], keys: &SealKeys) -> Result<Self, error::Format> {
+        let deser = SealedBiscuit::decode(slice)?;
+        match deser.key_id.and_then(|id| Some((id, keys.get(id)?))) {
+            Some((key_id, secret)) => deser.verify(Some(key_id), secret)?,
+            None => {
+                let mut accepted = keys.key_ids().collect::<Vec<_>>();
+                accepted.sort_unstable();
+                return Err(error::Format::UnknownSealKey {
+                    accepted,
+                    actual: deser.key_id,
+                });
+            }
+        }
+
+        Ok(deser)
+    }
+
+    fn decode(slice: &[u8]) -> Result<Self, error::Format> {
         let proto: schema::SealedBiscuit = schema::SealedBiscuit::decode(slice).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
 
-        let deser = SealedBiscuit {
+        Ok(SealedBiscuit {
             authority: proto.authority,
             blocks: proto.blocks,
             signature: proto.signature,
-        };
+            key_id: proto.key_id,
+        })
+    }
 
+    fn mac(&self, secret: &[u8]) -> HmacSha256 {
         let mut mac = HmacSha256::new_varkey(secret).unwrap();
-        mac.update(&deser.authority);
-        for block in deser.blocks.iter() {
-            mac.update(&block);
+        // the key id comes first, with a fixed size after a tag, so it cannot
+        // be confused with the content of the blocks. Tokens sealed without a
+        // key id keep the same signature as before
+        if let Some(key_id) = self.key_id {
+            mac.update(KEY_ID_TAG);
+            mac.update(&key_id.to_le_bytes());
         }
+        mac.update(&self.authority);
+        for block in self.blocks.iter() {
+            mac.update(block);
+        }
+        mac
+    }
 
-        mac.verify(&deser.signature)
-            .map_err(|_| error::Format::SealedSignature)?;
-
-        Ok(deser)
+    fn verify(&self, expected: Option<u32>, secret: &[u8]) -> Result<(), error::Format> {
+        self.mac(secret)
+            .verify(&self.signature)
+            .map_err(|_| error::Format::SealedSignature {
+                expected,
+                actual: self.key_id,
+            })
     }
 
-    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
-        let proto = schema::SealedBiscuit {
+    fn to_proto(&self) -> schema::SealedBiscuit {
+        schema::SealedBiscuit {
             authority: self.authority.clone(),
             blocks: self.blocks.clone(),
             signature: self.signature.clone(),
-        };
+            key_id: self.key_id,
+        }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        let proto = self.to_proto();
 
         let mut v = Vec::new();
         proto
@@ -96,12 +141,68 @@ impl SealedBiscuit {
     }
 
     pub fn serialized_size(&self) -> usize {
-        let proto = schema::SealedBiscuit {
-            authority: self.authority.clone(),
-            blocks: self.blocks.clone(),
-            signature: self.signature.clone(),
-        };
+        self.to_proto().encoded_len()
+    }
+}
+
+/// secrets accepted to verify sealed tokens, indexed by key id
+///
+/// this allows rotating the sealing secret: new tokens are sealed with
+/// [`Biscuit::seal_with_key_id`], while tokens sealed with the previous
+/// secret are still accepted until it is removed from the set
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, sealed::SealKeys}};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap();
+/// let sealed = token.seal_with_key_id(2, b"new secret").unwrap();
+///
+/// let mut keys = SealKeys::new();
+/// keys.insert(1, b"old secret");
+/// keys.insert(2, b"new secret");
+/// assert!(Biscuit::from_sealed_with_keys(&sealed, &keys).is_ok());
+///
+/// keys.remove(2);
+/// assert!(Biscuit::from_sealed_with_keys(&sealed, &keys).is_err());
+/// ```
+#[derive(Clone, Default)]
+pub struct SealKeys {
+    keys: HashMap<u32, Vec<u8>>,
+}
+
+impl SealKeys {
+    pub fn new() -> Self {
+        SealKeys::default()
+    }
+
+    /// adds a secret, replacing the one with the same key id
+    pub fn insert(&mut self, key_id: u32, secret: &[u8]) {
+        self.keys.insert(key_id, secret.to_vec());
+    }
+
+    /// removes a secret, returns `false` if the key id was not present
+    pub fn remove(&mut self, key_id: u32) -> bool {
+        self.keys.remove(&key_id).is_some()
+    }
+
+    pub fn get(&self, key_id: u32) -> Option<&[u8]> {
+        self.keys.get(&key_id).map(Vec::as_slice)
+    }
+
+    /// key ids of the secrets in the set, in no particular order
+    pub fn key_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.keys.keys().cloned()
+    }
+}
 
-        proto.encoded_len()
+impl std::fmt::Debug for SealKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // the secrets are not printed
+        let mut key_ids = self.key_ids().collect::<Vec<_>>();
+        key_ids.sort_unstable();
+        f.debug_struct("SealKeys")
+            .field("key_ids", &key_ids)
+            .finish()
     }
 }
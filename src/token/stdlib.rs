@@ -0,0 +1,321 @@
+//! reusable rules and checks for common authorization patterns
+//!
+//! each function returns a [`Fragment`], added to a verifier with
+//! [`Verifier::add_stdlib`](crate::token::verifier::Verifier::add_stdlib).
+//! The fragments rely on conventional facts, created by the helpers of this
+//! module, so tokens and verifiers agree on their names:
+//!
+//! | fragment                | token facts                         | verifier facts             |
+//! |-------------------------|-------------------------------------|----------------------------|
+//! | [`expiry`]              | [`expiration`]                      | `time`, from `set_time`    |
+//! | [`audience`]            | [`audience_fact`]                   |                            |
+//! | [`resource_hierarchy`]  | `right(#authority, resource, #op)`  | `resource`, `operation`, [`parent`] |
+//! | [`operations`]          |                                     | `operation`                |
+//! | [`ip_allowlist`]        |                                     | [`client_ip`]              |
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, stdlib}};
+//! use std::time::{Duration, SystemTime};
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_fact(stdlib::expiration(SystemTime::now() + Duration::from_secs(60))).unwrap();
+//! builder.add_authority_fact(stdlib::audience_fact("api.example.com")).unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! let mut verifier = token.verify(root.public()).unwrap();
+//! verifier.set_time();
+//! verifier.add_stdlib(stdlib::expiry());
+//! verifier.add_stdlib(stdlib::audience("api.example.com"));
+//! verifier.allow().unwrap();
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::builder::{
+    constrained_rule, date, fact, int, pred, rule, s, string, var, Binary, Check, Expression, Fact,
+    Op, Rule, Term,
+};
+use std::{fmt, net::Ipv4Addr, time::SystemTime};
+
+/// rules and checks added to a verifier
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fragment {
+    pub rules: Vec<Rule>,
+    pub checks: Vec<Check>,
+}
+
+impl fmt::Display for Fragment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rule in self.rules.iter() {
+            writeln!(f, "{};", rule)?;
+        }
+        for check in self.checks.iter() {
+            writeln!(f, "{};", check)?;
+        }
+        Ok(())
+    }
+}
+
+/// requires tokens to carry an [`expiration`] date, that has not passed
+///
+/// `check if expiration(#authority, $date), time(#ambient, $time), $time <= $date`
+pub fn expiry() -> Fragment {
+    Fragment {
+        rules: vec![],
+        checks: vec![Check {
+            queries: vec![constrained_rule(
+                "expiry",
+                &[var("date")],
+                &[
+                    pred("expiration", &[s("authority"), var("date")]),
+                    pred("time", &[s("ambient"), var("time")]),
+                ],
+                &[Expression {
+                    ops: vec![
+                        Op::Value(var("time")),
+                        Op::Value(var("date")),
+                        Op::Binary(Binary::LessOrEqual),
+                    ],
+                }],
+            )],
+        }],
+    }
+}
+
+/// expiration date of a token, for the authority block
+pub fn expiration<T: Into<SystemTime>>(date_time: T) -> Fact {
+    fact("expiration", &[s("authority"), date(&date_time.into())])
+}
+
+/// requires tokens to be issued for this audience
+///
+/// `check if audience(#authority, "audience")`
+pub fn audience(audience: &str) -> Fragment {
+    Fragment {
+        rules: vec![],
+        checks: vec![Check {
+            queries: vec![rule(
+                "audience",
+                &[string(audience)],
+                &[pred("audience", &[s("authority"), string(audience)])],
+            )],
+        }],
+    }
+}
+
+/// audience of a token, for the authority block
+pub fn audience_fact(audience: &str) -> Fact {
+    fact("audience", &[s("authority"), string(audience)])
+}
+
+/// applies the rights on a resource to its descendants
+///
+/// the verifier provides the [`parent`] of each resource, from the resource
+/// of the request to the root. The fragment derives the ancestors, the
+/// resource included:
+///
+/// ```text
+/// ancestor(#ambient, $resource, $resource) <- resource(#ambient, $resource);
+/// ancestor(#ambient, $resource, $parent) <- ancestor(#ambient, $resource, $child), parent(#ambient, $child, $parent);
+/// check if resource(#ambient, $resource), operation(#ambient, $operation), ancestor(#ambient, $resource, $ancestor), right(#authority, $ancestor, $operation);
+/// ```
+pub fn resource_hierarchy() -> Fragment {
+    Fragment {
+        rules: vec![
+            rule(
+                "ancestor",
+                &[s("ambient"), var("resource"), var("resource")],
+                &[pred("resource", &[s("ambient"), var("resource")])],
+            ),
+            rule(
+                "ancestor",
+                &[s("ambient"), var("resource"), var("parent")],
+                &[
+                    pred("ancestor", &[s("ambient"), var("resource"), var("child")]),
+                    pred("parent", &[s("ambient"), var("child"), var("parent")]),
+                ],
+            ),
+        ],
+        checks: vec![Check {
+            queries: vec![rule(
+                "hierarchy",
+                &[var("resource")],
+                &[
+                    pred("resource", &[s("ambient"), var("resource")]),
+                    pred("operation", &[s("ambient"), var("operation")]),
+                    pred(
+                        "ancestor",
+                        &[s("ambient"), var("resource"), var("ancestor")],
+                    ),
+                    pred(
+                        "right",
+                        &[s("authority"), var("ancestor"), var("operation")],
+                    ),
+                ],
+            )],
+        }],
+    }
+}
+
+/// parent of a resource, for the verifier
+pub fn parent(child: &str, parent: &str) -> Fact {
+    fact("parent", &[s("ambient"), string(child), string(parent)])
+}
+
+/// only accepts these operations
+///
+/// `check if operation(#ambient, $operation), [#operations].contains($operation)`
+pub fn operations(operations: &[&str]) -> Fragment {
+    Fragment {
+        rules: vec![],
+        checks: vec![Check {
+            queries: vec![constrained_rule(
+                "operations",
+                &[var("operation")],
+                &[pred("operation", &[s("ambient"), var("operation")])],
+                &[Expression {
+                    ops: vec![
+                        Op::Value(Term::Set(operations.iter().map(|o| s(o)).collect())),
+                        Op::Value(var("operation")),
+                        Op::Binary(Binary::Contains),
+                    ],
+                }],
+            )],
+        }],
+    }
+}
+
+/// only accepts clients from these IPv4 networks, given as an address and
+/// a prefix length
+///
+/// the check has one query per network:
+/// `check if client_ip(#ambient, $ip), $ip >= first, $ip <= last or ...`
+pub fn ip_allowlist(networks: &[(Ipv4Addr, u8)]) -> Fragment {
+    let queries = networks
+        .iter()
+        .map(|(address, prefix)| {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from((*prefix).min(32)))
+                .unwrap_or(0);
+            let first = u32::from(*address) & mask;
+            let last = first | !mask;
+            constrained_rule(
+                "ip",
+                &[var("ip")],
+                &[pred("client_ip", &[s("ambient"), var("ip")])],
+                &[
+                    Expression {
+                        ops: vec![
+                            Op::Value(var("ip")),
+                            Op::Value(int(i64::from(first))),
+                            Op::Binary(Binary::GreaterOrEqual),
+                        ],
+                    },
+                    Expression {
+                        ops: vec![
+                            Op::Value(var("ip")),
+                            Op::Value(int(i64::from(last))),
+                            Op::Binary(Binary::LessOrEqual),
+                        ],
+                    },
+                ],
+            )
+        })
+        .collect();
+
+    Fragment {
+        rules: vec![],
+        checks: vec![Check { queries }],
+    }
+}
+
+/// IPv4 address of the client, for the verifier, as an integer
+pub fn client_ip(address: Ipv4Addr) -> Fact {
+    fact(
+        "client_ip",
+        &[s("ambient"), int(i64::from(u32::from(address)))],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::{verifier::Verifier, Biscuit};
+    use std::time::Duration;
+
+    fn verify(token: &Biscuit, root: &KeyPair, setup: impl FnOnce(&mut Verifier)) -> bool {
+        let mut verifier = token.verify(root.public()).unwrap();
+        setup(&mut verifier);
+        verifier.allow().unwrap();
+        verifier.verify().is_ok()
+    }
+
+    #[test]
+    fn fragments() {
+        let root = KeyPair::new();
+        let now = SystemTime::now();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(expiration(now + Duration::from_secs(60)))
+            .unwrap();
+        builder
+            .add_authority_fact(audience_fact("api.example.com"))
+            .unwrap();
+        builder.add_right("/docs", "read");
+        let token = builder.build().unwrap();
+
+        assert!(verify(&token, &root, |v| {
+            v.set_time();
+            v.add_stdlib(expiry());
+            v.add_stdlib(audience("api.example.com"));
+        }));
+        assert!(!verify(&token, &root, |v| v.add_stdlib(expiry())));
+        assert!(!verify(&token, &root, |v| v.add_stdlib(audience("other"))));
+
+        let hierarchy = |resource: &str, operation: &str| {
+            let resource = resource.to_string();
+            let operation = operation.to_string();
+            move |v: &mut Verifier| {
+                v.add_resource(&resource);
+                v.add_operation(&operation);
+                v.add_fact(parent("/docs/a/b", "/docs/a")).unwrap();
+                v.add_fact(parent("/docs/a", "/docs")).unwrap();
+                v.add_fact(parent("/other", "/")).unwrap();
+                v.add_stdlib(resource_hierarchy());
+            }
+        };
+        assert!(verify(&token, &root, hierarchy("/docs/a/b", "read")));
+        assert!(verify(&token, &root, hierarchy("/docs", "read")));
+        assert!(!verify(&token, &root, hierarchy("/docs/a/b", "write")));
+        assert!(!verify(&token, &root, hierarchy("/other", "read")));
+
+        assert!(verify(&token, &root, |v| {
+            v.add_operation("read");
+            v.add_stdlib(operations(&["read", "list"]));
+        }));
+        assert!(!verify(&token, &root, |v| {
+            v.add_operation("write");
+            v.add_stdlib(operations(&["read", "list"]));
+        }));
+
+        let networks = [
+            (Ipv4Addr::new(10, 1, 2, 3), 16),
+            (Ipv4Addr::new(192, 168, 0, 1), 32),
+        ];
+        let from = |ip: Ipv4Addr| {
+            move |v: &mut Verifier| {
+                v.add_fact(client_ip(ip)).unwrap();
+                v.add_stdlib(ip_allowlist(&networks));
+            }
+        };
+        assert!(verify(&token, &root, from(Ipv4Addr::new(10, 1, 255, 255))));
+        assert!(verify(&token, &root, from(Ipv4Addr::new(192, 168, 0, 1))));
+        assert!(!verify(&token, &root, from(Ipv4Addr::new(10, 2, 0, 0))));
+        assert!(!verify(&token, &root, from(Ipv4Addr::new(192, 168, 0, 2))));
+
+        assert_eq!(
+            ip_allowlist(&[(Ipv4Addr::new(0, 0, 0, 0), 0)]).to_string(),
+            "check if client_ip(#ambient, $ip), $ip >= 0, $ip <= 4294967295;\n"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! checks and policies with parameters
+//!
+//! a template is parsed once, with `{name}` parameters in place of some
+//! terms, then instantiated with different values:
+//!
+//! ```rust
+//! use biscuit_auth::token::{builder::Term, template::PolicyTemplate};
+//!
+//! let template = PolicyTemplate::new(
+//!     "allow if tenant(#ambient, {tenant}), operation(#ambient, {operation})",
+//! )
+//! .unwrap();
+//!
+//! let policy = template
+//!     .policy(&[("tenant", "acme".into()), ("operation", Term::Symbol("read".to_string()))])
+//!     .unwrap();
+//! assert_eq!(
+//!     policy.to_string(),
+//!     r#"allow if tenant(#ambient, "acme"), operation(#ambient, #read)"#
+//! );
+//! ```
+//!
+//! values replace terms, so unlike source generated with `format!`, they
+//! cannot modify the structure of the policy
+use super::builder::{Check, Policy, Term};
+use crate::{error, parser};
+use biscuit_parser::builder as parsed;
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::TryInto,
+};
+
+/// a check or policy with named parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyTemplate {
+    source: String,
+    template: Template,
+    parameters: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Template {
+    Check(parsed::Check),
+    Policy(parsed::Policy),
+}
+
+impl PolicyTemplate {
+    /// parses a template for a check (`check if ...`) or a policy
+    /// (`allow if ...` or `deny if ...`)
+    pub fn new(source: &str) -> Result<Self, error::Token> {
+        let mut template = match parser::check(source) {
+            Ok((_, check)) => Template::Check(check),
+            // the source started with `check if` but is invalid
+            Err(e @ nom::Err::Failure(_)) => return Err(parser::parse_error(source, e)),
+            Err(_) => parser::policy(source)
+                .map(|(_, policy)| Template::Policy(policy))
+                .map_err(|e| parser::parse_error(source, e))?,
+        };
+
+        let mut parameters = BTreeSet::new();
+        template.visit_terms_mut(&mut |term| {
+            if let parsed::Term::Parameter(name) = term {
+                parameters.insert(name.clone());
+            }
+        });
+
+        Ok(PolicyTemplate {
+            source: source.to_string(),
+            template,
+            parameters,
+        })
+    }
+
+    /// the source the template was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// names of the parameters used in the template
+    pub fn parameters(&self) -> impl Iterator<Item = &str> {
+        self.parameters.iter().map(|s| s.as_str())
+    }
+
+    pub fn is_check(&self) -> bool {
+        matches!(self.template, Template::Check(_))
+    }
+
+    /// creates a check from the template
+    ///
+    /// every parameter must have a value, and values cannot be variables
+    pub fn check(&self, values: &[(&str, Term)]) -> Result<Check, error::Token> {
+        match self.instantiate(values)? {
+            Template::Check(check) => check.try_into(),
+            Template::Policy(_) => Err(error::Token::ConversionError(
+                "the template is a policy, not a check".to_string(),
+            )),
+        }
+    }
+
+    /// creates a policy from the template
+    ///
+    /// every parameter must have a value, and values cannot be variables
+    pub fn policy(&self, values: &[(&str, Term)]) -> Result<Policy, error::Token> {
+        match self.instantiate(values)? {
+            Template::Policy(policy) => policy.try_into(),
+            Template::Check(_) => Err(error::Token::ConversionError(
+                "the template is a check, not a policy".to_string(),
+            )),
+        }
+    }
+
+    fn instantiate(&self, values: &[(&str, Term)]) -> Result<Template, error::Token> {
+        let mut bindings = HashMap::new();
+        for (name, value) in values.iter() {
+            if !self.parameters.contains(*name) {
+                return Err(error::Token::ConversionError(format!(
+                    "unknown parameter: {{{}}}",
+                    name
+                )));
+            }
+            if let Term::Variable(_) = value {
+                return Err(error::Token::ConversionError(format!(
+                    "parameter {{{}}} cannot be a variable",
+                    name
+                )));
+            }
+            bindings.insert(*name, parsed::Term::from(value.clone()));
+        }
+
+        if let Some(missing) = self
+            .parameters
+            .iter()
+            .find(|p| !bindings.contains_key(p.as_str()))
+        {
+            return Err(error::Token::ConversionError(format!(
+                "missing value for parameter: {{{}}}",
+                missing
+            )));
+        }
+
+        let mut template = self.template.clone();
+        template.visit_terms_mut(&mut |term| {
+            if let parsed::Term::Parameter(name) = term {
+                *term = bindings[name.as_str()].clone();
+            }
+        });
+
+        Ok(template)
+    }
+}
+
+impl Template {
+    fn queries_mut(&mut self) -> &mut Vec<parsed::Rule> {
+        match self {
+            Template::Check(check) => &mut check.queries,
+            Template::Policy(policy) => &mut policy.queries,
+        }
+    }
+
+    fn visit_terms_mut<F: FnMut(&mut parsed::Term)>(&mut self, f: &mut F) {
+        for rule in self.queries_mut().iter_mut() {
+            rule.visit_terms_mut(f);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! verifiers isolating the policies of each tenant
+//!
+//! a service shared by many tenants keeps, for each of them, a verifier
+//! holding its facts, rules, checks and policies. Tokens name their tenant
+//! with a fact of the authority block:
+//!
+//! ```text
+//! tenant(#authority, "acme");
+//! ```
+//!
+//! [`MultiTenantVerifier::verifier`] then returns a copy of that tenant's
+//! verifier, with the token loaded. Each tenant has its own world and symbol
+//! table, so the facts and policies of a tenant are never visible while
+//! verifying the tokens of another one.
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::{Biscuit, tenant::{self, MultiTenantVerifier}, verifier::Verifier}};
+//!
+//! let tenants = MultiTenantVerifier::new();
+//! let mut acme = Verifier::new().unwrap();
+//! acme.add_policy("allow if operation(#ambient, #read)").unwrap();
+//! tenants.set_tenant("acme", acme);
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_fact(tenant::tenant_fact("acme")).unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! let mut verifier = tenants.verifier(&token, root.public()).unwrap();
+//! verifier.add_operation("read");
+//! assert!(verifier.verify().is_ok());
+//! ```
+use super::builder::{fact, s, string, Fact};
+use super::verifier::Verifier;
+use super::Biscuit;
+use crate::crypto::PublicKey;
+use crate::datalog::ID;
+use crate::error;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// error returned when selecting the verifier of a token's tenant
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum TenantError {
+    /// the authority block has no `tenant(#authority, id)` fact
+    #[error("the token does not name a tenant")]
+    MissingTenant,
+    /// the authority block names more than one tenant
+    #[error("the token names multiple tenants")]
+    AmbiguousTenant,
+    /// no policies were set for this tenant
+    #[error("unknown tenant {0}")]
+    UnknownTenant(String),
+    #[error("invalid token: {0}")]
+    Token(error::Token),
+}
+
+impl From<error::Token> for TenantError {
+    fn from(e: error::Token) -> Self {
+        TenantError::Token(e)
+    }
+}
+
+/// verifiers of each tenant, shared between threads
+///
+/// the verifiers are frozen when set (see [`Verifier::freeze`]), so copying
+/// them for each token does not copy their facts. Tenants can be set and
+/// removed while tokens are verified.
+#[derive(Default)]
+pub struct MultiTenantVerifier {
+    tenants: RwLock<HashMap<String, Arc<Verifier>>>,
+}
+
+impl MultiTenantVerifier {
+    pub fn new() -> Self {
+        MultiTenantVerifier::default()
+    }
+
+    /// sets the facts, rules, checks and policies of a tenant, replacing the
+    /// previous ones
+    pub fn set_tenant(&self, tenant: &str, mut verifier: Verifier) {
+        verifier.freeze();
+        self.tenants
+            .write()
+            .unwrap()
+            .insert(tenant.to_string(), Arc::new(verifier));
+    }
+
+    /// removes a tenant, returning `false` if it was not set
+    pub fn remove_tenant(&self, tenant: &str) -> bool {
+        self.tenants.write().unwrap().remove(tenant).is_some()
+    }
+
+    pub fn contains_tenant(&self, tenant: &str) -> bool {
+        self.tenants.read().unwrap().contains_key(tenant)
+    }
+
+    /// number of tenants
+    pub fn len(&self) -> usize {
+        self.tenants.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.read().unwrap().is_empty()
+    }
+
+    /// copy of the verifier of the token's tenant, with the token loaded
+    ///
+    /// the request's facts can then be added before calling
+    /// [`Verifier::verify`]
+    pub fn verifier(&self, token: &Biscuit, root: PublicKey) -> Result<Verifier, TenantError> {
+        token.check_root_key(root)?;
+        let tenant = tenant_id(token)?;
+
+        let mut verifier = self
+            .tenants
+            .read()
+            .unwrap()
+            .get(&tenant)
+            .map(|verifier| Verifier::clone(verifier))
+            .ok_or(TenantError::UnknownTenant(tenant))?;
+        verifier.add_token(token, root)?;
+        Ok(verifier)
+    }
+}
+
+/// `tenant(#authority, id)`, for the authority block
+pub fn tenant_fact(tenant: &str) -> Fact {
+    fact("tenant", &[s("authority"), string(tenant)])
+}
+
+/// tenant named by the authority block of the token
+///
+/// this does not verify the token's signature
+pub fn tenant_id(token: &Biscuit) -> Result<String, TenantError> {
+    let symbols = token.symbols()?;
+    let (name, authority) = match (symbols.get("tenant"), symbols.get("authority")) {
+        (Some(name), Some(authority)) => (name, authority),
+        _ => return Err(TenantError::MissingTenant),
+    };
+
+    let mut tenants = token
+        .authority()?
+        .facts
+        .iter()
+        .filter(|fact| fact.predicate.name == name)
+        .filter_map(|fact| match fact.predicate.ids.as_slice() {
+            [ID::Symbol(origin), ID::Str(tenant)] if *origin == authority => Some(tenant),
+            _ => None,
+        });
+
+    let tenant = tenants.next().ok_or(TenantError::MissingTenant)?;
+    if tenants.any(|other| other != tenant) {
+        return Err(TenantError::AmbiguousTenant);
+    }
+    Ok(tenant.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::builder::BiscuitBuilder;
+
+    #[test]
+    fn isolation() {
+        let tenants = MultiTenantVerifier::new();
+        let mut acme = Verifier::new().unwrap();
+        acme.add_fact("owner(#ambient, \"alice\", \"/acme/report\")")
+            .unwrap();
+        acme.add_policy("allow if user(#authority, $user), resource(#ambient, $resource), owner(#ambient, $user, $resource)")
+            .unwrap();
+        tenants.set_tenant("acme", acme);
+        let mut globex = Verifier::new().unwrap();
+        globex
+            .add_policy("allow if user(#authority, $user), resource(#ambient, $resource), owner(#ambient, $user, $resource)")
+            .unwrap();
+        tenants.set_tenant("globex", globex);
+        assert_eq!(tenants.len(), 2);
+
+        let root = KeyPair::new();
+        let token = |setup: &dyn Fn(&mut BiscuitBuilder)| {
+            let mut builder = Biscuit::builder(&root);
+            builder
+                .add_authority_fact("user(#authority, \"alice\")")
+                .unwrap();
+            setup(&mut builder);
+            builder.build().unwrap()
+        };
+        let allowed = |token: &Biscuit| {
+            let mut verifier = tenants.verifier(token, root.public()).unwrap();
+            verifier.add_resource("/acme/report");
+            verifier.verify().is_ok()
+        };
+
+        let acme_token = token(&|b| b.add_authority_fact(tenant_fact("acme")).unwrap());
+        let globex_token = token(&|b| b.add_authority_fact(tenant_fact("globex")).unwrap());
+        assert_eq!(tenant_id(&acme_token), Ok("acme".to_string()));
+        assert!(allowed(&acme_token));
+        // the facts of acme are not visible to globex
+        assert!(!allowed(&globex_token));
+        // nor are the facts of a previous request
+        assert!(allowed(&acme_token));
+
+        assert_eq!(
+            tenants.verifier(&token(&|_| ()), root.public()).err(),
+            Some(TenantError::MissingTenant)
+        );
+        let both = token(&|b| {
+            b.add_authority_fact(tenant_fact("acme")).unwrap();
+            b.add_authority_fact(tenant_fact("globex")).unwrap();
+        });
+        assert_eq!(
+            tenants.verifier(&both, root.public()).err(),
+            Some(TenantError::AmbiguousTenant)
+        );
+
+        assert!(tenants.remove_tenant("globex"));
+        assert_eq!(
+            tenants.verifier(&globex_token, root.public()).err(),
+            Some(TenantError::UnknownTenant("globex".to_string()))
+        );
+        assert!(matches!(
+            tenants.verifier(&acme_token, KeyPair::new().public()),
+            Err(TenantError::Token(_))
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! tokens attenuated without checking their signature
+use super::builder::BlockBuilder;
+use super::{default_symbol_table, Biscuit};
+use crate::crypto::{KeyPair, PublicKey};
+use crate::error;
+use crate::format::{SerializedBiscuit, TermLimits};
+use rand_core::{CryptoRng, RngCore};
+
+/// a token whose signature was not verified
+///
+/// the holder of a token may not know the root public key, or not want to
+/// pay for the signature verification before adding a block: the token can
+/// then be decoded and attenuated as is, and the final verifier will check
+/// the signatures of all the blocks, including the ones added here.
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, unverified::UnverifiedBiscuit}};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder(&root).build().unwrap().to_vec().unwrap();
+///
+/// // the holder attenuates the token without the root public key
+/// let unverified = UnverifiedBiscuit::from(&token).unwrap();
+/// let mut block = unverified.create_block();
+/// block.check_operation("read");
+/// let attenuated = unverified.append(&KeyPair::new(), block).unwrap();
+/// let attenuated = attenuated.to_vec().unwrap();
+///
+/// // the verifier checks everything
+/// let token = Biscuit::from(&attenuated).unwrap();
+/// let mut verifier = token.verify(root.public()).unwrap();
+/// verifier.add_operation("read");
+/// verifier.allow().unwrap();
+/// assert!(verifier.verify().is_ok());
+/// ```
+#[derive(Clone, Debug)]
+pub struct UnverifiedBiscuit {
+    inner: Biscuit,
+}
+
+impl UnverifiedBiscuit {
+    /// deserializes a token without checking its signature
+    pub fn from(slice: &[u8]) -> Result<Self, error::Token> {
+        let container = SerializedBiscuit::decode(slice, None).map_err(error::Token::Format)?;
+
+        Ok(UnverifiedBiscuit {
+            inner: Biscuit::from_container(
+                container,
+                default_symbol_table(),
+                TermLimits::default(),
+            ),
+        })
+    }
+
+    /// deserializes a base64 encoded token without checking its signature
+    pub fn from_base64<T: AsRef<[u8]>>(slice: T) -> Result<Self, error::Token> {
+        let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
+        UnverifiedBiscuit::from(&decoded)
+    }
+
+    /// creates a new block builder
+    pub fn create_block(&self) -> BlockBuilder {
+        self.inner.create_block()
+    }
+
+    /// adds a new block to the token
+    ///
+    /// the new signature is aggregated with the unverified one, so the
+    /// result is only valid if the original token was
+    pub fn append(
+        &self,
+        keypair: &KeyPair,
+        block_builder: BlockBuilder,
+    ) -> Result<Self, error::Token> {
+        self.append_with_rng(&mut rand::rngs::OsRng, keypair, block_builder)
+    }
+
+    /// adds a new block to the token, using the provided CSPRNG
+    pub fn append_with_rng<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        keypair: &KeyPair,
+        block_builder: BlockBuilder,
+    ) -> Result<Self, error::Token> {
+        Ok(UnverifiedBiscuit {
+            inner: self.inner.append_with_rng(rng, keypair, block_builder)?,
+        })
+    }
+
+    /// checks the signature and the root public key of the token
+    pub fn verify(self, root: PublicKey) -> Result<Biscuit, error::Token> {
+        if let Some(container) = self.inner.container() {
+            container.verify().map_err(error::Token::Format)?;
+        }
+        self.inner.check_root_key(root)?;
+
+        Ok(self.inner)
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.inner.block_count()
+    }
+
+    /// revocation identifiers of each block, see [`Biscuit::revocation_ids`]
+    pub fn revocation_ids(&self) -> &[Vec<u8>] {
+        self.inner.revocation_ids()
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
+        self.inner.to_vec()
+    }
+
+    pub fn to_base64(&self) -> Result<String, error::Token> {
+        self.inner.to_base64()
+    }
+}
@@ -1,17 +1,29 @@
 //! Verifier structure and associated functions
+use super::binding;
 use super::builder::{
-    constrained_rule, date, fact, pred, s, string, var, Binary, Check, Expression, Fact, Op,
+    constrained_rule, date, fact, pred, rule, s, string, var, Binary, Check, Expression, Fact, Op,
     Policy, PolicyKind, Rule, Term, Unary,
 };
+use super::capability;
+use super::possession::{self, Proof};
+use super::rbac::{self, Permission};
+use super::revocation::RevocationStore;
+use super::stdlib::Fragment;
 use super::Biscuit;
 use crate::crypto::PublicKey;
 use crate::datalog;
 use crate::error;
+use crate::parser;
 use crate::time::Instant;
 use prost::Message;
 use std::{
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
     default::Default,
+    fmt,
+    iter::FromIterator,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
@@ -23,23 +35,63 @@ pub struct Verifier {
     world: datalog::World,
     symbols: datalog::SymbolTable,
     checks: Vec<Check>,
+    /// position in their source text of the checks added from Datalog source
+    check_spans: HashMap<usize, error::SourceSpan>,
     token_checks: Vec<Vec<datalog::Check>>,
+    /// facts provided by each block of the token, the authority block first
+    token_facts: Vec<HashSet<datalog::Fact>>,
+    /// rules provided by each block of the token, the authority block first
+    token_rules: Vec<Vec<datalog::Rule>>,
+    /// namespaces in which the token blocks can define facts, if restricted
+    block_namespaces: Option<HashSet<String>>,
     policies: Vec<Policy>,
+    policy_labels: HashMap<usize, String>,
     has_token: bool,
+    clock: Arc<dyn Clock + Send + Sync>,
+    metrics: Option<VerifierMetrics>,
+    dump_on_failure: bool,
+    failure: Option<VerifierFailure>,
+    /// older syntax found in the Datalog source
+    deprecations: Vec<VerifierWarning>,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    /// revocation identifiers and unique revocation identifiers of each
+    /// block of the token
+    token_revocation_ids: Vec<(Vec<u8>, Vec<u8>)>,
 }
 
 impl Verifier {
-    pub(crate) fn from_token(token: &Biscuit) -> Result<Self, error::Logic> {
-        let mut symbols = token.symbols.clone();
+    pub(crate) fn from_token(token: &Biscuit) -> Result<Self, error::Token> {
+        let mut symbols = token.symbols()?.clone();
         let world = token.generate_world(&mut symbols)?;
+        let mut token_facts = vec![token.authority()?.facts.iter().cloned().collect()];
+        token_facts.extend(
+            token
+                .blocks()?
+                .into_iter()
+                .map(|block| block.facts.iter().cloned().collect()),
+        );
+        let mut token_rules = vec![token.authority()?.rules.clone()];
+        token_rules.extend(token.blocks()?.into_iter().map(|block| block.rules.clone()));
 
         Ok(Verifier {
             world,
             symbols,
             checks: vec![],
-            token_checks: token.checks(),
+            check_spans: HashMap::new(),
+            token_checks: token.checks()?,
+            token_facts,
+            token_rules,
+            block_namespaces: None,
             policies: vec![],
+            policy_labels: HashMap::new(),
             has_token: true,
+            clock: Arc::new(SystemClock),
+            metrics: None,
+            dump_on_failure: false,
+            failure: None,
+            deprecations: Vec::new(),
+            revocation_store: None,
+            token_revocation_ids: token_revocation_ids(token),
         })
     }
 
@@ -53,17 +105,33 @@ impl Verifier {
     /// with the facts, rules and checks, and each time a token must be checked,
     /// clone the verifier and load the token with [`Verifier::add_token`]
     pub fn new() -> Result<Self, error::Logic> {
+        Ok(Verifier::empty())
+    }
+
+    fn empty() -> Self {
         let world = datalog::World::new();
         let symbols = super::default_symbol_table();
 
-        Ok(Verifier {
+        Verifier {
             world,
             symbols,
             checks: vec![],
+            check_spans: HashMap::new(),
             token_checks: vec![],
+            token_facts: vec![],
+            token_rules: vec![],
+            block_namespaces: None,
             policies: vec![],
+            policy_labels: HashMap::new(),
             has_token: false,
-        })
+            clock: Arc::new(SystemClock),
+            metrics: None,
+            dump_on_failure: false,
+            failure: None,
+            deprecations: Vec::new(),
+            revocation_store: None,
+            token_revocation_ids: Vec::new(),
+        }
     }
 
     pub fn from(slice: &[u8]) -> Result<Self, error::Token> {
@@ -83,8 +151,8 @@ impl Verifier {
 
         let world = datalog::World {
             facts: facts.drain(..).collect(),
-            rules,
-            privileged_rules,
+            rules: rules.into_iter().collect(),
+            privileged_rules: privileged_rules.into_iter().collect(),
         };
         let checks = checks
             .drain(..)
@@ -95,9 +163,21 @@ impl Verifier {
             world,
             symbols,
             checks,
+            check_spans: HashMap::new(),
             token_checks: vec![],
+            token_facts: vec![],
+            token_rules: vec![],
+            block_namespaces: None,
             policies,
+            policy_labels: HashMap::new(),
             has_token: false,
+            clock: Arc::new(SystemClock),
+            metrics: None,
+            dump_on_failure: false,
+            failure: None,
+            deprecations: Vec::new(),
+            revocation_store: None,
+            token_revocation_ids: Vec::new(),
         })
     }
 
@@ -120,8 +200,8 @@ impl Verifier {
             version: crate::token::MAX_SCHEMA_VERSION,
             symbols,
             facts: self.world.facts.iter().cloned().collect(),
-            rules: self.world.rules.clone(),
-            privileged_rules: self.world.privileged_rules.clone(),
+            rules: self.world.rules.iter().cloned().collect(),
+            privileged_rules: self.world.privileged_rules.iter().cloned().collect(),
             checks,
             policies: self.policies.clone(),
         };
@@ -140,59 +220,75 @@ impl Verifier {
     /// Loads a token's facts, rules and checks in a verifier
     pub fn add_token(&mut self, token: &Biscuit, root: PublicKey) -> Result<(), error::Token> {
         token.check_root_key(root)?;
+        let token_symbols = token.symbols()?;
+        let authority = token.authority()?;
+        let blocks = token.blocks()?;
 
         if self.has_token {
             return Err(error::Logic::VerifierNotEmpty.into());
         } else {
             self.has_token = true;
         }
+        self.add_delegation_check(token, root);
 
         let authority_index = self.symbols.get("authority").unwrap();
         let ambient_index = self.symbols.get("ambient").unwrap();
 
-        for fact in token.authority.facts.iter().cloned() {
+        let mut authority_facts = HashSet::new();
+        for fact in authority.facts.iter().cloned() {
             if fact.predicate.ids[0] == datalog::ID::Symbol(ambient_index) {
                 return Err(
-                    error::Logic::InvalidAuthorityFact(token.symbols.print_fact(&fact)).into(),
+                    error::Logic::InvalidAuthorityFact(token_symbols.print_fact(&fact)).into(),
                 );
             }
 
-            let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
+            let fact = Fact::convert_from(&fact, token_symbols).convert(&mut self.symbols);
+            authority_facts.insert(fact.clone());
             self.world.facts.insert(fact);
         }
+        self.token_facts.push(authority_facts);
 
-        let mut revocation_ids = token.revocation_identifiers();
         let revocation_id_sym = self.symbols.get("revocation_id").unwrap();
-        for (i, id) in revocation_ids.drain(..).enumerate() {
+        for (i, id) in token.revocation_ids().iter().enumerate() {
             self.world.facts.insert(datalog::Fact::new(
                 revocation_id_sym,
-                &[datalog::ID::Integer(i as i64), datalog::ID::Bytes(id)],
+                &[
+                    datalog::ID::Integer(i as i64),
+                    datalog::ID::Bytes(id.clone()),
+                ],
             ));
         }
 
-        let mut unique_revocation_ids = token.unique_revocation_identifiers();
         let unique_revocation_id_sym = self.symbols.insert("unique_revocation_id");
-        for (i, id) in unique_revocation_ids.drain(..).enumerate() {
+        for (i, id) in token.unique_revocation_ids().iter().enumerate() {
             self.world.facts.insert(datalog::Fact::new(
                 unique_revocation_id_sym,
-                &[datalog::ID::Integer(i as i64), datalog::ID::Bytes(id)],
+                &[
+                    datalog::ID::Integer(i as i64),
+                    datalog::ID::Bytes(id.clone()),
+                ],
             ));
         }
+        self.token_revocation_ids = token_revocation_ids(token);
 
-        for rule in token.authority.rules.iter().cloned() {
-            let r = Rule::convert_from(&rule, &token.symbols);
+        let mut authority_rules = Vec::new();
+        for rule in authority.rules.iter().cloned() {
+            let r = Rule::convert_from(&rule, token_symbols);
             let rule = r.convert(&mut self.symbols);
 
             if let Err(_message) = r.validate_variables() {
                 return Err(
-                    error::Logic::InvalidBlockRule(0, token.symbols.print_rule(&rule)).into(),
+                    error::Logic::InvalidBlockRule(0, token_symbols.print_rule(&rule)).into(),
                 );
             }
 
+            authority_rules.push(rule.clone());
             self.world.privileged_rules.push(rule);
         }
+        self.token_rules.push(authority_rules);
 
-        for (i, block) in token.blocks.iter().enumerate() {
+        for (i, block) in blocks.iter().enumerate() {
+            let mut block_facts = HashSet::new();
             // blocks cannot provide authority or ambient facts
             for fact in block.facts.iter().cloned() {
                 if fact.predicate.ids[0] == datalog::ID::Symbol(authority_index)
@@ -200,15 +296,18 @@ impl Verifier {
                 {
                     return Err(error::Logic::InvalidBlockFact(
                         i as u32,
-                        token.symbols.print_fact(&fact),
+                        token_symbols.print_fact(&fact),
                     )
                     .into());
                 }
 
-                let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
+                let fact = Fact::convert_from(&fact, token_symbols).convert(&mut self.symbols);
+                block_facts.insert(fact.clone());
                 self.world.facts.insert(fact);
             }
+            self.token_facts.push(block_facts);
 
+            let mut block_rules = Vec::new();
             for rule in block.rules.iter().cloned() {
                 // block rules cannot generate authority or ambient facts
                 if rule.head.ids[0] == datalog::ID::Symbol(authority_index)
@@ -216,40 +315,41 @@ impl Verifier {
                 {
                     return Err(error::Logic::InvalidBlockRule(
                         i as u32,
-                        token.symbols.print_rule(&rule),
+                        token_symbols.print_rule(&rule),
                     )
                     .into());
                 }
 
-                let r = Rule::convert_from(&rule, &token.symbols);
+                let r = Rule::convert_from(&rule, token_symbols);
 
                 if let Err(_message) = r.validate_variables() {
                     return Err(error::Logic::InvalidBlockRule(
                         i as u32,
-                        token.symbols.print_rule(&rule),
+                        token_symbols.print_rule(&rule),
                     )
                     .into());
                 }
 
                 let rule = r.convert(&mut self.symbols);
+                block_rules.push(rule.clone());
                 self.world.rules.push(rule);
             }
+            self.token_rules.push(block_rules);
         }
 
         let mut token_checks: Vec<Vec<datalog::Check>> = Vec::new();
-        let checks = token
-            .authority
+        let checks = authority
             .checks
             .iter()
-            .map(|c| Check::convert_from(&c, &token.symbols).convert(&mut self.symbols))
+            .map(|c| Check::convert_from(&c, token_symbols).convert(&mut self.symbols))
             .collect();
         token_checks.push(checks);
 
-        for block in token.blocks.iter() {
+        for block in blocks.iter() {
             let checks = block
                 .checks
                 .iter()
-                .map(|c| Check::convert_from(&c, &token.symbols).convert(&mut self.symbols))
+                .map(|c| Check::convert_from(&c, token_symbols).convert(&mut self.symbols))
                 .collect();
             token_checks.push(checks);
         }
@@ -259,15 +359,81 @@ impl Verifier {
     }
 
     /// add a fact to the verifier
-    pub fn add_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token> {
-        let fact = fact.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token>
+    where
+        F::Error: Into<error::Token>,
+    {
+        let fact = fact.try_into().map_err(Into::into)?;
         self.world.facts.insert(fact.convert(&mut self.symbols));
         Ok(())
     }
 
+    /// add facts to the verifier
+    ///
+    /// the facts are converted and inserted one by one, so they can come
+    /// straight from a database cursor without being collected first. If a
+    /// fact fails to convert, the facts before it are kept
+    pub fn add_facts<I, F>(&mut self, facts: I) -> Result<(), error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        F::Error: Into<error::Token>,
+    {
+        let facts = facts.into_iter();
+        self.world.facts.reserve(facts.size_hint().0);
+        for fact in facts {
+            let fact = fact.try_into().map_err(Into::into)?;
+            self.world.facts.insert(fact.convert(&mut self.symbols));
+        }
+        Ok(())
+    }
+
+    /// add facts describing a JSON value, like a request payload or a user
+    /// profile
+    ///
+    /// each field of the objects becomes an ambient fact, named after the
+    /// prefix and the path of the field, with the characters other than
+    /// letters, digits and `_` replaced by `_`:
+    ///
+    /// ```rust
+    /// # use biscuit_auth::token::verifier::Verifier;
+    /// let user = serde_json::json!({
+    ///     "name": "alice",
+    ///     "roles": ["admin", "dev"],
+    ///     "address": { "city": "Paris" },
+    ///     "teams": [{ "id": 1 }, { "id": 2 }],
+    /// });
+    ///
+    /// let mut verifier = Verifier::new().unwrap();
+    /// verifier.add_facts_from_json("user", &user).unwrap();
+    /// // user_name(#ambient, "alice"), user_roles(#ambient, ["admin", "dev"]),
+    /// // user_address_city(#ambient, "Paris"), user_teams_id(#ambient, 1),
+    /// // user_teams_id(#ambient, 2)
+    /// verifier.add_policy(r#"allow if user_roles(#ambient, $r), $r.contains("admin"), user_teams_id(#ambient, 2)"#).unwrap();
+    /// assert!(verifier.verify().is_ok());
+    /// ```
+    ///
+    /// arrays of strings, integers or booleans become sets, and the elements
+    /// of other arrays are added as separate facts. `null` values are
+    /// skipped, and numbers that are not 64 bits integers are rejected. No
+    /// fact is added if the value cannot be converted
+    #[cfg(feature = "json")]
+    pub fn add_facts_from_json(
+        &mut self,
+        predicate_prefix: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), error::Token> {
+        let mut facts = Vec::new();
+        json_facts(predicate_prefix.to_string(), value, &mut facts)?;
+        self.add_facts(facts)
+    }
+
     /// add a rule to the verifier
-    pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token> {
-        let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        let rule = rule.try_into().map_err(Into::into)?;
         self.world
             .privileged_rules
             .push(rule.convert(&mut self.symbols));
@@ -278,23 +444,25 @@ impl Verifier {
     pub fn query<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
         &mut self,
         rule: R,
-    ) -> Result<Vec<T>, error::Token> {
+    ) -> Result<Vec<T>, error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
         self.query_with_limits(rule, VerifierLimits::default())
     }
 
     /// run a query over the verifier's Datalog engine to gather data
     ///
     /// this method can specify custom runtime limits
-    pub fn query_with_limits<
-        R: TryInto<Rule>,
-        T: TryFrom<Fact, Error = E>,
-        E: Into<error::Token>,
-    >(
+    pub fn query_with_limits<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
         &mut self,
         rule: R,
         limits: VerifierLimits,
-    ) -> Result<Vec<T>, error::Token> {
-        let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
+    ) -> Result<Vec<T>, error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        let rule = rule.try_into().map_err(Into::into)?;
         let authority_index = self.symbols.get("authority").unwrap();
         let ambient_index = self.symbols.get("ambient").unwrap();
 
@@ -310,12 +478,41 @@ impl Verifier {
     }
 
     /// add a check to the verifier
-    pub fn add_check<R: TryInto<Check>>(&mut self, check: R) -> Result<(), error::Token> {
-        let check = check.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_check<R: TryInto<Check>>(&mut self, check: R) -> Result<(), error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        let check = check.try_into().map_err(Into::into)?;
         self.checks.push(check);
         Ok(())
     }
 
+    /// adds the rules and checks of a fragment of the standard library, see
+    /// [`stdlib`](crate::token::stdlib)
+    pub fn add_stdlib(&mut self, fragment: Fragment) {
+        self.extend(fragment.rules);
+        self.extend(fragment.checks);
+    }
+
+    /// requires the user of the token to have a permission, with the roles
+    /// and assignments of [`rbac`](crate::token::rbac)
+    ///
+    /// adds `check if user(#authority, $user), permission(#ambient, $user, permission)`
+    pub fn require_permission<P: Into<Permission>>(&mut self, permission: P) {
+        self.checks.push(rbac::permission_check(&permission.into()));
+    }
+
+    /// requires the token to grant a capability, see [`capability`](crate::token::capability)
+    ///
+    /// this adds the `resource` and `operation` facts of the request, checked
+    /// by attenuation blocks narrowing the capabilities, and a check that the
+    /// authority block granted `action` on `resource`
+    pub fn require_capability(&mut self, action: &str, resource: &str) {
+        self.add_resource(resource);
+        self.add_operation(action);
+        self.checks.push(capability::require(action, resource));
+    }
+
     pub fn add_resource(&mut self, resource: &str) {
         let fact = fact("resource", &[s("ambient"), string(resource)]);
         self.world.facts.insert(fact.convert(&mut self.symbols));
@@ -326,12 +523,109 @@ impl Verifier {
         self.world.facts.insert(fact.convert(&mut self.symbols));
     }
 
-    /// adds a fact with the current time
+    /// adds a fact with the current time, as returned by the verifier's clock
     pub fn set_time(&mut self) {
-        let fact = fact("time", &[s("ambient"), date(&SystemTime::now())]);
+        let fact = fact("time", &[s("ambient"), date(&self.clock.now())]);
         self.world.facts.insert(fact.convert(&mut self.symbols));
     }
 
+    /// restricts the namespaces in which the token blocks, the authority
+    /// block included, can define facts and rules
+    ///
+    /// predicates without a namespace are always accepted. With
+    /// `restrict_block_namespaces(&["auth"])`, a token can provide
+    /// `auth:role(...)` facts but not `billing:right(...)`, so the verifier
+    /// can rely on the predicates of its own namespace only coming from
+    /// its own facts and rules
+    pub fn restrict_block_namespaces(&mut self, namespaces: &[&str]) {
+        self.block_namespaces = Some(namespaces.iter().map(|n| n.to_string()).collect());
+    }
+
+    /// adds the thumbprint of the TLS certificate presented by the client,
+    /// given in DER, for tokens bound with
+    /// [`BiscuitBuilder::bind_to_certificate`](crate::token::builder::BiscuitBuilder::bind_to_certificate)
+    pub fn add_client_certificate(&mut self, certificate: &[u8]) {
+        let fact = binding::client_thumbprint(binding::CERTIFICATE, certificate);
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
+
+    /// adds the thumbprint of the public key presented by the client, as a
+    /// DER encoded SubjectPublicKeyInfo, for tokens bound with
+    /// [`BiscuitBuilder::bind_to_public_key`](crate::token::builder::BiscuitBuilder::bind_to_public_key)
+    pub fn add_client_public_key(&mut self, public_key: &[u8]) {
+        let fact = binding::client_thumbprint(binding::PUBLIC_KEY, public_key);
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
+
+    /// verifies a proof of possession of the key of the token's holder, for
+    /// tokens bound with
+    /// [`BiscuitBuilder::bind_to_holder`](crate::token::builder::BiscuitBuilder::bind_to_holder)
+    ///
+    /// `nonce` is the challenge sent by the server. If the proof is valid,
+    /// this adds a `possession` fact with the key, and returns `true`
+    pub fn verify_possession(&mut self, nonce: &[u8], proof: &Proof) -> bool {
+        let keys: Vec<Fact> = match self.query(rule(
+            "holder",
+            &[var("key")],
+            &[pred("holder_key", &[s("authority"), var("key")])],
+        )) {
+            Ok(keys) => keys,
+            Err(_) => return false,
+        };
+
+        let key = keys.iter().find_map(|f| match f.0.ids.first() {
+            Some(Term::Bytes(bytes)) => {
+                PublicKey::from_bytes(bytes).filter(|key| proof.verify(*key, nonce))
+            }
+            _ => None,
+        });
+        match key {
+            Some(key) => {
+                let fact = possession::possession(key);
+                self.world.facts.insert(fact.convert(&mut self.symbols));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// replaces the clock used by [`Verifier::set_time`], the system clock by default
+    ///
+    /// the verifier only reads the time through this clock, so tests and replay
+    /// tools can use a [`FixedClock`]
+    pub fn set_clock<C: Clock + Send + Sync + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// refuses the token after the expiration of its delegation, if it was
+    /// issued by a key delegated from `root`
+    pub(crate) fn add_delegation_check(&mut self, token: &Biscuit, root: PublicKey) {
+        if let Some(expiration) = token.delegated_expiration(root) {
+            let check = constrained_rule(
+                "delegation",
+                &[var("date")],
+                &[pred("time", &[s("ambient"), var("date")])],
+                &[Expression {
+                    ops: vec![
+                        var("date").into(),
+                        date(&expiration).into(),
+                        Op::Binary(Binary::LessOrEqual),
+                    ],
+                }],
+            );
+            self.checks.push(check.try_into().unwrap());
+        }
+    }
+
+    /// sets the revocation list checked before running the verification
+    ///
+    /// if a revocation identifier or unique revocation identifier of the
+    /// token is in the store, [`Verifier::verify`] fails with
+    /// [`error::Token::Revoked`]
+    pub fn set_revocation_store(&mut self, store: Arc<dyn RevocationStore>) {
+        self.revocation_store = Some(store);
+    }
+
     pub fn revocation_check(&mut self, ids: &[i64]) {
         let check = constrained_rule(
             "revocation_check",
@@ -349,13 +643,94 @@ impl Verifier {
         let _ = self.add_check(check);
     }
 
+    /// adds facts, rules, checks and policies from Datalog source
+    ///
+    /// elements are separated by `;`. `include` directives are rejected,
+    /// use [`Verifier::add_policy_file`] to load sources that contain them
+    pub fn add_code(&mut self, source: &str) -> Result<(), error::Token> {
+        let mut loaded = LoadedSource::default();
+        load_source(source, None, &mut Vec::new(), &mut loaded)?;
+        self.add_loaded(loaded);
+        Ok(())
+    }
+
+    /// adds facts, rules, checks and policies from a policy file
+    ///
+    /// the file and the ones it includes, with `include "file";` directives
+    /// at the beginning of the source, are loaded through the `loader`.
+    /// Included files are added before the content of the file including
+    /// them, so their policies are tested first.
+    ///
+    /// ```rust
+    /// # use biscuit_auth::token::verifier::Verifier;
+    /// # use std::collections::HashMap;
+    /// let mut files = HashMap::new();
+    /// files.insert(
+    ///     "common.datalog".to_string(),
+    ///     "deny if revoked(#authority);".to_string(),
+    /// );
+    /// files.insert(
+    ///     "main.datalog".to_string(),
+    ///     r#"include "common.datalog"; allow if admin(#authority);"#.to_string(),
+    /// );
+    ///
+    /// let mut verifier = Verifier::new().unwrap();
+    /// verifier.add_policy_file("main.datalog", &files).unwrap();
+    /// ```
+    ///
+    /// nothing is added to the verifier if one of the files cannot be loaded
+    /// or parsed
+    pub fn add_policy_file<L: PolicyLoader + ?Sized>(
+        &mut self,
+        path: &str,
+        loader: &L,
+    ) -> Result<(), error::Token> {
+        let mut loaded = LoadedSource::default();
+        let source = loader.load(path)?;
+        let mut stack = vec![path.to_string()];
+        load_source(&source, Some(&LoaderRef(loader)), &mut stack, &mut loaded)?;
+        self.add_loaded(loaded);
+        Ok(())
+    }
+
+    fn add_loaded(&mut self, loaded: LoadedSource) {
+        self.extend(loaded.facts);
+        self.extend(loaded.rules);
+        for (check, span) in loaded.checks {
+            self.check_spans.insert(self.checks.len(), span);
+            self.checks.push(check);
+        }
+        self.extend(loaded.policies);
+        self.deprecations.extend(loaded.deprecations);
+    }
+
     /// add a policy to the verifier
-    pub fn add_policy<R: TryInto<Policy>>(&mut self, policy: R) -> Result<(), error::Token> {
-        let policy = policy.try_into().map_err(|_| error::Token::ParseError)?;
+    pub fn add_policy<R: TryInto<Policy>>(&mut self, policy: R) -> Result<(), error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        let policy = policy.try_into().map_err(Into::into)?;
         self.policies.push(policy);
         Ok(())
     }
 
+    /// add a policy with a label, reported in the [`VerifierResult`] when it matches
+    ///
+    /// labels are not kept by [`Verifier::save`]
+    pub fn add_labeled_policy<R: TryInto<Policy>>(
+        &mut self,
+        label: &str,
+        policy: R,
+    ) -> Result<(), error::Token>
+    where
+        R::Error: Into<error::Token>,
+    {
+        self.add_policy(policy)?;
+        self.policy_labels
+            .insert(self.policies.len() - 1, label.to_string());
+        Ok(())
+    }
+
     pub fn allow(&mut self) -> Result<(), error::Token> {
         self.add_policy("allow if true")
     }
@@ -367,28 +742,205 @@ impl Verifier {
     /// checks all the checks
     ///
     /// on error, this can return a list of all the failed checks
-    /// on success, it returns the index of the policy that matched
-    pub fn verify(&mut self) -> Result<usize, error::Token> {
+    /// on success, it returns a [`VerifierResult`] describing the policy that matched
+    pub fn verify(&mut self) -> Result<VerifierResult, error::Token> {
         self.verify_with_limits(VerifierLimits::default())
     }
 
+    /// checks all the checks, stopping at a deadline
+    ///
+    /// this works like [`Verifier::verify_with_limits`], with the execution
+    /// time limited by whichever comes first of `limits.max_time` and the
+    /// deadline, so a request handler can give the verification exactly its
+    /// remaining time budget. If the deadline is already past, this returns
+    /// `RunLimit::Timeout` without running anything
+    pub fn verify_with_deadline(
+        &mut self,
+        mut limits: VerifierLimits,
+        deadline: std::time::Instant,
+    ) -> Result<VerifierResult, error::Token> {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining == Duration::default() {
+            return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+        }
+
+        limits.max_time = limits.max_time.min(remaining);
+        self.verify_with_limits(limits)
+    }
+
     /// checks all the checks
     ///
     /// on error, this can return a list of all the failed checks
     ///
     /// this method can specify custom runtime limits
-    pub fn verify_with_limits(&mut self, limits: VerifierLimits) -> Result<usize, error::Token> {
+    pub fn verify_with_limits(
+        &mut self,
+        limits: VerifierLimits,
+    ) -> Result<VerifierResult, error::Token> {
         let start = Instant::now();
+        let initial_facts = self.world.facts.len();
+        let mut iterations = 0;
+
+        let res = self.verify_inner(limits, start, &mut iterations);
+
+        self.metrics = Some(VerifierMetrics {
+            facts_generated: self.world.facts.len() - initial_facts,
+            facts: self.world.facts.len(),
+            iterations,
+            elapsed: start.elapsed(),
+        });
+
+        #[cfg(feature = "otel")]
+        crate::otel::record(&res, self.metrics.as_ref(), &self.token_revocation_ids);
+
+        self.failure = match &res {
+            Err(e) if self.dump_on_failure => {
+                let mut facts = self
+                    .world
+                    .facts
+                    .iter()
+                    .map(|f| self.symbols.print_fact(f))
+                    .collect::<Vec<_>>();
+                facts.sort();
+
+                Some(VerifierFailure {
+                    error: e.clone(),
+                    facts,
+                })
+            }
+            _ => None,
+        };
+
+        res
+    }
+
+    /// verifies several tokens in the context of this verifier
+    ///
+    /// the facts, rules, checks and policies of the verifier are those of the
+    /// request, and each token is added to a copy of it. The verifier is
+    /// frozen (see [`Verifier::freeze`]) before being copied, so its world
+    /// and its symbols are only built once for the whole batch.
+    ///
+    /// returns the result of each token, in the same order
+    pub fn verify_batch(
+        &self,
+        tokens: &[Biscuit],
+        root: PublicKey,
+    ) -> Vec<Result<VerifierResult, error::Token>> {
+        self.verify_batch_with_limits(tokens, root, VerifierLimits::default())
+    }
+
+    /// verifies several tokens in the context of this verifier, with custom runtime limits
+    ///
+    /// the limits apply to each token
+    pub fn verify_batch_with_limits(
+        &self,
+        tokens: &[Biscuit],
+        root: PublicKey,
+        limits: VerifierLimits,
+    ) -> Vec<Result<VerifierResult, error::Token>> {
+        let mut template = self.clone();
+        template.freeze();
+
+        tokens
+            .iter()
+            .map(|token| {
+                let mut verifier = template.clone();
+                verifier.add_token(token, root)?;
+                verifier.verify_with_limits(limits.clone())
+            })
+            .collect()
+    }
 
+    /// execution metrics of the last call to [`Verifier::verify`] or its variants
+    ///
+    /// this is `None` if the verifier was not run yet. The metrics are recorded
+    /// whether the verification succeeded or not
+    pub fn metrics(&self) -> Option<&VerifierMetrics> {
+        self.metrics.as_ref()
+    }
+
+    /// shares the facts and rules added so far with the future clones of
+    /// this verifier
+    ///
+    /// a verifier preloaded with a large set of facts can then be cloned
+    /// for every request without copying them. The facts and rules added
+    /// to a clone are only stored in that clone
+    pub fn freeze(&mut self) {
+        self.world.freeze();
+    }
+
+    /// keeps the facts of the world when the verification fails
+    ///
+    /// they are then available from [`Verifier::failure`]. This is disabled
+    /// by default, since it prints all the facts on every failure
+    pub fn dump_on_failure(&mut self, enabled: bool) {
+        self.dump_on_failure = enabled;
+    }
+
+    /// error and facts of the last failed verification
+    ///
+    /// this is `None` if the last verification succeeded, or if
+    /// [`Verifier::dump_on_failure`] was not enabled
+    pub fn failure(&self) -> Option<&VerifierFailure> {
+        self.failure.as_ref()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "verify", skip_all, err)
+    )]
+    fn verify_inner(
+        &mut self,
+        limits: VerifierLimits,
+        start: Instant,
+        iterations: &mut u32,
+    ) -> Result<VerifierResult, error::Token> {
         //FIXME: should check for the presence of any other symbol in the token
         if self.symbols.get("authority").is_none() || self.symbols.get("ambient").is_none() {
             return Err(error::Token::MissingSymbols);
         }
 
+        if let Some(store) = self.revocation_store.as_ref() {
+            let ids = self
+                .token_revocation_ids
+                .iter()
+                .flat_map(|(id, unique_id)| vec![id.as_slice(), unique_id.as_slice()])
+                .collect::<Vec<_>>();
+            if let Some(position) = store.first_revoked(&ids) {
+                return Err(error::Token::Revoked((position / 2) as u32));
+            }
+        }
+
+        if let Some(namespaces) = self.block_namespaces.as_ref() {
+            for (block_id, (facts, rules)) in self
+                .token_facts
+                .iter()
+                .zip(self.token_rules.iter())
+                .enumerate()
+            {
+                let predicates = facts
+                    .iter()
+                    .map(|f| &f.predicate)
+                    .chain(rules.iter().map(|r| &r.head));
+                for predicate in predicates {
+                    let name = self.symbols.print_symbol(predicate.name);
+                    if let Some(namespace) = datalog::namespace(&name) {
+                        if !namespaces.contains(namespace) {
+                            return Err(
+                                error::Logic::ForbiddenNamespace(block_id as u32, name).into()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
         let authority_index = self.symbols.get("authority").unwrap();
         let ambient_index = self.symbols.get("ambient").unwrap();
 
-        self.world
+        *iterations = self
+            .world
             .run_with_limits(limits.clone().into(), &[authority_index, ambient_index])
             .map_err(error::Token::RunLimit)?;
 
@@ -400,7 +952,11 @@ impl Verifier {
             let mut successful = false;
 
             for query in check.queries.iter() {
-                let res = self.world.query_match(query.convert(&mut self.symbols));
+                let query = query.convert(&mut self.symbols);
+                query
+                    .validate_expression_depth()
+                    .map_err(error::Token::RunLimit)?;
+                let res = self.world.query_match(query);
 
                 let now = Instant::now();
                 if now >= time_limit {
@@ -413,10 +969,19 @@ impl Verifier {
                 }
             }
 
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                check_id = i,
+                check = %self.symbols.print_check(&c),
+                successful,
+                "verifier check"
+            );
+
             if !successful {
                 errors.push(error::FailedCheck::Verifier(error::FailedVerifierCheck {
                     check_id: i as u32,
                     rule: self.symbols.print_check(&c),
+                    span: self.check_spans.get(&i).cloned(),
                 }));
             }
         }
@@ -426,6 +991,9 @@ impl Verifier {
                 let mut successful = false;
 
                 for query in check.queries.iter() {
+                    query
+                        .validate_expression_depth()
+                        .map_err(error::Token::RunLimit)?;
                     let res = self.world.query_match(query.clone());
 
                     let now = Instant::now();
@@ -439,11 +1007,21 @@ impl Verifier {
                     }
                 }
 
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    block_id = i,
+                    check_id = j,
+                    check = %self.symbols.print_check(check),
+                    successful,
+                    "block check"
+                );
+
                 if !successful {
                     errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
                         block_id: i as u32,
                         check_id: j as u32,
                         rule: self.symbols.print_check(check),
+                        span: None,
                     }));
                 }
             }
@@ -456,18 +1034,39 @@ impl Verifier {
         } else {
             for (i, policy) in self.policies.iter().enumerate() {
                 for query in policy.queries.iter() {
-                    let res = self.world.query_match(query.convert(&mut self.symbols));
+                    let query = query.convert(&mut self.symbols);
+                    query
+                        .validate_expression_depth()
+                        .map_err(error::Token::RunLimit)?;
+                    let res = query.find_bindings(&self.world.facts);
 
                     let now = Instant::now();
                     if now >= time_limit {
                         return Err(error::Token::RunLimit(error::RunLimit::Timeout));
                     }
 
-                    if res {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        policy_id = i,
+                        policy = %policy,
+                        matched = res.is_some(),
+                        "policy"
+                    );
+
+                    if let Some(bindings) = res {
                         return match policy.kind {
-                            PolicyKind::Allow => Ok(i),
+                            PolicyKind::Allow => Ok(VerifierResult {
+                                policy_id: i,
+                                label: self.policy_labels.get(&i).cloned(),
+                                policy: policy.to_string(),
+                                contributing_blocks: self.contributing_blocks(&query, &bindings),
+                            }),
                             PolicyKind::Deny => {
-                                Err(error::Token::FailedLogic(error::Logic::Deny(i)))
+                                Err(error::Token::FailedLogic(error::Logic::Deny {
+                                    policy_id: i,
+                                    label: self.policy_labels.get(&i).cloned(),
+                                    policy: policy.to_string(),
+                                }))
                             }
                         };
                     }
@@ -477,6 +1076,80 @@ impl Verifier {
         }
     }
 
+    /// explains why a check returned by [`Verifier::verify`] failed
+    ///
+    /// for each query of the check, this lists the predicates that matched
+    /// no fact, or the expressions that were false for all the facts matching
+    /// the predicates. If both are empty, the predicates match facts
+    /// separately, but not with the same values for their common variables
+    pub fn explain(&self, failed: &error::FailedCheck) -> Option<CheckFailureExplanation> {
+        let mut symbols = self.symbols.clone();
+        let check = match failed {
+            error::FailedCheck::Verifier(c) => {
+                self.checks.get(c.check_id as usize)?.convert(&mut symbols)
+            }
+            error::FailedCheck::Block(c) => self
+                .token_checks
+                .get(c.block_id as usize)?
+                .get(c.check_id as usize)?
+                .clone(),
+        };
+
+        let queries = check
+            .queries
+            .iter()
+            .map(|query| {
+                let (missing, failed) = query.explain_failure(&self.world.facts);
+                QueryFailureExplanation {
+                    query: symbols.print_rule_body(query),
+                    missing_predicates: missing
+                        .into_iter()
+                        .map(|i| symbols.print_predicate(&query.body[i]))
+                        .collect(),
+                    failed_expressions: failed
+                        .into_iter()
+                        .map(|i| symbols.print_expression(&query.expressions[i]))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Some(CheckFailureExplanation {
+            check: symbols.print_check(&check),
+            queries,
+        })
+    }
+
+    /// lists the token blocks that provided one of the facts matched by the query
+    fn contributing_blocks(
+        &self,
+        query: &datalog::Rule,
+        bindings: &HashMap<u32, datalog::ID>,
+    ) -> Vec<usize> {
+        let matched = query
+            .body
+            .iter()
+            .map(|predicate| {
+                let mut predicate = predicate.clone();
+                for id in predicate.ids.iter_mut() {
+                    if let datalog::ID::Variable(v) = id {
+                        if let Some(value) = bindings.get(v) {
+                            *id = value.clone();
+                        }
+                    }
+                }
+                datalog::Fact { predicate }
+            })
+            .collect::<Vec<_>>();
+
+        self.token_facts
+            .iter()
+            .enumerate()
+            .filter(|(_, facts)| matched.iter().any(|fact| facts.contains(fact)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
     /// prints the content of the verifier
     pub fn print_world(&self) -> String {
         let mut facts = self
@@ -530,6 +1203,86 @@ impl Verifier {
         )
     }
 
+    /// stable text representation of the content of the verifier, for
+    /// snapshot tests
+    ///
+    /// facts, rules and checks are sorted, so the output does not depend on
+    /// the order they were added in. Checks are grouped by origin, and the
+    /// policies keep their order since it changes the result. The revocation
+    /// identifiers are left out, as they change every time a token is minted
+    pub fn to_snapshot_string(&self) -> String {
+        let sorted = |mut elements: Vec<String>| {
+            elements.sort();
+            elements
+        };
+        let ignored = [
+            self.symbols.get("revocation_id"),
+            self.symbols.get("unique_revocation_id"),
+        ];
+        let mut sections = vec![
+            (
+                "facts".to_string(),
+                sorted(
+                    self.world
+                        .facts
+                        .iter()
+                        .filter(|f| !ignored.contains(&Some(f.predicate.name)))
+                        .map(|f| self.symbols.print_fact(f))
+                        .collect(),
+                ),
+            ),
+            (
+                "privileged rules".to_string(),
+                sorted(
+                    self.world
+                        .privileged_rules
+                        .iter()
+                        .map(|r| self.symbols.print_rule(r))
+                        .collect(),
+                ),
+            ),
+            (
+                "rules".to_string(),
+                sorted(
+                    self.world
+                        .rules
+                        .iter()
+                        .map(|r| self.symbols.print_rule(r))
+                        .collect(),
+                ),
+            ),
+            (
+                "verifier checks".to_string(),
+                sorted(self.checks.iter().map(|c| c.to_string()).collect()),
+            ),
+        ];
+        for (i, checks) in self.token_checks.iter().enumerate() {
+            if !checks.is_empty() {
+                sections.push((
+                    format!("checks of block {}", i),
+                    sorted(checks.iter().map(|c| self.symbols.print_check(c)).collect()),
+                ));
+            }
+        }
+        sections.push((
+            "policies".to_string(),
+            self.policies.iter().map(|p| p.to_string()).collect(),
+        ));
+
+        let mut snapshot = String::new();
+        for (i, (title, elements)) in sections.iter().enumerate() {
+            if i > 0 {
+                snapshot.push('\n');
+            }
+            snapshot.push_str(&format!("// {}\n", title));
+            for element in elements {
+                snapshot.push_str(element);
+                snapshot.push_str(";\n");
+            }
+        }
+        snapshot
+    }
+
     /// returns all of the data loaded in the verifier
     pub fn dump(&self) -> (Vec<Fact>, Vec<Rule>, Vec<Rule>, Vec<Check>, Vec<Policy>) {
         let mut checks = self.checks.clone();
@@ -560,6 +1313,458 @@ impl Verifier {
             self.policies.clone(),
         )
     }
+
+    /// lists the non fatal issues of the policies and token
+    ///
+    /// this looks at the verifier's content after [`Verifier::verify`], for
+    /// tools checking the hygiene of policies. It is not computed during the
+    /// verification, so it does not slow it down
+    pub fn warnings(&self) -> Vec<VerifierWarning> {
+        let mut symbols = self.symbols.clone();
+        let mut queries: Vec<datalog::Rule> = Vec::new();
+        for check in self.checks.iter() {
+            queries.extend(check.queries.iter().map(|q| q.convert(&mut symbols)));
+        }
+        for check in self.token_checks.iter().flatten() {
+            queries.extend(check.queries.iter().cloned());
+        }
+        let policies = self
+            .policies
+            .iter()
+            .map(|p| {
+                p.queries
+                    .iter()
+                    .map(|q| q.convert(&mut symbols))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let rules = self
+            .world
+            .privileged_rules
+            .iter()
+            .chain(self.world.rules.iter())
+            .collect::<Vec<_>>();
+        let all_queries = rules
+            .iter()
+            .copied()
+            .chain(queries.iter())
+            .chain(policies.iter().flatten())
+            .collect::<Vec<_>>();
+
+        let mut warnings = self.deprecations.clone();
+
+        let mut used_predicates = HashSet::new();
+        let mut used_symbols = HashSet::new();
+        for rule in all_queries.iter() {
+            used_predicates.extend(rule.body.iter().map(|p| p.name));
+            for predicate in std::iter::once(&rule.head).chain(rule.body.iter()) {
+                used_symbols.insert(predicate.name);
+                used_symbols.extend(predicate.ids.iter().flat_map(id_symbols));
+            }
+            for e in rule.expressions.iter() {
+                for op in e.ops.iter() {
+                    if let datalog::Op::Value(id) = op {
+                        used_symbols.extend(id_symbols(id));
+                    }
+                }
+            }
+        }
+
+        let ignored = [
+            symbols.get("revocation_id"),
+            symbols.get("unique_revocation_id"),
+        ];
+        let mut unused_facts = Vec::new();
+        for fact in self.world.facts.iter() {
+            used_symbols.insert(fact.predicate.name);
+            used_symbols.extend(fact.predicate.ids.iter().flat_map(id_symbols));
+            if !used_predicates.contains(&fact.predicate.name)
+                && !ignored.contains(&Some(fact.predicate.name))
+            {
+                unused_facts.push(symbols.print_fact(fact));
+            }
+        }
+        unused_facts.sort();
+        warnings.extend(unused_facts.into_iter().map(VerifierWarning::UnusedFact));
+
+        for rule in rules.iter() {
+            if rule.apply(&self.world.facts).next().is_none() {
+                warnings.push(VerifierWarning::UnusedRule(symbols.print_rule(rule)));
+            }
+        }
+
+        let default_symbols = super::default_symbol_table().len();
+        for (i, symbol) in symbols.symbols().iter().enumerate().skip(default_symbols) {
+            if !used_symbols.contains(&(i as u64)) {
+                warnings.push(VerifierWarning::UnusedSymbol(symbol.clone()));
+            }
+        }
+
+        if let Some(shadowed_by) = policies
+            .iter()
+            .position(|queries| queries.iter().any(always_matches))
+        {
+            for policy_id in shadowed_by + 1..policies.len() {
+                warnings.push(VerifierWarning::UnreachablePolicy {
+                    policy_id,
+                    shadowed_by,
+                });
+            }
+        }
+
+        warnings
+    }
+}
+
+/// symbols used by an ID, including the names of variables
+fn id_symbols(id: &datalog::ID) -> Vec<u64> {
+    match id {
+        datalog::ID::Symbol(s) => vec![*s],
+        datalog::ID::Variable(v) => vec![*v as u64],
+        datalog::ID::Set(set) => set.iter().flat_map(id_symbols).collect(),
+        _ => vec![],
+    }
+}
+
+/// a query without predicates, and whose expressions are all `true`
+fn always_matches(query: &datalog::Rule) -> bool {
+    query.body.is_empty()
+        && query
+            .expressions
+            .iter()
+            .all(|e| e.ops.len() == 1 && e.ops[0] == datalog::Op::Value(datalog::ID::Bool(true)))
+}
+
+/// revocation identifier and unique revocation identifier of each block
+fn token_revocation_ids(token: &Biscuit) -> Vec<(Vec<u8>, Vec<u8>)> {
+    token
+        .revocation_ids()
+        .iter()
+        .cloned()
+        .zip(token.unique_revocation_ids().iter().cloned())
+        .collect()
+}
+
+/// facts for a JSON value, see [`Verifier::add_facts_from_json`]
+#[cfg(feature = "json")]
+fn json_facts(
+    name: String,
+    value: &serde_json::Value,
+    facts: &mut Vec<Fact>,
+) -> Result<(), error::Token> {
+    use serde_json::Value;
+
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(Ok(string(s))),
+        Value::Bool(b) => Some(Ok(Term::Bool(*b))),
+        Value::Number(n) => Some(n.as_i64().map(Term::Integer).ok_or_else(|| {
+            error::Token::ConversionError(format!("{} is not a 64 bits integer, in {}", n, name))
+        })),
+        _ => None,
+    };
+
+    match value {
+        Value::Null => Ok(()),
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let key = key
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect::<String>();
+                let name = if name.is_empty() {
+                    key
+                } else {
+                    format!("{}_{}", name, key)
+                };
+                json_facts(name, value, facts)?;
+            }
+            Ok(())
+        }
+        _ if name.is_empty() => Err(error::Token::ConversionError(
+            "a JSON value that is not an object needs a predicate prefix".to_string(),
+        )),
+        Value::Array(elements) => {
+            let same_scalars = elements.windows(2).all(|pair| {
+                matches!(
+                    pair,
+                    [Value::String(_), Value::String(_)]
+                        | [Value::Bool(_), Value::Bool(_)]
+                        | [Value::Number(_), Value::Number(_)]
+                )
+            }) && !matches!(
+                elements.first(),
+                Some(Value::Null) | Some(Value::Object(_)) | Some(Value::Array(_))
+            );
+
+            if same_scalars {
+                let set = elements
+                    .iter()
+                    .filter_map(scalar)
+                    .collect::<Result<_, _>>()?;
+                facts.push(fact(&name, &[s("ambient"), Term::Set(set)]));
+            } else {
+                for element in elements {
+                    json_facts(name.clone(), element, facts)?;
+                }
+            }
+            Ok(())
+        }
+        value => {
+            let term = scalar(value).expect("strings, booleans and numbers are scalars")?;
+            facts.push(fact(&name, &[s("ambient"), term]));
+            Ok(())
+        }
+    }
+}
+
+/// non fatal issue found by [`Verifier::warnings`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifierWarning {
+    /// a fact that no rule, check or policy refers to
+    UnusedFact(String),
+    /// a rule that does not generate any fact
+    UnusedRule(String),
+    /// a symbol of the symbol table that appears nowhere
+    UnusedSymbol(String),
+    /// a policy that is never tested, because an earlier policy always matches
+    UnreachablePolicy {
+        policy_id: usize,
+        shadowed_by: usize,
+    },
+    /// an element written with the syntax of an older version of the language
+    Deprecated {
+        message: String,
+        /// the element written with the current syntax
+        replacement: String,
+        span: error::SourceSpan,
+    },
+}
+
+impl fmt::Display for VerifierWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifierWarning::UnusedFact(fact) => write!(f, "unused fact: {}", fact),
+            VerifierWarning::UnusedRule(rule) => write!(f, "rule never generated facts: {}", rule),
+            VerifierWarning::UnusedSymbol(symbol) => write!(f, "unused symbol: {}", symbol),
+            VerifierWarning::UnreachablePolicy {
+                policy_id,
+                shadowed_by,
+            } => write!(
+                f,
+                "policy {} is unreachable, policy {} always matches",
+                policy_id, shadowed_by
+            ),
+            VerifierWarning::Deprecated {
+                message,
+                replacement,
+                span,
+            } => write!(
+                f,
+                "line {}: {}, write `{}`",
+                span.line, message, replacement
+            ),
+        }
+    }
+}
+
+impl Extend<Fact> for Verifier {
+    fn extend<T: IntoIterator<Item = Fact>>(&mut self, iter: T) {
+        for fact in iter {
+            self.world.facts.insert(fact.convert(&mut self.symbols));
+        }
+    }
+}
+
+impl Extend<Rule> for Verifier {
+    fn extend<T: IntoIterator<Item = Rule>>(&mut self, iter: T) {
+        for rule in iter {
+            self.world
+                .privileged_rules
+                .push(rule.convert(&mut self.symbols));
+        }
+    }
+}
+
+impl Extend<Check> for Verifier {
+    fn extend<T: IntoIterator<Item = Check>>(&mut self, iter: T) {
+        self.checks.extend(iter);
+    }
+}
+
+impl Extend<Policy> for Verifier {
+    fn extend<T: IntoIterator<Item = Policy>>(&mut self, iter: T) {
+        self.policies.extend(iter);
+    }
+}
+
+/// creates an empty verifier (as with [`Verifier::new`]) containing those facts
+impl FromIterator<Fact> for Verifier {
+    fn from_iter<T: IntoIterator<Item = Fact>>(iter: T) -> Self {
+        let mut verifier = Verifier::empty();
+        verifier.extend(iter);
+        verifier
+    }
+}
+
+/// source of the current time for the verifier
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// reads the system's clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// always returns the same time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// provides the content of policy files for [`Verifier::add_policy_file`]
+pub trait PolicyLoader {
+    /// returns the Datalog source designated by `path`, as written in an
+    /// `include` directive
+    fn load(&self, path: &str) -> Result<String, error::Token>;
+}
+
+/// loads policy files from a directory
+///
+/// paths are relative to the directory, absolute paths and `..` are rejected
+#[derive(Clone, Debug)]
+pub struct DirectoryLoader {
+    root: PathBuf,
+}
+
+impl DirectoryLoader {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectoryLoader { root: root.into() }
+    }
+}
+
+impl PolicyLoader for DirectoryLoader {
+    fn load(&self, path: &str) -> Result<String, error::Token> {
+        let relative = Path::new(path);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(error::Token::LoadError(format!(
+                "{}: the path must be relative to the policy directory",
+                path
+            )));
+        }
+
+        std::fs::read_to_string(self.root.join(relative))
+            .map_err(|e| error::Token::LoadError(format!("{}: {}", path, e)))
+    }
+}
+
+/// loads policy files from memory, indexed by path
+impl PolicyLoader for HashMap<String, String> {
+    fn load(&self, path: &str) -> Result<String, error::Token> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| error::Token::LoadError(format!("{}: file not found", path)))
+    }
+}
+
+// allows unsized loaders to be used as trait objects
+struct LoaderRef<'a, L: PolicyLoader + ?Sized>(&'a L);
+
+impl<'a, L: PolicyLoader + ?Sized> PolicyLoader for LoaderRef<'a, L> {
+    fn load(&self, path: &str) -> Result<String, error::Token> {
+        self.0.load(path)
+    }
+}
+
+#[derive(Default)]
+struct LoadedSource {
+    facts: Vec<Fact>,
+    rules: Vec<Rule>,
+    checks: Vec<(Check, error::SourceSpan)>,
+    policies: Vec<Policy>,
+    deprecations: Vec<VerifierWarning>,
+}
+
+fn load_source(
+    source: &str,
+    loader: Option<&dyn PolicyLoader>,
+    stack: &mut Vec<String>,
+    loaded: &mut LoadedSource,
+) -> Result<(), error::Token> {
+    let (_, result) = parser::parse_source(source).map_err(|e| parser::source_error(source, e))?;
+    let file = stack.last().cloned();
+
+    for (_, path) in result.includes {
+        let loader = loader.ok_or_else(|| {
+            error::Token::LoadError(format!(
+                "{}: include directives require a policy loader",
+                path
+            ))
+        })?;
+
+        if stack.contains(&path) {
+            return Err(error::Token::LoadError(format!(
+                "{}: circular include ({})",
+                path,
+                stack.join(" -> ")
+            )));
+        }
+
+        let included = loader.load(&path)?;
+        stack.push(path);
+        load_source(&included, Some(loader), stack, loaded)?;
+        stack.pop();
+    }
+
+    for (_, fact) in result.facts {
+        loaded.facts.push(fact.try_into()?);
+    }
+    for (_, rule) in result.rules {
+        loaded.rules.push(rule.try_into()?);
+    }
+    for (text, check) in result.checks {
+        let span = source_span(source, text, file.clone());
+        loaded.checks.push((check.try_into()?, span));
+    }
+    for (_, policy) in result.policies {
+        loaded.policies.push(policy.try_into()?);
+    }
+    for deprecation in result.deprecations {
+        loaded.deprecations.push(VerifierWarning::Deprecated {
+            span: source_span(source, deprecation.input, file.clone()),
+            message: deprecation.message,
+            replacement: deprecation.replacement,
+        });
+    }
+
+    Ok(())
+}
+
+/// locates `element`, a slice of `source`, in the source text
+fn source_span(source: &str, element: &str, file: Option<String>) -> error::SourceSpan {
+    let start = element.as_ptr() as usize - source.as_ptr() as usize;
+    let (line, column) = parser::position(source, start);
+
+    error::SourceSpan {
+        file,
+        start,
+        end: start + element.len(),
+        line,
+        column,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -599,6 +1804,58 @@ impl Default for VerifierLimits {
     }
 }
 
+impl VerifierLimits {
+    /// suggests limits from the metrics of recorded verifications
+    ///
+    /// each limit is the highest value observed, increased by `margin`
+    /// (`0.5` allows 50% more than the highest value). Verifications that
+    /// used those limits may have been stopped before completion, so the
+    /// metrics should come from runs with generous limits.
+    ///
+    /// returns `None` if there are no metrics
+    ///
+    /// ```rust
+    /// use biscuit_auth::token::verifier::{VerifierLimits, VerifierMetrics};
+    /// use std::time::Duration;
+    ///
+    /// let recorded = vec![
+    ///     VerifierMetrics { facts_generated: 10, facts: 40, iterations: 3, elapsed: Duration::from_micros(200) },
+    ///     VerifierMetrics { facts_generated: 30, facts: 80, iterations: 2, elapsed: Duration::from_micros(500) },
+    /// ];
+    /// let limits = VerifierLimits::suggest(&recorded, 0.5).unwrap();
+    /// assert_eq!(limits.max_facts, 120);
+    /// assert_eq!(limits.max_iterations, 5);
+    /// assert_eq!(limits.max_time, Duration::from_micros(750));
+    /// ```
+    pub fn suggest<'a, I: IntoIterator<Item = &'a VerifierMetrics>>(
+        metrics: I,
+        margin: f64,
+    ) -> Option<Self> {
+        let mut metrics = metrics.into_iter().peekable();
+        metrics.peek()?;
+
+        let mut max_facts = 0;
+        let mut max_iterations = 0;
+        let mut max_time = Duration::default();
+        for m in metrics {
+            max_facts = max_facts.max(m.facts);
+            max_iterations = max_iterations.max(m.iterations);
+            max_time = max_time.max(m.elapsed);
+        }
+
+        let factor = 1.0 + margin.max(0.0);
+        let scale = |value: f64| (value * factor).ceil();
+
+        Some(VerifierLimits {
+            // the engine stops as soon as the number of facts or iterations
+            // reaches the limit
+            max_facts: (scale(max_facts as f64) as u32).max(max_facts as u32 + 1),
+            max_iterations: (scale(f64::from(max_iterations)) as u32).max(max_iterations + 1),
+            max_time: max_time.mul_f64(factor),
+        })
+    }
+}
+
 impl std::convert::From<VerifierLimits> for crate::datalog::RunLimits {
     fn from(limits: VerifierLimits) -> Self {
         crate::datalog::RunLimits {
@@ -608,3 +1865,66 @@ impl std::convert::From<VerifierLimits> for crate::datalog::RunLimits {
         }
     }
 }
+
+/// successful outcome of a verification
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifierResult {
+    /// index of the allow policy that matched
+    pub policy_id: usize,
+    /// label given with [`Verifier::add_labeled_policy`]
+    pub label: Option<String>,
+    /// source text of the policy
+    pub policy: String,
+    /// indexes of the token blocks (0 being the authority block) that provided
+    /// facts matched by the policy. Facts generated by rules are not traced back
+    /// to the blocks they were derived from
+    pub contributing_blocks: Vec<usize>,
+}
+
+/// execution metrics of a verification
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifierMetrics {
+    /// number of facts generated by the rules
+    pub facts_generated: usize,
+    /// number of facts in the world at the end of the verification, this is
+    /// what [`VerifierLimits::max_facts`] is compared to
+    pub facts: usize,
+    /// number of iterations of the rules applications that generated new facts
+    pub iterations: u32,
+    /// total execution time, including checks and policies
+    pub elapsed: Duration,
+}
+
+/// a failed verification, with the facts of the world at the time of failure
+///
+/// it is displayed on a single line, to be written to logs
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifierFailure {
+    pub error: error::Token,
+    /// facts of the world, sorted
+    pub facts: Vec<String>,
+}
+
+impl fmt::Display for VerifierFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}; facts: [{}]", self.error, self.facts.join(", "))
+    }
+}
+
+/// why a check failed, returned by [`Verifier::explain`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckFailureExplanation {
+    pub check: String,
+    /// one explanation per query of the check, in order
+    pub queries: Vec<QueryFailureExplanation>,
+}
+
+/// why a query of a check did not match
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryFailureExplanation {
+    pub query: String,
+    /// body predicates that matched no fact
+    pub missing_predicates: Vec<String>,
+    /// expressions that were false for all the facts matching the body
+    pub failed_expressions: Vec<String>,
+}
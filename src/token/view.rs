@@ -0,0 +1,122 @@
+//! typed view of the content of a block
+use super::builder::{Check, Fact, Rule};
+use super::Block;
+use crate::crypto::PublicKey;
+use crate::datalog::SymbolTable;
+use std::fmt;
+
+/// content of a block, with its symbols resolved
+///
+/// unlike [`Biscuit::print_block_source`](crate::token::Biscuit::print_block_source),
+/// the facts, rules and checks are kept as builder values, so they can be
+/// inspected or compared without parsing Datalog again
+///
+/// ```rust
+/// use biscuit_auth::{crypto::KeyPair, token::{Biscuit, builder::*}};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder(&root);
+/// builder.add_right("/file", "read");
+/// let token = builder.build().unwrap();
+///
+/// let authority = token.block(0).unwrap().unwrap();
+/// assert_eq!(authority.facts, vec![fact("right", &[s("authority"), string("/file"), s("read")])]);
+/// assert_eq!(authority.public_key, Some(root.public()));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockView {
+    /// position of the block, 0 being the authority block
+    pub index: usize,
+    pub facts: Vec<Fact>,
+    pub rules: Vec<Rule>,
+    pub checks: Vec<Check>,
+    /// free form text set with [`BlockBuilder::set_context`](crate::token::builder::BlockBuilder::set_context)
+    pub context: Option<String>,
+    /// format version used to generate this block
+    pub version: u32,
+    /// key used to sign this block: the root key for the authority block,
+    /// the key given to [`Biscuit::append`](crate::token::Biscuit::append)
+    /// for the others. Sealed tokens do not keep the keys
+    pub public_key: Option<PublicKey>,
+}
+
+impl BlockView {
+    pub(crate) fn new(
+        index: usize,
+        block: &Block,
+        symbols: &SymbolTable,
+        public_key: Option<PublicKey>,
+    ) -> Self {
+        BlockView {
+            index,
+            facts: block
+                .facts
+                .iter()
+                .map(|f| Fact::convert_from(f, symbols))
+                .collect(),
+            rules: block
+                .rules
+                .iter()
+                .map(|r| Rule::convert_from(r, symbols))
+                .collect(),
+            checks: block
+                .checks
+                .iter()
+                .map(|c| Check::convert_from(c, symbols))
+                .collect(),
+            context: block.context.clone(),
+            version: block.version,
+            public_key,
+        }
+    }
+}
+
+/// differences between two tokens, from [`Biscuit::diff`](crate::token::Biscuit::diff)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenDiff {
+    /// number of blocks, authority block included, that are identical and
+    /// signed with the same keys in both tokens. It is 0 if the tokens do
+    /// not share the same authority block
+    pub common: usize,
+    /// blocks following the common ones in the first token
+    pub removed: Vec<BlockView>,
+    /// blocks following the common ones in the second token
+    pub added: Vec<BlockView>,
+}
+
+impl TokenDiff {
+    /// returns `true` if both tokens have the same blocks
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+impl fmt::Display for TokenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "common blocks: {}", self.common)?;
+        for block in self.removed.iter() {
+            write_block(f, '-', block)?;
+        }
+        for block in self.added.iter() {
+            write_block(f, '+', block)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter, sign: char, block: &BlockView) -> fmt::Result {
+    writeln!(f, "{} block {}:", sign, block.index)?;
+    if let Some(context) = &block.context {
+        writeln!(f, "    context: {:?}", context)?;
+    }
+    for fact in block.facts.iter() {
+        writeln!(f, "    {};", fact)?;
+    }
+    for rule in block.rules.iter() {
+        writeln!(f, "    {};", rule)?;
+    }
+    for check in block.checks.iter() {
+        writeln!(f, "    {};", check)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! tonic integration
+//!
+//! [`BiscuitInterceptor`] reads the token from the `authorization` metadata
+//! of gRPC requests, checks it against the root public keys, runs a
+//! verifier with the current time, prepared by the optional authorizer,
+//! and attaches the token to the request
+//! extensions, where the handlers find it with [`biscuit`]:
+//!
+//! ```rust
+//! use biscuit_auth::{crypto::KeyPair, token::Biscuit, tonic::{biscuit, BiscuitInterceptor}};
+//! use tonic::{service::Interceptor, Code, Request};
+//!
+//! let root = KeyPair::new();
+//! let mut interceptor = BiscuitInterceptor::new(root.public()).authorizer(|_metadata, verifier| {
+//!     verifier.add_operation("read");
+//!     verifier.allow()
+//! });
+//!
+//! let token = Biscuit::builder(&root).build().unwrap();
+//! let mut request = Request::new(());
+//! request.metadata_mut().insert(
+//!     "authorization",
+//!     format!("Bearer {}", token.to_base64().unwrap()).parse().unwrap(),
+//! );
+//! let request = interceptor.call(request).unwrap();
+//! assert_eq!(biscuit(&request).unwrap().block_count(), 1);
+//!
+//! assert_eq!(interceptor.call(Request::new(())).unwrap_err().code(), Code::Unauthenticated);
+//! ```
+//!
+//! the interceptor is added to a server with
+//! `MyServiceServer::with_interceptor(service, interceptor)`
+use crate::crypto::PublicKey;
+use crate::error;
+use crate::http;
+use crate::token::{verifier::Verifier, Biscuit};
+use ::tonic::{metadata::MetadataMap, service::Interceptor, Request, Status};
+use std::{fmt, sync::Arc};
+
+/// function adding the facts and policies of a service to the verifier
+pub type Authorizer = dyn Fn(&MetadataMap, &mut Verifier) -> Result<(), error::Token> + Send + Sync;
+
+/// interceptor verifying the token of each request
+#[derive(Clone)]
+pub struct BiscuitInterceptor {
+    root_keys: Vec<PublicKey>,
+    authorizer: Option<Arc<Authorizer>>,
+}
+
+impl BiscuitInterceptor {
+    pub fn new(root: PublicKey) -> Self {
+        BiscuitInterceptor::with_root_keys(vec![root])
+    }
+
+    /// accepts tokens issued by any of these keys, as an example during a
+    /// root key rotation
+    pub fn with_root_keys(root_keys: Vec<PublicKey>) -> Self {
+        BiscuitInterceptor {
+            root_keys,
+            authorizer: None,
+        }
+    }
+
+    /// sets the function preparing the verifier for each request
+    ///
+    /// the verifier already contains the current time. The authorizer
+    /// should add the facts describing the request and the policies.
+    /// Without an authorizer, the checks of the token are verified with
+    /// the `allow if true` policy
+    pub fn authorizer<F>(mut self, authorizer: F) -> Self
+    where
+        F: Fn(&MetadataMap, &mut Verifier) -> Result<(), error::Token> + Send + Sync + 'static,
+    {
+        self.authorizer = Some(Arc::new(authorizer));
+        self
+    }
+
+    /// extracts the token from the metadata, verifies it, and runs the
+    /// authorizer
+    pub fn authorize(&self, metadata: &MetadataMap) -> Result<Biscuit, Status> {
+        let value = metadata
+            .get("authorization")
+            .ok_or_else(|| Status::unauthenticated("missing token"))?;
+        let token = match http::parse_token(value.as_bytes(), http::MAX_HEADER_SIZE) {
+            Ok((_, token)) => token,
+            Err(http::HeaderError::Token(e)) => return Err(status(&e)),
+            Err(_) => return Err(Status::unauthenticated("invalid authorization metadata")),
+        };
+
+        let root = self
+            .root_keys
+            .iter()
+            .find(|root| token.check_root_key(**root).is_ok())
+            .ok_or_else(|| status(&error::Format::UnknownPublicKey.into()))?;
+
+        let mut verifier = token.verify(*root).map_err(|e| status(&e))?;
+        verifier.set_time();
+        match self.authorizer.as_ref() {
+            Some(authorizer) => authorizer(metadata, &mut verifier),
+            None => verifier.allow(),
+        }
+        .map_err(|e| status(&e))?;
+        verifier.verify().map_err(|e| status(&e))?;
+
+        Ok(token)
+    }
+}
+
+impl Interceptor for BiscuitInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = self.authorize(request.metadata())?;
+        request.extensions_mut().insert(token);
+        Ok(request)
+    }
+}
+
+impl fmt::Debug for BiscuitInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BiscuitInterceptor")
+            .field("root_keys", &self.root_keys)
+            .field("authorizer", &self.authorizer.is_some())
+            .finish()
+    }
+}
+
+/// token attached to the request by [`BiscuitInterceptor`]
+pub fn biscuit<T>(request: &Request<T>) -> Option<&Biscuit> {
+    request.extensions().get::<Biscuit>()
+}
+
+/// converts an error to a gRPC status
+///
+/// invalid or revoked tokens give `UNAUTHENTICATED`, failed authorizations
+/// `PERMISSION_DENIED`, and errors in the authorizer `INTERNAL`. Only the
+/// [error code](error::Token::code) is sent in the message
+pub fn status(e: &error::Token) -> Status {
+    match e {
+        error::Token::Format(_)
+        | error::Token::Base64(_)
+        | error::Token::InvalidAuthorityIndex(_)
+        | error::Token::InvalidBlockIndex(_)
+        | error::Token::SymbolTableOverlap
+        | error::Token::MissingSymbols
+        | error::Token::Revoked(_) => Status::unauthenticated(e.code()),
+        error::Token::FailedLogic(_) | error::Token::RunLimit(_) => {
+            Status::permission_denied(e.code())
+        }
+        error::Token::InternalError
+        | error::Token::Sealed
+        | error::Token::ParseError(_)
+        | error::Token::ConversionError(_)
+        | error::Token::LoadError(_) => Status::internal(e.code()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use ::tonic::Code;
+
+    fn request(token: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn interceptor() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/files/a", "read");
+        let token = builder.build().unwrap().to_base64().unwrap();
+        let other = Biscuit::builder(&KeyPair::new())
+            .build()
+            .unwrap()
+            .to_base64()
+            .unwrap();
+
+        let interceptor =
+            BiscuitInterceptor::new(root.public()).authorizer(|metadata, verifier| {
+                let resource = metadata
+                    .get("x-resource")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default();
+                verifier.add_resource(resource);
+                verifier.add_operation("read");
+                verifier.add_policy("allow if right(#authority, $r, $o), resource(#ambient, $r), operation(#ambient, $o)")
+            });
+        let code = |mut request: Request<()>, resource: &str| {
+            request
+                .metadata_mut()
+                .insert("x-resource", resource.parse().unwrap());
+            interceptor
+                .authorize(request.metadata())
+                .map(|_| ())
+                .map_err(|e| e.code())
+        };
+
+        assert_eq!(code(request(&token), "/files/a"), Ok(()));
+        assert_eq!(
+            code(request(&token), "/files/b"),
+            Err(Code::PermissionDenied)
+        );
+        assert_eq!(
+            code(request(&other), "/files/a"),
+            Err(Code::Unauthenticated)
+        );
+        assert_eq!(
+            code(request("not a token"), "/files/a"),
+            Err(Code::Unauthenticated)
+        );
+        assert_eq!(
+            code(Request::new(()), "/files/a"),
+            Err(Code::Unauthenticated)
+        );
+
+        let mut interceptor = BiscuitInterceptor::new(root.public())
+            .authorizer(|_metadata, verifier| verifier.add_policy("allow if"));
+        assert_eq!(
+            interceptor.call(request(&token)).unwrap_err().code(),
+            Code::Internal
+        );
+
+        // the checks of the token are verified without an authorizer
+        let token = Biscuit::from_base64(&token).unwrap();
+        let mut block = token.create_block();
+        block.expiration_date(std::time::SystemTime::UNIX_EPOCH);
+        let expired = token
+            .append(&KeyPair::new(), block)
+            .unwrap()
+            .to_base64()
+            .unwrap();
+        let mut interceptor = BiscuitInterceptor::new(root.public());
+        assert!(interceptor
+            .call(request(&token.to_base64().unwrap()))
+            .is_ok());
+        assert_eq!(
+            interceptor.call(request(&expired)).unwrap_err().code(),
+            Code::PermissionDenied
+        );
+    }
+}